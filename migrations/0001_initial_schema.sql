@@ -1,4 +1,6 @@
-create table places
+-- The schema as it used to be applied by hand from schema.sql. Tables are created with `if not exists`
+-- so databases that were set up manually adopt the migration history without changes.
+create table if not exists places
 (
     place_id        varchar primary key,
     name            varchar,
@@ -11,7 +13,7 @@ create table places
     lng             double precision
 );
 
-create table user_favourite_places
+create table if not exists user_favourite_places
 (
     user_id   varchar,
     place_id  varchar,
@@ -21,7 +23,7 @@ create table user_favourite_places
     constraint user_favourite_places_fk foreign key (place_id) references places (place_id)
 );
 
-create table user_reviews
+create table if not exists user_reviews
 (
     user_id  varchar,
     place_id varchar,
@@ -30,7 +32,7 @@ create table user_reviews
     timestamp timestamp
 );
 
-create table user_reservations
+create table if not exists user_reservations
 (
     user_id               varchar,
     place_id              varchar,
@@ -38,9 +40,9 @@ create table user_reservations
     reservation_pax       int
 );
 
-create table voting_history
+create table if not exists voting_history
 (
     voted_places   json[],
     vote_timestamp int,
     voters         text[]
-);
\ No newline at end of file
+);
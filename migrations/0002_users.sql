@@ -0,0 +1,7 @@
+create table if not exists users
+(
+    user_id       varchar primary key,
+    username      varchar not null unique,
+    password_hash varchar not null,
+    created_at    bigint  not null
+);
@@ -0,0 +1,13 @@
+-- Every timestamp the application reads or writes is a unix epoch in seconds. user_reviews.timestamp was
+-- created as a `timestamp` and the other epoch columns as 32 bit ints, so store all of them as bigint.
+alter table user_reviews
+    alter column timestamp type bigint using extract(epoch from timestamp)::bigint;
+
+alter table user_favourite_places
+    alter column timestamp type bigint;
+
+alter table user_reservations
+    alter column reservation_timestamp type bigint;
+
+alter table voting_history
+    alter column vote_timestamp type bigint;
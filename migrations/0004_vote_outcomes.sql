@@ -0,0 +1,4 @@
+-- Result of the server side tally for sessions that were counted by the backend, null for sessions that
+-- were only recorded after the fact.
+alter table voting_history
+    add column if not exists outcome json;
@@ -0,0 +1,12 @@
+-- Nearby searches over stored places. earthdistance builds on cube, both live in public so the functions
+-- resolve for every schema that has public on its search path.
+create extension if not exists cube with schema public;
+create extension if not exists earthdistance with schema public;
+
+-- Google's 0 to 4 price scale, null when google does not know the price.
+alter table places
+    add column if not exists price_level smallint;
+
+-- earth_box lookups against this index narrow a nearby search down to a small box around the point
+-- before the exact distance is computed.
+create index if not exists places_location_idx on places using gist (ll_to_earth(lat, lng));
@@ -0,0 +1,13 @@
+-- Ranked restaurant search. Words are matched through search_vector, misspellings through trigram similarity.
+-- The `simple` configuration skips stemming and stop words, most place names are not English anyway.
+create extension if not exists pg_trgm with schema public;
+
+alter table places
+    add column if not exists search_vector tsvector generated always as (
+        setweight(to_tsvector('simple', coalesce(name, '')), 'A')
+        || setweight(to_tsvector('simple', coalesce(vicinity, '')), 'B')
+    ) stored;
+
+create index if not exists places_search_vector_idx on places using gin (search_vector);
+create index if not exists places_name_trgm_idx on places using gin (name gin_trgm_ops);
+create index if not exists places_vicinity_trgm_idx on places using gin (vicinity gin_trgm_ops);
@@ -0,0 +1,16 @@
+-- List endpoints page through rows ordered by timestamp with a cursor, these indexes serve those orderings.
+-- Vote sessions get an id so two sessions recorded in the same second still have a stable order.
+create index if not exists user_favourite_places_user_timestamp_idx
+    on user_favourite_places (user_id, timestamp desc, place_id desc);
+
+create index if not exists user_reviews_place_timestamp_idx
+    on user_reviews (place_id, timestamp desc, user_id desc);
+create index if not exists user_reviews_user_timestamp_idx
+    on user_reviews (user_id, timestamp desc, place_id desc);
+
+create index if not exists user_reservations_user_timestamp_idx
+    on user_reservations (user_id, reservation_timestamp, place_id);
+
+alter table voting_history
+    add column if not exists id bigserial;
+create index if not exists voting_history_voters_idx on voting_history using gin (voters);
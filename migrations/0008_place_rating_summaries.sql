@@ -0,0 +1,30 @@
+-- Running totals of user_reviews per place, kept up to date in the same transaction as every review change.
+-- The recent average depends on the time it is asked for, so it is read from user_reviews instead.
+create table if not exists place_rating_summaries
+(
+    place_id     varchar primary key,
+    review_count bigint           not null default 0,
+    rating_total double precision not null default 0,
+    one_star     bigint           not null default 0,
+    two_stars    bigint           not null default 0,
+    three_stars  bigint           not null default 0,
+    four_stars   bigint           not null default 0,
+    five_stars   bigint           not null default 0
+);
+
+-- ratings are rounded to the nearest star with half stars going up, floor(rating + 0.5) rather than round()
+insert into place_rating_summaries
+    (place_id, review_count, rating_total, one_star, two_stars, three_stars, four_stars, five_stars)
+select
+    place_id,
+    count(*),
+    coalesce(sum(rating), 0),
+    count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 1),
+    count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 2),
+    count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 3),
+    count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 4),
+    count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 5)
+from user_reviews
+where place_id is not null and rating is not null
+group by place_id
+on conflict (place_id) do nothing;
@@ -0,0 +1,41 @@
+-- One review per user and place. Duplicates posted before this was enforced are collapsed into the latest one,
+-- rows missing either id could never be read back through the API and are dropped.
+delete from user_reviews where user_id is null or place_id is null;
+
+delete from user_reviews older
+using user_reviews newer
+where older.user_id = newer.user_id
+  and older.place_id = newer.place_id
+  and (coalesce(older.timestamp, 0), older.ctid) < (coalesce(newer.timestamp, 0), newer.ctid);
+
+alter table user_reviews
+    add primary key (user_id, place_id);
+
+-- new reviews have to be of a stored place, `not valid` leaves reviews of places that were never stored alone
+alter table user_reviews
+    add constraint user_reviews_place_fk foreign key (place_id) references places (place_id) not valid;
+
+-- the duplicates were counted in the rating summaries, count again
+update place_rating_summaries s set
+    review_count = totals.review_count,
+    rating_total = totals.rating_total,
+    one_star = totals.one_star,
+    two_stars = totals.two_stars,
+    three_stars = totals.three_stars,
+    four_stars = totals.four_stars,
+    five_stars = totals.five_stars
+from (
+    select
+        place_id,
+        count(*) as review_count,
+        coalesce(sum(rating), 0) as rating_total,
+        count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 1) as one_star,
+        count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 2) as two_stars,
+        count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 3) as three_stars,
+        count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 4) as four_stars,
+        count(*) filter (where least(greatest(floor(rating + 0.5), 1), 5) = 5) as five_stars
+    from user_reviews
+    where rating is not null
+    group by place_id
+) totals
+where s.place_id = totals.place_id;
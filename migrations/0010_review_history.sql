@@ -0,0 +1,23 @@
+-- Reviews keep every version that was written, and removing one only marks it deleted so it can be restored.
+alter table user_reviews
+    add column if not exists deleted_at bigint;
+
+create table if not exists user_review_revisions
+(
+    user_id     varchar not null,
+    place_id    varchar not null,
+    revision    int     not null,
+    rating      double precision,
+    description varchar,
+    timestamp   bigint,
+
+    primary key (user_id, place_id, revision),
+    constraint user_review_revisions_review_fk foreign key (user_id, place_id)
+        references user_reviews (user_id, place_id) on delete cascade
+);
+
+-- whatever a review says today is the oldest version we know of
+insert into user_review_revisions (user_id, place_id, revision, rating, description, timestamp)
+select user_id, place_id, 1, rating, description, timestamp
+from user_reviews
+on conflict do nothing;
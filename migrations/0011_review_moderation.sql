@@ -0,0 +1,33 @@
+-- Reviews can be held back for a moderator, and anyone can report a review that is up.
+alter table user_reviews
+    add column if not exists status varchar not null default 'published';
+
+alter table user_reviews
+    drop constraint if exists user_reviews_status_check;
+alter table user_reviews
+    add constraint user_reviews_status_check check (status in ('published', 'pending', 'hidden'));
+
+create table if not exists review_reports
+(
+    id          bigserial primary key,
+    user_id     varchar not null,
+    place_id    varchar not null,
+    -- null when the banned term filter held the review back
+    reporter_id varchar,
+    reason      varchar not null,
+    created_at  bigint  not null,
+    resolved_at bigint,
+
+    constraint review_reports_review_fk foreign key (user_id, place_id)
+        references user_reviews (user_id, place_id) on delete cascade
+);
+
+-- one open report per reporter and review, the filter counts as one reporter
+create unique index if not exists review_reports_open_idx
+    on review_reports (user_id, place_id, coalesce(reporter_id, ''))
+    where resolved_at is null;
+
+-- the moderation queue, oldest open report first
+create index if not exists review_reports_queue_idx
+    on review_reports (created_at, id)
+    where resolved_at is null;
@@ -0,0 +1,27 @@
+-- Users vote on whether a review helped them, one vote per user and review. The counts are kept on the review
+-- so the reviews of a place can be ordered by them.
+alter table user_reviews
+    add column if not exists helpful_count   int not null default 0,
+    add column if not exists unhelpful_count int not null default 0;
+
+create table if not exists review_votes
+(
+    voter_id   varchar not null,
+    user_id    varchar not null,
+    place_id   varchar not null,
+    helpful    boolean not null,
+    created_at bigint  not null,
+
+    primary key (voter_id, user_id, place_id),
+    constraint review_votes_review_fk foreign key (user_id, place_id)
+        references user_reviews (user_id, place_id) on delete cascade
+);
+
+create index if not exists review_votes_review_idx
+    on review_votes (user_id, place_id);
+
+-- the other orders of the reviews of a place, newest first is served by user_reviews_place_timestamp_idx
+create index if not exists user_reviews_place_rating_idx
+    on user_reviews (place_id, rating, timestamp desc, user_id desc);
+create index if not exists user_reviews_place_helpful_idx
+    on user_reviews (place_id, helpful_count desc, timestamp desc, user_id desc);
@@ -0,0 +1,23 @@
+-- Photos attached to reviews. The images themselves live in the photo storage, this keeps where to find them.
+create table if not exists review_photos
+(
+    photo_id      varchar primary key,
+    user_id       varchar not null,
+    place_id      varchar not null,
+    -- upload order within the review
+    position      int     not null,
+    storage_key   varchar not null,
+    thumbnail_key varchar not null,
+    url           varchar not null,
+    thumbnail_url varchar not null,
+    content_type  varchar not null,
+    width         int     not null,
+    height        int     not null,
+    created_at    bigint  not null,
+
+    constraint review_photos_review_fk foreign key (user_id, place_id)
+        references user_reviews (user_id, place_id) on delete cascade
+);
+
+create index if not exists review_photos_review_idx
+    on review_photos (user_id, place_id, position);
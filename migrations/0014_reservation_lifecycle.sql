@@ -0,0 +1,44 @@
+-- Reservations get an id, so a user can hold several at one place, and a status. Reservations made before
+-- this were taken as booked, they start out confirmed.
+alter table user_reservations
+    add column if not exists reservation_id varchar;
+update user_reservations
+    set reservation_id = md5(random()::text || clock_timestamp()::text)::uuid::text
+    where reservation_id is null;
+alter table user_reservations
+    alter column reservation_id set not null;
+alter table user_reservations
+    drop constraint if exists user_reservations_pkey;
+alter table user_reservations
+    add constraint user_reservations_pkey primary key (reservation_id);
+
+alter table user_reservations
+    add column if not exists status varchar not null default 'confirmed';
+alter table user_reservations
+    drop constraint if exists user_reservations_status_check;
+alter table user_reservations
+    add constraint user_reservations_status_check
+        check (status in ('requested', 'confirmed', 'cancelled', 'completed', 'no_show'));
+alter table user_reservations
+    alter column status drop default;
+
+alter table user_reservations
+    add column if not exists created_at bigint,
+    add column if not exists updated_at bigint;
+update user_reservations
+    set created_at = extract(epoch from now())::bigint,
+        updated_at = extract(epoch from now())::bigint
+    where created_at is null;
+alter table user_reservations
+    alter column created_at set not null,
+    alter column updated_at set not null;
+
+-- lists page by time and then by id now that a place can come up twice
+drop index if exists user_reservations_user_timestamp_idx;
+create index if not exists user_reservations_user_timestamp_idx
+    on user_reservations (user_id, reservation_timestamp, reservation_id);
+
+-- the reservations waiting to be confirmed, soonest first
+create index if not exists user_reservations_requested_idx
+    on user_reservations (reservation_timestamp, reservation_id)
+    where status = 'requested';
@@ -0,0 +1,17 @@
+-- How many people a place seats per time slot and when it is open, see PlaceCapacity. Places without a row
+-- take reservations without limits.
+create table if not exists place_capacities
+(
+    place_id           varchar primary key,
+    seats_per_slot     int     not null check (seats_per_slot > 0),
+    slot_minutes       int     not null check (slot_minutes > 0),
+    utc_offset_minutes int     not null,
+    opening_hours      jsonb   not null,
+
+    constraint place_capacities_place_fk foreign key (place_id) references places (place_id) on delete cascade
+);
+
+-- the seats booked in a slot of a place
+create index if not exists user_reservations_place_timestamp_idx
+    on user_reservations (place_id, reservation_timestamp)
+    where status in ('requested', 'confirmed');
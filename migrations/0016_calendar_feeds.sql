@@ -0,0 +1,8 @@
+-- The secret tokens calendar apps subscribe to a user's reservations with, at most one per user. Only a hash of
+-- the token is kept, the token itself is shown once when it is made.
+create table if not exists calendar_feed_tokens
+(
+    user_id    varchar primary key,
+    token_hash varchar not null unique,
+    created_at bigint  not null
+);
@@ -0,0 +1,18 @@
+-- The reminders that went out for a reservation, per offset before the reservation time. Keyed on the time too,
+-- so a reservation that is moved is reminded of again.
+create table if not exists reservation_reminders
+(
+    reservation_id        varchar not null,
+    reservation_timestamp bigint  not null,
+    offset_secs           bigint  not null,
+    sent_at               bigint  not null,
+
+    primary key (reservation_id, reservation_timestamp, offset_secs),
+    constraint reservation_reminders_reservation_fk foreign key (reservation_id)
+        references user_reservations (reservation_id) on delete cascade
+);
+
+-- the reservations of every user starting soon
+create index if not exists user_reservations_upcoming_idx
+    on user_reservations (reservation_timestamp, reservation_id)
+    where status in ('requested', 'confirmed');
@@ -0,0 +1,5 @@
+-- photos are served by their storage key, as the photo or as its thumbnail
+create index if not exists review_photos_storage_key_idx
+    on review_photos (storage_key);
+create index if not exists review_photos_thumbnail_key_idx
+    on review_photos (thumbnail_key);
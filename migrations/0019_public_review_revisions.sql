@@ -0,0 +1,18 @@
+-- History is public, so it only shows versions that were public too. A version written while the review waited
+-- for a moderator or was hidden stays out unless a moderator publishes it.
+alter table user_review_revisions
+    add column if not exists published boolean not null default false;
+
+-- which of the versions written so far were public is not known, only the current text of a published review is
+update user_review_revisions
+set published = true
+from user_reviews
+where user_review_revisions.user_id = user_reviews.user_id
+  and user_review_revisions.place_id = user_reviews.place_id
+  and user_reviews.status = 'published'
+  and user_review_revisions.revision = (
+      select max(revision)
+      from user_review_revisions latest
+      where latest.user_id = user_reviews.user_id
+        and latest.place_id = user_reviews.place_id
+  );
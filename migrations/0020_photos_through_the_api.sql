@@ -0,0 +1,5 @@
+-- Photos used to be linked straight to the storage, where hiding or removing a review could not take them down.
+-- They are served by the API now, which checks the review first.
+update review_photos
+set url           = '/review/photos/' || storage_key,
+    thumbnail_url = '/review/photos/' || thumbnail_key;
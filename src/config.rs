@@ -1,16 +1,186 @@
-use clap::Parser;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Clone)]
 pub struct Config {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(env, long)]
     pub environment: String,
 
-    #[clap(env, long)]
-    pub postgres_url: String,
+    /// Storage backend, `memory` keeps everything in process and needs no database
+    #[clap(env, long, value_enum, default_value_t = RepositoryBackend::Postgres)]
+    pub repository_backend: RepositoryBackend,
 
+    /// Required when running with the postgres repository backend
     #[clap(env, long)]
+    pub postgres_url: Option<String>,
+
+    /// Apply pending schema migrations before the server starts accepting requests
+    #[clap(env, long, default_value_t = true, action = ArgAction::Set)]
+    pub run_migrations: bool,
+
+    /// Where places come from, `geojson` serves a local dataset and needs no API key or network
+    #[clap(env, long, value_enum, default_value_t = PlaceProviderBackend::Google)]
+    pub place_provider: PlaceProviderBackend,
+
+    /// Base url of the Google Places API, nearby search, details and photos are requested below it
+    #[clap(env, long, default_value = "https://maps.googleapis.com/maps/api/place")]
     pub google_maps_api_url: String,
 
+    /// Required when running with the google place provider
+    #[clap(env, long)]
+    pub google_api_key: Option<String>,
+
+    /// Required when running with the geojson place provider, a FeatureCollection of food venues
     #[clap(env, long)]
-    pub google_api_key: String,
-}
\ No newline at end of file
+    pub places_geojson_path: Option<String>,
+
+    /// How long to wait before using a `next_page_token`, google rejects tokens for a moment after handing them out
+    #[clap(env, long, default_value_t = 2000)]
+    pub google_page_token_delay_ms: u64,
+
+    /// How long a Google nearby search is served from the cache, in seconds
+    #[clap(env, long, default_value_t = 10 * 60)]
+    pub nearby_cache_ttl_secs: u64,
+
+    /// Most nearby searches held in the cache at once, 0 turns the cache off
+    #[clap(env, long, default_value_t = 1000)]
+    pub nearby_cache_max_entries: usize,
+
+    /// How long a removed review can still be restored, in seconds
+    #[clap(env, long, default_value_t = 5 * 60)]
+    pub review_undo_window_secs: i64,
+
+    /// Where photos attached to reviews are kept, `s3` needs the `S3_*` settings
+    #[clap(env, long, value_enum, default_value_t = PhotoStorageBackend::Local)]
+    pub photo_storage: PhotoStorageBackend,
+
+    /// Directory the local photo storage writes to
+    #[clap(env, long, default_value = "uploads")]
+    pub photo_storage_dir: String,
+
+    /// Required when running with the s3 photo storage, such as `https://s3.eu-west-1.amazonaws.com`
+    #[clap(env, long)]
+    pub s3_endpoint: Option<String>,
+
+    /// Required when running with the s3 photo storage
+    #[clap(env, long)]
+    pub s3_bucket: Option<String>,
+
+    #[clap(env, long, default_value = "us-east-1")]
+    pub s3_region: String,
+
+    /// Required when running with the s3 photo storage
+    #[clap(env, long)]
+    pub s3_access_key_id: Option<String>,
+
+    /// Required when running with the s3 photo storage
+    #[clap(env, long)]
+    pub s3_secret_access_key: Option<String>,
+
+    /// Most photos one review can hold
+    #[clap(env, long, default_value_t = 5)]
+    pub review_max_photos: usize,
+
+    /// Largest photo accepted, in bytes
+    #[clap(env, long, default_value_t = 5 * 1024 * 1024)]
+    pub photo_max_bytes: usize,
+
+    /// Longest side of the thumbnails generated for photos, in pixels
+    #[clap(env, long, default_value_t = 320)]
+    pub photo_thumbnail_size: u32,
+
+    /// Comma separated terms that hold a review back for moderation instead of publishing it
+    #[clap(env, long, value_delimiter = ',')]
+    pub moderation_banned_terms: Vec<String>,
+
+    /// Open reports from different users after which a review is taken down until a moderator looks at it
+    #[clap(env, long, default_value_t = 3)]
+    pub moderation_report_threshold: i64,
+
+    /// Comma separated user ids allowed to use the admin endpoints. Ids, not usernames, since anyone can sign up
+    /// with a username that is free
+    #[clap(env, long, value_delimiter = ',')]
+    pub admin_user_ids: Vec<String>,
+
+    /// Smallest party a reservation can be for
+    #[clap(env, long, default_value_t = 1)]
+    pub reservation_min_pax: u32,
+
+    /// Largest party a reservation can be for
+    #[clap(env, long, default_value_t = 20)]
+    pub reservation_max_pax: u32,
+
+    /// How many days ahead reservations can be made, from 1 to 3650
+    #[clap(env, long, default_value_t = 90, value_parser = clap::value_parser!(i64).range(1..=3650))]
+    pub reservation_max_days_ahead: i64,
+
+    /// Reservations of one user have to be at least this many seconds apart, 0 allows any overlap
+    #[clap(env, long, default_value_t = 2 * 60 * 60)]
+    pub reservation_overlap_window_secs: i64,
+
+    /// Comma separated minutes before a reservation that its reminders go out
+    #[clap(env, long, value_delimiter = ',', default_values_t = [24 * 60, 60])]
+    pub reminder_offsets_minutes: Vec<i64>,
+
+    /// How often the scheduler looks for reminders that are due, in seconds, 0 turns reminders off
+    #[clap(env, long, default_value_t = 60)]
+    pub reminder_interval_secs: u64,
+
+    /// Where reminders go, `webhook` needs `REMINDER_WEBHOOK_URL`
+    #[clap(env, long, value_enum, default_value_t = NotifierBackend::Log)]
+    pub reminder_notifier: NotifierBackend,
+
+    /// Required when running with the webhook notifier, reminders are posted to it as json
+    #[clap(env, long)]
+    pub reminder_webhook_url: Option<String>,
+
+    /// Signs webhook requests with HMAC-SHA256 in the `x-eatwherela-signature` header when set
+    #[clap(env, long)]
+    pub reminder_webhook_secret: Option<String>,
+
+    /// Where clients reach the API, such as `https://api.eatwherela.sg`. Calendar feed links are relative when unset
+    #[clap(env, long)]
+    pub api_public_url: Option<String>,
+
+    /// Secret used to sign the bearer tokens handed out on login
+    #[clap(env, long)]
+    pub jwt_secret: String,
+
+    /// How long a bearer token stays valid for, in seconds
+    #[clap(env, long, default_value_t = 7 * 24 * 60 * 60)]
+    pub jwt_expiry_secs: i64,
+}
+
+#[derive(Subcommand, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Run the API server, the default when no subcommand is given
+    Serve,
+    /// Apply pending schema migrations and exit
+    Migrate,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepositoryBackend {
+    Postgres,
+    Memory,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaceProviderBackend {
+    Google,
+    Geojson,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhotoStorageBackend {
+    Local,
+    S3,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifierBackend {
+    Log,
+    Webhook,
+}
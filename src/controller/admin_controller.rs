@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use axum::{Extension, Json, Router};
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post, put};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::controller::AppState;
+use crate::helpers::app_error::AppResult;
+use crate::helpers::auth::AdminUser;
+use crate::models::capacity::PlaceCapacity;
+use crate::models::page::PageParams;
+use crate::models::rating::ReviewStatus;
+use crate::models::reservation::ReservationStatus;
+use crate::repositories::Repository;
+
+/// Moderation and the reservation desk, open to the users listed in `ADMIN_USER_IDS`.
+pub fn router(app_state: AppState) -> Router {
+    Router::new()
+        .route("/reviews/reports", get(retrieve_report_queue))
+        .route("/reviews/approve", post(approve_review))
+        .route("/reviews/hide", post(hide_review))
+        .route("/reservations/requested", get(retrieve_requested_reservations))
+        .route("/reservations/status", post(set_reservation_status))
+        .route("/places/capacity", put(set_place_capacity))
+        .route_layer(Extension(app_state.repository.clone()))
+        .route_layer(Extension(app_state))
+}
+
+/// Open reports, oldest first, each with the review as it reads now.
+pub async fn retrieve_report_queue(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    _admin: AdminUser,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let reports = repository.retrieve_report_queue(&page.to_request()?).await?;
+
+    Ok((
+        StatusCode::OK,
+        json!(&reports).to_string()
+    ))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModerateReview {
+    /// The author of the review
+    pub user_id: String,
+    pub place_id: String,
+}
+
+/// Publishes the review and closes its reports.
+pub async fn approve_review(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    _admin: AdminUser,
+    Json(body): Json<ModerateReview>,
+) -> AppResult<impl IntoResponse> {
+    repository
+        .moderate_review(
+            &body.user_id,
+            &body.place_id,
+            ReviewStatus::Published,
+        ).await?;
+
+    Ok((StatusCode::OK, "Successfully approved the review"))
+}
+
+/// Hides the review from everyone but its author and closes its reports.
+pub async fn hide_review(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    _admin: AdminUser,
+    Json(body): Json<ModerateReview>,
+) -> AppResult<impl IntoResponse> {
+    repository
+        .moderate_review(
+            &body.user_id,
+            &body.place_id,
+            ReviewStatus::Hidden,
+        ).await?;
+
+    Ok((StatusCode::OK, "Successfully hid the review"))
+}
+
+/// Reservations of every user waiting to be confirmed, soonest first.
+pub async fn retrieve_requested_reservations(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    _admin: AdminUser,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let reservations = repository.retrieve_requested_reservations(&page.to_request()?).await?;
+
+    Ok((
+        StatusCode::OK,
+        json!(&reservations).to_string()
+    ))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SetReservationStatus {
+    pub reservation_id: String,
+    pub status: ReservationStatus,
+}
+
+/// Confirms, cancels, completes or marks a no show on any reservation, as far as its status allows.
+pub async fn set_reservation_status(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    _admin: AdminUser,
+    Json(body): Json<SetReservationStatus>,
+) -> AppResult<impl IntoResponse> {
+    let reservation = repository
+        .set_reservation_status(
+            None,
+            &body.reservation_id,
+            body.status,
+        ).await?;
+
+    Ok((
+        StatusCode::OK,
+        json!(&reservation).to_string()
+    ))
+}
+
+/// Sets up or replaces the seats and opening hours of a place. Reservations already made are left as they are.
+pub async fn set_place_capacity(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    _admin: AdminUser,
+    Json(body): Json<PlaceCapacity>,
+) -> AppResult<impl IntoResponse> {
+    body.validate()?;
+    repository.set_place_capacity(&body).await?;
+
+    Ok((StatusCode::OK, "Successfully set the capacity of the place"))
+}
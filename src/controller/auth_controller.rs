@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use axum::{Extension, Json, Router};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::post;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use crate::controller::AppState;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::auth::{hash_password, issue_token, verify_password, DUMMY_PASSWORD_HASH};
+use crate::models::user::User;
+use crate::repositories::Repository;
+
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 32;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+pub fn router(app_state: AppState) -> Router {
+    Router::new()
+        .route("/signup", post(signup))
+        .route("/login", post(login))
+        .route_layer(Extension(app_state.repository.clone()))
+        .route_layer(Extension(app_state))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+pub async fn signup(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Json(body): Json<Credentials>,
+) -> AppResult<impl IntoResponse> {
+    let username = body.username.trim();
+    let username_length = username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&username_length) {
+        return Err(AppError::Validation(format!(
+            "Username must be between {} and {} characters",
+            MIN_USERNAME_LENGTH,
+            MAX_USERNAME_LENGTH,
+        )));
+    }
+    if body.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LENGTH,
+        )));
+    }
+
+    let user = User {
+        user_id: Uuid::new_v4().to_string(),
+        username: username.to_string(),
+        password_hash: hash_password(body.password).await?,
+        created_at: OffsetDateTime::now_utc().unix_timestamp(),
+    };
+    repository.create_user(&user).await?;
+
+    let token = issue_token(&user.user_id, &user.username, &app_state.config)?;
+    Ok((
+        StatusCode::CREATED,
+        json!({
+            "user_id": user.user_id,
+            "username": user.username,
+            "token": token,
+        }).to_string(),
+    ))
+}
+
+pub async fn login(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Json(body): Json<Credentials>,
+) -> AppResult<impl IntoResponse> {
+    let invalid_credentials = || AppError::Unauthorized("Invalid username or password".to_string());
+    let Some(user) = repository.retrieve_user_by_username(body.username.trim()).await? else {
+        verify_password(body.password, DUMMY_PASSWORD_HASH.to_string()).await;
+        return Err(invalid_credentials());
+    };
+    if !verify_password(body.password, user.password_hash.clone()).await {
+        return Err(invalid_credentials());
+    }
+
+    let token = issue_token(&user.user_id, &user.username, &app_state.config)?;
+    Ok((
+        StatusCode::OK,
+        json!({
+            "user_id": user.user_id,
+            "username": user.username,
+            "token": token,
+        }).to_string(),
+    ))
+}
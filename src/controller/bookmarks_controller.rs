@@ -6,97 +6,67 @@ use axum::extract::Query;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use serde_json::json;
-use tracing::warn;
 use crate::controller::AppState;
-use crate::repositories::postgres_repo::PostgresConnectionRepo;
+use crate::helpers::app_error::AppResult;
+use crate::helpers::auth::AuthUser;
+use crate::models::page::PageParams;
+use crate::repositories::Repository;
 
 pub fn router(app_state: AppState) -> Router {
-    let postgres_repo = Arc::new(PostgresConnectionRepo::new(
-        app_state.postgres_connection
-    ));
-
     Router::new()
         .route("/", post(bookmark_restaurant))
         .route("/remove", delete(remove_bookmark))
         .route("/restaurants", get(retrieve_favourite_restaurants))
-        .route_layer(Extension(postgres_repo))
+        .route_layer(Extension(app_state.repository))
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BookmarkRestaurant {
-    pub user_id: String,
     pub place_id: String,
 }
 
 pub async fn bookmark_restaurant(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
     Json(body): Json<BookmarkRestaurant>,
-) -> impl IntoResponse {
-    let add_to_bookmark_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    repository
         .bookmark_place(
-            &body.user_id,
+            &user.user_id,
             &body.place_id,
-        ).await;
-    return match add_to_bookmark_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully bookmarked restaurant").into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong adding restaurant to bookmark due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to add bookmark, please try again").into_response()
-        }
-    };
+        ).await?;
+
+    Ok((StatusCode::OK, "Successfully bookmarked restaurant"))
 }
 
 pub async fn remove_bookmark(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
     Query(query): Query<BookmarkRestaurant>,
-) -> impl IntoResponse {
-    let remove_bookmark_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    repository
         .remove_bookmark(
-            &query.user_id,
+            &user.user_id,
             &query.place_id,
-        ).await;
-
-    return match remove_bookmark_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully removed bookmarked restaurant").into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong removing restaurant from bookmarks due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to remove bookmark, please try again").into_response()
-        }
-    };
-}
+        ).await?;
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct GetFavouriteRestaurantParam {
-    pub user_id: String,
+    Ok((StatusCode::OK, "Successfully removed bookmarked restaurant"))
 }
 
 pub async fn retrieve_favourite_restaurants(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
-    Query(query): Query<GetFavouriteRestaurantParam>,
-) -> impl IntoResponse {
-    let favourite_restaurants_res = postgres_repo
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let restaurants = repository
         .retrieve_bookmarked_places(
-            &query.user_id
+            &user.user_id,
+            &page.to_request()?,
         )
-        .await;
+        .await?;
 
-    return match favourite_restaurants_res {
-        Ok(restaurants) => {
-            (
-                StatusCode::OK,
-                json!(&restaurants).to_string(),
-            ).into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong retrieving favourite restaurants due to: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                "Failed to retrieve favourite restaurants, please try again!",
-            ).into_response();
-        }
-    };
-}
\ No newline at end of file
+    Ok((
+        StatusCode::OK,
+        json!(&restaurants).to_string(),
+    ))
+}
@@ -2,27 +2,37 @@ use std::sync::Arc;
 use axum::extract::Query;
 use axum::response::IntoResponse;
 use axum::{Extension, Router};
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::routing::get;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::json;
 use tracing::warn;
 use crate::controller::AppState;
-use crate::models::restaurant::{Location, Photo, Restaurant};
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::geo::parse_location;
+use crate::helpers::nearby_cache::NearbySearchKey;
+use crate::models::google_places::{NearbyPage, PlaceDetailsResponse, PlacesStatus};
+use crate::models::restaurant::NearbyRestaurantsQuery;
 use crate::models::restaurant_image::RestaurantImage;
-use crate::repositories::postgres_repo::PostgresConnectionRepo;
+use crate::providers::{NearbySearch, PlaceProvider};
+use crate::repositories::Repository;
 
-pub fn router(app_state: AppState) -> Router {
-    let postgres_repo = Arc::new(PostgresConnectionRepo::new(
-        app_state.postgres_connection.clone()
-    ));
+pub const CACHE_STATUS_HEADER: &str = "x-cache";
+/// Cursor for the next page of a search, passed back as `pagetoken`. Absent on the last page.
+pub const NEXT_PAGE_TOKEN_HEADER: &str = "x-next-page-token";
+/// Google hands out at most three pages of twenty results for one search.
+pub const MAX_NEARBY_PAGES: u8 = 3;
+/// Most stored places handed back when google is unavailable, the same as one page of google results.
+pub const NEARBY_FALLBACK_LIMIT: i64 = 20;
 
+pub fn router(app_state: AppState) -> Router {
     Router::new()
         .route("/", get(proxy_google_places_api))
+        .route("/cache-metrics", get(retrieve_nearby_cache_metrics))
         .route("/photo", get(proxy_google_places_photo))
         .route("/place-details", get(proxy_google_places_details))
+        .route_layer(Extension(app_state.repository.clone()))
         .route_layer(Extension(app_state))
-        .route_layer(Extension(postgres_repo))
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -31,80 +41,145 @@ pub struct GooglePlacesApiParams {
     pub radius: String,
     pub r#type: String,
     pub minprice: String,
+    /// The `x-next-page-token` of an earlier response, continues that search
+    #[serde(default)]
+    pub pagetoken: Option<String>,
+    /// How many pages to fetch and merge into one list, defaults to 1
+    #[serde(default)]
+    pub pages: Option<String>,
 }
 
 pub async fn proxy_google_places_api(
     Extension(app_state): Extension<AppState>,
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
     Query(query): Query<GooglePlacesApiParams>,
-) -> impl IntoResponse {
-    let url = format!(
-        "{}?location={}&radius={}&type={}&minprice={}&key={}",
-        app_state.config.google_maps_api_url,
-        query.location.replace("%2C", ","),
-        query.radius,
-        query.r#type,
-        query.minprice,
-        app_state.config.google_api_key
-    );
-    let google_places_api_response = app_state
-        .http_client
-        .get(url)
-        .send()
-        .await;
+) -> AppResult<impl IntoResponse> {
+    let location = parse_location(&query.location.replace("%2C", ","))
+        .ok_or_else(|| AppError::Validation(format!("location must be a lat,lng pair, got: {}", query.location)))?;
+    let radius_metres = query
+        .radius
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|radius| *radius > 0.0)
+        .ok_or_else(|| AppError::Validation(format!("radius must be a positive number of metres, got: {}", query.radius)))?;
+
+    let pages = match query.pages.as_deref().map(str::trim) {
+        None | Some("") => 1,
+        Some(pages) => pages
+            .parse::<u8>()
+            .ok()
+            .filter(|pages| (1..=MAX_NEARBY_PAGES).contains(pages))
+            .ok_or_else(|| AppError::Validation(format!("pages must be between 1 and {}, got: {}", MAX_NEARBY_PAGES, pages)))?,
+    };
+    let page_token = query.pagetoken.as_deref().map(str::trim).filter(|token| !token.is_empty());
+
+    // a page token points into one particular search, only first pages are worth caching
+    let cache_key = NearbySearchKey::new(&location, radius_metres, &query.r#type, &query.minprice, pages);
+    if page_token.is_none() {
+        if let Some(page) = app_state.nearby_cache.get(&cache_key) {
+            return Ok(nearby_response("HIT", page));
+        }
+    }
 
-    let mut list_of_restaurants: Vec<Restaurant> = Vec::new();
-    match google_places_api_response {
-        Ok(response) => {
-            // extract the restaurants here to return to frontend
-            match response.json::<Value>().await {
-                Ok(response_body) => {
-                    for restaurant_val in response_body["results"].as_array().unwrap() {
-                        let photo = restaurant_val["photos"][0].as_object().unwrap();
-                        let restaurant = Restaurant {
-                            place_id: restaurant_val["place_id"].to_string().replace('"', ""),
-                            name: restaurant_val["name"].to_string().replace('"', "").replace("'", ""),
-                            photos: Photo {
-                                height: photo.get("height").unwrap().to_string().replace('"', "").parse().unwrap(),
-                                photo_reference: photo.get("photo_reference").unwrap().to_string().replace('"', ""),
-                                width: photo.get("width").unwrap().to_string().replace('"', "").parse().unwrap(),
-                            },
-                            rating: restaurant_val["rating"].to_string().replace('"', "").parse().unwrap(),
-                            vicinity: restaurant_val["vicinity"].to_string().replace('"', ""),
-                            geometry: Location {
-                                lat: restaurant_val["geometry"]["location"]["lat"].to_string().replace('"', "").parse().unwrap(),
-                                lng: restaurant_val["geometry"]["location"]["lng"].to_string().replace('"', "").parse().unwrap(),
-                            },
-                        };
-
-                        list_of_restaurants.push(restaurant);
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to extract response body due to: {}", e);
-                }
+    let search = NearbySearch {
+        location: location.clone(),
+        radius_metres,
+        place_type: query.r#type.clone(),
+        minprice: query.minprice.clone(),
+        page_token: page_token.map(str::to_string),
+    };
+    let nearby_page = match fetch_nearby_pages(app_state.place_provider.as_ref(), search, pages).await {
+        Ok(page) => page,
+        Err(e @ (AppError::Upstream(_) | AppError::Unavailable(_))) if page_token.is_none() => {
+            // whatever we have stored around the point beats an error page while google is down
+            let stored_restaurants = repository
+                .retrieve_restaurants_near(&NearbyRestaurantsQuery::new(location, radius_metres, NEARBY_FALLBACK_LIMIT))
+                .await?;
+            if stored_restaurants.is_empty() {
+                return Err(e);
             }
+
+            warn!("Serving stored places for {} after {} failed: {}", query.location, app_state.place_provider.name(), e);
+            app_state.nearby_cache.record_fallback();
+            return Ok(nearby_response("FALLBACK", NearbyPage {
+                restaurants: stored_restaurants.into_iter().map(|nearby| nearby.restaurant).collect(),
+                next_page_token: None,
+            }));
         }
-        Err(e) => {
-            warn!("Failed query google places api due to: {}", e);
-        }
-    }
+        Err(e) => return Err(e),
+    };
 
     // Store the places in database for retrieval
-    let store_res = postgres_repo
-        .store_browsed_places(list_of_restaurants.clone())
+    // a failure here should not stop us from returning the results we already have
+    let store_res = repository
+        .store_browsed_places(nearby_page.restaurants.clone())
         .await;
-    match store_res {
-        Ok(_) => {}
-        Err(e) => {
-            warn!("Something happened: {}", e);
+    if let Err(e) = store_res {
+        warn!("Failed to store browsed places due to: {}", e);
+    }
+    if page_token.is_none() {
+        app_state.nearby_cache.insert(cache_key, nearby_page.clone());
+    }
+
+    Ok(nearby_response("MISS", nearby_page))
+}
+
+pub async fn retrieve_nearby_cache_metrics(
+    Extension(app_state): Extension<AppState>,
+) -> AppResult<impl IntoResponse> {
+    Ok((
+        StatusCode::OK,
+        json!(app_state.nearby_cache.metrics()).to_string(),
+    ))
+}
+
+/// Fetches `pages` pages starting at `page_token`, or at the first page, and merges them into one list.
+async fn fetch_nearby_pages(
+    place_provider: &dyn PlaceProvider,
+    mut search: NearbySearch,
+    pages: u8,
+) -> AppResult<NearbyPage> {
+    let mut aggregated = place_provider.nearby_search(&search).await?;
+    for _ in 1..pages {
+        let Some(next_page_token) = aggregated.next_page_token.clone() else {
+            break;
+        };
+        // a token that was just handed out may not be usable yet
+        tokio::time::sleep(place_provider.page_token_delay()).await;
+        search.page_token = Some(next_page_token);
+        match place_provider.nearby_search(&search).await {
+            Ok(page) => aggregated.append(page),
+            Err(e) => {
+                // keep the pages we have, the cursor still points at the one that failed
+                warn!("Stopped aggregating nearby pages from {} after: {}", place_provider.name(), e);
+                break;
+            }
         }
     }
+    Ok(aggregated)
+}
 
-    return (
+/// `x-cache` tells clients, and whoever is debugging quota usage, where the results came from.
+fn nearby_response(
+    cache_status: &'static str,
+    page: NearbyPage,
+) -> (StatusCode, HeaderMap, String) {
+    let mut headers = HeaderMap::new();
+    headers.insert(CACHE_STATUS_HEADER, HeaderValue::from_static(cache_status));
+    match page.next_page_token.as_deref().map(HeaderValue::from_str) {
+        Some(Ok(next_page_token)) => {
+            headers.insert(NEXT_PAGE_TOKEN_HEADER, next_page_token);
+        }
+        Some(Err(_)) => warn!("Dropping a next page token that is not a valid header value"),
+        None => {}
+    }
+
+    (
         StatusCode::OK,
-        json!(list_of_restaurants).to_string(),
-    ).into_response();
+        headers,
+        json!(page.restaurants).to_string(),
+    )
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -115,42 +190,16 @@ pub struct GooglePlacesPhotoParams {
 pub async fn proxy_google_places_photo(
     Extension(app_state): Extension<AppState>,
     Query(query): Query<GooglePlacesPhotoParams>,
-) -> impl IntoResponse {
-    let url = format!(
-        "https://maps.googleapis.com/maps/api/place/photo?maxwidth=400&photoreference={}&key={}",
-        query.photo_reference,
-        app_state.config.google_api_key
-    );
-
-    let response = app_state
-        .http_client
-        .get(url)
-        .send()
-        .await;
+) -> AppResult<impl IntoResponse> {
+    let image_url = app_state.place_provider.photo_url(&query.photo_reference).await?;
+    let restaurant_image = RestaurantImage {
+        image_url,
+    };
 
-    match response {
-        Ok(response) => {
-            let response_url = response.url();
-            let domain = response_url.domain().unwrap().to_string();
-            let path = response_url.path().to_string();
-            let result = format!("{}{}", domain, path);
-            let restaurant_image = RestaurantImage {
-                image_url: result,
-            };
-
-            return (
-                StatusCode::OK,
-                json!(restaurant_image).to_string()
-            ).into_response();
-        }
-        Err(e) => {
-            warn!("Failed query google places api for photo due to: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Something went wrong! Please try again".to_string()
-            ).into_response();
-        }
-    }
+    Ok((
+        StatusCode::OK,
+        json!(restaurant_image).to_string()
+    ))
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -161,43 +210,18 @@ pub struct PlaceDetailsParam {
 pub async fn proxy_google_places_details(
     Extension(app_state): Extension<AppState>,
     Query(query): Query<PlaceDetailsParam>,
-) -> impl IntoResponse {
-    let url = format!(
-        "https://maps.googleapis.com/maps/api/place/details/json?place_id={}&key={}",
-        query.place_id,
-        app_state.config.google_api_key
-    );
-
-    let response = app_state
-        .http_client
-        .get(url)
-        .send()
-        .await;
-
-    match response {
-        Ok(response) => {
-            match response.json::<Value>().await {
-                Ok(response_body) => {
-                    return (
-                        StatusCode::OK,
-                        response_body.to_string()
-                    ).into_response();
-                }
-                Err(e) => {
-                    warn!("Failed to extract response body due to: {}", e);
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Something went wrong! Please try again".to_string()
-                    ).into_response();
-                }
-            }
-        }
-        Err(e) => {
-            warn!("Failed to query google places api for place details due to: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Something went wrong! Please try again".to_string()
-            ).into_response();
-        }
-    }
-}
\ No newline at end of file
+) -> AppResult<impl IntoResponse> {
+    let details = app_state.place_provider.place_details(&query.place_id).await?;
+    // same shape google sends, so existing clients can keep reading `result`
+    let response_body = PlaceDetailsResponse {
+        status: PlacesStatus::Ok,
+        result: Some(details),
+        html_attributions: Vec::new(),
+        error_message: None,
+    };
+
+    Ok((
+        StatusCode::OK,
+        json!(response_body).to_string()
+    ))
+}
@@ -1,17 +1,23 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use anyhow::Context;
 use axum::{Extension, Router};
-use bb8_postgres::bb8::Pool;
-use bb8_postgres::PostgresConnectionManager;
-use bb8_postgres::tokio_postgres::NoTls;
-use reqwest::{Client, Method};
+use reqwest::Method;
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::info;
 use crate::config::Config;
 use crate::helpers::handler_404::page_not_found_handler;
+use crate::helpers::moderation::BannedTerms;
+use crate::helpers::nearby_cache::{NearbySearchCache, PAGE_TOKEN_LIFETIME};
+use crate::providers::PlaceProvider;
+use crate::repositories::Repository;
+use crate::storage::PhotoStorage;
+use crate::voting::VotingRooms;
 
+pub mod admin_controller;
+pub mod auth_controller;
 pub mod bookmarks_controller;
 pub mod google_places_api;
 pub mod health_check;
@@ -20,26 +26,70 @@ pub mod user_reservation_controller;
 pub mod user_review_controller;
 pub mod vote_controller;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<Config>,
-    pub postgres_connection: Pool<PostgresConnectionManager<NoTls>>,
-    pub http_client: Client,
+    pub repository: Arc<dyn Repository>,
+    pub place_provider: Arc<dyn PlaceProvider>,
+    pub voting_rooms: VotingRooms,
+    pub nearby_cache: Arc<NearbySearchCache>,
+    pub banned_terms: Arc<BannedTerms>,
+    pub photo_storage: Arc<dyn PhotoStorage>,
+}
+
+impl AppState {
+    pub fn new(
+        config: Config,
+        repository: Arc<dyn Repository>,
+        place_provider: Arc<dyn PlaceProvider>,
+        photo_storage: Arc<dyn PhotoStorage>,
+    ) -> Self {
+        let nearby_cache = NearbySearchCache::new(
+            Duration::from_secs(config.nearby_cache_ttl_secs),
+            PAGE_TOKEN_LIFETIME,
+            config.nearby_cache_max_entries,
+        );
+        let banned_terms = BannedTerms::new(&config.moderation_banned_terms);
+
+        AppState {
+            config: Arc::new(config),
+            repository,
+            place_provider,
+            voting_rooms: VotingRooms::new(),
+            nearby_cache: Arc::new(nearby_cache),
+            banned_terms: Arc::new(banned_terms),
+            photo_storage,
+        }
+    }
 }
 
 pub async fn serve(
-    postgres_connection: Pool<PostgresConnectionManager<NoTls>>,
+    repository: Arc<dyn Repository>,
+    place_provider: Arc<dyn PlaceProvider>,
+    photo_storage: Arc<dyn PhotoStorage>,
     config: &Config,
 ) -> anyhow::Result<()> {
-    let reqwest_client = Client::new();
+    info!("Discovering places with the {} provider", place_provider.name());
+    info!("Keeping review photos in {} storage", photo_storage.name());
+    let app_state = AppState::new(config.clone(), repository, place_provider, photo_storage);
+    let application = application(app_state);
 
-    let app_state = AppState {
-        config: Arc::new(config.clone()),
-        postgres_connection,
-        http_client: reqwest_client,
-    };
+    let port = SocketAddr::from(([0, 0, 0, 0], 3000));
+    info!("API server listening on port: {}", port);
+    axum::Server::bind(&port)
+        .serve(application.into_make_service())
+        .await
+        .context("Error spinning up the API server")
+}
 
-    let application = router_endpoints(app_state.clone())
+/// The full application router with all middleware applied, shared by the server and the tests.
+pub fn application(
+    app_state: AppState,
+) -> Router {
+    router_endpoints(app_state.clone())
         .layer(
             ServiceBuilder::new()
                 .layer(
@@ -56,24 +106,19 @@ pub async fn serve(
                 )
                 .layer(Extension(app_state))
         )
-        .fallback(page_not_found_handler);
-
-    let port = SocketAddr::from(([0, 0, 0, 0], 3000));
-    info!("API server listening on port: {}", port);
-    axum::Server::bind(&port)
-        .serve(application.into_make_service())
-        .await
-        .context("Error spinning up the API server")
+        .fallback(page_not_found_handler)
 }
 
 pub fn router_endpoints(
     app_state: AppState,
 ) -> Router {
     health_check::router()
+        .nest("/auth", auth_controller::router(app_state.clone()))
         .nest("/google", google_places_api::router(app_state.clone()))
         .nest("/restaurant", restaurant_controller::router(app_state.clone()))
         .nest("/bookmark", bookmarks_controller::router(app_state.clone()))
         .nest("/review", user_review_controller::router(app_state.clone()))
         .nest("/reservation", user_reservation_controller::router(app_state.clone()))
         .nest("/vote", vote_controller::router(app_state.clone()))
+        .nest("/admin", admin_controller::router(app_state.clone()))
 }
\ No newline at end of file
@@ -6,19 +6,23 @@ use axum::routing::{get};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::warn;
 use crate::controller::AppState;
-use crate::repositories::postgres_repo::PostgresConnectionRepo;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::geo::parse_location;
+use crate::helpers::params::optional_param;
+use crate::models::page::parse_limit;
+use crate::models::restaurant::{NearbyRestaurantsQuery, RatedRestaurant};
+use crate::repositories::Repository;
 
-pub fn router(app_state: AppState) -> Router {
-    let postgres_repo = Arc::new(PostgresConnectionRepo::new(
-        app_state.postgres_connection
-    ));
+/// The same ceiling google puts on a nearby search radius.
+pub const MAX_NEARBY_RADIUS_METRES: f64 = 50_000.0;
 
+pub fn router(app_state: AppState) -> Router {
     Router::new()
         .route("/", get(retrieve_restaurant))
         .route("/search", get(search_restaurants_by_name))
-        .route_layer(Extension(postgres_repo))
+        .route("/nearby", get(retrieve_restaurants_nearby))
+        .route_layer(Extension(app_state.repository))
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -27,65 +31,101 @@ pub struct GetRestaurantParam {
 }
 
 pub async fn retrieve_restaurant(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
     Query(query): Query<GetRestaurantParam>,
-) -> impl IntoResponse {
-    let restaurant_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    let restaurant = repository
         .retrieve_restaurant(
             &query.place_id
-        ).await;
-
-    return match restaurant_res {
-        Ok(restaurant) => {
-            if restaurant.is_some() {
-                (
-                    StatusCode::OK,
-                    json!(&restaurant.unwrap()).to_string()
-                ).into_response()
-            } else {
-                (
-                    StatusCode::OK,
-                    json!("{}").to_string()
-                ).into_response()
-            }
-        }
-        Err(e) => {
-            warn!("Something went wrong retrieving restaurant due to: {}", e);
-            (
-                StatusCode::BAD_REQUEST,
-                "Failed to retrieve restaurant, please try again!"
-            ).into_response()
-        }
+        ).await?
+        .ok_or_else(|| AppError::NotFound(format!("No restaurant found with place_id: {}", query.place_id)))?;
+    let rated_restaurant = RatedRestaurant {
+        user_rating: repository.retrieve_rating_summary(&restaurant.place_id).await?,
+        restaurant,
     };
+
+    Ok((
+        StatusCode::OK,
+        json!(&rated_restaurant).to_string()
+    ))
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct SearchRestaurantParam {
     pub restaurant_name: String,
+    pub limit: Option<String>,
+    pub offset: Option<String>,
 }
 
+/// Ranked search over the names and vicinities of stored places, each result carries its `score`.
 pub async fn search_restaurants_by_name(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
     Query(query): Query<SearchRestaurantParam>,
-) -> impl IntoResponse {
-    let restaurants_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    let search_terms = query.restaurant_name.trim();
+    if search_terms.is_empty() {
+        return Err(AppError::Validation("restaurant_name must not be empty".to_string()));
+    }
+    let limit = parse_limit(query.limit.as_deref())?;
+    let offset = optional_param("offset", query.offset.as_deref(), |offset: &i64| *offset >= 0, "zero or more")?
+        .unwrap_or(0);
+
+    let restaurants = repository
         .search_for_restaurants(
-            &query.restaurant_name
-        ).await;
+            search_terms,
+            limit,
+            offset,
+        ).await?;
 
-    return match restaurants_res {
-        Ok(restaurants) => {
-            (
-                StatusCode::OK,
-                json!(&restaurants).to_string()
-            ).into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong searching for restaurants due to: {}", e);
-            (
-                StatusCode::BAD_REQUEST,
-                "{}".to_string()
-            ).into_response()
+    Ok((
+        StatusCode::OK,
+        json!(&restaurants).to_string()
+    ))
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct NearbyRestaurantsParam {
+    pub location: String,
+    pub radius: String,
+    pub min_rating: Option<String>,
+    pub min_price: Option<String>,
+    pub max_price: Option<String>,
+    pub limit: Option<String>,
+}
+
+/// Places we have stored around a point, closest first. Nothing here reaches google.
+pub async fn retrieve_restaurants_nearby(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Query(query): Query<NearbyRestaurantsParam>,
+) -> AppResult<impl IntoResponse> {
+    let location = parse_location(&query.location)
+        .ok_or_else(|| AppError::Validation(format!("location must be a lat,lng pair, got: {}", query.location)))?;
+    let radius_metres = optional_param(
+        "radius",
+        Some(&query.radius),
+        |radius: &f64| *radius > 0.0 && *radius <= MAX_NEARBY_RADIUS_METRES,
+        &format!("a number of metres up to {}", MAX_NEARBY_RADIUS_METRES),
+    )?
+    .ok_or_else(|| AppError::Validation("radius is required".to_string()))?;
+
+    let limit = parse_limit(query.limit.as_deref())?;
+
+    let mut nearby_query = NearbyRestaurantsQuery::new(location, radius_metres, limit);
+    nearby_query.min_rating = optional_param("min_rating", query.min_rating.as_deref(), |rating| (0.0..=5.0).contains(rating), "between 0 and 5")?;
+    nearby_query.min_price_level = optional_param("min_price", query.min_price.as_deref(), |price| *price <= 4, "between 0 and 4")?;
+    nearby_query.max_price_level = optional_param("max_price", query.max_price.as_deref(), |price| *price <= 4, "between 0 and 4")?;
+    if let (Some(min), Some(max)) = (nearby_query.min_price_level, nearby_query.max_price_level) {
+        if min > max {
+            return Err(AppError::Validation(format!("min_price {} is above max_price {}", min, max)));
         }
-    };
-}
\ No newline at end of file
+    }
+
+    let restaurants = repository
+        .retrieve_restaurants_near(&nearby_query)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        json!(&restaurants).to_string()
+    ))
+}
@@ -0,0 +1,1867 @@
+//! Integration tests running the full axum router on top of the in-memory repository.
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use axum::body::Body;
+use axum::extract::Query;
+use axum::http::{Method, Request, StatusCode};
+use axum::Router;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tower::ServiceExt;
+use crate::config::{Config, NotifierBackend, PhotoStorageBackend, PlaceProviderBackend, RepositoryBackend};
+use crate::controller::{application, AppState};
+use crate::helpers::auth::issue_token;
+use crate::helpers::images::tests::png;
+use crate::models::page::PageRequest;
+use crate::models::restaurant::{Location, Photo, Restaurant};
+use crate::providers::build_place_provider;
+use crate::repositories::in_memory_repo::InMemoryRepo;
+use crate::repositories::postgres_repo::tests::{seed_places, TestDatabase, HOSTILE_INPUTS};
+use crate::repositories::Repository;
+use crate::storage::build_photo_storage;
+
+/// The only id in `ADMIN_USER_IDS` of the test config.
+const TEST_ADMIN_ID: &str = "0b9a4b8e-6f0e-4c58-9a2f-5d7c3e1a0f42";
+
+fn test_config() -> Config {
+    Config {
+        command: None,
+        environment: "Test".to_string(),
+        repository_backend: RepositoryBackend::Memory,
+        postgres_url: None,
+        run_migrations: false,
+        place_provider: PlaceProviderBackend::Google,
+        google_maps_api_url: "http://127.0.0.1:9/maps/api/place".to_string(),
+        google_api_key: Some("test-key".to_string()),
+        places_geojson_path: None,
+        google_page_token_delay_ms: 0,
+        nearby_cache_ttl_secs: 600,
+        nearby_cache_max_entries: 100,
+        photo_storage: PhotoStorageBackend::Local,
+        photo_storage_dir: std::env::temp_dir()
+            .join(format!("eat-where-la-photos-{}", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string(),
+        s3_endpoint: None,
+        s3_bucket: None,
+        s3_region: "us-east-1".to_string(),
+        s3_access_key_id: None,
+        s3_secret_access_key: None,
+        review_max_photos: 3,
+        photo_max_bytes: 64 * 1024,
+        photo_thumbnail_size: 32,
+        review_undo_window_secs: 300,
+        moderation_banned_terms: Vec::new(),
+        moderation_report_threshold: 3,
+        admin_user_ids: vec![TEST_ADMIN_ID.to_string()],
+        reservation_min_pax: 1,
+        reservation_max_pax: 20,
+        reservation_max_days_ahead: 30,
+        reservation_overlap_window_secs: 3600,
+        reminder_offsets_minutes: vec![24 * 60, 60],
+        reminder_interval_secs: 0,
+        reminder_notifier: NotifierBackend::Log,
+        reminder_webhook_url: None,
+        reminder_webhook_secret: None,
+        api_public_url: None,
+        jwt_secret: "test-secret".to_string(),
+        jwt_expiry_secs: 3600,
+    }
+}
+
+fn test_restaurant(place_id: &str, name: &str) -> Restaurant {
+    Restaurant {
+        place_id: place_id.to_string(),
+        name: name.to_string(),
+        photos: Some(Photo {
+            height: 400,
+            photo_reference: format!("{}-photo", place_id),
+            width: 300,
+        }),
+        rating: Some(4.2),
+        price_level: Some(1),
+        vicinity: "1 Kadayanallur St, Singapore".to_string(),
+        geometry: Location {
+            lat: 1.2806,
+            lng: 103.8443,
+        },
+    }
+}
+
+async fn test_app() -> (Router, Arc<InMemoryRepo>) {
+    test_app_with_config(test_config()).await
+}
+
+async fn test_app_with_config(config: Config) -> (Router, Arc<InMemoryRepo>) {
+    let repository = Arc::new(InMemoryRepo::new());
+    repository
+        .store_browsed_places(vec![
+            test_restaurant("maxwell-tian-tian", "Tian Tian Hainanese Chicken Rice"),
+            test_restaurant("maxwell-zhen-zhen", "Zhen Zhen Porridge"),
+        ])
+        .await
+        .unwrap();
+
+    let place_provider = build_place_provider(&config).unwrap();
+    let photo_storage = build_photo_storage(&config).unwrap();
+    let app_state = AppState::new(config, repository.clone(), place_provider, photo_storage);
+
+    (application(app_state), repository)
+}
+
+async fn send(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, String) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri);
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        None => request.body(Body::empty()).unwrap(),
+    };
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+fn parse(body: &str) -> Value {
+    serde_json::from_str(body).unwrap()
+}
+
+async fn signup(app: &Router, username: &str) -> String {
+    let (status, body) = send(
+        app,
+        Method::POST,
+        "/auth/signup",
+        None,
+        Some(json!({ "username": username, "password": "correct horse battery" })),
+    ).await;
+    assert_eq!(status, StatusCode::CREATED);
+    parse(&body)["token"].as_str().unwrap().to_string()
+}
+
+fn admin_token() -> String {
+    issue_token(TEST_ADMIN_ID, "moderator", &test_config()).unwrap()
+}
+
+#[tokio::test]
+async fn unknown_route_falls_back_to_teapot() {
+    let (app, _) = test_app().await;
+
+    let (status, _) = send(&app, Method::GET, "/does-not-exist", None, None).await;
+    assert_eq!(status, StatusCode::IM_A_TEAPOT);
+
+    let (status, body) = send(&app, Method::GET, "/health", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Server is healthy");
+}
+
+#[tokio::test]
+async fn restaurant_lookup_and_search() {
+    let (app, _) = test_app().await;
+
+    let (status, body) = send(&app, Method::GET, "/restaurant?place_id=maxwell-zhen-zhen", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body)["name"], "Zhen Zhen Porridge");
+
+    let (status, body) = send(&app, Method::GET, "/restaurant/search?restaurant_name=chicken", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let results = parse(&body);
+    assert_eq!(results.as_array().unwrap().len(), 1);
+    assert_eq!(results[0]["place_id"], "maxwell-tian-tian");
+    assert!(results[0]["score"].as_f64().unwrap() > 0.0);
+
+    let (status, body) = send(&app, Method::GET, "/restaurant/search?restaurant_name=chikn%20rice", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body)[0]["place_id"], "maxwell-tian-tian");
+
+    // both places share the vicinity, the name match ranks first
+    let (_, body) = send(&app, Method::GET, "/restaurant/search?restaurant_name=kadayanallur%20porridge", None, None).await;
+    assert_eq!(parse(&body)[0]["place_id"], "maxwell-zhen-zhen");
+    let (_, body) = send(&app, Method::GET, "/restaurant/search?restaurant_name=kadayanallur&limit=1&offset=1", None, None).await;
+    assert_eq!(parse(&body).as_array().unwrap().len(), 1);
+
+    for invalid in ["restaurant_name=%20", "restaurant_name=rice&limit=0", "restaurant_name=rice&offset=-1"] {
+        let (status, _) = send(&app, Method::GET, &format!("/restaurant/search?{}", invalid), None, None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn nearby_stored_restaurants_are_filtered_and_ordered_by_distance() {
+    let (app, repository) = test_app().await;
+    let mut lau_pa_sat = test_restaurant("lau-pa-sat", "Lau Pa Sat");
+    lau_pa_sat.geometry = Location { lat: 1.2807, lng: 103.8504 };
+    lau_pa_sat.rating = Some(4.0);
+    lau_pa_sat.price_level = Some(2);
+    let mut unrated = test_restaurant("maxwell-unrated", "Unrated Stall");
+    unrated.geometry.lat += 0.0002;
+    unrated.rating = None;
+    unrated.price_level = None;
+    repository.store_browsed_places(vec![lau_pa_sat, unrated]).await.unwrap();
+    let nearby = "/restaurant/nearby?location=1.2806,103.8443";
+
+    let (status, body) = send(&app, Method::GET, &format!("{}&radius=1000", nearby), None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let restaurants = parse(&body);
+    let place_ids: Vec<&str> = restaurants.as_array().unwrap().iter().map(|r| r["place_id"].as_str().unwrap()).collect();
+    assert_eq!(place_ids, ["maxwell-tian-tian", "maxwell-zhen-zhen", "maxwell-unrated", "lau-pa-sat"]);
+    assert_eq!(restaurants[0]["distance_metres"], 0.0);
+    assert!(restaurants[3]["distance_metres"].as_f64().unwrap() > 600.0);
+
+    let (_, body) = send(&app, Method::GET, &format!("{}&radius=100", nearby), None, None).await;
+    assert_eq!(parse(&body).as_array().unwrap().len(), 3);
+
+    let (_, body) = send(&app, Method::GET, &format!("{}&radius=1000&min_rating=4.1", nearby), None, None).await;
+    assert_eq!(parse(&body).as_array().unwrap().len(), 2);
+
+    let (_, body) = send(&app, Method::GET, &format!("{}&radius=1000&min_price=2&max_price=3&limit=5", nearby), None, None).await;
+    assert_eq!(parse(&body)[0]["place_id"], "lau-pa-sat");
+
+    let (_, body) = send(&app, Method::GET, &format!("{}&radius=1000&limit=1", nearby), None, None).await;
+    assert_eq!(parse(&body).as_array().unwrap().len(), 1);
+
+    for invalid in ["radius=0", "radius=60000", "radius=500&min_rating=6", "radius=500&max_price=5", "radius=500&min_price=3&max_price=1", "radius=500&limit=0"] {
+        let (status, body) = send(&app, Method::GET, &format!("{}&{}", nearby, invalid), None, None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", invalid);
+        assert_eq!(parse(&body)["code"], "validation_failed");
+    }
+}
+
+/// Stand-in for the google nearby search endpoint, counting how often it gets called.
+async fn fake_google_places(calls: Arc<AtomicUsize>) -> String {
+    let body = json!({
+        "status": "OK",
+        "results": [{
+            "place_id": "amoy-street-a-noodle",
+            "name": "A Noodle Story",
+            "photos": [{ "height": 400, "photo_reference": "a-noodle-photo", "width": 300 }],
+            "rating": 4.5,
+            "vicinity": "7 Maxwell Rd, Singapore",
+            "geometry": { "location": { "lat": 1.2799, "lng": 103.8466 } },
+        }],
+    });
+    fake_google_places_returning(calls, body.to_string()).await
+}
+
+/// Serves `body` for every nearby search, so recorded Google responses can be replayed. Details always answer
+/// with the recorded Tian Tian details. Returns the base url to configure.
+async fn fake_google_places_returning(calls: Arc<AtomicUsize>, body: String) -> String {
+    let handler = move || {
+        let calls = calls.clone();
+        let body = body.clone();
+        async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            ([("content-type", "application/json")], body)
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(
+            Router::new()
+                .route("/nearbysearch/json", axum::routing::get(handler))
+                .route("/details/json", axum::routing::get(|| async {
+                    let details = include_str!("../../tests/fixtures/google_places/place_details_ok.json");
+                    ([("content-type", "application/json")], details)
+                }))
+                .into_make_service(),
+        );
+    tokio::spawn(server);
+
+    format!("http://{}", address)
+}
+
+#[tokio::test]
+async fn nearby_search_is_served_from_the_cache() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut config = test_config();
+    config.google_maps_api_url = fake_google_places(calls.clone()).await;
+    let (app, repository) = test_app_with_config(config).await;
+
+    let (status, body) = send(
+        &app,
+        Method::GET,
+        "/google?location=1.28061,103.84431&radius=500&type=restaurant&minprice=1",
+        None,
+        None,
+    ).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body)[0]["place_id"], "amoy-street-a-noodle");
+    assert!(repository.retrieve_restaurant("amoy-street-a-noodle").await.unwrap().is_some());
+
+    // a few metres away falls in the same grid cell, and the radius is compared as a number
+    let request = Request::builder()
+        .uri("/google?location=1.28089,103.84449&radius=500.0&type=restaurant&minprice=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-cache"], "HIT");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // a different radius is a different search
+    let (status, _) = send(
+        &app,
+        Method::GET,
+        "/google?location=1.28061,103.84431&radius=1000&type=restaurant&minprice=1",
+        None,
+        None,
+    ).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    let (_, body) = send(&app, Method::GET, "/google/cache-metrics", None, None).await;
+    let metrics = parse(&body);
+    assert_eq!(metrics["hits"], 1);
+    assert_eq!(metrics["misses"], 2);
+    assert_eq!(metrics["entries"], 2);
+}
+
+#[tokio::test]
+async fn nearby_search_falls_back_to_stored_places() {
+    // test_config points google at a port nothing listens on
+    let (app, _) = test_app().await;
+
+    let request = Request::builder()
+        .uri("/google?location=1.2806,103.8443&radius=500&type=restaurant&minprice=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-cache"], "FALLBACK");
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(parse(std::str::from_utf8(&bytes).unwrap()).as_array().unwrap().len(), 2);
+
+    // nothing stored around changi, so the upstream failure comes through
+    let (status, body) = send(
+        &app,
+        Method::GET,
+        "/google?location=1.3644,103.9915&radius=500&type=restaurant&minprice=1",
+        None,
+        None,
+    ).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(parse(&body)["code"], "upstream_failure");
+
+    let (status, _) = send(&app, Method::GET, "/google?location=maxwell&radius=500&type=restaurant&minprice=1", None, None).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (_, body) = send(&app, Method::GET, "/google/cache-metrics", None, None).await;
+    let metrics = parse(&body);
+    assert_eq!(metrics["fallbacks"], 1);
+    assert_eq!(metrics["entries"], 0);
+}
+
+/// Three pages of results, the first use of each page token is rejected like google does before it goes live.
+async fn fake_paged_google_places(calls: Arc<AtomicUsize>) -> String {
+    let used_tokens = Arc::new(std::sync::Mutex::new(HashSet::new()));
+    let handler = move |Query(params): Query<HashMap<String, String>>| {
+        let calls = calls.clone();
+        let used_tokens = used_tokens.clone();
+        async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let place = |place_id: &str| json!({
+                "place_id": place_id,
+                "name": place_id,
+                "vicinity": "Amoy Street, Singapore",
+                "geometry": { "location": { "lat": 1.2799, "lng": 103.8466 } },
+            });
+            let page_token = params.get("pagetoken").cloned();
+            if let Some(page_token) = &page_token {
+                if used_tokens.lock().unwrap().insert(page_token.clone()) {
+                    return axum::Json(json!({ "status": "INVALID_REQUEST", "results": [] }));
+                }
+            }
+            axum::Json(match page_token.as_deref() {
+                None => json!({ "status": "OK", "results": [place("a"), place("b")], "next_page_token": "page-2" }),
+                Some("page-2") => json!({ "status": "OK", "results": [place("b"), place("c")], "next_page_token": "page-3" }),
+                Some("page-3") => json!({ "status": "OK", "results": [place("d")] }),
+                Some(_) => json!({ "status": "INVALID_REQUEST", "results": [] }),
+            })
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(Router::new().route("/nearbysearch/json", axum::routing::get(handler)).into_make_service());
+    tokio::spawn(server);
+
+    format!("http://{}", address)
+}
+
+async fn nearby_page(app: &Router, uri: &str) -> (StatusCode, Option<String>, Vec<String>) {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let next_page_token = response
+        .headers()
+        .get("x-next-page-token")
+        .map(|token| token.to_str().unwrap().to_string());
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let place_ids = match parse(std::str::from_utf8(&bytes).unwrap()) {
+        Value::Array(restaurants) => restaurants
+            .iter()
+            .map(|restaurant| restaurant["place_id"].as_str().unwrap().to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    (status, next_page_token, place_ids)
+}
+
+#[tokio::test]
+async fn nearby_search_pages_through_google_results() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut config = test_config();
+    config.google_maps_api_url = fake_paged_google_places(calls.clone()).await;
+    let (app, _) = test_app_with_config(config).await;
+    let search = "/google?location=1.28061,103.84431&radius=500&type=restaurant&minprice=1";
+
+    let (status, next_page_token, place_ids) = nearby_page(&app, search).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(next_page_token.as_deref(), Some("page-2"));
+    assert_eq!(place_ids, ["a", "b"]);
+
+    // a repeated search gets the cached first page and can still page on from it
+    let (status, next_page_token, place_ids) = nearby_page(&app, search).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(next_page_token.as_deref(), Some("page-2"));
+    assert_eq!(place_ids, ["a", "b"]);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // the token is retried until google accepts it
+    let (status, next_page_token, place_ids) = nearby_page(&app, &format!("{}&pagetoken=page-2", search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(next_page_token.as_deref(), Some("page-3"));
+    assert_eq!(place_ids, ["b", "c"]);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    let (status, next_page_token, place_ids) = nearby_page(&app, &format!("{}&pagetoken=page-3", search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(next_page_token.is_none());
+    assert_eq!(place_ids, ["d"]);
+
+    let (status, _, _) = nearby_page(&app, &format!("{}&pagetoken=expired", search)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn nearby_search_aggregates_pages() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut config = test_config();
+    config.google_maps_api_url = fake_paged_google_places(calls.clone()).await;
+    let (app, repository) = test_app_with_config(config).await;
+    let search = "/google?location=1.28061,103.84431&radius=500&type=restaurant&minprice=1";
+
+    let (status, next_page_token, place_ids) = nearby_page(&app, &format!("{}&pages=3", search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(next_page_token.is_none());
+    assert_eq!(place_ids, ["a", "b", "c", "d"]);
+    assert!(repository.retrieve_restaurant("d").await.unwrap().is_some());
+    let calls_for_three_pages = calls.load(Ordering::SeqCst);
+
+    // the aggregated list is cached on its own
+    let (_, _, place_ids) = nearby_page(&app, &format!("{}&pages=3", search)).await;
+    assert_eq!(place_ids.len(), 4);
+    assert_eq!(calls.load(Ordering::SeqCst), calls_for_three_pages);
+
+    let (_, next_page_token, place_ids) = nearby_page(&app, &format!("{}&pages=1", search)).await;
+    assert_eq!(next_page_token.as_deref(), Some("page-2"));
+    assert_eq!(place_ids, ["a", "b"]);
+
+    for pages in ["0", "4", "all"] {
+        let (status, body) = send(&app, Method::GET, &format!("{}&pages={}", search, pages), None, None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(parse(&body)["message"].as_str().unwrap().contains("pages must be between 1 and 3"));
+    }
+}
+
+#[tokio::test]
+async fn nearby_search_parses_recorded_google_responses() {
+    let nearby_search_ok = include_str!("../../tests/fixtures/google_places/nearby_search_ok.json");
+    let mut config = test_config();
+    config.google_maps_api_url = fake_google_places_returning(Arc::default(), nearby_search_ok.to_string()).await;
+    let (app, repository) = test_app_with_config(config).await;
+
+    let (status, body) = send(&app, Method::GET, "/google?location=1.2804,103.8448&radius=500&type=restaurant&minprice=1", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let restaurants = parse(&body);
+    assert_eq!(restaurants.as_array().unwrap().len(), 3);
+    assert!(restaurants[1]["photos"].is_null());
+    assert!(restaurants[2]["rating"].is_null());
+    let stored = repository.retrieve_restaurant(restaurants[1]["place_id"].as_str().unwrap()).await.unwrap();
+    assert!(stored.unwrap().photos.is_none());
+
+    let zero_results = include_str!("../../tests/fixtures/google_places/nearby_search_zero_results.json");
+    let mut config = test_config();
+    config.google_maps_api_url = fake_google_places_returning(Arc::default(), zero_results.to_string()).await;
+    let (app, _) = test_app_with_config(config).await;
+
+    let (status, body) = send(&app, Method::GET, "/google?location=1.3644,103.9915&radius=500&type=restaurant&minprice=1", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body), json!([]));
+}
+
+#[tokio::test]
+async fn nearby_search_over_quota_is_unavailable() {
+    let over_query_limit = include_str!("../../tests/fixtures/google_places/nearby_search_over_query_limit.json");
+    let mut config = test_config();
+    config.google_maps_api_url = fake_google_places_returning(Arc::default(), over_query_limit.to_string()).await;
+    let (app, _) = test_app_with_config(config).await;
+
+    // stored places around maxwell still answer the request
+    let request = Request::builder()
+        .uri("/google?location=1.2806,103.8443&radius=500&type=restaurant&minprice=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-cache"], "FALLBACK");
+
+    let (status, body) = send(&app, Method::GET, "/google?location=1.3644,103.9915&radius=500&type=restaurant&minprice=1", None, None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(parse(&body)["code"], "service_unavailable");
+}
+
+#[tokio::test]
+async fn place_details_come_from_the_configured_google_url() {
+    let mut config = test_config();
+    // the nearby search url older deployments configured still works
+    let base_url = fake_google_places_returning(Arc::default(), String::new()).await;
+    config.google_maps_api_url = format!("{}/nearbysearch/json", base_url);
+    let (app, _) = test_app_with_config(config).await;
+
+    let (status, body) = send(&app, Method::GET, "/google/place-details?place_id=ChIJ6ZbH8w0Z2jERWzLnJm7YnfY", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = parse(&body);
+    assert_eq!(body["status"], "OK");
+    assert_eq!(body["result"]["formatted_phone_number"], "9691 4852");
+}
+
+#[tokio::test]
+async fn geojson_provider_serves_places_offline() {
+    let mut config = test_config();
+    config.place_provider = PlaceProviderBackend::Geojson;
+    config.google_api_key = None;
+    config.places_geojson_path = Some(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/places/maxwell_food.geojson").to_string());
+    let (app, repository) = test_app_with_config(config).await;
+
+    let (status, body) = send(&app, Method::GET, "/google?location=1.2803,103.8447&radius=500&type=restaurant&minprice=1", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let restaurants = parse(&body);
+    assert_eq!(restaurants.as_array().unwrap().len(), 3);
+    assert_eq!(restaurants[0]["name"], "Maxwell Food Centre");
+    assert!(repository.retrieve_restaurant("geojson:node:2404563981").await.unwrap().is_some());
+
+    let (status, body) = send(&app, Method::GET, "/google/place-details?place_id=geojson:node:2404563981", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body)["result"]["name"], "Tian Tian Hainanese Chicken Rice");
+
+    let (status, body) = send(&app, Method::GET, "/google/photo?photo_reference=anything", None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(parse(&body)["code"], "not_found");
+}
+
+#[test]
+fn each_place_provider_needs_its_settings() {
+    let mut config = test_config();
+    config.google_api_key = None;
+    assert!(build_place_provider(&config).is_err());
+
+    config.place_provider = PlaceProviderBackend::Geojson;
+    assert!(build_place_provider(&config).is_err());
+}
+
+#[tokio::test]
+async fn signup_and_login() {
+    let (app, _) = test_app().await;
+    signup(&app, "alice").await;
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/auth/signup",
+        None,
+        Some(json!({ "username": "alice", "password": "another password" })),
+    ).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(parse(&body)["code"], "conflict");
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/auth/signup",
+        None,
+        Some(json!({ "username": "bob", "password": "short" })),
+    ).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(parse(&body)["code"], "validation_failed");
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/auth/login",
+        None,
+        Some(json!({ "username": "alice", "password": "correct horse battery" })),
+    ).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = parse(&body)["token"].as_str().unwrap().to_string();
+
+    let (status, _) = send(&app, Method::GET, "/bookmark/restaurants", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/auth/login",
+        None,
+        Some(json!({ "username": "alice", "password": "wrong password" })),
+    ).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(parse(&body)["code"], "unauthorized");
+}
+
+#[tokio::test]
+async fn user_endpoints_require_a_valid_token() {
+    let (app, _) = test_app().await;
+
+    for (method, uri) in [
+        (Method::GET, "/bookmark/restaurants"),
+        (Method::GET, "/review/user"),
+        (Method::GET, "/reservation"),
+        (Method::GET, "/reservation/list"),
+        (Method::GET, "/vote"),
+    ] {
+        let (status, body) = send(&app, method.clone(), uri, None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+        assert_eq!(parse(&body)["code"], "unauthorized");
+
+        let (status, _) = send(&app, method.clone(), uri, Some("not-a-token"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+    }
+}
+
+#[tokio::test]
+async fn bookmark_lifecycle() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+    let bob = signup(&app, "bob").await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/bookmark",
+        Some(&alice),
+        Some(json!({ "place_id": "maxwell-tian-tian" })),
+    ).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::GET, "/bookmark/restaurants", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"][0]["place_id"], "maxwell-tian-tian");
+
+    // bookmarks are scoped to the authenticated user
+    let (_, body) = send(&app, Method::GET, "/bookmark/restaurants", Some(&bob), None).await;
+    assert_eq!(parse(&body), json!({ "items": [], "next_cursor": null }));
+    let (status, _) = send(&app, Method::DELETE, "/bookmark/remove?place_id=maxwell-tian-tian", Some(&bob), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&app, Method::DELETE, "/bookmark/remove?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::GET, "/bookmark/restaurants", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+}
+
+#[tokio::test]
+async fn list_endpoints_hand_out_cursors() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+
+    for vote_timestamp in [1_700_000_000, 1_700_000_100, 1_700_000_200] {
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/vote",
+            Some(&alice),
+            Some(json!({ "user_ids": [], "voted_places": [], "vote_timestamp": vote_timestamp })),
+        ).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, body) = send(&app, Method::GET, "/vote?limit=2", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let first_page = parse(&body);
+    assert_eq!(first_page["items"][0]["vote_timestamp"], 1_700_000_200);
+    assert_eq!(first_page["items"][1]["vote_timestamp"], 1_700_000_100);
+    let next_cursor = first_page["next_cursor"].as_str().unwrap();
+
+    let (_, body) = send(&app, Method::GET, &format!("/vote?limit=2&cursor={}", next_cursor), Some(&alice), None).await;
+    let last_page = parse(&body);
+    assert_eq!(last_page["items"].as_array().unwrap().len(), 1);
+    assert_eq!(last_page["items"][0]["vote_timestamp"], 1_700_000_000);
+    assert_eq!(last_page["next_cursor"], Value::Null);
+
+    for uri in ["/vote?cursor=not-a-cursor", "/vote?limit=0", "/vote?limit=101", "/bookmark/restaurants?cursor=zz"] {
+        let (status, body) = send(&app, Method::GET, uri, Some(&alice), None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", uri);
+        assert_eq!(parse(&body)["code"], "validation_failed");
+    }
+}
+
+#[tokio::test]
+async fn review_lifecycle() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+
+    let review = json!({
+        "place_id": "maxwell-tian-tian",
+        "rating": 4.5,
+        "description": "Worth the queue, don't skip the chilli",
+    });
+    let (status, _) = send(&app, Method::POST, "/review", Some(&alice), Some(review.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let updated_review = json!({
+        "place_id": "maxwell-tian-tian",
+        "rating": 3.0,
+        "description": "Queue is getting too long",
+    });
+    let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(updated_review)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // one review per user and place, a second one has to go through PUT
+    let (status, body) = send(&app, Method::POST, "/review", Some(&alice), Some(review.clone())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(parse(&body)["code"], "conflict");
+
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    let reviews = &parse(&body)["items"];
+    assert_eq!(reviews.as_array().unwrap().len(), 1);
+    assert_eq!(reviews[0]["rating"], 3.0);
+    assert_eq!(reviews[0]["description"], "Queue is getting too long");
+
+    let (status, _) = send(&app, Method::DELETE, "/review?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+}
+
+#[tokio::test]
+async fn reviews_are_upserted_and_validated() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+
+    let review = json!({ "place_id": "maxwell-zhen-zhen", "rating": 3.5, "description": "Smooth porridge" });
+    let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let review = json!({ "place_id": "maxwell-zhen-zhen", "rating": 4.0, "description": "Even better the second time" });
+    let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&alice), None).await;
+    let reviews = &parse(&body)["items"];
+    assert_eq!(reviews.as_array().unwrap().len(), 1);
+    assert_eq!(reviews[0]["rating"], 4.0);
+
+    for rating in [0.0, 4.2, 5.5, -1.0] {
+        for method in [Method::POST, Method::PUT] {
+            let review = json!({ "place_id": "maxwell-tian-tian", "rating": rating, "description": "" });
+            let (status, body) = send(&app, method.clone(), "/review", Some(&alice), Some(review)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{} {}", method, rating);
+            assert_eq!(parse(&body)["code"], "validation_failed");
+        }
+    }
+
+    let review = json!({ "place_id": "nowhere", "rating": 4.0, "description": "" });
+    let (status, _) = send(&app, Method::POST, "/review", Some(&alice), Some(review.clone())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, Method::DELETE, "/review?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reviews_keep_their_history_and_can_be_restored() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+
+    for (rating, description) in [(3.0, "Decent"), (4.0, "Better on a weekday"), (4.5, "Go before noon")] {
+        let review = json!({ "place_id": "maxwell-tian-tian", "rating": rating, "description": description });
+        let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(review)).await;
+        assert!(status.is_success());
+    }
+
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&alice), None).await;
+    let alice_id = parse(&body)["items"][0]["user_id"].as_str().unwrap().to_string();
+    let history_uri = format!("/review/history?user_id={}&place_id=maxwell-tian-tian", alice_id);
+    let (status, body) = send(&app, Method::GET, &format!("{}&limit=2", history_uri), None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let history = parse(&body);
+    assert_eq!(history["items"][0]["revision"], 3);
+    assert_eq!(history["items"][0]["description"], "Go before noon");
+    assert_eq!(history["items"][1]["revision"], 2);
+    let cursor = history["next_cursor"].as_str().unwrap();
+    let (_, body) = send(&app, Method::GET, &format!("{}&cursor={}", history_uri, cursor), None, None).await;
+    assert_eq!(parse(&body)["items"][0]["description"], "Decent");
+
+    // a removed review drops out of the lists and the summary until it is restored
+    let (status, _) = send(&app, Method::DELETE, "/review?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+    assert_eq!(parse(&body)["summary"]["count"], 0);
+    let (status, _) = send(&app, Method::GET, &history_uri, None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = send(&app, Method::GET, "/review/user?include_deleted=true", Some(&alice), None).await;
+    assert!(parse(&body)["items"][0]["deleted_at"].is_i64());
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+
+    let (status, _) = send(&app, Method::POST, "/review/restore?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["items"][0]["description"], "Go before noon");
+    assert_eq!(parse(&body)["summary"]["count"], 1);
+    let (status, _) = send(&app, Method::POST, "/review/restore?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn removed_reviews_are_final_after_the_undo_window() {
+    let (app, _) = test_app_with_config(Config {
+        review_undo_window_secs: -1,
+        ..test_config()
+    }).await;
+    let alice = signup(&app, "alice").await;
+
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 2.0, "description": "Too salty" });
+    send(&app, Method::POST, "/review", Some(&alice), Some(review)).await;
+    send(&app, Method::DELETE, "/review?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    let (status, _) = send(&app, Method::POST, "/review/restore?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // the place can be reviewed afresh
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 4.0, "description": "Less salty now" });
+    let (status, _) = send(&app, Method::POST, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn user_id_of(app: &Router, token: &str) -> String {
+    let (_, body) = send(app, Method::GET, "/review/user?include_deleted=true", Some(token), None).await;
+    parse(&body)["items"][0]["user_id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn banned_terms_hold_reviews_back_for_a_moderator() {
+    let (app, _) = test_app_with_config(Config {
+        moderation_banned_terms: vec!["food poisoning".to_string()],
+        ..test_config()
+    }).await;
+    let alice = signup(&app, "alice").await;
+    let admin = admin_token();
+
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 1.0, "description": "Got FOOD-POISONING here" });
+    let (status, _) = send(&app, Method::POST, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+    assert_eq!(parse(&body)["summary"]["count"], 0);
+    // the author still sees what they wrote
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"][0]["status"], "pending");
+
+    let (status, _) = send(&app, Method::GET, "/admin/reviews/reports", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, Method::GET, "/admin/reviews/reports", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // admin rights go with the id, taking the admin's username gets nothing
+    let (status, _) = send(&app, Method::GET, "/admin/reviews/reports", Some(&signup(&app, "moderator").await), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = send(&app, Method::GET, "/admin/reviews/reports", Some(&admin), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let queue = parse(&body);
+    assert_eq!(queue["items"].as_array().unwrap().len(), 1);
+    assert_eq!(queue["items"][0]["reporter_id"], Value::Null);
+    assert_eq!(queue["items"][0]["reason"], "Contains the banned term: food poisoning");
+    assert_eq!(queue["items"][0]["review"]["description"], "Got FOOD-POISONING here");
+
+    let alice_id = user_id_of(&app, &alice).await;
+    let moderated = json!({ "user_id": alice_id, "place_id": "maxwell-tian-tian" });
+    let (status, _) = send(&app, Method::POST, "/admin/reviews/approve", Some(&admin), Some(moderated)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["items"][0]["status"], "published");
+    assert_eq!(parse(&body)["summary"]["count"], 1);
+    let (_, body) = send(&app, Method::GET, "/admin/reviews/reports", Some(&admin), None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+
+    // an approved review keeps its status through clean edits
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 2.0, "description": "Still unwell" });
+    let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["items"][0]["description"], "Still unwell");
+}
+
+#[tokio::test]
+async fn versions_held_back_by_the_filter_stay_out_of_history() {
+    let (app, _) = test_app_with_config(Config {
+        moderation_banned_terms: vec!["food poisoning".to_string()],
+        ..test_config()
+    }).await;
+    let alice = signup(&app, "alice").await;
+    let admin = admin_token();
+
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 1.0, "description": "Got food poisoning here" });
+    let (status, _) = send(&app, Method::POST, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let alice_id = user_id_of(&app, &alice).await;
+    // the moderator only ever sees the clean version
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 2.0, "description": "Left feeling unwell" });
+    let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::OK);
+    let moderated = json!({ "user_id": alice_id, "place_id": "maxwell-tian-tian" });
+    let (status, _) = send(&app, Method::POST, "/admin/reviews/approve", Some(&admin), Some(moderated)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let history_uri = format!("/review/history?user_id={}&place_id=maxwell-tian-tian", alice_id);
+    let (status, body) = send(&app, Method::GET, &history_uri, None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let descriptions = |body: &str| -> Vec<String> {
+        parse(body)["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|revision| revision["description"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(descriptions(&body), ["Left feeling unwell"]);
+
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 3.0, "description": "Better the second time" });
+    let (status, _) = send(&app, Method::PUT, "/review", Some(&alice), Some(review)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, Method::GET, &history_uri, None, None).await;
+    assert_eq!(descriptions(&body), ["Better the second time", "Left feeling unwell"]);
+}
+
+#[tokio::test]
+async fn reported_reviews_come_down_until_a_moderator_decides() {
+    let (app, _) = test_app_with_config(Config {
+        moderation_report_threshold: 2,
+        ..test_config()
+    }).await;
+    let alice = signup(&app, "alice").await;
+    let admin = admin_token();
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 5.0, "description": "Best in town" });
+    send(&app, Method::POST, "/review", Some(&alice), Some(review)).await;
+    let alice_id = user_id_of(&app, &alice).await;
+
+    let report = json!({ "user_id": alice_id, "place_id": "maxwell-tian-tian", "reason": "Advertising" });
+    let (status, _) = send(&app, Method::POST, "/review/report", Some(&alice), Some(report.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let blank = json!({ "user_id": alice_id, "place_id": "maxwell-tian-tian", "reason": "  " });
+    let bob = signup(&app, "bob").await;
+    let (status, _) = send(&app, Method::POST, "/review/report", Some(&bob), Some(blank)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = send(&app, Method::POST, "/review/report", Some(&bob), Some(report.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, Method::POST, "/review/report", Some(&bob), Some(report.clone())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["summary"]["count"], 1);
+
+    let carol = signup(&app, "carol").await;
+    let (status, _) = send(&app, Method::POST, "/review/report", Some(&carol), Some(report.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+    assert_eq!(parse(&body)["summary"]["count"], 0);
+    // nothing left to report once it is down
+    let dave = signup(&app, "dave").await;
+    let (status, _) = send(&app, Method::POST, "/review/report", Some(&dave), Some(report)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, body) = send(&app, Method::GET, "/admin/reviews/reports?limit=1", Some(&admin), None).await;
+    let queue = parse(&body);
+    assert_eq!(queue["items"][0]["reason"], "Advertising");
+    assert_eq!(queue["items"][0]["review"]["status"], "pending");
+    let cursor = queue["next_cursor"].as_str().unwrap();
+    let (_, body) = send(&app, Method::GET, &format!("/admin/reviews/reports?cursor={}", cursor), Some(&admin), None).await;
+    assert_eq!(parse(&body)["items"].as_array().unwrap().len(), 1);
+    assert!(parse(&body)["next_cursor"].is_null());
+
+    let moderated = json!({ "user_id": alice_id, "place_id": "maxwell-tian-tian" });
+    let (status, _) = send(&app, Method::POST, "/admin/reviews/hide", Some(&admin), Some(moderated)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = send(&app, Method::GET, "/admin/reviews/reports", Some(&admin), None).await;
+    assert_eq!(parse(&body)["items"], json!([]));
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"][0]["status"], "hidden");
+
+    let unknown = json!({ "user_id": alice_id, "place_id": "maxwell-zhen-zhen" });
+    let (status, _) = send(&app, Method::POST, "/admin/reviews/approve", Some(&admin), Some(unknown)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn restaurant_reviews_sort_by_rating_and_helpful_votes() {
+    let (app, _) = test_app().await;
+    let mut tokens = HashMap::new();
+    for (username, rating) in [("alice", 4.5), ("bob", 1.0), ("carol", 3.0), ("dave", 4.0)] {
+        let token = signup(&app, username).await;
+        let review = json!({ "place_id": "maxwell-tian-tian", "rating": rating, "description": username });
+        send(&app, Method::POST, "/review", Some(&token), Some(review)).await;
+        tokens.insert(username, token);
+    }
+    let carol_id = user_id_of(&app, &tokens["carol"]).await;
+    let bob_id = user_id_of(&app, &tokens["bob"]).await;
+
+    let vote = |user_id: &str, helpful: bool| json!({ "user_id": user_id, "place_id": "maxwell-tian-tian", "helpful": helpful });
+    for voter in ["alice", "bob", "dave"] {
+        let (status, _) = send(&app, Method::PUT, "/review/vote", Some(&tokens[voter]), Some(vote(&carol_id, true))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    send(&app, Method::PUT, "/review/vote", Some(&tokens["alice"]), Some(vote(&bob_id, true))).await;
+    // voting again changes the vote instead of adding one
+    send(&app, Method::PUT, "/review/vote", Some(&tokens["alice"]), Some(vote(&bob_id, false))).await;
+    let (status, _) = send(&app, Method::PUT, "/review/vote", Some(&tokens["carol"]), Some(vote(&carol_id, true))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let unknown = json!({ "user_id": carol_id, "place_id": "maxwell-zhen-zhen", "helpful": true });
+    let (status, _) = send(&app, Method::PUT, "/review/vote", Some(&tokens["alice"]), Some(unknown)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let descriptions = |body: &str| -> Vec<String> {
+        parse(body)["items"].as_array().unwrap().iter().map(|review| review["description"].as_str().unwrap().to_string()).collect()
+    };
+    let reviews_uri = "/review/restaurant?place_id=maxwell-tian-tian";
+    let (_, body) = send(&app, Method::GET, &format!("{}&sort=highest", reviews_uri), None, None).await;
+    assert_eq!(descriptions(&body), ["alice", "dave", "carol", "bob"]);
+    let (_, body) = send(&app, Method::GET, &format!("{}&sort=lowest&limit=3", reviews_uri), None, None).await;
+    assert_eq!(descriptions(&body), ["bob", "carol", "dave"]);
+    let cursor = parse(&body)["next_cursor"].as_str().unwrap().to_string();
+    let (_, body) = send(&app, Method::GET, &format!("{}&sort=lowest&cursor={}", reviews_uri, cursor), None, None).await;
+    assert_eq!(descriptions(&body), ["alice"]);
+    let (status, _) = send(&app, Method::GET, &format!("{}&sort=highest&cursor={}", reviews_uri, cursor), None, None).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (_, body) = send(&app, Method::GET, &format!("{}&sort=most_helpful", reviews_uri), None, None).await;
+    let reviews = parse(&body);
+    assert_eq!(reviews["items"][0]["description"], "carol");
+    assert_eq!(reviews["items"][0]["helpful_count"], 3);
+    let bob_review = reviews["items"].as_array().unwrap().iter().find(|review| review["description"] == "bob").unwrap();
+    assert_eq!((bob_review["helpful_count"].clone(), bob_review["unhelpful_count"].clone()), (json!(0), json!(1)));
+
+    let (_, body) = send(&app, Method::GET, &format!("{}&stars=4", reviews_uri), None, None).await;
+    assert_eq!(descriptions(&body), ["dave"]);
+    // the summary still covers every review
+    assert_eq!(parse(&body)["summary"]["count"], 4);
+    let (_, body) = send(&app, Method::GET, &format!("{}&stars=5&sort=highest", reviews_uri), None, None).await;
+    assert_eq!(descriptions(&body), ["alice"]);
+    for invalid in ["stars=0", "stars=six", "sort=oldest"] {
+        let (status, _) = send(&app, Method::GET, &format!("{}&{}", reviews_uri, invalid), None, None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", invalid);
+    }
+
+    let remove_uri = format!("/review/vote?user_id={}&place_id=maxwell-tian-tian", carol_id);
+    let (status, _) = send(&app, Method::DELETE, &remove_uri, Some(&tokens["dave"]), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, Method::DELETE, &remove_uri, Some(&tokens["dave"]), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = send(&app, Method::GET, &format!("{}&sort=most_helpful&limit=1", reviews_uri), None, None).await;
+    assert_eq!(parse(&body)["items"][0]["helpful_count"], 2);
+
+    // edits keep the votes
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 3.5, "description": "carol again" });
+    send(&app, Method::PUT, "/review", Some(&tokens["carol"]), Some(review)).await;
+    let (_, body) = send(&app, Method::GET, &format!("{}&sort=most_helpful&limit=1", reviews_uri), None, None).await;
+    assert_eq!(parse(&body)["items"][0]["description"], "carol again");
+    assert_eq!(parse(&body)["items"][0]["helpful_count"], 2);
+}
+
+async fn upload_photos(app: &Router, token: &str, place_id: &str, photos: &[Vec<u8>]) -> (StatusCode, String) {
+    let boundary = "review-photos-boundary";
+    let mut body = Vec::new();
+    for (index, photo) in photos.iter().enumerate() {
+        body.extend_from_slice(format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"photo{}.png\"\r\nContent-Type: image/png\r\n\r\n",
+            boundary, index
+        ).as_bytes());
+        body.extend_from_slice(photo);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/review/photos?place_id={}", place_id))
+        .header("authorization", format!("Bearer {}", token))
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+async fn fetch_bytes(app: &Router, uri: &str) -> (StatusCode, Option<String>, Vec<u8>) {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let content_type = response.headers().get("content-type").map(|value| value.to_str().unwrap().to_string());
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, content_type, bytes.to_vec())
+}
+
+#[tokio::test]
+async fn review_photos_are_stored_with_thumbnails() {
+    let (app, _) = test_app().await;
+    let token = signup(&app, "alice").await;
+    let (status, _) = upload_photos(&app, &token, "maxwell-tian-tian", &[png(40, 20)]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 4.5, "description": "Look at that rice" });
+    send(&app, Method::POST, "/review", Some(&token), Some(review)).await;
+    let (status, body) = upload_photos(&app, &token, "maxwell-tian-tian", &[png(40, 20), png(8, 8)]).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let photos = parse(&body);
+    assert_eq!((photos[0]["width"].clone(), photos[0]["height"].clone()), (json!(40), json!(20)));
+    assert_eq!(photos[0]["content_type"], "image/png");
+    assert!(photos[0].get("storage_key").is_none());
+
+    let url = photos[0]["url"].as_str().unwrap();
+    let (status, content_type, bytes) = fetch_bytes(&app, url).await;
+    assert_eq!((status, content_type.as_deref()), (StatusCode::OK, Some("image/png")));
+    assert_eq!(bytes, png(40, 20));
+    let (status, content_type, bytes) = fetch_bytes(&app, photos[0]["thumbnail_url"].as_str().unwrap()).await;
+    assert_eq!((status, content_type.as_deref()), (StatusCode::OK, Some("image/jpeg")));
+    let thumbnail = image::load_from_memory(&bytes).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (32, 16));
+    let (status, _, _) = fetch_bytes(&app, "/review/photos/reviews/..%2F..%2Fetc%2Fpasswd").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    assert_eq!(parse(&body)["items"][0]["photos"], photos);
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&token), None).await;
+    assert_eq!(parse(&body)["items"][0]["photos"].as_array().unwrap().len(), 2);
+
+    // a review holds at most three photos, across uploads too
+    let (status, _) = upload_photos(&app, &token, "maxwell-tian-tian", &[png(8, 8), png(8, 8)]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = upload_photos(&app, &token, "maxwell-tian-tian", &vec![png(8, 8); 4]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = upload_photos(&app, &token, "maxwell-tian-tian", &[b"GIF89a but not really".to_vec()]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = upload_photos(&app, &token, "maxwell-tian-tian", &[b"<svg></svg>".to_vec()]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = upload_photos(&app, &token, "maxwell-tian-tian", &[vec![0; 65 * 1024]]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    // edits keep the photos
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 4.0, "description": "Still good" });
+    send(&app, Method::PUT, "/review", Some(&token), Some(review)).await;
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&token), None).await;
+    assert_eq!(parse(&body)["items"][0]["photos"].as_array().unwrap().len(), 2);
+
+    let remove_uri = format!("/review/photos?place_id=maxwell-tian-tian&photo_id={}", photos[0]["photo_id"].as_str().unwrap());
+    let other = signup(&app, "bob").await;
+    let (status, _) = send(&app, Method::DELETE, &remove_uri, Some(&other), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, Method::DELETE, &remove_uri, Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = fetch_bytes(&app, url).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = send(&app, Method::GET, "/review/user", Some(&token), None).await;
+    assert_eq!(parse(&body)["items"][0]["photos"], json!([photos[1]]));
+    let (status, _) = upload_photos(&app, &token, "maxwell-tian-tian", &[png(8, 8), png(8, 8)]).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn review_photos_are_only_served_while_their_review_is_published() {
+    let config = test_config();
+    let storage_dir = std::path::PathBuf::from(&config.photo_storage_dir);
+    let (app, _) = test_app_with_config(config).await;
+    let token = signup(&app, "alice").await;
+    let admin = admin_token();
+    let review = json!({ "place_id": "maxwell-tian-tian", "rating": 4.5, "description": "Look at that rice" });
+    send(&app, Method::POST, "/review", Some(&token), Some(review)).await;
+    let (_, body) = upload_photos(&app, &token, "maxwell-tian-tian", &[png(40, 20)]).await;
+    let url = parse(&body)[0]["url"].as_str().unwrap().to_string();
+    let thumbnail_url = parse(&body)[0]["thumbnail_url"].as_str().unwrap().to_string();
+
+    let request = Request::builder().uri(&url).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["cache-control"], "public, max-age=300");
+
+    let moderated = json!({ "user_id": user_id_of(&app, &token).await, "place_id": "maxwell-tian-tian" });
+    send(&app, Method::POST, "/admin/reviews/hide", Some(&admin), Some(moderated.clone())).await;
+    assert_eq!(fetch_bytes(&app, &url).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(fetch_bytes(&app, &thumbnail_url).await.0, StatusCode::NOT_FOUND);
+    send(&app, Method::POST, "/admin/reviews/approve", Some(&admin), Some(moderated)).await;
+    assert_eq!(fetch_bytes(&app, &url).await.0, StatusCode::OK);
+
+    send(&app, Method::DELETE, "/review?place_id=maxwell-tian-tian", Some(&token), None).await;
+    assert_eq!(fetch_bytes(&app, &url).await.0, StatusCode::NOT_FOUND);
+    send(&app, Method::POST, "/review/restore?place_id=maxwell-tian-tian", Some(&token), None).await;
+    assert_eq!(fetch_bytes(&app, &thumbnail_url).await.0, StatusCode::OK);
+
+    // files in the storage that belong to no review are not served either
+    std::fs::write(storage_dir.join("reviews").join("stray.png"), png(8, 8)).unwrap();
+    assert_eq!(fetch_bytes(&app, "/review/photos/reviews/stray.png").await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn user_ratings_summarise_reviews() {
+    let (app, _) = test_app().await;
+
+    let (_, body) = send(&app, Method::GET, "/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    let restaurant = parse(&body);
+    assert_eq!(restaurant["rating"], 4.2);
+    assert_eq!(restaurant["user_rating"]["count"], 0);
+    assert_eq!(restaurant["user_rating"]["average"], Value::Null);
+
+    for (username, rating) in [("alice", 5.0), ("bob", 4.0), ("carol", 2.5)] {
+        let token = signup(&app, username).await;
+        let review = json!({ "place_id": "maxwell-tian-tian", "rating": rating, "description": "" });
+        let (status, _) = send(&app, Method::POST, "/review", Some(&token), Some(review)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (_, body) = send(&app, Method::GET, "/restaurant?place_id=maxwell-tian-tian", None, None).await;
+    let user_rating = &parse(&body)["user_rating"];
+    assert_eq!(user_rating["count"], 3);
+    assert_eq!(user_rating["average"].as_f64().unwrap(), 11.5 / 3.0);
+    assert_eq!(user_rating["histogram"], json!([0, 0, 1, 1, 1]));
+    assert_eq!(user_rating["recent_count"], 3);
+    assert_eq!(user_rating["trend"], 0.0);
+
+    let (_, body) = send(&app, Method::GET, "/review/restaurant?place_id=maxwell-tian-tian&limit=1", None, None).await;
+    let reviews = parse(&body);
+    assert_eq!(reviews["items"].as_array().unwrap().len(), 1);
+    assert_eq!(reviews["summary"]["count"], 3);
+    assert_eq!(reviews["summary"]["histogram"], json!([0, 0, 1, 1, 1]));
+}
+
+#[tokio::test]
+async fn reservation_lifecycle() {
+    let (app, repository) = test_app().await;
+    let alice = signup(&app, "alice").await;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let mut reservation_ids = Vec::new();
+    for (place_id, reservation_time) in [("maxwell-tian-tian", now + 3600), ("maxwell-tian-tian", now + 7200)] {
+        let (status, body) = send(
+            &app,
+            Method::POST,
+            "/reservation",
+            Some(&alice),
+            Some(json!({
+                "place_id": place_id,
+                "reservation_time": reservation_time,
+                "reservation_pax": 2,
+            })),
+        ).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let reservation = parse(&body);
+        assert_eq!(reservation["status"], "requested");
+        assert_eq!(reservation["created_at"], reservation["updated_at"]);
+        reservation_ids.push(reservation["reservation_id"].as_str().unwrap().to_string());
+    }
+    // the API only takes reservations still to come
+    let (_, body) = send(&app, Method::GET, "/reservation", Some(&alice), None).await;
+    let alice_id = parse(&body)["items"][0]["user_id"].as_str().unwrap().to_string();
+    let past = repository.add_reservations(&alice_id, "maxwell-zhen-zhen", now - 3600, 2, 0).await.unwrap();
+    reservation_ids.insert(1, past.reservation_id);
+
+    // two bookings at the same place, on different days
+    let (_, body) = send(&app, Method::GET, "/reservation", Some(&alice), None).await;
+    let upcoming = &parse(&body)["items"];
+    assert_eq!(upcoming.as_array().unwrap().len(), 2);
+    assert_eq!((upcoming[0]["place_id"].clone(), upcoming[1]["place_id"].clone()), (json!("maxwell-tian-tian"), json!("maxwell-tian-tian")));
+
+    let (_, body) = send(&app, Method::GET, "/reservation/list", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"].as_array().unwrap().len(), 3);
+
+    let admin = admin_token();
+    let confirm = json!({ "reservation_id": reservation_ids[2], "status": "confirmed" });
+    let (status, _) = send(&app, Method::POST, "/admin/reservations/status", Some(&alice), Some(confirm.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, body) = send(&app, Method::GET, "/admin/reservations/requested", Some(&admin), None).await;
+    assert_eq!(parse(&body)["items"].as_array().unwrap().len(), 3);
+    let (status, body) = send(&app, Method::POST, "/admin/reservations/status", Some(&admin), Some(confirm)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body)["status"], "confirmed");
+    let complete = json!({ "reservation_id": reservation_ids[0], "status": "completed" });
+    let (status, _) = send(&app, Method::POST, "/admin/reservations/status", Some(&admin), Some(complete)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // a changed reservation has to be confirmed again
+    let change = json!({ "reservation_id": reservation_ids[2], "reservation_time": now + 10800, "reservation_pax": 4 });
+    let bob = signup(&app, "bob").await;
+    let (status, _) = send(&app, Method::PUT, "/reservation", Some(&bob), Some(change.clone())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = send(&app, Method::PUT, "/reservation", Some(&alice), Some(change)).await;
+    assert_eq!(status, StatusCode::OK);
+    let changed = parse(&body);
+    assert_eq!((changed["reservation_pax"].clone(), changed["status"].clone()), (json!(4), json!("requested")));
+
+    let cancel_uri = format!("/reservation?reservation_id={}", reservation_ids[0]);
+    let (status, _) = send(&app, Method::DELETE, &cancel_uri, Some(&bob), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = send(&app, Method::DELETE, &cancel_uri, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body)["status"], "cancelled");
+    let (status, _) = send(&app, Method::DELETE, &cancel_uri, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let change = json!({ "reservation_id": reservation_ids[0], "reservation_time": now + 3600, "reservation_pax": 2 });
+    let (status, _) = send(&app, Method::PUT, "/reservation", Some(&alice), Some(change)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // only the other booking at the place is left to come, the cancelled one is kept in the full list
+    let (_, body) = send(&app, Method::GET, "/reservation", Some(&alice), None).await;
+    let upcoming = &parse(&body)["items"];
+    assert_eq!(upcoming.as_array().unwrap().len(), 1);
+    assert_eq!(upcoming[0]["reservation_id"], reservation_ids[2].as_str());
+    let (_, body) = send(&app, Method::GET, "/reservation/list", Some(&alice), None).await;
+    assert_eq!(parse(&body)["items"].as_array().unwrap().len(), 3);
+}
+
+/// A capacity open 11:00 to 14:00 every day in hourly slots of four seats, and 11:00 a week from now.
+fn week_from_now_at_eleven() -> (Value, String, i64) {
+    let opening_hours: Vec<Value> = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"]
+        .iter()
+        .map(|day| json!({ "day": day, "opens": "11:00", "closes": "14:00" }))
+        .collect();
+    let capacity = json!({
+        "place_id": "maxwell-tian-tian",
+        "seats_per_slot": 4,
+        "slot_minutes": 60,
+        "opening_hours": opening_hours,
+    });
+    let offset = time::UtcOffset::from_hms(8, 0, 0).unwrap();
+    let date = (OffsetDateTime::now_utc().to_offset(offset) + time::Duration::days(7)).date();
+    let eleven = date.with_hms(11, 0, 0).unwrap().assume_offset(offset).unix_timestamp();
+    (capacity, date.to_string(), eleven)
+}
+
+#[tokio::test]
+async fn reservations_fit_the_capacity_of_the_place() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+    let admin = admin_token();
+    let (capacity, date, eleven) = week_from_now_at_eleven();
+    let availability_uri = format!("/reservation/availability?place_id=maxwell-tian-tian&date={}&pax=3", date);
+    let (status, _) = send(&app, Method::GET, &availability_uri, None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&app, Method::PUT, "/admin/places/capacity", Some(&alice), Some(capacity.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let mut invalid = capacity.clone();
+    invalid["opening_hours"][0]["closes"] = json!("25:00");
+    let (status, _) = send(&app, Method::PUT, "/admin/places/capacity", Some(&admin), Some(invalid)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let mut unknown = capacity.clone();
+    unknown["place_id"] = json!("nowhere");
+    let (status, _) = send(&app, Method::PUT, "/admin/places/capacity", Some(&admin), Some(unknown)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, Method::PUT, "/admin/places/capacity", Some(&admin), Some(capacity)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let starts = |body: &str| -> Vec<i64> {
+        parse(body)["slots"].as_array().unwrap().iter().map(|slot| slot["start"].as_i64().unwrap()).collect()
+    };
+    let (_, body) = send(&app, Method::GET, &availability_uri, None, None).await;
+    assert_eq!(starts(&body), [eleven, eleven + 3600, eleven + 7200]);
+
+    let book = |time: i64, pax: u32| json!({ "place_id": "maxwell-tian-tian", "reservation_time": time, "reservation_pax": pax });
+    let (status, body) = send(&app, Method::POST, "/reservation", Some(&alice), Some(book(eleven, 2))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let first_id = parse(&body)["reservation_id"].as_str().unwrap().to_string();
+    let (status, _) = send(&app, Method::POST, "/reservation", Some(&admin), Some(book(eleven, 3))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(&app, Method::POST, "/reservation", Some(&admin), Some(book(eleven + 1800, 2))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(&app, Method::POST, "/reservation", Some(&admin), Some(book(eleven + 3 * 3600, 2))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    send(&app, Method::POST, "/reservation", Some(&admin), Some(book(eleven + 3600, 4))).await;
+
+    let (_, body) = send(&app, Method::GET, &availability_uri, None, None).await;
+    assert_eq!(starts(&body), [eleven + 7200]);
+    let (_, body) = send(&app, Method::GET, &availability_uri.replace("pax=3", "pax=2"), None, None).await;
+    assert_eq!(parse(&body)["slots"][0], json!({ "start": eleven, "end": eleven + 3600, "seats_left": 2 }));
+
+    // a reservation does not take seats from itself when it changes, and cancelling gives them back
+    let change = json!({ "reservation_id": first_id, "reservation_time": eleven, "reservation_pax": 4 });
+    let (status, _) = send(&app, Method::PUT, "/reservation", Some(&alice), Some(change)).await;
+    assert_eq!(status, StatusCode::OK);
+    let change = json!({ "reservation_id": first_id, "reservation_time": eleven + 3600, "reservation_pax": 1 });
+    let (status, _) = send(&app, Method::PUT, "/reservation", Some(&alice), Some(change)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    send(&app, Method::DELETE, &format!("/reservation?reservation_id={}", first_id), Some(&alice), None).await;
+    let (_, body) = send(&app, Method::GET, &availability_uri, None, None).await;
+    assert_eq!(starts(&body), [eleven, eleven + 7200]);
+
+    for invalid in [format!("date={}&pax=0", date), format!("date={}&pax=many", date), format!("date={}", date), "date=next-week&pax=3".to_string()] {
+        let uri = format!("/reservation/availability?place_id=maxwell-tian-tian&{}", invalid);
+        let (status, _) = send(&app, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", invalid);
+    }
+    // places without a capacity take reservations as before
+    let zhen_zhen = json!({ "place_id": "maxwell-zhen-zhen", "reservation_time": eleven + 1234, "reservation_pax": 20 });
+    let (status, _) = send(&app, Method::POST, "/reservation", Some(&alice), Some(zhen_zhen)).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn reservations_break_rules_with_their_own_codes() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let book = |place_id: &str, time: i64, pax: i64| json!({ "place_id": place_id, "reservation_time": time, "reservation_pax": pax });
+
+    for (reservation, code) in [
+        (book("maxwell-tian-tian", now - 60, 2), "reservation_in_past"),
+        (book("maxwell-tian-tian", -1, 2), "reservation_in_past"),
+        (book("maxwell-tian-tian", now + 31 * 24 * 60 * 60, 2), "reservation_too_far_ahead"),
+        (book("maxwell-tian-tian", now + 3600, 0), "reservation_pax_out_of_range"),
+        (book("maxwell-tian-tian", now + 3600, -3), "reservation_pax_out_of_range"),
+        (book("maxwell-tian-tian", now + 3600, 21), "reservation_pax_out_of_range"),
+        (book("nowhere", now + 3600, 2), "reservation_unknown_place"),
+    ] {
+        let (status, body) = send(&app, Method::POST, "/reservation", Some(&alice), Some(reservation)).await;
+        assert_eq!((status, parse(&body)["code"].clone()), (StatusCode::UNPROCESSABLE_ENTITY, json!(code)));
+    }
+
+    let (status, body) = send(&app, Method::POST, "/reservation", Some(&alice), Some(book("maxwell-tian-tian", now + 3600, 2))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let reservation_id = parse(&body)["reservation_id"].as_str().unwrap().to_string();
+    let (status, body) = send(&app, Method::POST, "/reservation", Some(&alice), Some(book("maxwell-zhen-zhen", now + 5400, 2))).await;
+    assert_eq!((status, parse(&body)["code"].clone()), (StatusCode::UNPROCESSABLE_ENTITY, json!("reservation_overlaps")));
+    // other users are not in the way
+    let bob = signup(&app, "bob").await;
+    let (status, _) = send(&app, Method::POST, "/reservation", Some(&bob), Some(book("maxwell-zhen-zhen", now + 5400, 2))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // changes follow the same rules
+    let change = |time: i64, pax: i64| json!({ "reservation_id": reservation_id, "reservation_time": time, "reservation_pax": pax });
+    let (_, body) = send(&app, Method::PUT, "/reservation", Some(&alice), Some(change(now - 60, 2))).await;
+    assert_eq!(parse(&body)["code"], "reservation_in_past");
+    let (_, body) = send(&app, Method::PUT, "/reservation", Some(&alice), Some(change(now + 3600, 50))).await;
+    assert_eq!(parse(&body)["code"], "reservation_pax_out_of_range");
+    let (status, _) = send(&app, Method::PUT, "/reservation", Some(&alice), Some(change(now + 3700, 3))).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn reservations_export_to_calendars() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+    let bob = signup(&app, "bob").await;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let book = |place_id: &str, time: i64| json!({ "place_id": place_id, "reservation_time": time, "reservation_pax": 2 });
+    let (_, body) = send(&app, Method::POST, "/reservation", Some(&alice), Some(book("maxwell-tian-tian", now + 3600))).await;
+    let reservation_id = parse(&body)["reservation_id"].as_str().unwrap().to_string();
+    let (_, body) = send(&app, Method::POST, "/reservation", Some(&alice), Some(book("maxwell-zhen-zhen", now + 3 * 3600))).await;
+    let cancelled_id = parse(&body)["reservation_id"].as_str().unwrap().to_string();
+    send(&app, Method::DELETE, &format!("/reservation?reservation_id={}", cancelled_id), Some(&alice), None).await;
+
+    let (status, _) = send(&app, Method::GET, "/reservation/calendar", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, calendar) = send(&app, Method::GET, "/reservation/calendar", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<&str> = calendar.split("\r\n").collect();
+    for expected in [
+        format!("UID:{}@eatwherela", reservation_id),
+        "SUMMARY:Tian Tian Hainanese Chicken Rice (table for 2)".to_string(),
+        "LOCATION:1 Kadayanallur St\\, Singapore".to_string(),
+        "GEO:1.2806;103.8443".to_string(),
+        "STATUS:TENTATIVE".to_string(),
+    ] {
+        assert!(lines.contains(&expected.as_str()), "{} missing from\n{}", expected, calendar);
+    }
+    assert!(!calendar.contains(&cancelled_id));
+
+    // the feed serves the same calendar without logging in, until its token is replaced or removed
+    let (status, body) = send(&app, Method::POST, "/reservation/calendar/feed", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let first_url = parse(&body)["url"].as_str().unwrap().to_string();
+    assert!(first_url.starts_with("/reservation/calendar/feed/"), "{}", first_url);
+    let (status, content_type, feed) = fetch_bytes(&app, &first_url).await;
+    assert_eq!((status, content_type.as_deref()), (StatusCode::OK, Some("text/calendar; charset=utf-8")));
+    assert_eq!(String::from_utf8(feed).unwrap(), calendar);
+
+    let (_, body) = send(&app, Method::POST, "/reservation/calendar/feed", Some(&alice), None).await;
+    let second_url = parse(&body)["url"].as_str().unwrap().to_string();
+    assert_eq!(fetch_bytes(&app, &first_url).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(fetch_bytes(&app, &second_url).await.0, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::POST, "/reservation/calendar/feed", Some(&bob), None).await;
+    let (_, _, feed) = fetch_bytes(&app, parse(&body)["url"].as_str().unwrap()).await;
+    assert!(!String::from_utf8(feed).unwrap().contains("BEGIN:VEVENT"));
+
+    let (status, _) = send(&app, Method::DELETE, "/reservation/calendar/feed", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetch_bytes(&app, &second_url).await.0, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, Method::DELETE, "/reservation/calendar/feed", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn vote_history_round_trip() {
+    let (app, repository) = test_app().await;
+    let alice = signup(&app, "alice").await;
+    let bob = signup(&app, "bob").await;
+    let bob_id = repository.retrieve_user_by_username("bob").await.unwrap().unwrap().user_id;
+
+    // other users named in the body are not written to, the session is only recorded for the caller
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/vote",
+        Some(&alice),
+        Some(json!({
+            "user_ids": [bob_id, "carol"],
+            "voted_places": [{ "place_id": "maxwell-tian-tian", "votes": 2 }],
+            "vote_timestamp": 1_700_000_000,
+        })),
+    ).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::GET, "/vote", Some(&alice), None).await;
+    let histories = &parse(&body)["items"];
+    assert_eq!(histories[0]["user_ids"].as_array().unwrap().len(), 1);
+    assert_ne!(histories[0]["user_ids"][0], json!(bob_id));
+    assert_eq!(histories[0]["voted_places"][0]["place_id"], "maxwell-tian-tian");
+
+    let (_, body) = send(&app, Method::GET, "/vote", Some(&bob), None).await;
+    assert!(parse(&body)["items"].as_array().unwrap().is_empty());
+    assert!(repository.retrieve_user_vote_history("carol", &PageRequest::default()).await.unwrap().items.is_empty());
+}
+
+#[tokio::test]
+async fn tally_endpoint_returns_rounds_and_records_the_outcome() {
+    let (app, repository) = test_app().await;
+    let alice = signup(&app, "alice").await;
+
+    // tian-tian leads on first preferences until ah-seng's supporter moves over to zhen-zhen
+    let tally_request = json!({
+        "method": "ranked_choice",
+        "candidates": ["tian-tian", "zhen-zhen", "ah-seng"],
+        "ballots": [
+            { "user_id": "bob", "choices": ["tian-tian"] },
+            { "user_id": "carol", "choices": ["tian-tian"] },
+            { "user_id": "dave", "choices": ["zhen-zhen"] },
+            { "user_id": "erin", "choices": ["zhen-zhen", "tian-tian"] },
+            { "user_id": "frank", "choices": ["ah-seng", "zhen-zhen"] },
+        ],
+    });
+    let (status, body) = send(&app, Method::POST, "/vote/tally", Some(&alice), Some(tally_request.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let result = parse(&body);
+    assert_eq!(result["winner"], "zhen-zhen");
+    assert_eq!(result["rounds"].as_array().unwrap().len(), 2);
+    assert_eq!(result["rounds"][0]["eliminated"], "ah-seng");
+    assert_eq!(result["rounds"][1]["counts"][1]["votes"], 3);
+
+    // nothing is stored unless asked for
+    let (_, body) = send(&app, Method::GET, "/vote", Some(&alice), None).await;
+    assert!(parse(&body)["items"].as_array().unwrap().is_empty());
+
+    let mut recorded_request = tally_request;
+    recorded_request["record"] = json!(true);
+    let (status, _) = send(&app, Method::POST, "/vote/tally", Some(&alice), Some(recorded_request)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::GET, "/vote", Some(&alice), None).await;
+    let histories = &parse(&body)["items"];
+    assert_eq!(histories[0]["outcome"]["winner"], "zhen-zhen");
+    assert_eq!(histories[0]["voted_places"][0]["place_id"], "zhen-zhen");
+    assert_eq!(histories[0]["outcome"]["rounds"].as_array().unwrap().len(), 2);
+    assert_eq!(histories[0]["user_ids"].as_array().unwrap().len(), 1);
+    // the users the ballots name are not written to
+    assert!(repository.retrieve_user_vote_history("frank", &PageRequest::default()).await.unwrap().items.is_empty());
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/vote/tally",
+        Some(&alice),
+        Some(json!({ "candidates": ["tian-tian"], "ballots": [{ "choices": ["nowhere"] }] })),
+    ).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn voting_room_rest_endpoints() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/vote/room",
+        Some(&alice),
+        Some(json!({ "place_ids": ["maxwell-tian-tian", "maxwell-tian-tian"] })),
+    ).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(parse(&body)["code"], "validation_failed");
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/vote/room",
+        Some(&alice),
+        Some(json!({ "place_ids": ["maxwell-tian-tian", "does-not-exist"] })),
+    ).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/vote/room",
+        Some(&alice),
+        Some(json!({ "place_ids": ["maxwell-tian-tian", "maxwell-zhen-zhen"] })),
+    ).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let room = parse(&body);
+    let join_code = room["join_code"].as_str().unwrap().to_string();
+    assert_eq!(join_code.len(), 6);
+    assert_eq!(room["candidates"].as_array().unwrap().len(), 2);
+
+    let uri = format!("/vote/room/{}", join_code.to_lowercase());
+    let (status, body) = send(&app, Method::GET, &uri, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(parse(&body)["join_code"], join_code.as_str());
+
+    let (status, _) = send(&app, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, Method::GET, "/vote/room/ZZZZZZ", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+type RoomSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn connect_to_room(address: SocketAddr, join_code: &str, token: &str) -> RoomSocket {
+    let url = format!("ws://{}/vote/room/{}/ws?token={}", address, join_code, token);
+    let (socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    socket
+}
+
+/// Reads events until one of the given type arrives, skipping membership noise along the way.
+async fn next_event(socket: &mut RoomSocket, event_type: &str) -> Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("timed out waiting for a room event")
+            .unwrap()
+            .unwrap();
+        if let WsMessage::Text(text) = message {
+            let event = parse(&text);
+            if event["type"] == event_type {
+                return event;
+            }
+        }
+    }
+}
+
+async fn send_event(socket: &mut RoomSocket, event: Value) {
+    socket.send(WsMessage::Text(event.to_string())).await.unwrap();
+}
+
+#[tokio::test]
+async fn voting_room_runs_a_round_over_websockets() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+    let bob = signup(&app, "bob").await;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener).unwrap().serve(app.clone().into_make_service());
+    tokio::spawn(server);
+
+    let (_, body) = send(
+        &app,
+        Method::POST,
+        "/vote/room",
+        Some(&alice),
+        Some(json!({ "place_ids": ["maxwell-tian-tian", "maxwell-zhen-zhen"] })),
+    ).await;
+    let join_code = parse(&body)["join_code"].as_str().unwrap().to_string();
+
+    let mut alice_socket = connect_to_room(address, &join_code, &alice).await;
+    let state = next_event(&mut alice_socket, "state").await;
+    assert_eq!(state["candidates"][1]["place_id"], "maxwell-zhen-zhen");
+
+    let mut bob_socket = connect_to_room(address, &join_code, &bob).await;
+    let state = next_event(&mut bob_socket, "state").await;
+    assert_eq!(state["members"].as_array().unwrap().len(), 2);
+    assert_eq!(next_event(&mut alice_socket, "member_joined").await["username"], "alice");
+    assert_eq!(next_event(&mut alice_socket, "member_joined").await["username"], "bob");
+
+    // bob is not the host and cannot end the round
+    send_event(&mut bob_socket, json!({ "type": "close" })).await;
+    assert_eq!(next_event(&mut bob_socket, "error").await["code"], "forbidden");
+    send_event(&mut bob_socket, json!({ "type": "vote", "place_id": "somewhere-else" })).await;
+    assert_eq!(next_event(&mut bob_socket, "error").await["code"], "validation_failed");
+
+    send_event(&mut bob_socket, json!({ "type": "vote", "place_id": "maxwell-zhen-zhen" })).await;
+    let vote = next_event(&mut alice_socket, "vote_cast").await;
+    assert_eq!(vote["counts"][1]["votes"], 1);
+
+    send_event(&mut alice_socket, json!({ "type": "vote", "place_id": "maxwell-zhen-zhen" })).await;
+    next_event(&mut bob_socket, "vote_cast").await;
+    send_event(&mut alice_socket, json!({ "type": "close" })).await;
+
+    for socket in [&mut alice_socket, &mut bob_socket] {
+        let closed = next_event(socket, "closed").await;
+        assert_eq!(closed["winner"]["place_id"], "maxwell-zhen-zhen");
+        assert_eq!(closed["counts"][0]["votes"], 2);
+        assert_eq!(closed["outcome"]["method"], "plurality");
+    }
+
+    let (_, body) = send(&app, Method::GET, "/vote", Some(&bob), None).await;
+    let histories = &parse(&body)["items"];
+    assert_eq!(histories.as_array().unwrap().len(), 1);
+    assert_eq!(histories[0]["user_ids"].as_array().unwrap().len(), 2);
+    assert_eq!(histories[0]["voted_places"][0]["place_id"], "maxwell-zhen-zhen");
+    assert_eq!(histories[0]["outcome"]["winner"], "maxwell-zhen-zhen");
+
+    // a closed room is gone
+    let (status, _) = send(&app, Method::GET, &format!("/vote/room/{}", join_code), Some(&alice), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn errors_render_as_json_with_matching_status() {
+    let (app, _) = test_app().await;
+    let alice = signup(&app, "alice").await;
+
+    let (status, body) = send(&app, Method::GET, "/restaurant?place_id=does-not-exist", None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let error = parse(&body);
+    assert_eq!(error["code"], "not_found");
+    assert_eq!(error["message"], "No restaurant found with place_id: does-not-exist");
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/bookmark",
+        Some(&alice),
+        Some(json!({ "place_id": "does-not-exist" })),
+    ).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(parse(&body)["code"], "not_found");
+
+    let (status, body) = send(&app, Method::DELETE, "/review?place_id=maxwell-tian-tian", Some(&alice), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(parse(&body)["code"], "not_found");
+}
+
+/// `path` with its query string encoded the way a browser would.
+fn with_query(path: &str, params: &[(&str, &str)]) -> String {
+    let url = reqwest::Url::parse_with_params(&format!("http://localhost{}", path), params).unwrap();
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_POSTGRES_URL"]
+async fn hostile_input_round_trips_through_the_endpoints() {
+    let db = TestDatabase::setup().await;
+    let restaurants = seed_places(&db).await;
+    let config = test_config();
+    let place_provider = build_place_provider(&config).unwrap();
+    let photo_storage = build_photo_storage(&config).unwrap();
+    let app = application(AppState::new(config, Arc::new(db.repo.clone()), place_provider, photo_storage));
+    let in_two_days = OffsetDateTime::now_utc().unix_timestamp() + 2 * 24 * 60 * 60;
+
+    let mut users = Vec::new();
+    for (input, restaurant) in HOSTILE_INPUTS.iter().zip(&restaurants) {
+        let username: String = input.chars().take(32).collect();
+        let credentials = json!({ "username": username, "password": input.repeat(2) });
+        let (status, body) = send(&app, Method::POST, "/auth/signup", None, Some(credentials.clone())).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", input);
+        let (user_id, token) = (parse(&body)["user_id"].clone(), parse(&body)["token"].as_str().unwrap().to_string());
+        let (status, body) = send(&app, Method::POST, "/auth/login", None, Some(credentials.clone())).await;
+        assert_eq!((status, parse(&body)["user_id"].clone()), (StatusCode::OK, user_id.clone()), "{}", input);
+        let (status, _) = send(&app, Method::POST, "/auth/signup", None, Some(credentials)).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", input);
+
+        let place_id = restaurant.place_id.as_str();
+        let (status, body) = send(&app, Method::GET, &with_query("/restaurant", &[("place_id", place_id)]), None, None).await;
+        assert_eq!((status, parse(&body)["name"].as_str()), (StatusCode::OK, Some(*input)));
+        let (status, body) = send(&app, Method::GET, &with_query("/restaurant/search", &[("restaurant_name", input)]), None, None).await;
+        assert_eq!(status, StatusCode::OK, "{}", input);
+        assert!(parse(&body).is_array(), "{}", input);
+
+        let (status, _) = send(&app, Method::POST, "/bookmark", Some(&token), Some(json!({ "place_id": place_id }))).await;
+        assert_eq!(status, StatusCode::OK, "{}", input);
+        let (_, body) = send(&app, Method::GET, "/bookmark/restaurants", Some(&token), None).await;
+        assert_eq!(parse(&body)["items"][0]["place_id"], place_id);
+
+        let review = json!({ "place_id": place_id, "rating": 4.0, "description": input });
+        let (status, _) = send(&app, Method::POST, "/review", Some(&token), Some(review)).await;
+        assert_eq!(status, StatusCode::OK, "{}", input);
+        let (_, body) = send(&app, Method::GET, &with_query("/review/restaurant", &[("place_id", place_id)]), None, None).await;
+        assert_eq!(parse(&body)["items"][0]["description"], *input);
+        let history_uri = with_query("/review/history", &[("user_id", user_id.as_str().unwrap()), ("place_id", place_id)]);
+        let (_, body) = send(&app, Method::GET, &history_uri, None, None).await;
+        assert_eq!(parse(&body)["items"][0]["description"], *input);
+
+        let booking = json!({ "place_id": place_id, "reservation_time": in_two_days, "reservation_pax": 2 });
+        let (status, _) = send(&app, Method::POST, "/reservation", Some(&token), Some(booking)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", input);
+        let (_, body) = send(&app, Method::GET, "/reservation/list", Some(&token), None).await;
+        assert_eq!(parse(&body)["items"][0]["place_id"], place_id);
+
+        users.push((user_id, token, place_id));
+    }
+
+    // everyone reports the review before theirs, with the hostile input as the reason
+    for (index, input) in HOSTILE_INPUTS.iter().enumerate() {
+        let (user_id, _, place_id) = &users[(index + users.len() - 1) % users.len()];
+        let report = json!({ "user_id": user_id, "place_id": place_id, "reason": input });
+        let (status, _) = send(&app, Method::POST, "/review/report", Some(&users[index].1), Some(report)).await;
+        assert_eq!(status, StatusCode::OK, "{}", input);
+    }
+    let (_, body) = send(&app, Method::GET, "/admin/reviews/reports", Some(&admin_token()), None).await;
+    let mut reasons: Vec<String> = parse(&body)["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|report| report["reason"].as_str().unwrap().to_string())
+        .collect();
+    reasons.sort();
+    let mut expected: Vec<String> = HOSTILE_INPUTS.iter().map(|input| input.to_string()).collect();
+    expected.sort();
+    assert_eq!(reasons, expected);
+
+    for table in ["users", "places", "user_favourite_places", "user_reviews", "user_reservations", "review_reports"] {
+        assert_eq!(db.count(table).await, HOSTILE_INPUTS.len() as i64, "{}", table);
+    }
+    db.teardown().await;
+}
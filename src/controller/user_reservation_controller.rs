@@ -1,128 +1,303 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use axum::{Extension, Json, Router};
-use axum::extract::Query;
-use axum::http::StatusCode;
+use axum::extract::{Path, Query};
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
-use axum::routing::{get, post, delete};
+use axum::routing::{get, post, put, delete};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::warn;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use uuid::Uuid;
 use crate::controller::AppState;
-use crate::repositories::postgres_repo::PostgresConnectionRepo;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::auth::AuthUser;
+use crate::helpers::icalendar::{render_calendar, ReservationEvent, CALENDAR_CONTENT_TYPE, DEFAULT_EVENT_MINUTES};
+use crate::helpers::params::optional_param;
+use crate::models::capacity::{parse_date, Availability};
+use crate::models::page::{Cursor, PageParams, PageRequest, MAX_PAGE_LIMIT};
+use crate::models::reservation::{Reservation, ReservationRules, ReservationStatus};
+use crate::repositories::Repository;
 
 pub fn router(app_state: AppState) -> Router {
-    let postgres_repo = Arc::new(PostgresConnectionRepo::new(
-        app_state.postgres_connection
-    ));
-
     Router::new()
         .route("/", get(get_all_existing_reservations))
         .route("/list", get(get_all_reservations))
+        .route("/availability", get(get_availability))
         .route("/", post(add_reservation))
-        .route("/", delete(delete_reservation))
-        .route_layer(Extension(postgres_repo))
+        .route("/", put(modify_reservation))
+        .route("/", delete(cancel_reservation))
+        .route("/calendar", get(get_calendar))
+        .route("/calendar/feed", post(create_calendar_feed))
+        .route("/calendar/feed", delete(remove_calendar_feed))
+        .route("/calendar/feed/:token", get(get_calendar_feed))
+        .route_layer(Extension(app_state.repository.clone()))
+        .route_layer(Extension(app_state))
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ReserveRestaurant {
-    pub user_id: String,
     pub place_id: String,
     pub reservation_time: i64,
-    pub reservation_pax: u32,
+    pub reservation_pax: i64,
 }
 
+/// Books a table, the answer carries the id of the new reservation. Each rule the booking breaks has its own
+/// error code, see `ReservationRules`.
 pub async fn add_reservation(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
     Json(body): Json<ReserveRestaurant>,
-) -> impl IntoResponse {
-    let add_reservation_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    let rules = reservation_rules(&app_state);
+    let reservation_pax = rules.check(body.reservation_time, body.reservation_pax, OffsetDateTime::now_utc().unix_timestamp())?;
+    let reservation = repository
         .add_reservations(
-            &body.user_id,
+            &user.user_id,
             &body.place_id,
             body.reservation_time,
-            body.reservation_pax,
-        ).await;
+            reservation_pax,
+            rules.overlap_window_secs,
+        ).await?;
 
-    return match add_reservation_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully added reservation for restaurant").into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong adding reservation for restaurant due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to add reservation, please try again.").into_response()
-        }
-    };
+    Ok((StatusCode::CREATED, json!(reservation).to_string()))
+}
+
+fn reservation_rules(
+    app_state: &AppState,
+) -> ReservationRules {
+    let config = &app_state.config;
+    ReservationRules {
+        min_pax: config.reservation_min_pax,
+        max_pax: config.reservation_max_pax,
+        max_days_ahead: config.reservation_max_days_ahead,
+        overlap_window_secs: config.reservation_overlap_window_secs,
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct DeleteReservationQuery {
-    pub user_id: String,
-    pub place_id: String,
+pub struct ModifyReservation {
+    pub reservation_id: String,
+    pub reservation_time: i64,
+    pub reservation_pax: i64,
 }
 
-pub async fn delete_reservation(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
-    Query(query): Query<DeleteReservationQuery>,
-) -> impl IntoResponse {
-    let remove_reservation_res = postgres_repo
-        .remove_reservation(
-            &query.user_id,
-            &query.place_id,
-        ).await;
+/// Moves a reservation to another time or party size, it has to be confirmed again.
+pub async fn modify_reservation(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Json(body): Json<ModifyReservation>,
+) -> AppResult<impl IntoResponse> {
+    let rules = reservation_rules(&app_state);
+    let reservation_pax = rules.check(body.reservation_time, body.reservation_pax, OffsetDateTime::now_utc().unix_timestamp())?;
+    let reservation = repository
+        .modify_reservation(
+            &user.user_id,
+            &body.reservation_id,
+            body.reservation_time,
+            reservation_pax,
+            rules.overlap_window_secs,
+        ).await?;
 
-    return match remove_reservation_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully removed reservation").into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong removing reservation due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to remove reservation, please try again.").into_response()
-        }
-    };
+    Ok((StatusCode::OK, json!(reservation).to_string()))
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct GetReservationQuery {
-    pub user_id: String,
+pub struct CancelReservationQuery {
+    pub reservation_id: String,
+}
+
+/// Cancelled reservations are kept, they still show up in `/reservation/list`.
+pub async fn cancel_reservation(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(query): Query<CancelReservationQuery>,
+) -> AppResult<impl IntoResponse> {
+    let reservation = repository
+        .set_reservation_status(
+            Some(&user.user_id),
+            &query.reservation_id,
+            ReservationStatus::Cancelled,
+        ).await?;
+
+    Ok((StatusCode::OK, json!(reservation).to_string()))
 }
 
 pub async fn get_all_existing_reservations(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
-    Query(query): Query<GetReservationQuery>,
-) -> impl IntoResponse {
-    let user_reservations_res = postgres_repo
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let reservations = repository
         .retrieve_all_user_valid_reservations(
-            &query.user_id
-        ).await;
+            &user.user_id,
+            &page.to_request()?,
+        ).await?;
 
-    return match user_reservations_res {
-        Ok(reservations) => {
-            (StatusCode::OK, json!(reservations).to_string()).into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong retrieving user's reservations due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to retrieve reservations, please try again.").into_response()
-        }
-    };
+    Ok((StatusCode::OK, json!(reservations).to_string()))
 }
 
 pub async fn get_all_reservations(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
-    Query(query): Query<GetReservationQuery>,
-) -> impl IntoResponse {
-    let user_reservations_res = postgres_repo
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let reservations = repository
         .retrieve_all_user_reservations(
-            &query.user_id
-        ).await;
+            &user.user_id,
+            &page.to_request()?,
+        ).await?;
 
-    return match user_reservations_res {
-        Ok(reservations) => {
-            (StatusCode::OK, json!(reservations).to_string()).into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong retrieving user's reservations due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to retrieve reservations, please try again.").into_response()
-        }
+    Ok((StatusCode::OK, json!(reservations).to_string()))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AvailabilityQuery {
+    pub place_id: String,
+    /// Local to the place, `YYYY-MM-DD`
+    pub date: String,
+    #[serde(default)]
+    pub pax: Option<String>,
+}
+
+/// The slots of a place on a day that still seat the party. Only places with a capacity know their slots.
+pub async fn get_availability(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Query(query): Query<AvailabilityQuery>,
+) -> AppResult<impl IntoResponse> {
+    let pax = optional_param("pax", query.pax.as_deref(), |pax: &u32| *pax > 0, "a positive number")?
+        .ok_or_else(|| AppError::Validation("pax is required".to_string()))?;
+    let date = parse_date(&query.date)?;
+    let capacity = repository
+        .retrieve_place_capacity(&query.place_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No capacity set up for place_id: {}", query.place_id)))?;
+
+    let slots = capacity.slots_on(date);
+    let booked = match (slots.first(), slots.last()) {
+        (Some(first), Some(last)) => repository.retrieve_booked_seats(&query.place_id, first.start, last.end).await?,
+        _ => Default::default(),
     };
+    let availability = Availability {
+        slots: capacity.free_slots(date, pax, &booked, OffsetDateTime::now_utc().unix_timestamp()),
+        place_id: query.place_id,
+        date: query.date,
+        pax,
+    };
+
+    Ok((StatusCode::OK, json!(availability).to_string()))
+}
+
+/// The reservations still to come as an `.ics` file to import into a calendar.
+pub async fn get_calendar(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let calendar = reservation_calendar(repository.as_ref(), &user.user_id).await?;
+
+    Ok((StatusCode::OK, [(header::CONTENT_TYPE, CALENDAR_CONTENT_TYPE)], calendar))
+}
+
+/// Makes a secret link calendar apps can subscribe to, replacing the one the user had. The token is only
+/// shown now, just its hash is stored.
+pub async fn create_calendar_feed(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    repository
+        .set_calendar_feed_token(
+            &user.user_id,
+            &calendar_feed_token_hash(&token),
+        ).await?;
+
+    let url = format!(
+        "{}/reservation/calendar/feed/{}",
+        app_state.config.api_public_url.as_deref().unwrap_or_default().trim_end_matches('/'),
+        token
+    );
+    Ok((StatusCode::CREATED, json!({ "url": url }).to_string()))
+}
+
+pub async fn remove_calendar_feed(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    repository.remove_calendar_feed_token(&user.user_id).await?;
+
+    Ok((StatusCode::OK, "Successfully removed calendar feed"))
+}
+
+/// The calendar behind a feed link, calendar apps cannot log in so the token stands in for the user.
+pub async fn get_calendar_feed(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Path(token): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let user_id = repository
+        .retrieve_calendar_feed_user(&calendar_feed_token_hash(&token))
+        .await?
+        .ok_or_else(|| AppError::NotFound("No calendar feed found".to_string()))?;
+    let calendar = reservation_calendar(repository.as_ref(), &user_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, CALENDAR_CONTENT_TYPE),
+            (header::CACHE_CONTROL, "private, no-cache"),
+        ],
+        calendar,
+    ))
+}
+
+fn calendar_feed_token_hash(
+    token: &str,
+) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
 }
 
+/// Every requested and confirmed reservation of the user still to come, events lasting a slot of the place.
+async fn reservation_calendar(
+    repository: &dyn Repository,
+    user_id: &str,
+) -> AppResult<String> {
+    let mut reservations: Vec<Reservation> = Vec::new();
+    let mut page = PageRequest::first(MAX_PAGE_LIMIT);
+    loop {
+        let next = repository.retrieve_all_user_valid_reservations(user_id, &page).await?;
+        reservations.extend(next.items);
+        match next.next_cursor {
+            Some(cursor) => page.after = Some(Cursor::decode(&cursor)?),
+            None => break,
+        }
+    }
+
+    let mut places = HashMap::new();
+    for reservation in &reservations {
+        if places.contains_key(&reservation.place_id) {
+            continue;
+        }
+        let restaurant = repository.retrieve_restaurant(&reservation.place_id).await?;
+        let slot_minutes = repository
+            .retrieve_place_capacity(&reservation.place_id)
+            .await?
+            .map_or(DEFAULT_EVENT_MINUTES, |capacity| capacity.slot_minutes);
+        places.insert(reservation.place_id.clone(), (restaurant, slot_minutes));
+    }
+
+    let events: Vec<ReservationEvent> = reservations
+        .iter()
+        .map(|reservation| {
+            let (restaurant, slot_minutes) = &places[&reservation.place_id];
+            ReservationEvent {
+                reservation,
+                restaurant: restaurant.as_ref(),
+                duration_minutes: *slot_minutes,
+            }
+        })
+        .collect();
+    Ok(render_calendar(&events))
+}
@@ -1,162 +1,475 @@
 use std::sync::Arc;
 use axum::{Extension, Json, Router};
-use axum::extract::Query;
-use axum::http::StatusCode;
+use axum::extract::{DefaultBodyLimit, Multipart, Path, Query};
+use axum::extract::multipart::MultipartError;
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
 use axum::routing::{get, post, delete, put};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use time::OffsetDateTime;
 use tracing::warn;
+use uuid::Uuid;
 use crate::controller::AppState;
-use crate::repositories::postgres_repo::PostgresConnectionRepo;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::auth::AuthUser;
+use crate::helpers::images::{prepare_photo, PreparedPhoto};
+use crate::models::page::PageParams;
+use crate::helpers::params::optional_param;
+use crate::models::rating::{validate_rating, RestaurantReviews, ReviewSort};
+use crate::models::review_photo::ReviewPhoto;
+use crate::repositories::Repository;
+use crate::storage::{content_type_of, photo_url, PhotoStorage};
 
-pub fn router(app_state: AppState) -> Router {
-    let postgres_repo = Arc::new(PostgresConnectionRepo::new(
-        app_state.postgres_connection
-    ));
+/// Answer to a review the banned term filter held back, it is stored but not shown yet.
+const HELD_BACK_MESSAGE: &str = "Review received, it will be published once a moderator has looked at it";
+/// Room for the multipart boundaries and headers on top of the photos themselves.
+const MULTIPART_OVERHEAD_BYTES: usize = 64 * 1024;
 
+pub fn router(app_state: AppState) -> Router {
+    let upload_limit = app_state.config.review_max_photos * app_state.config.photo_max_bytes + MULTIPART_OVERHEAD_BYTES;
     Router::new()
         .route("/user", get(retrieve_user_reviews))
         .route("/restaurant", get(retrieve_restaurant_reviews))
         .route("/", post(add_review))
         .route("/", delete(remove_review))
-        .route("/", put(update_review))
-        .route_layer(Extension(postgres_repo))
+        .route("/", put(upsert_review))
+        .route("/restore", post(restore_review))
+        .route("/history", get(retrieve_review_history))
+        .route("/report", post(report_review))
+        .route("/vote", put(vote_on_review))
+        .route("/vote", delete(remove_review_vote))
+        .route("/photos", post(upload_review_photos).layer(DefaultBodyLimit::max(upload_limit)))
+        .route("/photos", delete(remove_review_photo))
+        .route("/photos/*key", get(retrieve_review_photo))
+        .route_layer(Extension(app_state.repository.clone()))
+        .route_layer(Extension(app_state))
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Review {
-    pub user_id: String,
     pub place_id: String,
     pub rating: f64,
     pub description: String,
 }
 
 pub async fn add_review(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
     Json(body): Json<Review>,
-) -> impl IntoResponse {
-    let add_user_review_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    validate_rating(body.rating)?;
+    let held_back = held_back_reason(&app_state, &body);
+    repository
         .add_user_review(
-            &body.user_id,
+            &user.user_id,
             &body.place_id,
             body.rating,
             &body.description,
-        ).await;
+            held_back.as_deref(),
+        ).await?;
 
-    return match add_user_review_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully added review for the restaurant").into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong adding review for restaurant due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to add review for restaurant, please try again").into_response()
-        }
-    };
+    if held_back.is_some() {
+        return Ok((StatusCode::ACCEPTED, HELD_BACK_MESSAGE));
+    }
+    Ok((StatusCode::OK, "Successfully added review for the restaurant"))
+}
+
+/// Why the banned term filter holds the review back, if it does.
+fn held_back_reason(
+    app_state: &AppState,
+    review: &Review,
+) -> Option<String> {
+    app_state
+        .banned_terms
+        .find_in(&review.description)
+        .map(|term| format!("Contains the banned term: {}", term))
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RemoveReviewQuery {
-    pub user_id: String,
     pub place_id: String,
 }
 
 pub async fn remove_review(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
     Query(query): Query<RemoveReviewQuery>,
-) -> impl IntoResponse {
-    let remove_review_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    repository
         .remove_review(
-            &query.user_id,
+            &user.user_id,
             &query.place_id,
-        ).await;
+        ).await?;
 
-    return match remove_review_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully removed review for restaurant").into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong removing review due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to remove review, please try again.").into_response()
-        }
-    };
+    Ok((StatusCode::OK, "Successfully removed review for restaurant"))
 }
 
-pub async fn update_review(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+/// Undoes `remove_review`, for as long as `review_undo_window_secs` allows.
+pub async fn restore_review(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(query): Query<RemoveReviewQuery>,
+) -> AppResult<impl IntoResponse> {
+    let deleted_since = OffsetDateTime::now_utc().unix_timestamp() - app_state.config.review_undo_window_secs;
+    repository
+        .restore_review(
+            &user.user_id,
+            &query.place_id,
+            deleted_since,
+        ).await?;
+
+    Ok((StatusCode::OK, "Successfully restored review for restaurant"))
+}
+
+/// Writes the review of the user for the place, replacing the one they already have.
+pub async fn upsert_review(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
     Json(body): Json<Review>,
-) -> impl IntoResponse {
-    let update_review_res = postgres_repo
-        .update_review(
-            &body.user_id,
+) -> AppResult<impl IntoResponse> {
+    validate_rating(body.rating)?;
+    let held_back = held_back_reason(&app_state, &body);
+    let created = repository
+        .upsert_review(
+            &user.user_id,
             &body.place_id,
             body.rating,
             &body.description,
-        ).await;
+            held_back.as_deref(),
+        ).await?;
 
-    return match update_review_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully updated review for restaurant").into_response()
+    if held_back.is_some() {
+        return Ok((StatusCode::ACCEPTED, HELD_BACK_MESSAGE));
+    }
+    if created {
+        return Ok((StatusCode::CREATED, "Successfully added review for the restaurant"));
+    }
+    Ok((StatusCode::OK, "Successfully updated review for restaurant"))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoteOnReview {
+    /// The author of the review
+    pub user_id: String,
+    pub place_id: String,
+    pub helpful: bool,
+}
+
+/// Marks someone else's review helpful or not, voting again changes the vote.
+pub async fn vote_on_review(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Json(body): Json<VoteOnReview>,
+) -> AppResult<impl IntoResponse> {
+    if body.user_id == user.user_id {
+        return Err(AppError::Validation("You cannot vote on your own review".to_string()));
+    }
+
+    repository
+        .vote_on_review(
+            &user.user_id,
+            &body.user_id,
+            &body.place_id,
+            body.helpful,
+        ).await?;
+
+    Ok((StatusCode::OK, "Successfully voted on the review"))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoveReviewVoteQuery {
+    /// The author of the review
+    pub user_id: String,
+    pub place_id: String,
+}
+
+pub async fn remove_review_vote(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(query): Query<RemoveReviewVoteQuery>,
+) -> AppResult<impl IntoResponse> {
+    repository
+        .remove_review_vote(
+            &user.user_id,
+            &query.user_id,
+            &query.place_id,
+        ).await?;
+
+    Ok((StatusCode::OK, "Successfully removed vote on the review"))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReportReview {
+    /// The author of the review
+    pub user_id: String,
+    pub place_id: String,
+    pub reason: String,
+}
+
+/// Flags a review for the moderators. Enough reports take it down until one of them decides.
+pub async fn report_review(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Json(body): Json<ReportReview>,
+) -> AppResult<impl IntoResponse> {
+    if body.user_id == user.user_id {
+        return Err(AppError::Validation("You cannot report your own review".to_string()));
+    }
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::Validation("reason must not be empty".to_string()));
+    }
+
+    repository
+        .report_review(
+            &user.user_id,
+            &body.user_id,
+            &body.place_id,
+            reason,
+            app_state.config.moderation_report_threshold,
+        ).await?;
+
+    Ok((StatusCode::OK, "Successfully reported the review"))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReviewPhotosQuery {
+    pub place_id: String,
+}
+
+/// Attaches the `photo` parts of a multipart upload to the review of the user for the place. Every photo is
+/// checked before any is stored, and the upload is taken whole or not at all.
+pub async fn upload_review_photos(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(query): Query<ReviewPhotosQuery>,
+    mut multipart: Multipart,
+) -> AppResult<impl IntoResponse> {
+    let config = &app_state.config;
+    let mut uploads = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() != Some("photo") {
+            continue;
         }
-        Err(e) => {
-            warn!("Something went wrong updating review for restaurant due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to update review for restaurant, please try again.").into_response()
+        if uploads.len() == config.review_max_photos {
+            return Err(AppError::Validation(format!("at most {} photos can be uploaded at once", config.review_max_photos)));
         }
-    };
+        uploads.push(field.bytes().await.map_err(multipart_error)?.to_vec());
+    }
+    if uploads.is_empty() {
+        return Err(AppError::Validation("expected at least one multipart field named photo".to_string()));
+    }
+
+    let (max_bytes, thumbnail_size) = (config.photo_max_bytes, config.photo_thumbnail_size);
+    let prepared = tokio::task::spawn_blocking(move || {
+        uploads
+            .into_iter()
+            .map(|bytes| prepare_photo(bytes, max_bytes, thumbnail_size))
+            .collect::<AppResult<Vec<PreparedPhoto>>>()
+    })
+        .await
+        .map_err(|e| AppError::Database(format!("Photo processing failed: {}", e)))??;
+
+    let storage = app_state.photo_storage.as_ref();
+    let created_at = OffsetDateTime::now_utc().unix_timestamp();
+    let photos: Vec<ReviewPhoto> = prepared
+        .iter()
+        .map(|photo| {
+            let photo_id = Uuid::new_v4().to_string();
+            let storage_key = format!("reviews/{}.{}", photo_id, photo.extension);
+            let thumbnail_key = format!("reviews/{}_thumb.jpg", photo_id);
+            ReviewPhoto {
+                url: photo_url(&storage_key),
+                thumbnail_url: photo_url(&thumbnail_key),
+                content_type: photo.content_type.to_string(),
+                width: i64::from(photo.width),
+                height: i64::from(photo.height),
+                created_at,
+                photo_id,
+                storage_key,
+                thumbnail_key,
+            }
+        })
+        .collect();
+
+    let attached = async {
+        for (photo, prepared) in photos.iter().zip(prepared) {
+            storage.put(&photo.storage_key, &photo.content_type, prepared.bytes).await?;
+            storage.put(&photo.thumbnail_key, "image/jpeg", prepared.thumbnail).await?;
+        }
+        repository
+            .add_review_photos(
+                &user.user_id,
+                &query.place_id,
+                &photos,
+                config.review_max_photos,
+            ).await
+    }.await;
+    if let Err(e) = attached {
+        delete_stored_photos(storage, &photos).await;
+        return Err(e);
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        json!(&photos).to_string()
+    ))
+}
+
+fn multipart_error(
+    e: MultipartError,
+) -> AppError {
+    AppError::Validation(format!("invalid multipart upload: {}", e))
+}
+
+/// Best effort, a photo left behind in the storage is only wasted space.
+async fn delete_stored_photos(
+    storage: &dyn PhotoStorage,
+    photos: &[ReviewPhoto],
+) {
+    for key in photos.iter().flat_map(|photo| [&photo.storage_key, &photo.thumbnail_key]) {
+        if let Err(e) = storage.delete(key).await {
+            warn!("Failed to delete {} from the {} photo storage: {}", key, storage.name(), e);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoveReviewPhotoQuery {
+    pub place_id: String,
+    pub photo_id: String,
+}
+
+pub async fn remove_review_photo(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(query): Query<RemoveReviewPhotoQuery>,
+) -> AppResult<impl IntoResponse> {
+    let photo = repository
+        .remove_review_photo(
+            &user.user_id,
+            &query.place_id,
+            &query.photo_id,
+        ).await?;
+    delete_stored_photos(app_state.photo_storage.as_ref(), &[photo]).await;
+
+    Ok((StatusCode::OK, "Successfully removed the photo from the review"))
+}
+
+/// Serves the photos of any photo storage while their review is published. Moderators can take a review
+/// down at any time, so clients only cache photos briefly.
+pub async fn retrieve_review_photo(
+    Extension(app_state): Extension<AppState>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Path(key): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    repository
+        .retrieve_published_review_photo(&key)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No photo found for key: {}", key)))?;
+    let bytes = app_state.photo_storage.get(&key).await?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type_of(&key)),
+            (header::CACHE_CONTROL, "public, max-age=300"),
+        ],
+        bytes,
+    ))
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RetrieveRestaurantReviews {
     pub place_id: String,
+    /// newest, highest, lowest or most_helpful, defaults to newest
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// Only reviews rounding to this many stars, 1 to 5
+    #[serde(default)]
+    pub stars: Option<String>,
 }
 
 pub async fn retrieve_restaurant_reviews(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
     Query(query): Query<RetrieveRestaurantReviews>,
-) -> impl IntoResponse {
-    let retrieve_user_review_res = postgres_repo
-        .retrieve_restaurant_reviews(
-            &query.place_id
-        ).await;
-
-    return match retrieve_user_review_res {
-        Ok(reviews) => {
-            (
-                StatusCode::OK,
-                json!(&reviews).to_string()
-            ).into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong retrieving reviews for restaurant due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to retrieve reviews for restaurant, please try again").into_response()
-        }
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let sort = optional_param("sort", query.sort.as_deref(), |_: &ReviewSort| true, "newest, highest, lowest or most_helpful")?
+        .unwrap_or_default();
+    let stars = optional_param("stars", query.stars.as_deref(), |stars| (1..=5).contains(stars), "between 1 and 5")?;
+
+    let reviews = RestaurantReviews {
+        page: repository
+            .retrieve_restaurant_reviews(
+                &query.place_id,
+                sort,
+                stars,
+                &page.to_request()?,
+            ).await?,
+        summary: repository.retrieve_rating_summary(&query.place_id).await?,
     };
+
+    Ok((
+        StatusCode::OK,
+        json!(&reviews).to_string()
+    ))
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-pub struct RetrieveUserReviewsQuery {
-    pub user_id: String,
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RetrieveUserReviews {
+    /// Also list removed reviews that can still be restored, or could once
+    #[serde(default)]
+    pub include_deleted: bool,
 }
 
 pub async fn retrieve_user_reviews(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
-    Query(query): Query<RetrieveUserReviewsQuery>,
-) -> impl IntoResponse {
-    let user_reviewed_restaurants_res = postgres_repo
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(query): Query<RetrieveUserReviews>,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let reviews = repository
         .get_user_reviews(
-            &query.user_id
-        ).await;
-
-    return match user_reviewed_restaurants_res {
-        Ok(reviews) => {
-            (
-                StatusCode::OK,
-                json!(&reviews).to_string()
-            ).into_response()
-        }
-        Err(e) => {
-            warn!("Something went wrong retrieving user reviews for restaurant due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to retrieve user reviews for restaurant, please try again").into_response()
-        }
-    };
-}
\ No newline at end of file
+            &user.user_id,
+            query.include_deleted,
+            &page.to_request()?,
+        ).await?;
+
+    Ok((
+        StatusCode::OK,
+        json!(&reviews).to_string()
+    ))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RetrieveReviewHistory {
+    pub user_id: String,
+    pub place_id: String,
+}
+
+/// Every version of one review, newest first.
+pub async fn retrieve_review_history(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Query(query): Query<RetrieveReviewHistory>,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let revisions = repository
+        .retrieve_review_history(
+            &query.user_id,
+            &query.place_id,
+            &page.to_request()?,
+        ).await?;
+
+    Ok((
+        StatusCode::OK,
+        json!(&revisions).to_string()
+    ))
+}
@@ -1,76 +1,298 @@
 use std::sync::Arc;
 use axum::{Extension, Json, Router};
-use axum::extract::Query;
+use axum::extract::{Path, Query};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::response::IntoResponse;
+use futures::{SinkExt, StreamExt};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tracing::warn;
 use axum::routing::{get, post};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
 use crate::controller::AppState;
-use crate::repositories::postgres_repo::PostgresConnectionRepo;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::auth::AuthUser;
+use crate::models::page::PageParams;
+use crate::models::restaurant::Restaurant;
+use crate::repositories::Repository;
+use crate::voting::{lock_room, SharedRoom, VotingRooms};
+use crate::voting::room::{ClientMessage, RoomEvent};
+use crate::voting::tally::{tally, Ballot, VotingMethod};
 
-pub fn router(app_state: AppState) -> Router {
-    let postgres_repo = Arc::new(PostgresConnectionRepo::new(
-        app_state.postgres_connection
-    ));
+pub const MIN_CANDIDATES: usize = 2;
+pub const MAX_CANDIDATES: usize = 20;
 
+pub fn router(app_state: AppState) -> Router {
     Router::new()
         .route("/", post(persist_vote_history))
         .route("/", get(retrieve_vote_history))
-        .route_layer(Extension(postgres_repo))
+        .route("/tally", post(tally_votes))
+        .route("/room", post(create_voting_room))
+        .route("/room/:join_code", get(retrieve_voting_room))
+        .route("/room/:join_code/ws", get(join_voting_room))
+        .route_layer(Extension(app_state.repository.clone()))
+        .route_layer(Extension(app_state.voting_rooms.clone()))
+        .route_layer(Extension(app_state))
 }
 
+/// A session voted on elsewhere, recorded for the caller only. Other voters record it themselves.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct VotingHistory {
-    user_ids: Vec<String>,
     voted_places: Value,
     vote_timestamp: i64,
 }
 
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CreateVotingRoom {
+    #[serde(default)]
+    method: VotingMethod,
+    place_ids: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TallyVotes {
+    #[serde(default)]
+    method: VotingMethod,
+    candidates: Vec<String>,
+    ballots: Vec<Ballot>,
+    /// Also store the session and its outcome in the vote history of the caller. The `user_id` of a ballot is
+    /// whatever the client says, so the users it names are never written to.
+    #[serde(default)]
+    record: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RoomSocketQuery {
+    token: Option<String>,
+}
+
 pub async fn persist_vote_history(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
     Json(body): Json<VotingHistory>,
-) -> impl IntoResponse {
-    let store_vote_history_res = postgres_repo
+) -> AppResult<impl IntoResponse> {
+    repository
         .store_vote_history(
-            body.user_ids,
+            vec![user.user_id],
             body.voted_places,
             body.vote_timestamp,
-        ).await;
+            None,
+        ).await?;
 
-    return match store_vote_history_res {
-        Ok(_) => {
-            (StatusCode::OK, "Successfully persisted voting history record").into_response()
-        }
+    Ok((StatusCode::OK, "Successfully persisted voting history record"))
+}
+
+pub async fn retrieve_vote_history(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Query(page): Query<PageParams>,
+) -> AppResult<impl IntoResponse> {
+    let vote_histories = repository
+        .retrieve_user_vote_history(
+            &user.user_id,
+            &page.to_request()?,
+        ).await?;
+
+    Ok((StatusCode::OK, json!(vote_histories).to_string()))
+}
+
+pub async fn tally_votes(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    user: AuthUser,
+    Json(body): Json<TallyVotes>,
+) -> AppResult<impl IntoResponse> {
+    let result = tally(body.method, &body.candidates, &body.ballots)?;
+
+    if body.record {
+        repository
+            .store_vote_history(
+                vec![user.user_id],
+                json!(result.standings()),
+                OffsetDateTime::now_utc().unix_timestamp(),
+                Some(&result),
+            ).await?;
+    }
+
+    Ok((StatusCode::OK, json!(result).to_string()))
+}
+
+pub async fn create_voting_room(
+    Extension(repository): Extension<Arc<dyn Repository>>,
+    Extension(voting_rooms): Extension<VotingRooms>,
+    user: AuthUser,
+    Json(body): Json<CreateVotingRoom>,
+) -> AppResult<impl IntoResponse> {
+    let candidates = retrieve_candidates(&repository, body.place_ids).await?;
+    let room = voting_rooms.create_room(&user, body.method, candidates)?;
+    let snapshot = lock_room(&room)?.snapshot();
+
+    Ok((StatusCode::CREATED, json!(snapshot).to_string()))
+}
+
+pub async fn retrieve_voting_room(
+    Extension(voting_rooms): Extension<VotingRooms>,
+    _user: AuthUser,
+    Path(join_code): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let room = voting_rooms.get_room(&join_code)?;
+    let snapshot = lock_room(&room)?.snapshot();
+
+    Ok((StatusCode::OK, json!(snapshot).to_string()))
+}
+
+/// Browsers cannot set headers on a WebSocket handshake, so the token comes in as `?token=` instead.
+pub async fn join_voting_room(
+    Extension(app_state): Extension<AppState>,
+    Path(join_code): Path<String>,
+    Query(query): Query<RoomSocketQuery>,
+    ws: WebSocketUpgrade,
+) -> AppResult<impl IntoResponse> {
+    let token = query
+        .token
+        .ok_or_else(|| AppError::Unauthorized("Missing token query parameter".to_string()))?;
+    let user = AuthUser::from_token(&token, &app_state.config)?;
+    let room = app_state.voting_rooms.get_room(&join_code)?;
+
+    Ok(ws.on_upgrade(move |socket| run_room_socket(socket, app_state, room, user)))
+}
+
+async fn run_room_socket(
+    socket: WebSocket,
+    app_state: AppState,
+    room: SharedRoom,
+    user: AuthUser,
+) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let joined = lock_room(&room).and_then(|mut room| {
+        let events = room.join(&user)?;
+        Ok((events, room.snapshot()))
+    });
+    let (mut events, snapshot) = match joined {
+        Ok(joined) => joined,
         Err(e) => {
-            warn!("Something went wrong persisting vote history due to: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to persist vote history, please try again.").into_response()
+            let _ = sender.send(Message::Text(json!(RoomEvent::from(e)).to_string())).await;
+            return;
         }
     };
-}
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct VoteHistoryParam {
-    user_id: String,
+    // replies meant for this connection only, such as the initial state and errors
+    let (direct_sender, mut direct_receiver) = mpsc::unbounded_channel();
+    let _ = direct_sender.send(RoomEvent::State(snapshot));
+
+    let mut forward_events = tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                biased;
+                Some(event) = direct_receiver.recv() => event,
+                received = events.recv() => match received {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+            };
+
+            let closed = matches!(event, RoomEvent::Closed { .. });
+            if sender.send(Message::Text(json!(event).to_string())).await.is_err() {
+                break;
+            }
+            if closed {
+                let _ = sender.send(Message::Close(None)).await;
+                break;
+            }
+        }
+    });
+
+    loop {
+        tokio::select! {
+            _ = &mut forward_events => break,
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if let Err(e) = handle_client_message(&app_state, &room, &user, &text).await {
+                        let _ = direct_sender.send(e.into());
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    forward_events.abort();
+    if let Ok(mut room) = lock_room(&room) {
+        room.leave(&user.user_id);
+    }
 }
 
-pub async fn retrieve_vote_history(
-    Extension(postgres_repo): Extension<Arc<PostgresConnectionRepo>>,
-    Query(query): Query<VoteHistoryParam>,
-) -> impl IntoResponse {
-    let user_vote_histories_res = postgres_repo
-        .retrieve_user_vote_history(
-            &query.user_id
-        ).await;
+async fn handle_client_message(
+    app_state: &AppState,
+    room: &SharedRoom,
+    user: &AuthUser,
+    text: &str,
+) -> AppResult<()> {
+    let message = serde_json::from_str::<ClientMessage>(text)
+        .map_err(|e| AppError::Validation(format!("Unrecognised message: {}", e)))?;
 
-    return match user_vote_histories_res {
-        Ok(vote_histories) => {
-            (StatusCode::OK, json!(vote_histories).to_string()).into_response()
+    match message {
+        ClientMessage::SetCandidates { place_ids } => {
+            let candidates = retrieve_candidates(&app_state.repository, place_ids).await?;
+            lock_room(room)?.set_candidates(&user.user_id, candidates)
         }
-        Err(e) => {
-            warn!("Something went wrong retrieving user's voting histories: {}", e);
-            (StatusCode::BAD_REQUEST, "Failed to retrieve user's voting histories, please try again.").into_response()
+        ClientMessage::Vote { place_id } => lock_room(room)?.cast_ballot(&user.user_id, vec![place_id]),
+        ClientMessage::Ballot { choices } => lock_room(room)?.cast_ballot(&user.user_id, choices),
+        ClientMessage::Close => {
+            let outcome = lock_room(room)?.begin_close(&user.user_id)?;
+            let stored = app_state.repository
+                .store_vote_history(
+                    outcome.voter_ids.clone(),
+                    json!(outcome.counts),
+                    OffsetDateTime::now_utc().unix_timestamp(),
+                    Some(&outcome.result),
+                ).await;
+
+            let mut room = lock_room(room)?;
+            match stored {
+                Ok(()) => {
+                    room.finish_close(outcome);
+                    app_state.voting_rooms.remove_room(&room.join_code)
+                }
+                Err(e) => {
+                    room.abort_close();
+                    Err(e)
+                }
+            }
         }
-    };
-}
\ No newline at end of file
+    }
+}
+
+async fn retrieve_candidates(
+    repository: &Arc<dyn Repository>,
+    place_ids: Vec<String>,
+) -> AppResult<Vec<Restaurant>> {
+    let mut unique_place_ids: Vec<String> = Vec::new();
+    for place_id in place_ids {
+        if !unique_place_ids.contains(&place_id) {
+            unique_place_ids.push(place_id);
+        }
+    }
+    if !(MIN_CANDIDATES..=MAX_CANDIDATES).contains(&unique_place_ids.len()) {
+        return Err(AppError::Validation(format!(
+            "A vote needs between {} and {} different candidates",
+            MIN_CANDIDATES,
+            MAX_CANDIDATES,
+        )));
+    }
+
+    let mut candidates = Vec::with_capacity(unique_place_ids.len());
+    for place_id in unique_place_ids {
+        let candidate = repository
+            .retrieve_restaurant(&place_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("No restaurant found with place_id: {}", place_id)))?;
+        candidates.push(candidate);
+    }
+
+    Ok(candidates)
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use bb8_postgres::tokio_postgres::error::SqlState;
+use serde_json::json;
+use tracing::{error, warn};
+
+pub type AppResult<T> = Result<T, AppError>;
+
+/// Error type shared by the repositories and controllers. Every variant maps onto a single HTTP status
+/// and renders as `{"code": "...", "message": "..."}` so clients can rely on one error shape.
+#[derive(Debug)]
+pub enum AppError {
+    Unauthorized(String),
+    /// Signed in, but not allowed to do this.
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Validation(String),
+    /// A validation failure with a code of its own, for rules clients tell apart. Always a 422.
+    Rejected(&'static str, String),
+    Upstream(String),
+    /// An upstream service is deliberately refusing us for now, for example an exhausted quota.
+    Unavailable(String),
+    Database(String),
+}
+
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Validation(_) | AppError::Rejected(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Rejected(code, _) => code,
+            AppError::Upstream(_) => "upstream_failure",
+            AppError::Unavailable(_) => "service_unavailable",
+            AppError::Database(_) => "database_failure",
+        }
+    }
+
+    /// Message safe to hand back to clients, upstream and database details only go to the logs.
+    pub fn public_message(&self) -> String {
+        match self {
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::Validation(message)
+            | AppError::Rejected(_, message)
+            | AppError::Unavailable(message) => message.clone(),
+            AppError::Upstream(_) => "Failed to reach an upstream service, please try again".to_string(),
+            AppError::Database(_) => "Something went wrong! Please try again".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Unauthorized(message) => write!(f, "unauthorized: {}", message),
+            AppError::Forbidden(message) => write!(f, "forbidden: {}", message),
+            AppError::NotFound(message) => write!(f, "not found: {}", message),
+            AppError::Conflict(message) => write!(f, "conflict: {}", message),
+            AppError::Validation(message) => write!(f, "validation failed: {}", message),
+            AppError::Rejected(code, message) => write!(f, "rejected with {}: {}", code, message),
+            AppError::Upstream(message) => write!(f, "upstream failure: {}", message),
+            AppError::Unavailable(message) => write!(f, "service unavailable: {}", message),
+            AppError::Database(message) => write!(f, "database failure: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        if status.is_server_error() {
+            error!("Request failed with {}", self);
+        } else {
+            warn!("Request rejected with {}", self);
+        }
+
+        (
+            status,
+            Json(json!({
+                "code": self.code(),
+                "message": self.public_message(),
+            })),
+        ).into_response()
+    }
+}
+
+impl From<bb8_postgres::tokio_postgres::Error> for AppError {
+    fn from(e: bb8_postgres::tokio_postgres::Error) -> Self {
+        match e.code() {
+            Some(code) if *code == SqlState::UNIQUE_VIOLATION => {
+                AppError::Conflict("The record already exists".to_string())
+            }
+            Some(code) if *code == SqlState::FOREIGN_KEY_VIOLATION => {
+                AppError::NotFound("The referenced record does not exist".to_string())
+            }
+            _ => AppError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(e: anyhow::Error) -> Self {
+        AppError::Database(e.to_string())
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        AppError::Upstream(e.to_string())
+    }
+}
@@ -0,0 +1,180 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use crate::config::Config;
+use crate::controller::AppState;
+use crate::helpers::app_error::{AppError, AppResult};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Claims {
+    pub sub: String,
+    pub username: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Argon2 is deliberately slow, so hashing runs on the blocking pool instead of stalling the runtime.
+pub async fn hash_password(
+    password: String,
+) -> AppResult<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::Database(format!("Failed to hash password: {}", e)))
+    })
+        .await
+        .map_err(|e| AppError::Database(format!("Password hashing task failed: {}", e)))?
+}
+
+/// Hash with the same parameters as `hash_password`, checked against when the user does not exist so that a
+/// login takes as long either way and does not tell which usernames are taken.
+pub const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$xawproWv4xRos1hFsRjZMQ$GfzzGkvK+pj4Z4XphVRbgifvz7+qq0US2z6MgvKhK1Y";
+
+pub async fn verify_password(
+    password: String,
+    password_hash: String,
+) -> bool {
+    tokio::task::spawn_blocking(move || match PasswordHash::new(&password_hash) {
+        Ok(parsed_hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok(),
+        Err(_) => false,
+    })
+        .await
+        .unwrap_or(false)
+}
+
+pub fn issue_token(
+    user_id: &str,
+    username: &str,
+    config: &Config,
+) -> AppResult<String> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let claims = Claims {
+        sub: user_id.to_string(),
+        username: username.to_string(),
+        iat: now,
+        exp: now + config.jwt_expiry_secs,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+    ).map_err(|e| AppError::Database(format!("Failed to sign token: {}", e)))
+}
+
+pub fn decode_token(
+    token: &str,
+    config: &Config,
+) -> AppResult<Claims> {
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+        &Validation::default(),
+    )
+        .map(|token_data| token_data.claims)
+        .map_err(|_| AppError::Unauthorized("Invalid or expired token".to_string()))
+}
+
+/// The user making the request, taken from the `Authorization: Bearer <token>` header.
+/// Handlers that take this extractor reject anonymous requests with a 401.
+#[derive(Clone, Debug)]
+pub struct AuthUser {
+    pub user_id: String,
+    pub username: String,
+}
+
+impl AuthUser {
+    /// Resolves a raw token, for transports that cannot carry an `Authorization` header such as
+    /// browser WebSockets.
+    pub fn from_token(
+        token: &str,
+        config: &Config,
+    ) -> AppResult<Self> {
+        let claims = decode_token(token.trim(), config)?;
+        Ok(AuthUser {
+            user_id: claims.sub,
+            username: claims.username,
+        })
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let app_state = parts
+            .extensions
+            .get::<AppState>()
+            .ok_or_else(|| AppError::Database("AppState missing from request extensions".to_string()))?;
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+
+        AuthUser::from_token(token, &app_state.config)
+    }
+}
+
+/// A signed in user whose id is listed in `ADMIN_USER_IDS`. Anyone else gets a 403, anonymous requests still a 401.
+#[derive(Clone, Debug)]
+pub struct AdminUser(pub AuthUser);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let auth_user = AuthUser::from_request_parts(parts, state).await?;
+        let app_state = parts
+            .extensions
+            .get::<AppState>()
+            .ok_or_else(|| AppError::Database("AppState missing from request extensions".to_string()))?;
+
+        if !app_state.config.admin_user_ids.iter().any(|user_id| user_id.trim() == auth_user.user_id) {
+            return Err(AppError::Forbidden(format!("{} is not an admin", auth_user.username)));
+        }
+        Ok(AdminUser(auth_user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_dummy_hash_costs_as_much_as_a_real_one() {
+        let dummy = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+        let real_hash = hash_password("correct horse battery".to_string()).await.unwrap();
+        let real = PasswordHash::new(&real_hash).unwrap();
+        assert_eq!((dummy.algorithm, dummy.version, dummy.params), (real.algorithm, real.version, real.params));
+        assert!(!verify_password("correct horse battery".to_string(), DUMMY_PASSWORD_HASH.to_string()).await);
+    }
+}
@@ -0,0 +1,30 @@
+use crate::models::restaurant::Location;
+
+pub const EARTH_RADIUS_METRES: f64 = 6_371_000.0;
+
+/// Great circle distance between two points, close enough for "what is nearby" at city scale.
+pub fn haversine_distance_metres(
+    from: &Location,
+    to: &Location,
+) -> f64 {
+    let lat_delta = (to.lat - from.lat).to_radians();
+    let lng_delta = (to.lng - from.lng).to_radians();
+    let a = (lat_delta / 2.0).sin().powi(2)
+        + from.lat.to_radians().cos() * to.lat.to_radians().cos() * (lng_delta / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_METRES * a.sqrt().min(1.0).asin()
+}
+
+/// Parses the `lat,lng` pair Google takes as a location.
+pub fn parse_location(
+    location: &str,
+) -> Option<Location> {
+    let (lat, lng) = location.split_once(',')?;
+    let lat = lat.trim().parse::<f64>().ok()?;
+    let lng = lng.trim().parse::<f64>().ok()?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) {
+        return None;
+    }
+
+    Some(Location { lat, lng })
+}
@@ -0,0 +1,198 @@
+//! Reservations as an RFC 5545 calendar, for calendar apps to import once or subscribe to.
+use time::macros::format_description;
+use time::OffsetDateTime;
+use crate::models::reservation::{Reservation, ReservationStatus};
+use crate::models::restaurant::Restaurant;
+
+pub const CALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+/// How long an event lasts at a place without a capacity, whose slots would tell.
+pub const DEFAULT_EVENT_MINUTES: u32 = 90;
+const PRODUCT_ID: &str = "-//EatWhereLa//Reservations//EN";
+const CALENDAR_NAME: &str = "EatWhereLa reservations";
+/// Domain part of the event UIDs, which stay the same for a reservation however often it is exported.
+const UID_DOMAIN: &str = "eatwherela";
+/// Longest line allowed, in octets without the line break.
+const MAX_LINE_OCTETS: usize = 75;
+
+/// A reservation along with what its event says about the place.
+pub struct ReservationEvent<'a> {
+    pub reservation: &'a Reservation,
+    /// Unset when the place is not stored anymore, the event then only has the party size.
+    pub restaurant: Option<&'a Restaurant>,
+    pub duration_minutes: u32,
+}
+
+/// A `VCALENDAR` holding an event per reservation, lines folded and ended with CRLF.
+pub fn render_calendar(
+    events: &[ReservationEvent],
+) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(CALENDAR_NAME)),
+    ];
+    for event in events {
+        lines.extend(event_lines(event));
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+fn event_lines(
+    event: &ReservationEvent,
+) -> Vec<String> {
+    let reservation = event.reservation;
+    let place_name = event.restaurant.map_or("Reservation", |restaurant| restaurant.name.as_str());
+    let status = match reservation.status {
+        ReservationStatus::Confirmed => "CONFIRMED",
+        ReservationStatus::Requested => "TENTATIVE",
+        _ => "CANCELLED",
+    };
+    let end = reservation.reservation_timestamp + i64::from(event.duration_minutes) * 60;
+
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@{}", reservation.reservation_id, UID_DOMAIN),
+        format!("DTSTAMP:{}", utc_date_time(reservation.updated_at)),
+        format!("CREATED:{}", utc_date_time(reservation.created_at)),
+        format!("LAST-MODIFIED:{}", utc_date_time(reservation.updated_at)),
+        format!("DTSTART:{}", utc_date_time(reservation.reservation_timestamp)),
+        format!("DTEND:{}", utc_date_time(end)),
+        format!("SUMMARY:{}", escape_text(&format!("{} (table for {})", place_name, reservation.reservation_pax))),
+        format!("STATUS:{}", status),
+    ];
+    if let Some(restaurant) = event.restaurant {
+        lines.push(format!("LOCATION:{}", escape_text(&restaurant.vicinity)));
+        lines.push(format!("GEO:{};{}", restaurant.geometry.lat, restaurant.geometry.lng));
+    }
+    lines.push("END:VEVENT".to_string());
+    lines
+}
+
+/// `YYYYMMDDTHHMMSSZ`, timestamps out of range end up at the epoch.
+fn utc_date_time(
+    timestamp: i64,
+) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+        .format(format_description!("[year][month][day]T[hour][minute][second]Z"))
+        .unwrap_or_default()
+}
+
+/// Escapes a TEXT value, line breaks become `\n`.
+fn escape_text(
+    text: &str,
+) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            character if character.is_control() => {}
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// The line and its CRLF, longer lines are continued on lines starting with a space. Never splits a character.
+fn fold_line(
+    line: &str,
+) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut line_octets = 0;
+    for character in line.chars() {
+        if line_octets + character.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // the space counts towards the continued line
+            line_octets = 1;
+        }
+        folded.push(character);
+        line_octets += character.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::restaurant::Location;
+    use super::*;
+
+    fn reservation() -> Reservation {
+        Reservation {
+            reservation_id: "5d0c8e4e-0f4a-4b8e-9a43-2f1b0d1c9e77".to_string(),
+            user_id: "user".to_string(),
+            place_id: "maxwell-tian-tian".to_string(),
+            // 2026-10-20 19:00 in Singapore
+            reservation_timestamp: 1_792_494_000,
+            reservation_pax: 4,
+            status: ReservationStatus::Confirmed,
+            created_at: 1_792_400_000,
+            updated_at: 1_792_410_000,
+        }
+    }
+
+    fn restaurant() -> Restaurant {
+        Restaurant {
+            place_id: "maxwell-tian-tian".to_string(),
+            name: "Tian Tian; Hainanese, Chicken Rice".to_string(),
+            photos: None,
+            rating: None,
+            price_level: None,
+            vicinity: "1 Kadayanallur St, #01-10/11 Maxwell Food Centre\nSingapore".to_string(),
+            geometry: Location { lat: 1.280_3, lng: 103.844_8 },
+        }
+    }
+
+    #[test]
+    fn reservations_become_events() {
+        let reservation = reservation();
+        let restaurant = restaurant();
+        let calendar = render_calendar(&[ReservationEvent {
+            reservation: &reservation,
+            restaurant: Some(&restaurant),
+            duration_minutes: 90,
+        }]);
+
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(calendar.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+        let unfolded = calendar.replace("\r\n ", "");
+        let lines: Vec<&str> = unfolded.split("\r\n").collect();
+        for expected in [
+            "UID:5d0c8e4e-0f4a-4b8e-9a43-2f1b0d1c9e77@eatwherela",
+            "DTSTART:20261020T110000Z",
+            "DTEND:20261020T123000Z",
+            "SUMMARY:Tian Tian\\; Hainanese\\, Chicken Rice (table for 4)",
+            "LOCATION:1 Kadayanallur St\\, #01-10/11 Maxwell Food Centre\\nSingapore",
+            "GEO:1.2803;103.8448",
+            "STATUS:CONFIRMED",
+        ] {
+            assert!(lines.contains(&expected), "{} missing from\n{}", expected, calendar);
+        }
+
+        let requested = Reservation { status: ReservationStatus::Requested, ..reservation.clone() };
+        let calendar = render_calendar(&[ReservationEvent { reservation: &requested, restaurant: None, duration_minutes: 90 }]);
+        assert!(calendar.contains("\r\nSTATUS:TENTATIVE\r\n"));
+        assert!(calendar.contains("\r\nSUMMARY:Reservation (table for 4)\r\n"));
+        assert!(!calendar.contains("GEO:"));
+    }
+
+    #[test]
+    fn long_lines_fold_between_characters() {
+        let line = format!("SUMMARY:{}", "海南鸡饭".repeat(10));
+        let folded = fold_line(&line);
+        for part in folded.trim_end_matches("\r\n").split("\r\n") {
+            assert!(part.len() <= MAX_LINE_OCTETS, "{} octets", part.len());
+        }
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", line));
+        assert_eq!(fold_line("END:VEVENT"), "END:VEVENT\r\n");
+    }
+}
@@ -0,0 +1,115 @@
+//! Checks on uploaded photos and the thumbnails made of them. Decoding is CPU bound, callers run it on the
+//! blocking pool.
+use std::io::Cursor;
+use image::imageops::FilterType;
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use crate::helpers::app_error::{AppError, AppResult};
+
+/// Widest and tallest photo accepted, a photo claiming more is most likely a decompression bomb.
+pub const MAX_PHOTO_DIMENSION: u32 = 10_000;
+/// Quality thumbnails are encoded at.
+const THUMBNAIL_JPEG_QUALITY: u8 = 80;
+
+/// An uploaded photo that decoded fine, with its thumbnail.
+#[derive(Debug)]
+pub struct PreparedPhoto {
+    /// Extension matching the actual format, whatever the upload claimed to be.
+    pub extension: &'static str,
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Always a jpeg, the longest side at most the thumbnail size.
+    pub thumbnail: Vec<u8>,
+}
+
+/// Accepts jpeg, png, webp and gif photos of at most `max_bytes`, told apart by their content rather than
+/// by the name or type the client gave them. The photo is kept as uploaded.
+pub fn prepare_photo(
+    bytes: Vec<u8>,
+    max_bytes: usize,
+    thumbnail_size: u32,
+) -> AppResult<PreparedPhoto> {
+    if bytes.len() > max_bytes {
+        return Err(AppError::Validation(format!("photos can be at most {} bytes, got: {}", max_bytes, bytes.len())));
+    }
+    let unsupported = || AppError::Validation("photos have to be jpeg, png, webp or gif images".to_string());
+    let format = image::guess_format(&bytes).map_err(|_| unsupported())?;
+    let (extension, content_type) = match format {
+        ImageFormat::Jpeg => ("jpg", "image/jpeg"),
+        ImageFormat::Png => ("png", "image/png"),
+        ImageFormat::WebP => ("webp", "image/webp"),
+        ImageFormat::Gif => ("gif", "image/gif"),
+        _ => return Err(unsupported()),
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_PHOTO_DIMENSION);
+    limits.max_image_height = Some(MAX_PHOTO_DIMENSION);
+    let mut reader = Reader::with_format(Cursor::new(&bytes), format);
+    reader.limits(limits);
+    let image = reader
+        .decode()
+        .map_err(|e| AppError::Validation(format!("photo could not be read as {}: {}", extension, e)))?;
+
+    Ok(PreparedPhoto {
+        extension,
+        content_type,
+        width: image.width(),
+        height: image.height(),
+        thumbnail: thumbnail_of(&image, thumbnail_size)?,
+        bytes,
+    })
+}
+
+fn thumbnail_of(
+    image: &DynamicImage,
+    thumbnail_size: u32,
+) -> AppResult<Vec<u8>> {
+    let thumbnail = match image.width().max(image.height()) > thumbnail_size {
+        true => image.resize(thumbnail_size, thumbnail_size, FilterType::Triangle),
+        false => image.clone(),
+    };
+    // jpeg has no alpha channel, transparency turns black
+    let mut encoded = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(thumbnail.to_rgb8())
+        .write_to(&mut encoded, ImageOutputFormat::Jpeg(THUMBNAIL_JPEG_QUALITY))
+        .map_err(|e| AppError::Database(format!("Failed to encode thumbnail: {}", e)))?;
+    Ok(encoded.into_inner())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use image::{Rgb, RgbImage};
+    use super::*;
+
+    /// A png of the given size, for tests that need an upload.
+    pub fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut encoded = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([200, 120, 40])))
+            .write_to(&mut encoded, ImageOutputFormat::Png)
+            .unwrap();
+        encoded.into_inner()
+    }
+
+    #[test]
+    fn photos_get_a_jpeg_thumbnail() {
+        let photo = prepare_photo(png(200, 100), 64 * 1024, 50).unwrap();
+        assert_eq!((photo.extension, photo.content_type), ("png", "image/png"));
+        assert_eq!((photo.width, photo.height), (200, 100));
+
+        let thumbnail = image::load_from_memory(&photo.thumbnail).unwrap();
+        assert_eq!(image::guess_format(&photo.thumbnail).unwrap(), ImageFormat::Jpeg);
+        assert_eq!((thumbnail.width(), thumbnail.height()), (50, 25));
+    }
+
+    #[test]
+    fn only_small_enough_images_are_accepted() {
+        let error = prepare_photo(png(200, 100), 100, 50).unwrap_err();
+        assert_eq!(error.code(), "validation_failed");
+        for not_an_image in [b"GIF89a but not really".to_vec(), b"%PDF-1.7".to_vec(), Vec::new()] {
+            assert_eq!(prepare_photo(not_an_image, 1024, 50).unwrap_err().code(), "validation_failed");
+        }
+    }
+}
@@ -1 +1,10 @@
-pub mod handler_404;
\ No newline at end of file
+pub mod app_error;
+pub mod auth;
+pub mod geo;
+pub mod handler_404;
+pub mod icalendar;
+pub mod images;
+pub mod moderation;
+pub mod nearby_cache;
+pub mod params;
+pub mod text_search;
\ No newline at end of file
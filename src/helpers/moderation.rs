@@ -0,0 +1,59 @@
+//! The banned term filter reviews pass through before they are published.
+use crate::helpers::text_search::words;
+
+/// Terms that hold a review back for moderation. Terms match whole words regardless of case and punctuation,
+/// so a banned "ass" does not catch "class", and a term of several words has to appear as that phrase.
+#[derive(Clone, Debug, Default)]
+pub struct BannedTerms {
+    terms: Vec<Vec<String>>,
+}
+
+impl BannedTerms {
+    pub fn new(
+        terms: &[String],
+    ) -> Self {
+        BannedTerms {
+            terms: terms
+                .iter()
+                .map(|term| words(term))
+                .filter(|term| !term.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The first banned term found in `text`, lowercased.
+    pub fn find_in(
+        &self,
+        text: &str,
+    ) -> Option<String> {
+        if self.terms.is_empty() {
+            return None;
+        }
+
+        let text_words = words(text);
+        self.terms
+            .iter()
+            .find(|term| text_words.windows(term.len()).any(|window| window == term.as_slice()))
+            .map(|term| term.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_whole_words_and_phrases_match() {
+        let banned_terms = BannedTerms::new(&["ass".to_string(), "food POISONING".to_string(), " ".to_string()]);
+
+        assert_eq!(banned_terms.find_in("What an ASS!").as_deref(), Some("ass"));
+        assert_eq!(banned_terms.find_in("Got food-poisoning here").as_deref(), Some("food poisoning"));
+        assert_eq!(banned_terms.find_in("First class chicken rice"), None);
+        assert_eq!(banned_terms.find_in("Poisoning the food? No"), None);
+        assert_eq!(BannedTerms::new(&[]).find_in("anything at all"), None);
+    }
+}
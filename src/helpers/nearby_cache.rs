@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::models::google_places::NearbyPage;
+use crate::models::restaurant::Location;
+
+/// Locations are snapped to a grid of 1/1000th of a degree, roughly 110m, before they are used as a key.
+/// Clients a few metres apart then share an entry instead of each costing a Google call.
+pub const GRID_CELLS_PER_DEGREE: f64 = 1000.0;
+/// How long Google accepts the `next_page_token` of a page. It is not documented, in practice tokens stop working
+/// after a few minutes, so cached pages only point on to the next page for a while.
+pub const PAGE_TOKEN_LIFETIME: Duration = Duration::from_secs(120);
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct NearbySearchKey {
+    lat_cell: i64,
+    lng_cell: i64,
+    // the parsed radius, so `500` and `500.0` share an entry. Floats are not `Eq`, their bits are
+    radius_bits: u64,
+    place_type: String,
+    minprice: String,
+    pages: u8,
+}
+
+impl NearbySearchKey {
+    pub fn new(
+        location: &Location,
+        radius_metres: f64,
+        place_type: &str,
+        minprice: &str,
+        pages: u8,
+    ) -> Self {
+        NearbySearchKey {
+            lat_cell: (location.lat * GRID_CELLS_PER_DEGREE).round() as i64,
+            lng_cell: (location.lng * GRID_CELLS_PER_DEGREE).round() as i64,
+            radius_bits: radius_metres.to_bits(),
+            place_type: place_type.trim().to_lowercase(),
+            minprice: minprice.trim().to_string(),
+            pages,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct NearbyCacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub evictions: u64,
+    pub fallbacks: u64,
+    pub entries: usize,
+    pub max_entries: usize,
+    pub ttl_secs: u64,
+}
+
+struct CachedSearch {
+    page: NearbyPage,
+    stored_at: Instant,
+    // insertion order, instants can tie when entries are stored back to back
+    sequence: u64,
+}
+
+/// In process TTL cache for Google nearby searches. It is only an optimisation, so a poisoned lock is
+/// recovered rather than failing the request.
+pub struct NearbySearchCache {
+    ttl: Duration,
+    page_token_lifetime: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<NearbySearchKey, CachedSearch>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    fallbacks: AtomicU64,
+    next_sequence: AtomicU64,
+}
+
+impl NearbySearchCache {
+    /// A `max_entries` of 0 turns caching off, every lookup is then a miss. Cached pages lose their next page
+    /// token once it is `page_token_lifetime` old.
+    pub fn new(
+        ttl: Duration,
+        page_token_lifetime: Duration,
+        max_entries: usize,
+    ) -> Self {
+        NearbySearchCache {
+            ttl,
+            page_token_lifetime,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            fallbacks: AtomicU64::new(0),
+            next_sequence: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(
+        &self,
+        key: &NearbySearchKey,
+    ) -> Option<NearbyPage> {
+        let mut entries = self.lock_entries();
+        let cached = match entries.get(key) {
+            Some(cached) if cached.stored_at.elapsed() < self.ttl => {
+                let mut page = cached.page.clone();
+                if cached.stored_at.elapsed() >= self.page_token_lifetime {
+                    page.next_page_token = None;
+                }
+                Some(page)
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+
+        let counter = if cached.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    pub fn insert(
+        &self,
+        key: NearbySearchKey,
+        page: NearbyPage,
+    ) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.lock_entries();
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            let ttl = self.ttl;
+            let before = entries.len();
+            entries.retain(|_, cached| cached.stored_at.elapsed() < ttl);
+
+            // still full of live entries, make room by dropping the oldest one
+            if entries.len() >= self.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.sequence)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+            self.evictions.fetch_add((before - entries.len()) as u64, Ordering::Relaxed);
+        }
+
+        entries.insert(key, CachedSearch {
+            page,
+            stored_at: Instant::now(),
+            sequence: self.next_sequence.fetch_add(1, Ordering::Relaxed),
+        });
+    }
+
+    /// Counts a request that was answered from stored places because Google could not be reached.
+    pub fn record_fallback(&self) {
+        self.fallbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> NearbyCacheMetrics {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+
+        NearbyCacheMetrics {
+            hits,
+            misses,
+            hit_rate: if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 },
+            evictions: self.evictions.load(Ordering::Relaxed),
+            fallbacks: self.fallbacks.load(Ordering::Relaxed),
+            entries: self.lock_entries().len(),
+            max_entries: self.max_entries,
+            ttl_secs: self.ttl.as_secs(),
+        }
+    }
+
+    fn lock_entries(&self) -> MutexGuard<'_, HashMap<NearbySearchKey, CachedSearch>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::restaurant::{Photo, Restaurant};
+    use super::*;
+
+    fn page(place_id: &str) -> NearbyPage {
+        let restaurant = Restaurant {
+            place_id: place_id.to_string(),
+            name: place_id.to_string(),
+            photos: Some(Photo {
+                height: 1,
+                photo_reference: String::new(),
+                width: 1,
+            }),
+            rating: Some(4.0),
+            price_level: Some(2),
+            vicinity: String::new(),
+            geometry: Location {
+                lat: 1.3,
+                lng: 103.8,
+            },
+        };
+        NearbyPage {
+            restaurants: vec![restaurant],
+            next_page_token: None,
+        }
+    }
+
+    fn key(lat: f64, lng: f64) -> NearbySearchKey {
+        NearbySearchKey::new(&Location { lat, lng }, 500.0, "restaurant", "1", 1)
+    }
+
+    #[test]
+    fn nearby_points_share_a_grid_cell() {
+        assert_eq!(key(1.28061, 103.84431), key(1.28089, 103.84449));
+        assert_ne!(key(1.2806, 103.8443), key(1.2816, 103.8443));
+        let wider_radius = NearbySearchKey::new(&Location { lat: 1.2806, lng: 103.8443 }, 1000.0, "restaurant", "1", 1);
+        assert_ne!(key(1.2806, 103.8443), wider_radius);
+        let more_pages = NearbySearchKey::new(&Location { lat: 1.2806, lng: 103.8443 }, 500.0, "restaurant", "1", 3);
+        assert_ne!(key(1.2806, 103.8443), more_pages);
+    }
+
+    #[test]
+    fn hits_and_misses_are_counted() {
+        let cache = NearbySearchCache::new(Duration::from_secs(60), PAGE_TOKEN_LIFETIME, 10);
+        assert!(cache.get(&key(1.3, 103.8)).is_none());
+
+        cache.insert(key(1.3, 103.8), page("a"));
+        assert_eq!(cache.get(&key(1.3, 103.8)).unwrap().restaurants[0].place_id, "a");
+
+        let metrics = cache.metrics();
+        assert_eq!((metrics.hits, metrics.misses, metrics.entries), (1, 1, 1));
+        assert_eq!(metrics.hit_rate, 0.5);
+    }
+
+    #[test]
+    fn expired_entries_are_not_served() {
+        let cache = NearbySearchCache::new(Duration::ZERO, PAGE_TOKEN_LIFETIME, 10);
+        cache.insert(key(1.3, 103.8), page("a"));
+
+        assert!(cache.get(&key(1.3, 103.8)).is_none());
+        assert_eq!(cache.metrics().entries, 0);
+    }
+
+    #[test]
+    fn the_oldest_entry_makes_room_when_full() {
+        let cache = NearbySearchCache::new(Duration::from_secs(60), PAGE_TOKEN_LIFETIME, 2);
+        cache.insert(key(1.1, 103.8), page("a"));
+        cache.insert(key(1.2, 103.8), page("b"));
+        cache.insert(key(1.3, 103.8), page("c"));
+
+        assert!(cache.get(&key(1.1, 103.8)).is_none());
+        assert!(cache.get(&key(1.2, 103.8)).is_some());
+        assert!(cache.get(&key(1.3, 103.8)).is_some());
+        assert_eq!(cache.metrics().evictions, 1);
+    }
+
+    #[test]
+    fn page_tokens_are_served_while_google_still_takes_them() {
+        let with_token = NearbyPage { next_page_token: Some("page-2".to_string()), ..page("a") };
+        let cache = NearbySearchCache::new(Duration::from_secs(60), PAGE_TOKEN_LIFETIME, 10);
+        cache.insert(key(1.3, 103.8), with_token.clone());
+        assert_eq!(cache.get(&key(1.3, 103.8)).unwrap().next_page_token.as_deref(), Some("page-2"));
+
+        let cache = NearbySearchCache::new(Duration::from_secs(60), Duration::ZERO, 10);
+        cache.insert(key(1.3, 103.8), with_token);
+        let cached = cache.get(&key(1.3, 103.8)).unwrap();
+        assert_eq!((cached.restaurants.len(), cached.next_page_token), (1, None));
+    }
+
+    #[test]
+    fn zero_max_entries_disables_the_cache() {
+        let cache = NearbySearchCache::new(Duration::from_secs(60), PAGE_TOKEN_LIFETIME, 0);
+        cache.insert(key(1.3, 103.8), page("a"));
+        assert!(cache.get(&key(1.3, 103.8)).is_none());
+    }
+}
@@ -0,0 +1,20 @@
+use std::str::FromStr;
+use crate::helpers::app_error::{AppError, AppResult};
+
+/// A query parameter that may be left out. Anything given that does not parse or is not `valid` is rejected.
+pub fn optional_param<T: FromStr>(
+    name: &str,
+    value: Option<&str>,
+    valid: impl Fn(&T) -> bool,
+    expected: &str,
+) -> AppResult<Option<T>> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        None => Ok(None),
+        Some(value) => value
+            .parse::<T>()
+            .ok()
+            .filter(|parsed| valid(parsed))
+            .map(Some)
+            .ok_or_else(|| AppError::Validation(format!("{} must be {}, got: {}", name, expected, value))),
+    }
+}
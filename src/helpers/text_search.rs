@@ -0,0 +1,77 @@
+//! Restaurant search scoring for the in-memory repository. Postgres does the same with full-text search and
+//! pg_trgm, this mirrors it closely enough that results match while the exact scores differ a little.
+use std::collections::HashSet;
+
+/// Lowest trigram word similarity that still counts as a match, low enough to forgive a missing letter or
+/// two in a short word like "chikn".
+pub const WORD_SIMILARITY_THRESHOLD: f64 = 0.4;
+
+/// Lowercased alphanumeric words, the way the `simple` text search configuration splits text.
+pub fn words(
+    text: &str,
+) -> Vec<String> {
+    text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Trigrams of every word padded with two spaces in front and one behind, like pg_trgm.
+pub fn trigrams(
+    text: &str,
+) -> HashSet<String> {
+    words(text)
+        .iter()
+        .flat_map(|word| {
+            let padded: Vec<char> = format!("  {} ", word).chars().collect();
+            padded.windows(3).map(|window| window.iter().collect::<String>()).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Share of the query's trigrams found in the text. pg_trgm's `word_similarity` only looks at the best
+/// matching stretch of the text, so this is never lower than what postgres computes.
+pub fn word_similarity(
+    query: &str,
+    text: &str,
+) -> f64 {
+    let query_trigrams = trigrams(query);
+    if query_trigrams.is_empty() {
+        return 0.0;
+    }
+
+    let text_trigrams = trigrams(text);
+    query_trigrams.intersection(&text_trigrams).count() as f64 / query_trigrams.len() as f64
+}
+
+/// Every word of the query appears as a word of the text, what `websearch_to_tsquery` asks for.
+pub fn contains_all_words(
+    query: &str,
+    text: &str,
+) -> bool {
+    let query_words = words(query);
+    let text_words: HashSet<String> = words(text).into_iter().collect();
+    !query_words.is_empty() && query_words.iter().all(|word| text_words.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typos_still_share_most_trigrams() {
+        let similarity = word_similarity("chikn rice", "Tian Tian Hainanese Chicken Rice");
+        assert!(similarity >= WORD_SIMILARITY_THRESHOLD, "{}", similarity);
+        assert!(word_similarity("laksa", "Tian Tian Hainanese Chicken Rice") < WORD_SIMILARITY_THRESHOLD);
+        assert_eq!(word_similarity("%", "100% real"), 0.0);
+    }
+
+    #[test]
+    fn every_query_word_has_to_be_present() {
+        assert!(contains_all_words("Chicken  rice", "Tian Tian Hainanese Chicken Rice"));
+        assert!(!contains_all_words("chicken porridge", "Tian Tian Hainanese Chicken Rice"));
+        assert!(!contains_all_words("--", "Tian Tian Hainanese Chicken Rice"));
+    }
+}
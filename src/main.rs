@@ -1,17 +1,35 @@
+// Handlers deliberately `return match ...` on their final response for readability.
+#![allow(clippy::needless_return)]
+
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
+use anyhow::anyhow;
 use bb8_postgres::bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 use bb8_postgres::tokio_postgres::NoTls;
 use clap::Parser;
 use dotenv::dotenv;
-use crate::config::Config;
+use tracing::info;
+use crate::config::{Command, Config, RepositoryBackend};
+use crate::notifications::build_notifier;
+use crate::notifications::reminders::ReminderScheduler;
+use crate::providers::build_place_provider;
+use crate::repositories::in_memory_repo::InMemoryRepo;
+use crate::repositories::migrations::run_migrations;
+use crate::repositories::postgres_repo::PostgresConnectionRepo;
+use crate::repositories::Repository;
+use crate::storage::build_photo_storage;
 
 pub mod controller;
 pub mod helpers;
 pub mod models;
+pub mod notifications;
+pub mod providers;
 pub mod repositories;
 pub mod config;
+pub mod storage;
+pub mod voting;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -19,21 +37,70 @@ async fn main() -> anyhow::Result<()> {
     let config = Config::parse();
     tracing_subscriber::fmt::init();
 
+    if config.command == Some(Command::Migrate) {
+        if config.repository_backend != RepositoryBackend::Postgres {
+            return Err(anyhow!("Migrations only apply to the postgres repository backend"));
+        }
+        let pool = build_postgres_pool(&config).await?;
+        let applied_versions = run_migrations(&pool).await?;
+        info!("Applied {} migration(s): {:?}", applied_versions.len(), applied_versions);
+        return Ok(());
+    }
+
+    let repository: Arc<dyn Repository> = match config.repository_backend {
+        RepositoryBackend::Postgres => {
+            let pool = build_postgres_pool(&config).await?;
+            if config.run_migrations {
+                let applied_versions = run_migrations(&pool).await?;
+                info!("Applied {} migration(s) on startup: {:?}", applied_versions.len(), applied_versions);
+            }
+
+            Arc::new(PostgresConnectionRepo::new(pool))
+        }
+        RepositoryBackend::Memory => {
+            info!("Using the in-memory repository, nothing will be persisted");
+            Arc::new(InMemoryRepo::new())
+        }
+    };
+
+    let place_provider = build_place_provider(&config)?;
+    let photo_storage = build_photo_storage(&config)?;
+
+    if config.reminder_interval_secs > 0 {
+        let notifier = build_notifier(&config)?;
+        ReminderScheduler::new(repository.clone(), notifier, &config.reminder_offsets_minutes)?
+            .spawn(Duration::from_secs(config.reminder_interval_secs));
+    } else {
+        info!("Reservation reminders are turned off");
+    }
+
+    controller::serve(
+        repository,
+        place_provider,
+        photo_storage,
+        &config,
+    ).await?;
+
+    Ok(())
+}
+
+async fn build_postgres_pool(
+    config: &Config,
+) -> anyhow::Result<Pool<PostgresConnectionManager<NoTls>>> {
+    let postgres_url = config
+        .postgres_url
+        .as_ref()
+        .ok_or_else(|| anyhow!("POSTGRES_URL must be set when using the postgres repository backend"))?;
     let cores = num_cpus::get();
 
-    let postgres_config = bb8_postgres::tokio_postgres::config::Config::from_str(&config.postgres_url)?;
+    let postgres_config = bb8_postgres::tokio_postgres::config::Config::from_str(postgres_url)?;
     let postgres_manager = PostgresConnectionManager::new(postgres_config, NoTls);
 
-    let pool_build_result = Pool::builder()
+    let pool = Pool::builder()
         .connection_timeout(Duration::from_secs(15))
         .max_size((cores * 2) as u32)
         .build(postgres_manager)
         .await?;
 
-    controller::serve(
-        pool_build_result,
-        &config,
-    ).await?;
-
-    Ok(())
+    Ok(pool)
 }
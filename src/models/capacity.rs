@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use time::macros::format_description;
+use time::{Date, OffsetDateTime, Time, UtcOffset, Weekday};
+use crate::helpers::app_error::{AppError, AppResult};
+
+/// Singapore time, where the places are.
+pub const DEFAULT_UTC_OFFSET_MINUTES: i32 = 8 * 60;
+/// Longest a slot can be, a day.
+pub const MAX_SLOT_MINUTES: u32 = 24 * 60;
+
+/// How many people a place seats and when. Reservations start at the start of a slot, and the party sizes of
+/// the requested and confirmed reservations of a slot add up to at most `seats_per_slot`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlaceCapacity {
+    pub place_id: String,
+    pub seats_per_slot: u32,
+    pub slot_minutes: u32,
+    /// The times of `opening_hours` are local to this offset from UTC.
+    #[serde(default = "default_utc_offset_minutes")]
+    pub utc_offset_minutes: i32,
+    pub opening_hours: Vec<OpeningHours>,
+}
+
+fn default_utc_offset_minutes() -> i32 {
+    DEFAULT_UTC_OFFSET_MINUTES
+}
+
+/// One stretch a place is open on a day of the week, `opens` and `closes` as `HH:MM`. A place opening twice a
+/// day has two of them, `closes` can be `24:00` but not past midnight.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OpeningHours {
+    pub day: DayOfWeek,
+    pub opens: String,
+    pub closes: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DayOfWeek {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl From<Weekday> for DayOfWeek {
+    fn from(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Monday => DayOfWeek::Monday,
+            Weekday::Tuesday => DayOfWeek::Tuesday,
+            Weekday::Wednesday => DayOfWeek::Wednesday,
+            Weekday::Thursday => DayOfWeek::Thursday,
+            Weekday::Friday => DayOfWeek::Friday,
+            Weekday::Saturday => DayOfWeek::Saturday,
+            Weekday::Sunday => DayOfWeek::Sunday,
+        }
+    }
+}
+
+/// A slot of a day, as unix timestamps, with the seats that are not booked yet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Slot {
+    pub start: i64,
+    pub end: i64,
+    pub seats_left: u32,
+}
+
+/// The free slots of a place on a day for a party.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Availability {
+    pub place_id: String,
+    pub date: String,
+    pub pax: u32,
+    pub slots: Vec<Slot>,
+}
+
+/// Parses a `YYYY-MM-DD` date.
+pub fn parse_date(
+    date: &str,
+) -> AppResult<Date> {
+    Date::parse(date, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| AppError::Validation(format!("date must be formatted as YYYY-MM-DD, got: {}", date)))
+}
+
+/// Minutes since midnight of an `HH:MM` time, `24:00` included.
+fn minute_of_day(
+    time: &str,
+) -> AppResult<u32> {
+    let invalid = || AppError::Validation(format!("opening hours must be formatted as HH:MM, got: {}", time));
+    let (hours, minutes) = time.split_once(':').ok_or_else(invalid)?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return Err(invalid());
+    }
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    match hours * 60 + minutes {
+        minute if minutes < 60 && minute <= 24 * 60 => Ok(minute),
+        _ => Err(invalid()),
+    }
+}
+
+impl PlaceCapacity {
+    /// Rejects configurations without seats or with slots that cannot be laid out.
+    pub fn validate(&self) -> AppResult<()> {
+        if self.seats_per_slot == 0 {
+            return Err(AppError::Validation("seats_per_slot must be at least 1".to_string()));
+        }
+        if self.slot_minutes == 0 || self.slot_minutes > MAX_SLOT_MINUTES {
+            return Err(AppError::Validation(format!("slot_minutes must be between 1 and {}", MAX_SLOT_MINUTES)));
+        }
+        if self.utc_offset_minutes.abs() >= 24 * 60 {
+            return Err(AppError::Validation("utc_offset_minutes must be within a day".to_string()));
+        }
+        for hours in &self.opening_hours {
+            if minute_of_day(&hours.opens)? >= minute_of_day(&hours.closes)? {
+                return Err(AppError::Validation(format!("opening hours close before they open: {}-{}", hours.opens, hours.closes)));
+            }
+        }
+        Ok(())
+    }
+
+    fn offset(&self) -> UtcOffset {
+        UtcOffset::from_whole_seconds(self.utc_offset_minutes * 60).unwrap_or(UtcOffset::UTC)
+    }
+
+    /// Every slot of the local `date`, earliest first, with all of their seats left. Slots that would run
+    /// past closing time are left out.
+    pub fn slots_on(
+        &self,
+        date: Date,
+    ) -> Vec<Slot> {
+        let midnight = date.with_time(Time::MIDNIGHT).assume_offset(self.offset()).unix_timestamp();
+        let day = DayOfWeek::from(date.weekday());
+        let mut slots: Vec<Slot> = self.opening_hours
+            .iter()
+            .filter(|hours| hours.day == day)
+            .filter_map(|hours| Some((minute_of_day(&hours.opens).ok()?, minute_of_day(&hours.closes).ok()?)))
+            .flat_map(|(opens, closes)| {
+                (opens..closes)
+                    .step_by(self.slot_minutes.max(1) as usize)
+                    .filter(move |start| start + self.slot_minutes <= closes)
+            })
+            .map(|start| Slot {
+                start: midnight + i64::from(start) * 60,
+                end: midnight + i64::from(start + self.slot_minutes) * 60,
+                seats_left: self.seats_per_slot,
+            })
+            .collect();
+        slots.sort_by_key(|slot| slot.start);
+        slots.dedup_by_key(|slot| slot.start);
+        slots
+    }
+
+    /// The slots of the local `date` still to come with seats for `pax` people next to the `booked` seats,
+    /// by slot start.
+    pub fn free_slots(
+        &self,
+        date: Date,
+        pax: u32,
+        booked: &HashMap<i64, u32>,
+        now: i64,
+    ) -> Vec<Slot> {
+        self.slots_on(date)
+            .into_iter()
+            .filter(|slot| slot.start > now)
+            .map(|slot| Slot {
+                seats_left: slot.seats_left.saturating_sub(booked.get(&slot.start).copied().unwrap_or_default()),
+                ..slot
+            })
+            .filter(|slot| slot.seats_left >= pax)
+            .collect()
+    }
+
+    /// The slot a reservation at `timestamp` takes, which has to start right then.
+    pub fn slot_at(
+        &self,
+        timestamp: i64,
+    ) -> AppResult<Slot> {
+        OffsetDateTime::from_unix_timestamp(timestamp)
+            .ok()
+            .map(|time| time.to_offset(self.offset()).date())
+            .and_then(|date| self.slots_on(date).into_iter().find(|slot| slot.start == timestamp))
+            .ok_or_else(|| AppError::Validation(format!(
+                "{} only takes reservations at the start of a slot while it is open",
+                self.place_id
+            )))
+    }
+
+    /// Conflict when `pax` more people do not fit next to the `booked` seats of the slot at `timestamp`.
+    pub fn check_booking(
+        &self,
+        timestamp: i64,
+        pax: u32,
+        booked: u32,
+    ) -> AppResult<()> {
+        self.slot_at(timestamp)?;
+        let seats_left = self.seats_per_slot.saturating_sub(booked);
+        if pax > seats_left {
+            return Err(AppError::Conflict(format!("Only {} seats are left at that time", seats_left)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+    use super::*;
+
+    fn capacity() -> PlaceCapacity {
+        PlaceCapacity {
+            place_id: "maxwell-tian-tian".to_string(),
+            seats_per_slot: 10,
+            slot_minutes: 90,
+            utc_offset_minutes: DEFAULT_UTC_OFFSET_MINUTES,
+            opening_hours: vec![
+                OpeningHours { day: DayOfWeek::Tuesday, opens: "11:00".to_string(), closes: "14:30".to_string() },
+                OpeningHours { day: DayOfWeek::Tuesday, opens: "18:00".to_string(), closes: "20:00".to_string() },
+            ],
+        }
+    }
+
+    #[test]
+    fn slots_fit_inside_the_opening_hours() {
+        // 2026-10-20 is a tuesday, 11:00 in Singapore is 03:00 UTC
+        let slots = capacity().slots_on(date!(2026 - 10 - 20));
+        let eleven = date!(2026 - 10 - 20).with_time(time::macros::time!(3:00)).assume_utc().unix_timestamp();
+        let starts: Vec<i64> = slots.iter().map(|slot| (slot.start - eleven) / 60).collect();
+        assert_eq!(starts, [0, 90, 7 * 60]);
+        assert_eq!(slots[0].end - slots[0].start, 90 * 60);
+        assert!(capacity().slots_on(date!(2026 - 10 - 21)).is_empty());
+
+        assert!(capacity().check_booking(eleven + 90 * 60, 10, 0).is_ok());
+        assert_eq!(capacity().check_booking(eleven + 60 * 60, 2, 0).unwrap_err().code(), "validation_failed");
+        assert_eq!(capacity().check_booking(eleven, 3, 8).unwrap_err().code(), "conflict");
+
+        let booked = HashMap::from([(eleven, 8), (eleven + 90 * 60, 6)]);
+        let free = capacity().free_slots(date!(2026 - 10 - 20), 4, &booked, eleven);
+        assert_eq!(free.iter().map(|slot| slot.seats_left).collect::<Vec<_>>(), [4, 10]);
+    }
+
+    #[test]
+    fn capacities_are_validated() {
+        assert!(capacity().validate().is_ok());
+        let mut invalid = capacity();
+        invalid.opening_hours[0].closes = "10:00".to_string();
+        assert!(invalid.validate().is_err());
+        for time in ["24:01", "9:00", "12:60", "noon"] {
+            invalid.opening_hours[0].closes = time.to_string();
+            assert!(invalid.validate().is_err(), "{}", time);
+        }
+        let invalid = PlaceCapacity { seats_per_slot: 0, ..capacity() };
+        assert!(invalid.validate().is_err());
+        let invalid = PlaceCapacity { slot_minutes: 0, ..capacity() };
+        assert!(invalid.validate().is_err());
+        assert_eq!(parse_date("2026-10-20").unwrap(), date!(2026 - 10 - 20));
+        assert!(parse_date("20/10/2026").is_err());
+    }
+}
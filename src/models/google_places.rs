@@ -0,0 +1,315 @@
+//! Typed views of the Google Places responses we consume. Only the fields the app uses are modelled,
+//! everything Google does not promise to send is optional so an unusual result cannot fail the request.
+use serde::{Deserialize, Serialize};
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::models::restaurant::{Location, Photo, Restaurant};
+
+/// The `status` field of every Places response.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PlacesStatus {
+    Ok,
+    ZeroResults,
+    OverQueryLimit,
+    RequestDenied,
+    InvalidRequest,
+    NotFound,
+    UnknownError,
+    #[serde(other)]
+    Unrecognised,
+}
+
+impl PlacesStatus {
+    /// Turns a failed status into the matching `AppError`. `ZERO_RESULTS` is a successful empty answer.
+    pub fn ensure_ok(
+        &self,
+        error_message: Option<&str>,
+    ) -> AppResult<()> {
+        let detail = error_message.unwrap_or("no error message");
+        match self {
+            PlacesStatus::Ok | PlacesStatus::ZeroResults => Ok(()),
+            PlacesStatus::OverQueryLimit => Err(AppError::Unavailable(
+                "Google Places quota is used up (OVER_QUERY_LIMIT), please try again later".to_string(),
+            )),
+            PlacesStatus::InvalidRequest => Err(AppError::Validation(format!(
+                "Google Places rejected the request (INVALID_REQUEST): {}",
+                detail,
+            ))),
+            PlacesStatus::NotFound => Err(AppError::NotFound(
+                "Google Places has no place matching the request (NOT_FOUND)".to_string(),
+            )),
+            PlacesStatus::RequestDenied | PlacesStatus::UnknownError | PlacesStatus::Unrecognised => {
+                Err(AppError::Upstream(format!("Google Places returned {:?}: {}", self, detail)))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlacePhoto {
+    pub height: i64,
+    pub width: i64,
+    pub photo_reference: String,
+    #[serde(default)]
+    pub html_attributions: Vec<String>,
+}
+
+impl From<PlacePhoto> for Photo {
+    fn from(photo: PlacePhoto) -> Self {
+        Photo {
+            height: photo.height,
+            photo_reference: photo.photo_reference,
+            width: photo.width,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlaceGeometry {
+    pub location: Location,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OpeningHours {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_now: Option<bool>,
+    #[serde(default)]
+    pub weekday_text: Vec<String>,
+}
+
+/// One entry of a nearby search.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlaceResult {
+    pub place_id: String,
+    pub name: String,
+    pub geometry: PlaceGeometry,
+    #[serde(default)]
+    pub vicinity: String,
+    #[serde(default)]
+    pub photos: Vec<PlacePhoto>,
+    #[serde(default)]
+    pub rating: Option<f64>,
+    #[serde(default)]
+    pub user_ratings_total: Option<u32>,
+    #[serde(default)]
+    pub price_level: Option<u8>,
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+impl From<PlaceResult> for Restaurant {
+    fn from(place: PlaceResult) -> Self {
+        Restaurant {
+            place_id: place.place_id,
+            name: place.name,
+            photos: place.photos.into_iter().next().map(Photo::from),
+            rating: place.rating,
+            price_level: place.price_level,
+            vicinity: place.vicinity,
+            geometry: place.geometry.location,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NearbySearchResponse {
+    pub status: PlacesStatus,
+    #[serde(default)]
+    pub results: Vec<PlaceResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+}
+
+impl NearbySearchResponse {
+    pub fn into_restaurants(self) -> AppResult<Vec<Restaurant>> {
+        self.into_page().map(|page| page.restaurants)
+    }
+
+    pub fn into_page(self) -> AppResult<NearbyPage> {
+        self.status.ensure_ok(self.error_message.as_deref())?;
+        Ok(NearbyPage {
+            restaurants: self.results.into_iter().map(Restaurant::from).collect(),
+            next_page_token: self.next_page_token,
+        })
+    }
+}
+
+/// One or more pages of nearby results, with the cursor for the page after the last one.
+#[derive(Clone, Debug, Default)]
+pub struct NearbyPage {
+    pub restaurants: Vec<Restaurant>,
+    pub next_page_token: Option<String>,
+}
+
+impl NearbyPage {
+    /// Appends a later page, dropping places an earlier page already returned.
+    pub fn append(
+        &mut self,
+        page: NearbyPage,
+    ) {
+        for restaurant in page.restaurants {
+            if !self.restaurants.iter().any(|seen| seen.place_id == restaurant.place_id) {
+                self.restaurants.push(restaurant);
+            }
+        }
+        self.next_page_token = page.next_page_token;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlaceDetails {
+    pub place_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatted_address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatted_phone_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub international_phone_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vicinity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<PlaceGeometry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_ratings_total: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_level: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opening_hours: Option<OpeningHours>,
+    #[serde(default)]
+    pub photos: Vec<PlacePhoto>,
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlaceDetailsResponse {
+    pub status: PlacesStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<PlaceDetails>,
+    #[serde(default)]
+    pub html_attributions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+}
+
+impl PlaceDetailsResponse {
+    pub fn into_details(self) -> AppResult<PlaceDetails> {
+        self.status.ensure_ok(self.error_message.as_deref())?;
+        self.result
+            .ok_or_else(|| AppError::NotFound("Google Places returned no details for the place".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEARBY_SEARCH_OK: &str = include_str!("../../tests/fixtures/google_places/nearby_search_ok.json");
+    const NEARBY_SEARCH_ZERO_RESULTS: &str =
+        include_str!("../../tests/fixtures/google_places/nearby_search_zero_results.json");
+    const NEARBY_SEARCH_OVER_QUERY_LIMIT: &str =
+        include_str!("../../tests/fixtures/google_places/nearby_search_over_query_limit.json");
+    const NEARBY_SEARCH_REQUEST_DENIED: &str =
+        include_str!("../../tests/fixtures/google_places/nearby_search_request_denied.json");
+    const PLACE_DETAILS_OK: &str = include_str!("../../tests/fixtures/google_places/place_details_ok.json");
+    const PLACE_DETAILS_NOT_FOUND: &str =
+        include_str!("../../tests/fixtures/google_places/place_details_not_found.json");
+
+    fn nearby(fixture: &str) -> NearbySearchResponse {
+        serde_json::from_str(fixture).unwrap()
+    }
+
+    #[test]
+    fn nearby_results_tolerate_missing_photos_and_rating() {
+        let restaurants = nearby(NEARBY_SEARCH_OK).into_restaurants().unwrap();
+        assert_eq!(restaurants.len(), 3);
+
+        let rated = &restaurants[0];
+        assert_eq!(rated.name, "Tian Tian Hainanese Chicken Rice");
+        assert_eq!(rated.rating, Some(4.1));
+        assert_eq!(rated.photos.as_ref().unwrap().width, 4032);
+        assert_eq!(rated.geometry.lat, 1.2803565);
+
+        let without_photos = &restaurants[1];
+        assert!(without_photos.photos.is_none());
+        assert_eq!(without_photos.rating, Some(4.3));
+
+        let unrated = &restaurants[2];
+        assert!(unrated.rating.is_none());
+        assert!(unrated.photos.is_some());
+    }
+
+    #[test]
+    fn nearby_keeps_the_next_page_token() {
+        let response = nearby(NEARBY_SEARCH_OK);
+        assert_eq!(response.status, PlacesStatus::Ok);
+        assert!(response.next_page_token.unwrap().starts_with("AcJnMuE"));
+    }
+
+    #[test]
+    fn appended_pages_drop_repeated_places() {
+        let mut aggregated = nearby(NEARBY_SEARCH_OK).into_page().unwrap();
+        let mut second_page = nearby(NEARBY_SEARCH_OK).into_page().unwrap();
+        second_page.restaurants.truncate(1);
+        second_page.restaurants[0].place_id = "a-new-place".to_string();
+        second_page.restaurants.push(aggregated.restaurants[1].clone());
+        second_page.next_page_token = None;
+
+        aggregated.append(second_page);
+        assert_eq!(aggregated.restaurants.len(), 4);
+        assert_eq!(aggregated.restaurants[3].place_id, "a-new-place");
+        assert!(aggregated.next_page_token.is_none());
+    }
+
+    #[test]
+    fn zero_results_is_an_empty_success() {
+        let response = nearby(NEARBY_SEARCH_ZERO_RESULTS);
+        assert_eq!(response.status, PlacesStatus::ZeroResults);
+        assert!(response.into_restaurants().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_statuses_map_onto_app_errors() {
+        let error = nearby(NEARBY_SEARCH_OVER_QUERY_LIMIT).into_restaurants().unwrap_err();
+        assert_eq!(error.code(), "service_unavailable");
+        assert!(error.public_message().contains("OVER_QUERY_LIMIT"));
+
+        let error = nearby(NEARBY_SEARCH_REQUEST_DENIED).into_restaurants().unwrap_err();
+        assert_eq!(error.code(), "upstream_failure");
+        assert!(error.to_string().contains("The provided API key is invalid."));
+    }
+
+    #[test]
+    fn unknown_statuses_do_not_fail_parsing() {
+        let response = nearby(r#"{"status": "SOMETHING_NEW", "results": []}"#);
+        assert_eq!(response.status, PlacesStatus::Unrecognised);
+        assert_eq!(response.into_restaurants().unwrap_err().code(), "upstream_failure");
+    }
+
+    #[test]
+    fn place_details_parse_into_typed_fields() {
+        let response: PlaceDetailsResponse = serde_json::from_str(PLACE_DETAILS_OK).unwrap();
+        let details = response.into_details().unwrap();
+
+        assert_eq!(details.place_id, "ChIJ6ZbH8w0Z2jERWzLnJm7YnfY");
+        assert_eq!(details.formatted_phone_number.as_deref(), Some("9691 4852"));
+        assert!(details.website.is_none());
+        assert_eq!(details.opening_hours.unwrap().weekday_text.len(), 7);
+        assert_eq!(details.photos.len(), 2);
+    }
+
+    #[test]
+    fn missing_place_details_are_not_found() {
+        let response: PlaceDetailsResponse = serde_json::from_str(PLACE_DETAILS_NOT_FOUND).unwrap();
+        assert_eq!(response.into_details().unwrap_err().code(), "not_found");
+    }
+}
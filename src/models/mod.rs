@@ -1,5 +1,11 @@
+pub mod capacity;
+pub mod google_places;
+pub mod moderation;
+pub mod page;
 pub mod rating;
 pub mod reservation;
 pub mod restaurant;
 pub mod restaurant_image;
+pub mod review_photo;
+pub mod user;
 pub mod vote;
\ No newline at end of file
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+use crate::models::rating::RestaurantRating;
+
+/// A reason to look at a review, filed by a user or by the banned term filter. Reports stay open until a
+/// moderator approves or hides the review.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReviewReport {
+    pub id: i64,
+    /// The author and place of the reported review.
+    pub user_id: String,
+    pub place_id: String,
+    /// Unset when the banned term filter held the review back.
+    pub reporter_id: Option<String>,
+    pub reason: String,
+    pub created_at: i64,
+    #[serde(default)]
+    pub resolved_at: Option<i64>,
+}
+
+/// An entry of the moderation queue, the report with the review as it reads now.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReportedReview {
+    #[serde(flatten)]
+    pub report: ReviewReport,
+    pub review: RestaurantRating,
+}
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::params::optional_param;
+
+/// Items handed back when a list request names no `limit`, and the most one page can hold.
+pub const DEFAULT_PAGE_LIMIT: i64 = 20;
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// One page of a list endpoint. Pass `next_cursor` back as `cursor` for the page after it, it is absent
+/// on the last page.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from items fetched in list order, each with its cursor. Repositories fetch one item
+    /// more than the limit, that extra item only tells whether another page follows.
+    pub fn from_overfetched(
+        items: Vec<(Cursor, T)>,
+        limit: i64,
+    ) -> Self {
+        let limit = usize::try_from(limit).unwrap_or(0);
+        let has_more = items.len() > limit;
+        let items: Vec<(Cursor, T)> = items.into_iter().take(limit).collect();
+        let next_cursor = match items.last() {
+            Some((cursor, _)) if has_more => Some(cursor.encode()),
+            _ => None,
+        };
+
+        Page {
+            items: items.into_iter().map(|(_, item)| item).collect(),
+            next_cursor,
+        }
+    }
+
+    pub fn map<U>(
+        self,
+        f: impl FnMut(T) -> U,
+    ) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+/// Position of an item in a list ordered by timestamp. `key` tells apart items with the same timestamp,
+/// it is whatever the list orders by next, like the place id of a bookmark.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub timestamp: i64,
+    pub key: String,
+}
+
+impl Cursor {
+    pub fn new(
+        timestamp: i64,
+        key: impl Into<String>,
+    ) -> Self {
+        Cursor {
+            timestamp,
+            key: key.into(),
+        }
+    }
+
+    /// Hex keeps the cursor opaque to clients and safe to put in a query string as is.
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.timestamp, self.key)
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decode(
+        cursor: &str,
+    ) -> AppResult<Self> {
+        let invalid = || AppError::Validation(format!("cursor is not one handed out by this API: {}", cursor));
+        if !cursor.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|index| cursor.get(index..index + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (timestamp, key) = decoded.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Cursor {
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            key: key.to_string(),
+        })
+    }
+
+    /// The key as a number, for lists whose tie breaker is a numeric id.
+    pub fn numeric_key(&self) -> AppResult<i64> {
+        self.key
+            .parse()
+            .map_err(|_| AppError::Validation("cursor does not belong to this list".to_string()))
+    }
+
+    /// Where `(timestamp, key)` sorts relative to this cursor, for repositories that order in memory.
+    pub fn cmp_position(
+        &self,
+        timestamp: i64,
+        key: &str,
+    ) -> std::cmp::Ordering {
+        (timestamp, key).cmp(&(self.timestamp, self.key.as_str()))
+    }
+}
+
+/// Which page of a list to fetch.
+#[derive(Clone, Debug)]
+pub struct PageRequest {
+    /// Continue after this item, start from the top when unset.
+    pub after: Option<Cursor>,
+    pub limit: i64,
+}
+
+impl PageRequest {
+    pub fn first(
+        limit: i64,
+    ) -> Self {
+        PageRequest {
+            after: None,
+            limit,
+        }
+    }
+
+    /// The number of rows to ask the database for, see `Page::from_overfetched`.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        PageRequest::first(DEFAULT_PAGE_LIMIT)
+    }
+}
+
+/// The `cursor` and `limit` query parameters every list endpoint takes.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct PageParams {
+    pub cursor: Option<String>,
+    pub limit: Option<String>,
+}
+
+impl PageParams {
+    pub fn to_request(&self) -> AppResult<PageRequest> {
+        let after = match self.cursor.as_deref().map(str::trim).filter(|cursor| !cursor.is_empty()) {
+            Some(cursor) => Some(Cursor::decode(cursor)?),
+            None => None,
+        };
+
+        Ok(PageRequest {
+            after,
+            limit: parse_limit(self.limit.as_deref())?,
+        })
+    }
+}
+
+pub fn parse_limit(
+    limit: Option<&str>,
+) -> AppResult<i64> {
+    let limit = optional_param(
+        "limit",
+        limit,
+        |limit| (1..=MAX_PAGE_LIMIT).contains(limit),
+        &format!("between 1 and {}", MAX_PAGE_LIMIT),
+    )?;
+    Ok(limit.unwrap_or(DEFAULT_PAGE_LIMIT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_survive_a_round_trip() {
+        let cursor = Cursor::new(1_700_000_000, "O'Brien's: 鸡饭 & more");
+        let encoded = cursor.encode();
+        assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+
+        for invalid in ["abc", "zz", "31", "not a cursor"] {
+            assert_eq!(Cursor::decode(invalid).unwrap_err().code(), "validation_failed", "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn the_extra_item_only_produces_a_cursor() {
+        let items: Vec<(Cursor, &str)> = ["a", "b", "c"]
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| (Cursor::new(index as i64, item), item))
+            .collect();
+
+        let page = Page::from_overfetched(items.clone(), 2);
+        assert_eq!(page.items, ["a", "b"]);
+        assert_eq!(Cursor::decode(&page.next_cursor.unwrap()).unwrap(), Cursor::new(1, "b"));
+
+        let last_page = Page::from_overfetched(items, 3);
+        assert_eq!(last_page.items.len(), 3);
+        assert!(last_page.next_cursor.is_none());
+    }
+}
@@ -1,4 +1,14 @@
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::models::page::{Cursor, Page};
+use crate::models::review_photo::ReviewPhoto;
+
+/// Lowest and highest rating a review can give, in steps of half a star.
+pub const MIN_RATING: f64 = 0.5;
+pub const MAX_RATING: f64 = 5.0;
+/// Reviews written within this many seconds count towards the recent average, thirty days.
+pub const RECENT_RATING_WINDOW_SECS: i64 = 30 * 24 * 60 * 60;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RestaurantRating {
@@ -7,4 +17,303 @@ pub struct RestaurantRating {
     pub rating: f64,
     pub description: String,
     pub timestamp: i64,
-}
\ No newline at end of file
+    /// Set while a removed review can still be restored, removed reviews are left out of lists by default.
+    #[serde(default)]
+    pub deleted_at: Option<i64>,
+    #[serde(default)]
+    pub status: ReviewStatus,
+    /// Votes of other users on whether the review helped them.
+    #[serde(default)]
+    pub helpful_count: i64,
+    #[serde(default)]
+    pub unhelpful_count: i64,
+    /// In the order they were uploaded.
+    #[serde(default)]
+    pub photos: Vec<ReviewPhoto>,
+}
+
+/// Whether a review is shown to everyone. Only its author sees it while it waits for a moderator or once
+/// it was hidden.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewStatus {
+    #[default]
+    Published,
+    /// Held back by the banned term filter or taken down after enough reports.
+    Pending,
+    Hidden,
+}
+
+impl ReviewStatus {
+    /// The value of the `status` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewStatus::Published => "published",
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Hidden => "hidden",
+        }
+    }
+
+    /// The check constraint on the column keeps out anything else, a status we do not know stays hidden.
+    pub fn from_column(
+        status: &str,
+    ) -> Self {
+        match status {
+            "published" => ReviewStatus::Published,
+            "pending" => ReviewStatus::Pending,
+            _ => ReviewStatus::Hidden,
+        }
+    }
+}
+
+/// Orders of the reviews of a place. Ties go to the newer review.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewSort {
+    #[default]
+    Newest,
+    Highest,
+    Lowest,
+    MostHelpful,
+}
+
+impl FromStr for ReviewSort {
+    type Err = AppError;
+
+    fn from_str(
+        sort: &str,
+    ) -> Result<Self, Self::Err> {
+        match sort {
+            "newest" => Ok(ReviewSort::Newest),
+            "highest" => Ok(ReviewSort::Highest),
+            "lowest" => Ok(ReviewSort::Lowest),
+            "most_helpful" => Ok(ReviewSort::MostHelpful),
+            sort => Err(AppError::Validation(format!("Unknown review sort: {}", sort))),
+        }
+    }
+}
+
+/// Where a review sits in a sorted list of reviews, read back from a cursor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReviewPosition {
+    pub sort_value: i64,
+    pub timestamp: i64,
+    pub user_id: String,
+}
+
+impl ReviewSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewSort::Newest => "newest",
+            ReviewSort::Highest => "highest",
+            ReviewSort::Lowest => "lowest",
+            ReviewSort::MostHelpful => "most_helpful",
+        }
+    }
+
+    /// What the list is ordered by first, descending. Ratings count in half stars and lowest first is
+    /// highest first negated, so every order reads `(sort_value, timestamp, user_id)` from the top.
+    pub fn sort_value(
+        &self,
+        review: &RestaurantRating,
+    ) -> i64 {
+        match self {
+            ReviewSort::Newest => review.timestamp,
+            ReviewSort::Highest => (review.rating * 2.0).round() as i64,
+            ReviewSort::Lowest => -(review.rating * 2.0).round() as i64,
+            ReviewSort::MostHelpful => review.helpful_count,
+        }
+    }
+
+    /// The cursor names its sort so it cannot be used to continue a list in another order.
+    pub fn cursor(
+        &self,
+        review: &RestaurantRating,
+    ) -> Cursor {
+        Cursor::new(self.sort_value(review), format!("{}:{}:{}", self.as_str(), review.timestamp, review.user_id))
+    }
+
+    pub fn position(
+        &self,
+        cursor: &Cursor,
+    ) -> AppResult<ReviewPosition> {
+        let invalid = || AppError::Validation("cursor does not belong to this list".to_string());
+        let (sort, rest) = cursor.key.split_once(':').ok_or_else(invalid)?;
+        let (timestamp, user_id) = rest.split_once(':').ok_or_else(invalid)?;
+        if sort != self.as_str() {
+            return Err(invalid());
+        }
+
+        Ok(ReviewPosition {
+            sort_value: cursor.timestamp,
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            user_id: user_id.to_string(),
+        })
+    }
+}
+
+/// One version of a review, every write keeps the one before it. Revisions are numbered from 1.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReviewRevision {
+    pub user_id: String,
+    pub place_id: String,
+    pub revision: i64,
+    pub rating: f64,
+    pub description: String,
+    pub timestamp: i64,
+}
+
+/// What our own users think of a place, kept apart from the google `rating` on the place itself.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RatingSummary {
+    /// Unset until the place has its first review.
+    pub average: Option<f64>,
+    pub count: i64,
+    /// Reviews per star, one star first. Ratings are rounded to the nearest star, half stars up.
+    pub histogram: [i64; 5],
+    /// Average of the reviews written in the last thirty days, unset when there are none.
+    pub recent_average: Option<f64>,
+    pub recent_count: i64,
+    /// How far the recent average sits above the overall one, negative when a place is slipping.
+    pub trend: Option<f64>,
+}
+
+/// A page of the reviews of a place together with the summary of all of them.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RestaurantReviews {
+    #[serde(flatten)]
+    pub page: Page<RestaurantRating>,
+    pub summary: RatingSummary,
+}
+
+impl RatingSummary {
+    /// Builds the summary from running totals, which is how postgres keeps them.
+    pub fn from_totals(
+        count: i64,
+        total: f64,
+        histogram: [i64; 5],
+        recent_count: i64,
+        recent_total: f64,
+    ) -> Self {
+        let average = (count > 0).then(|| total / count as f64);
+        let recent_average = (recent_count > 0).then(|| recent_total / recent_count as f64);
+        let trend = match (recent_average, average) {
+            (Some(recent_average), Some(average)) => Some(recent_average - average),
+            _ => None,
+        };
+
+        RatingSummary {
+            average,
+            count,
+            histogram,
+            recent_average,
+            recent_count,
+            trend,
+        }
+    }
+
+    /// Summarises `reviews` as of `now`.
+    pub fn from_reviews<'a>(
+        reviews: impl IntoIterator<Item = &'a RestaurantRating>,
+        now: i64,
+    ) -> Self {
+        let (mut count, mut total, mut histogram) = (0, 0.0, [0; 5]);
+        let (mut recent_count, mut recent_total) = (0, 0.0);
+        for review in reviews {
+            count += 1;
+            total += review.rating;
+            histogram[star_index(review.rating)] += 1;
+            if review.timestamp > now - RECENT_RATING_WINDOW_SECS {
+                recent_count += 1;
+                recent_total += review.rating;
+            }
+        }
+        Self::from_totals(count, total, histogram, recent_count, recent_total)
+    }
+}
+
+/// Ratings go from half a star to five stars in half star steps.
+pub fn validate_rating(
+    rating: f64,
+) -> AppResult<()> {
+    if (MIN_RATING..=MAX_RATING).contains(&rating) && (rating * 2.0).fract() == 0.0 {
+        return Ok(());
+    }
+    Err(AppError::Validation(format!(
+        "rating must be between {} and {} in steps of 0.5, got: {}",
+        MIN_RATING,
+        MAX_RATING,
+        rating,
+    )))
+}
+
+/// Histogram slot of a rating, the same rounding as `star` in the place_rating_summaries migration.
+pub fn star_index(
+    rating: f64,
+) -> usize {
+    ((rating + 0.5).floor().clamp(1.0, 5.0) as usize) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(rating: f64, timestamp: i64) -> RestaurantRating {
+        RestaurantRating {
+            user_id: format!("user-{}-{}", rating, timestamp),
+            place_id: "maxwell-tian-tian".to_string(),
+            rating,
+            description: String::new(),
+            timestamp,
+            deleted_at: None,
+            status: ReviewStatus::Published,
+            helpful_count: 0,
+            unhelpful_count: 0,
+            photos: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn half_stars_round_up_into_the_histogram() {
+        assert_eq!([0.0, 0.5, 1.4, 2.5, 4.49, 4.5, 5.0].map(star_index), [0, 0, 0, 2, 3, 4, 4]);
+    }
+
+    #[test]
+    fn ratings_come_in_half_stars() {
+        for rating in [0.5, 1.0, 3.5, 5.0] {
+            assert!(validate_rating(rating).is_ok(), "{}", rating);
+        }
+        for rating in [0.0, -1.0, 4.2, 5.5, f64::NAN, f64::INFINITY] {
+            assert_eq!(validate_rating(rating).unwrap_err().code(), "validation_failed", "{}", rating);
+        }
+    }
+
+    #[test]
+    fn review_cursors_keep_to_their_sort() {
+        let mut review = review(3.5, 1_700_000_000);
+        review.user_id = "user:with:colons".to_string();
+        assert_eq!(ReviewSort::Lowest.sort_value(&review), -7);
+
+        let cursor = Cursor::decode(&ReviewSort::Highest.cursor(&review).encode()).unwrap();
+        let position = ReviewSort::Highest.position(&cursor).unwrap();
+        assert_eq!(position, ReviewPosition { sort_value: 7, timestamp: 1_700_000_000, user_id: review.user_id.clone() });
+        assert_eq!(ReviewSort::Newest.position(&cursor).unwrap_err().code(), "validation_failed");
+        assert_eq!(ReviewSort::Newest.position(&Cursor::new(1, "bob")).unwrap_err().code(), "validation_failed");
+    }
+
+    #[test]
+    fn recent_reviews_show_the_trend() {
+        let now = 1_700_000_000;
+        let reviews = [review(5.0, now - RECENT_RATING_WINDOW_SECS - 1), review(5.0, now - 100), review(2.0, now - 10)];
+
+        let summary = RatingSummary::from_reviews(&reviews, now);
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.average, Some(4.0));
+        assert_eq!(summary.histogram, [0, 1, 0, 0, 2]);
+        assert_eq!(summary.recent_count, 2);
+        assert_eq!(summary.recent_average, Some(3.5));
+        assert_eq!(summary.trend, Some(-0.5));
+
+        assert_eq!(RatingSummary::from_reviews(&[], now), RatingSummary::default());
+    }
+}
@@ -1,9 +1,191 @@
 use serde::{Deserialize, Serialize};
+use crate::helpers::app_error::{AppError, AppResult};
+
+/// Codes of the rules a reservation has to follow, clients get them back as the `code` of a 422.
+pub const RESERVATION_IN_PAST: &str = "reservation_in_past";
+pub const RESERVATION_TOO_FAR_AHEAD: &str = "reservation_too_far_ahead";
+pub const RESERVATION_PAX_OUT_OF_RANGE: &str = "reservation_pax_out_of_range";
+pub const RESERVATION_UNKNOWN_PLACE: &str = "reservation_unknown_place";
+pub const RESERVATION_OVERLAPS: &str = "reservation_overlaps";
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Reservation {
+    /// Generated by the server when the reservation is made.
+    pub reservation_id: String,
     pub user_id: String,
     pub place_id: String,
     pub reservation_timestamp: i64,
     pub reservation_pax: u32,
-}
\ No newline at end of file
+    pub status: ReservationStatus,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Where a reservation is in its life. New and changed reservations are requested until they are confirmed,
+/// and cancelled, completed and no show reservations stay that way.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationStatus {
+    Requested,
+    Confirmed,
+    Cancelled,
+    Completed,
+    NoShow,
+}
+
+impl ReservationStatus {
+    /// The value of the `status` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReservationStatus::Requested => "requested",
+            ReservationStatus::Confirmed => "confirmed",
+            ReservationStatus::Cancelled => "cancelled",
+            ReservationStatus::Completed => "completed",
+            ReservationStatus::NoShow => "no_show",
+        }
+    }
+
+    /// The check constraint on the column keeps out anything else, a status we do not know is not kept.
+    pub fn from_column(
+        status: &str,
+    ) -> Self {
+        match status {
+            "requested" => ReservationStatus::Requested,
+            "confirmed" => ReservationStatus::Confirmed,
+            "completed" => ReservationStatus::Completed,
+            "no_show" => ReservationStatus::NoShow,
+            _ => ReservationStatus::Cancelled,
+        }
+    }
+
+    /// Requested and confirmed reservations are still going to happen, and can be changed or cancelled.
+    pub fn is_active(&self) -> bool {
+        matches!(self, ReservationStatus::Requested | ReservationStatus::Confirmed)
+    }
+
+    /// The status of the reservation once its time or party size changed, which needs confirming again.
+    pub fn after_change(self) -> AppResult<ReservationStatus> {
+        if !self.is_active() {
+            return Err(AppError::Conflict(format!("A {} reservation cannot be changed", self.as_str())));
+        }
+        Ok(ReservationStatus::Requested)
+    }
+
+    /// Conflict unless a reservation in this status can be moved to `next`. Only a confirmed reservation
+    /// can be completed or missed.
+    pub fn transition_to(
+        self,
+        next: ReservationStatus,
+    ) -> AppResult<ReservationStatus> {
+        let allowed = match next {
+            ReservationStatus::Requested => false,
+            ReservationStatus::Confirmed => self == ReservationStatus::Requested,
+            ReservationStatus::Cancelled => self.is_active(),
+            ReservationStatus::Completed | ReservationStatus::NoShow => self == ReservationStatus::Confirmed,
+        };
+        if !allowed {
+            return Err(AppError::Conflict(format!(
+                "A {} reservation cannot become {}",
+                self.as_str(),
+                next.as_str()
+            )));
+        }
+        Ok(next)
+    }
+}
+
+/// Limits on the time and party size of reservations, from the `RESERVATION_*` settings. Whether the place
+/// exists and whether the user has another reservation close by are up to the repository.
+#[derive(Clone, Copy, Debug)]
+pub struct ReservationRules {
+    pub min_pax: u32,
+    pub max_pax: u32,
+    pub max_days_ahead: i64,
+    /// Reservations of a user starting less than this far apart overlap, 0 allows any.
+    pub overlap_window_secs: i64,
+}
+
+impl ReservationRules {
+    /// The party size once the reservation passed.
+    pub fn check(
+        &self,
+        reservation_timestamp: i64,
+        reservation_pax: i64,
+        now: i64,
+    ) -> AppResult<u32> {
+        let reservation_pax = u32::try_from(reservation_pax)
+            .ok()
+            .filter(|pax| (self.min_pax..=self.max_pax).contains(pax))
+            .ok_or_else(|| AppError::Rejected(RESERVATION_PAX_OUT_OF_RANGE, format!(
+                "Reservations are for {} to {} people, got: {}",
+                self.min_pax,
+                self.max_pax,
+                reservation_pax
+            )))?;
+        if reservation_timestamp <= now {
+            return Err(AppError::Rejected(RESERVATION_IN_PAST, "Reservations have to be in the future".to_string()));
+        }
+        if reservation_timestamp > now.saturating_add(self.max_days_ahead.saturating_mul(24 * 60 * 60)) {
+            return Err(AppError::Rejected(RESERVATION_TOO_FAR_AHEAD, format!(
+                "Reservations can be made at most {} days ahead",
+                self.max_days_ahead
+            )));
+        }
+        Ok(reservation_pax)
+    }
+}
+
+pub fn reservation_at_unknown_place(
+    place_id: &str,
+) -> AppError {
+    AppError::Rejected(RESERVATION_UNKNOWN_PLACE, format!("No restaurant found with place_id: {}", place_id))
+}
+
+pub fn overlapping_reservation(
+    overlap_window_secs: i64,
+) -> AppError {
+    AppError::Rejected(RESERVATION_OVERLAPS, format!(
+        "You already have a reservation within {} minutes of that time",
+        overlap_window_secs / 60
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_only_move_forward() {
+        use ReservationStatus::*;
+        assert_eq!(Requested.transition_to(Confirmed).unwrap(), Confirmed);
+        assert_eq!(Requested.transition_to(Cancelled).unwrap(), Cancelled);
+        assert_eq!(Confirmed.transition_to(NoShow).unwrap(), NoShow);
+        for (from, to) in [(Requested, Completed), (Confirmed, Requested), (Cancelled, Confirmed), (Completed, Cancelled), (NoShow, Completed)] {
+            assert_eq!(from.transition_to(to).unwrap_err().code(), "conflict", "{:?} to {:?}", from, to);
+        }
+        assert_eq!(Confirmed.after_change().unwrap(), Requested);
+        assert_eq!(Completed.after_change().unwrap_err().code(), "conflict");
+        for status in [Requested, Confirmed, Cancelled, Completed, NoShow] {
+            assert_eq!(ReservationStatus::from_column(status.as_str()), status);
+        }
+    }
+
+    #[test]
+    fn reservations_keep_to_the_rules() {
+        let rules = ReservationRules { min_pax: 1, max_pax: 8, max_days_ahead: 30, overlap_window_secs: 0 };
+        let now = 1_800_000_000;
+        assert_eq!(rules.check(now + 60, 8, now).unwrap(), 8);
+        assert!(rules.check(now + 30 * 24 * 60 * 60, 1, now).is_ok());
+        let rejected = |timestamp: i64, pax: i64| rules.check(timestamp, pax, now).unwrap_err().code();
+        assert_eq!(rejected(now + 60, 0), RESERVATION_PAX_OUT_OF_RANGE);
+        assert_eq!(rejected(now + 60, -2), RESERVATION_PAX_OUT_OF_RANGE);
+        assert_eq!(rejected(now + 60, 9), RESERVATION_PAX_OUT_OF_RANGE);
+        assert_eq!(rejected(now, 2), RESERVATION_IN_PAST);
+        assert_eq!(rejected(-1, 2), RESERVATION_IN_PAST);
+        assert_eq!(rejected(now + 30 * 24 * 60 * 60 + 1, 2), RESERVATION_TOO_FAR_AHEAD);
+
+        // a limit too large to add up stops at the end of time instead of overflowing
+        let unlimited = ReservationRules { max_days_ahead: i64::MAX, ..rules };
+        assert!(unlimited.check(i64::MAX, 2, now).is_ok());
+    }
+}
@@ -1,23 +1,93 @@
 use serde::{Deserialize, Serialize};
+use crate::models::rating::RatingSummary;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Restaurant {
     pub place_id: String,
     pub name: String,
-    pub photos: Photo,
-    pub rating: f64,
+    /// First photo google has for the place, not every place has one.
+    pub photos: Option<Photo>,
+    /// Unset for places nobody has rated yet.
+    pub rating: Option<f64>,
+    /// Google's 0 (free) to 4 (very expensive) scale, unset when the price is unknown.
+    #[serde(default)]
+    pub price_level: Option<u8>,
     pub vicinity: String,
     pub geometry: Location,
 }
 
+/// A stored place along with what our users rated it, `rating` stays google's.
 #[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RatedRestaurant {
+    #[serde(flatten)]
+    pub restaurant: Restaurant,
+    pub user_rating: RatingSummary,
+}
+
+/// A stored place found around a search point.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct NearbyRestaurant {
+    #[serde(flatten)]
+    pub restaurant: Restaurant,
+    pub distance_metres: f64,
+}
+
+/// A stored place matching a search, higher scores are better matches.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RestaurantSearchResult {
+    #[serde(flatten)]
+    pub restaurant: Restaurant,
+    pub score: f64,
+}
+
+/// Which stored places to look for around a point. Places without a rating or a price level never pass
+/// the filter on it.
+#[derive(Clone, Debug)]
+pub struct NearbyRestaurantsQuery {
+    pub location: Location,
+    pub radius_metres: f64,
+    pub min_rating: Option<f64>,
+    pub min_price_level: Option<u8>,
+    pub max_price_level: Option<u8>,
+    pub limit: i64,
+}
+
+impl NearbyRestaurantsQuery {
+    /// Every stored place within the radius, without filters.
+    pub fn new(
+        location: Location,
+        radius_metres: f64,
+        limit: i64,
+    ) -> Self {
+        NearbyRestaurantsQuery {
+            location,
+            radius_metres,
+            min_rating: None,
+            min_price_level: None,
+            max_price_level: None,
+            limit,
+        }
+    }
+
+    pub fn matches(
+        &self,
+        restaurant: &Restaurant,
+    ) -> bool {
+        let rated_enough = self.min_rating.is_none_or(|min| restaurant.rating.is_some_and(|rating| rating >= min));
+        let above_min_price = self.min_price_level.is_none_or(|min| restaurant.price_level.is_some_and(|price| price >= min));
+        let below_max_price = self.max_price_level.is_none_or(|max| restaurant.price_level.is_some_and(|price| price <= max));
+        rated_enough && above_min_price && below_max_price
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Photo {
     pub height: i64,
     pub photo_reference: String,
     pub width: i64,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Location {
     pub lat: f64,
     pub lng: f64,
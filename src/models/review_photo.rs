@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+/// A photo attached to a review, `url` and `thumbnail_url` are where clients fetch the images from.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReviewPhoto {
+    pub photo_id: String,
+    pub url: String,
+    pub thumbnail_url: String,
+    pub content_type: String,
+    pub width: i64,
+    pub height: i64,
+    pub created_at: i64,
+    /// Where the photo storage keeps the photo and its thumbnail, only the server needs these.
+    #[serde(skip)]
+    pub storage_key: String,
+    #[serde(skip)]
+    pub thumbnail_key: String,
+}
@@ -1,9 +1,13 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::voting::tally::TallyResult;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct VoteHistory {
     pub user_ids: Vec<String>,
     pub vote_timestamp: i64,
     pub voted_places: Vec<Value>,
+    /// Only set for sessions tallied by the server.
+    #[serde(default)]
+    pub outcome: Option<TallyResult>,
 }
\ No newline at end of file
@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+use tracing::info;
+use crate::helpers::app_error::AppResult;
+use crate::notifications::{Notifier, ReservationReminder};
+
+/// Writes reminders to the server log, for development and for deployments nobody listens to yet.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn notify(
+        &self,
+        reminder: &ReservationReminder,
+    ) -> AppResult<()> {
+        let reservation = &reminder.reservation;
+        info!(
+            "Reminding {} of reservation {} at {} for {} in {} minutes",
+            reservation.user_id,
+            reservation.reservation_id,
+            reminder.restaurant.as_ref().map_or(reservation.place_id.as_str(), |restaurant| restaurant.name.as_str()),
+            reservation.reservation_pax,
+            reminder.starts_in_secs / 60
+        );
+        Ok(())
+    }
+}
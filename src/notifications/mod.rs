@@ -0,0 +1,54 @@
+//! Reminders of upcoming reservations, found by the scheduler in `reminders` and sent out through a `Notifier`.
+use std::sync::Arc;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::config::{Config, NotifierBackend};
+use crate::helpers::app_error::AppResult;
+use crate::models::reservation::Reservation;
+use crate::models::restaurant::Restaurant;
+use crate::notifications::log::LogNotifier;
+use crate::notifications::webhook::WebhookNotifier;
+
+pub mod log;
+pub mod reminders;
+pub mod webhook;
+
+/// A reservation coming up, sent once per reservation time and offset.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReservationReminder {
+    pub reservation: Reservation,
+    /// Unset when the place is not stored anymore.
+    pub restaurant: Option<Restaurant>,
+    /// Which of the `REMINDER_OFFSETS_MINUTES` the reminder is for.
+    pub offset_minutes: i64,
+    /// How long until the reservation when the reminder went out, in seconds.
+    pub starts_in_secs: i64,
+}
+
+/// Where reminders go. The scheduler sends a reminder again if its notifier fails.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Short name for logs, `log` or `webhook`.
+    fn name(&self) -> &'static str;
+
+    async fn notify(
+        &self,
+        reminder: &ReservationReminder,
+    ) -> AppResult<()>;
+}
+
+pub fn build_notifier(
+    config: &Config,
+) -> anyhow::Result<Arc<dyn Notifier>> {
+    match config.reminder_notifier {
+        NotifierBackend::Log => Ok(Arc::new(LogNotifier)),
+        NotifierBackend::Webhook => {
+            let url = config
+                .reminder_webhook_url
+                .as_deref()
+                .ok_or_else(|| anyhow!("REMINDER_WEBHOOK_URL must be set when using the webhook notifier"))?;
+            Ok(Arc::new(WebhookNotifier::new(url, config.reminder_webhook_secret.clone())?))
+        }
+    }
+}
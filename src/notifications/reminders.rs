@@ -0,0 +1,269 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::anyhow;
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+use crate::helpers::app_error::AppResult;
+use crate::models::page::{Cursor, PageRequest, MAX_PAGE_LIMIT};
+use crate::models::reservation::Reservation;
+use crate::notifications::{Notifier, ReservationReminder};
+use crate::repositories::Repository;
+
+/// Sends a reminder some time before each requested or confirmed reservation, once per offset. What was sent
+/// is kept by the repository, so reminders survive restarts and several servers can run a scheduler each.
+pub struct ReminderScheduler {
+    repository: Arc<dyn Repository>,
+    notifier: Arc<dyn Notifier>,
+    offsets_secs: Vec<i64>,
+}
+
+impl ReminderScheduler {
+    /// Rejects offsets that are not in the future of a reminder.
+    pub fn new(
+        repository: Arc<dyn Repository>,
+        notifier: Arc<dyn Notifier>,
+        offsets_minutes: &[i64],
+    ) -> anyhow::Result<Self> {
+        if let Some(offset) = offsets_minutes.iter().find(|offset| **offset <= 0) {
+            return Err(anyhow!("REMINDER_OFFSETS_MINUTES have to be positive, got: {}", offset));
+        }
+        let mut offsets_secs: Vec<i64> = offsets_minutes.iter().map(|offset| offset * 60).collect();
+        offsets_secs.sort_unstable();
+        offsets_secs.dedup();
+
+        Ok(ReminderScheduler {
+            repository,
+            notifier,
+            offsets_secs,
+        })
+    }
+
+    /// Looks for due reminders every `interval` for as long as the server runs.
+    pub fn spawn(
+        self,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        info!("Sending reservation reminders through the {} notifier", self.notifier.name());
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                match self.send_due_reminders(OffsetDateTime::now_utc().unix_timestamp()).await {
+                    Ok(0) => {}
+                    Ok(sent) => info!("Sent {} reservation reminder(s)", sent),
+                    Err(e) => warn!("Failed to look for reservation reminders due to: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Sends the reminders due at `now` and hands back how many went out. A reservation gets one reminder at
+    /// a time, when several of its offsets are due at once only the shortest is sent. Reminders the notifier
+    /// fails to send are tried again next time.
+    pub async fn send_due_reminders(
+        &self,
+        now: i64,
+    ) -> AppResult<usize> {
+        let Some(longest_offset) = self.offsets_secs.last() else { return Ok(0) };
+        let mut page = PageRequest::first(MAX_PAGE_LIMIT);
+        let mut sent = 0;
+        loop {
+            let reservations = self.repository.retrieve_reservations_starting(now, now + longest_offset, &page).await?;
+            for reservation in reservations.items {
+                let due = due_offsets(&reservation, &self.offsets_secs, now);
+                if due.is_empty() {
+                    continue;
+                }
+                // looked up before claiming, a claim is only made when the reminder can go out right away
+                let restaurant = self.repository.retrieve_restaurant(&reservation.place_id).await?;
+                let claimed = self.repository
+                    .claim_reservation_reminders(&reservation.reservation_id, reservation.reservation_timestamp, &due)
+                    .await?;
+                let Some(offset_secs) = claimed.iter().min().copied() else { continue };
+
+                let reminder = ReservationReminder {
+                    restaurant,
+                    offset_minutes: offset_secs / 60,
+                    starts_in_secs: reservation.reservation_timestamp - now,
+                    reservation,
+                };
+                match self.notifier.notify(&reminder).await {
+                    Ok(()) => sent += 1,
+                    Err(e) => {
+                        warn!(
+                            "Failed to send the reminder of reservation {} through the {} notifier: {}",
+                            reminder.reservation.reservation_id,
+                            self.notifier.name(),
+                            e
+                        );
+                        self.repository
+                            .release_reservation_reminders(
+                                &reminder.reservation.reservation_id,
+                                reminder.reservation.reservation_timestamp,
+                                &claimed,
+                            ).await?;
+                    }
+                }
+            }
+            match reservations.next_cursor {
+                Some(cursor) => page.after = Some(Cursor::decode(&cursor)?),
+                None => return Ok(sent),
+            }
+        }
+    }
+}
+
+/// The offsets whose reminders are due at `now`. Reminders that were due before the reservation was made or
+/// last changed are left out, a reservation booked an hour ahead is not reminded of a day ahead.
+fn due_offsets(
+    reservation: &Reservation,
+    offsets_secs: &[i64],
+    now: i64,
+) -> Vec<i64> {
+    offsets_secs
+        .iter()
+        .copied()
+        .filter(|offset| {
+            let due_at = reservation.reservation_timestamp - offset;
+            due_at <= now && due_at >= reservation.updated_at
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+    use async_trait::async_trait;
+    use crate::helpers::app_error::AppError;
+    use crate::models::reservation::ReservationStatus;
+    use crate::models::restaurant::{Location, Restaurant};
+    use crate::repositories::in_memory_repo::InMemoryRepo;
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+
+    /// Keeps what it was sent, and fails while `failing` is set.
+    #[derive(Default)]
+    struct RecordingNotifier {
+        sent: Mutex<Vec<ReservationReminder>>,
+        failing: AtomicBool,
+    }
+
+    impl RecordingNotifier {
+        fn take(&self) -> Vec<(String, i64)> {
+            self.sent
+                .lock()
+                .unwrap()
+                .drain(..)
+                .map(|reminder| (reminder.reservation.reservation_id, reminder.offset_minutes))
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl Notifier for RecordingNotifier {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        async fn notify(
+            &self,
+            reminder: &ReservationReminder,
+        ) -> AppResult<()> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(AppError::Upstream("notifier is down".to_string()));
+            }
+            self.sent.lock().unwrap().push(reminder.clone());
+            Ok(())
+        }
+    }
+
+    async fn repository() -> Arc<InMemoryRepo> {
+        let repository = Arc::new(InMemoryRepo::new());
+        repository
+            .store_browsed_places(vec![Restaurant {
+                place_id: "maxwell-tian-tian".to_string(),
+                name: "Tian Tian Hainanese Chicken Rice".to_string(),
+                photos: None,
+                rating: None,
+                price_level: None,
+                vicinity: "1 Kadayanallur St, Singapore".to_string(),
+                geometry: Location { lat: 1.2806, lng: 103.8443 },
+            }])
+            .await
+            .unwrap();
+        repository
+    }
+
+    fn scheduler(
+        repository: &Arc<InMemoryRepo>,
+        notifier: &Arc<RecordingNotifier>,
+    ) -> ReminderScheduler {
+        ReminderScheduler::new(repository.clone(), notifier.clone(), &[60, 24 * 60]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reminders_go_out_once_per_offset() {
+        let repository = repository().await;
+        let notifier = Arc::new(RecordingNotifier::default());
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let in_two_days = repository.add_reservations("alice", "maxwell-tian-tian", now + 48 * HOUR, 2, 0).await.unwrap();
+        let in_two_hours = repository.add_reservations("bob", "maxwell-tian-tian", now + 2 * HOUR, 2, 0).await.unwrap();
+        let cancelled = repository.add_reservations("carol", "maxwell-tian-tian", now + 30 * HOUR, 2, 0).await.unwrap();
+        repository.set_reservation_status(None, &cancelled.reservation_id, ReservationStatus::Cancelled).await.unwrap();
+
+        let scheduler = scheduler(&repository, &notifier);
+        // booked less than a day ahead, so only the reminder an hour ahead is left for it
+        assert_eq!(scheduler.send_due_reminders(now + 60).await.unwrap(), 0);
+        assert_eq!(scheduler.send_due_reminders(now + HOUR + 60).await.unwrap(), 1);
+        assert_eq!(notifier.take(), [(in_two_hours.reservation_id.clone(), 60)]);
+
+        assert_eq!(scheduler.send_due_reminders(now + 24 * HOUR + 60).await.unwrap(), 1);
+        let sent = notifier.sent.lock().unwrap()[0].clone();
+        assert_eq!(sent.restaurant.unwrap().name, "Tian Tian Hainanese Chicken Rice");
+        assert_eq!(sent.starts_in_secs, 24 * HOUR - 60);
+        assert_eq!(notifier.take(), [(in_two_days.reservation_id.clone(), 24 * 60)]);
+        assert_eq!(scheduler.send_due_reminders(now + 24 * HOUR + 120).await.unwrap(), 0);
+
+        // a restarted scheduler knows what was sent, and a notifier that fails gets the reminder again
+        let scheduler = ReminderScheduler::new(repository.clone(), notifier.clone(), &[24 * 60, 60]).unwrap();
+        notifier.failing.store(true, Ordering::SeqCst);
+        assert_eq!(scheduler.send_due_reminders(now + 47 * HOUR + 60).await.unwrap(), 0);
+        notifier.failing.store(false, Ordering::SeqCst);
+        assert_eq!(scheduler.send_due_reminders(now + 47 * HOUR + 120).await.unwrap(), 1);
+        assert_eq!(notifier.take(), [(in_two_days.reservation_id.clone(), 60)]);
+        assert_eq!(scheduler.send_due_reminders(now + 47 * HOUR + 180).await.unwrap(), 0);
+        // nothing once the reservation started
+        assert_eq!(scheduler.send_due_reminders(now + 48 * HOUR).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn moved_reservations_are_reminded_again() {
+        let repository = repository().await;
+        let notifier = Arc::new(RecordingNotifier::default());
+        let scheduler = scheduler(&repository, &notifier);
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let reservation = repository.add_reservations("alice", "maxwell-tian-tian", now + 30 * 60, 2, 0).await.unwrap();
+        // a reminder that was due before the reservation was made is never sent
+        assert_eq!(scheduler.send_due_reminders(now + 60).await.unwrap(), 0);
+
+        repository.modify_reservation("alice", &reservation.reservation_id, now + 3 * HOUR, 4, 0).await.unwrap();
+        assert_eq!(scheduler.send_due_reminders(now + 2 * HOUR).await.unwrap(), 1);
+        assert_eq!(notifier.take(), [(reservation.reservation_id.clone(), 60)]);
+        assert_eq!(scheduler.send_due_reminders(now + 2 * HOUR + 60).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn offsets_have_to_be_positive() {
+        let repository = Arc::new(InMemoryRepo::new());
+        let notifier = Arc::new(RecordingNotifier::default());
+        assert!(ReminderScheduler::new(repository.clone(), notifier.clone(), &[60, 0]).is_err());
+        assert!(ReminderScheduler::new(repository.clone(), notifier.clone(), &[-60]).is_err());
+        let scheduler = ReminderScheduler::new(repository, notifier, &[60, 24 * 60, 60]).unwrap();
+        assert_eq!(scheduler.offsets_secs, [HOUR, 24 * HOUR]);
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+use anyhow::Context;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use sha2::Sha256;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::notifications::{Notifier, ReservationReminder};
+
+/// Header carrying `sha256=<hex hmac of the body>` when the webhook has a secret.
+pub const SIGNATURE_HEADER: &str = "x-eatwherela-signature";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts every reminder as json to a url, whoever listens there passes it on to the user. With a secret the
+/// body is signed with HMAC-SHA256, receivers compute the same over the raw body to tell the request is ours.
+pub struct WebhookNotifier {
+    url: Url,
+    secret: Option<String>,
+    http_client: Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(
+        url: &str,
+        secret: Option<String>,
+    ) -> anyhow::Result<Self> {
+        Ok(WebhookNotifier {
+            url: Url::parse(url).with_context(|| format!("REMINDER_WEBHOOK_URL is not a url: {}", url))?,
+            secret,
+            http_client: Client::builder().timeout(WEBHOOK_TIMEOUT).build()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(
+        &self,
+        reminder: &ReservationReminder,
+    ) -> AppResult<()> {
+        let body = serde_json::to_vec(reminder)
+            .map_err(|e| AppError::Upstream(format!("Could not encode the reminder: {}", e)))?;
+        let mut request = self
+            .http_client
+            .post(self.url.clone())
+            .header("content-type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+        }
+
+        let response = request.body(body).send().await?;
+        if !response.status().is_success() {
+            return Err(AppError::Upstream(format!(
+                "Reminder webhook answered {} for reservation {}",
+                response.status(),
+                reminder.reservation.reservation_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn sign(
+    secret: &str,
+    body: &[u8],
+) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any length");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use axum::body::Bytes;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::{Extension, Router};
+    use crate::models::reservation::{Reservation, ReservationStatus};
+    use super::*;
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+    async fn receive(
+        Extension(received): Extension<Received>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        let signed = headers.contains_key(SIGNATURE_HEADER);
+        received.lock().unwrap().push((headers, body));
+        if signed { StatusCode::NO_CONTENT } else { StatusCode::SERVICE_UNAVAILABLE }
+    }
+
+    fn reminder() -> ReservationReminder {
+        ReservationReminder {
+            reservation: Reservation {
+                reservation_id: "5d0c8e4e".to_string(),
+                user_id: "alice".to_string(),
+                place_id: "maxwell-tian-tian".to_string(),
+                reservation_timestamp: 1_792_494_000,
+                reservation_pax: 4,
+                status: ReservationStatus::Confirmed,
+                created_at: 1_792_400_000,
+                updated_at: 1_792_400_000,
+            },
+            restaurant: None,
+            offset_minutes: 60,
+            starts_in_secs: 3540,
+        }
+    }
+
+    #[tokio::test]
+    async fn reminders_are_posted_signed() {
+        let received = Received::default();
+        let app = Router::new().route("/hook", post(receive)).layer(Extension(received.clone()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        let url = format!("http://{}/hook", address);
+
+        WebhookNotifier::new(&url, Some("webhook-secret".to_string())).unwrap().notify(&reminder()).await.unwrap();
+        // the test receiver turns unsigned requests away
+        let error = WebhookNotifier::new(&url, None).unwrap().notify(&reminder()).await.unwrap_err();
+        assert_eq!(error.code(), "upstream_failure");
+
+        let received = received.lock().unwrap();
+        let (headers, body) = &received[0];
+        assert_eq!(headers[SIGNATURE_HEADER], format!("sha256={}", sign("webhook-secret", body)));
+        let posted: ReservationReminder = serde_json::from_slice(body).unwrap();
+        assert_eq!((posted.reservation.reservation_id.as_str(), posted.offset_minutes), ("5d0c8e4e", 60));
+        assert_eq!(received.len(), 2);
+    }
+
+    #[test]
+    fn signatures_match_the_rfc_4231_example() {
+        // test case 2 of RFC 4231
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        );
+    }
+}
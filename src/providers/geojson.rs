@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::geo::haversine_distance_metres;
+use crate::models::google_places::{NearbyPage, OpeningHours, PlaceDetails, PlaceGeometry};
+use crate::models::restaurant::{Location, Restaurant};
+use crate::providers::{NearbySearch, PlaceProvider};
+
+/// Results per page, the same as google.
+pub const PAGE_SIZE: usize = 20;
+/// OpenStreetMap `amenity` values matched by the `restaurant` and `food` types.
+const FOOD_AMENITIES: [&str; 7] = ["restaurant", "fast_food", "cafe", "food_court", "bar", "pub", "ice_cream"];
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(default)]
+    geometry: Option<Geometry>,
+    #[serde(default)]
+    properties: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Geometry {
+    r#type: String,
+    #[serde(default)]
+    coordinates: Vec<Value>,
+}
+
+struct OfflinePlace {
+    restaurant: Restaurant,
+    details: PlaceDetails,
+    amenity: Option<String>,
+}
+
+/// Places read once from a GeoJSON FeatureCollection, such as an OpenStreetMap extract exported with
+/// overpass-turbo. Needs no API key and no network, which is what development and CI want.
+///
+/// Only named `Point` features are used. Tags follow OpenStreetMap, `amenity`, `addr:*`, `phone`, `website`
+/// and `opening_hours`. The dataset has no prices, ratings or photos, so `minprice` is ignored.
+pub struct GeoJsonPlaceProvider {
+    places: Vec<OfflinePlace>,
+}
+
+impl GeoJsonPlaceProvider {
+    pub fn from_file(
+        path: &str,
+    ) -> anyhow::Result<Self> {
+        let geojson = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read places from {}", path))?;
+        Self::from_geojson(&geojson).with_context(|| format!("Failed to parse places from {}", path))
+    }
+
+    pub fn from_geojson(
+        geojson: &str,
+    ) -> anyhow::Result<Self> {
+        let collection: FeatureCollection = serde_json::from_str(geojson)?;
+        let feature_count = collection.features.len();
+        let places: Vec<OfflinePlace> = collection
+            .features
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, feature)| offline_place(index, feature))
+            .collect();
+        if places.len() < feature_count {
+            warn!("Skipped {} features without a name or a point geometry", feature_count - places.len());
+        }
+
+        Ok(GeoJsonPlaceProvider { places })
+    }
+
+    pub fn len(&self) -> usize {
+        self.places.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.places.is_empty()
+    }
+}
+
+fn offline_place(
+    index: usize,
+    feature: Feature,
+) -> Option<OfflinePlace> {
+    let tag = |key: &str| feature.properties.get(key).and_then(Value::as_str).map(str::to_string);
+    let name = tag("name")?;
+    let geometry = feature.geometry.as_ref().filter(|geometry| geometry.r#type == "Point")?;
+    // geojson orders coordinates longitude first
+    let location = Location {
+        lng: geometry.coordinates.first()?.as_f64()?,
+        lat: geometry.coordinates.get(1)?.as_f64()?,
+    };
+
+    let id = match feature.id.as_ref().or_else(|| feature.properties.get("@id")) {
+        Some(Value::String(id)) => id.clone(),
+        Some(Value::Number(id)) => id.to_string(),
+        _ => index.to_string(),
+    };
+    let street = [tag("addr:housenumber"), tag("addr:street")]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let vicinity = [Some(street), tag("addr:city")]
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let amenity = tag("amenity");
+
+    let restaurant = Restaurant {
+        // slashes in osm ids like node/123 would break the place id path segments
+        place_id: format!("geojson:{}", id.replace('/', ":")),
+        name: name.clone(),
+        photos: None,
+        rating: None,
+        price_level: None,
+        vicinity: vicinity.clone(),
+        geometry: location.clone(),
+    };
+    let details = PlaceDetails {
+        place_id: restaurant.place_id.clone(),
+        name,
+        formatted_address: Some(vicinity.clone()).filter(|vicinity| !vicinity.is_empty()),
+        formatted_phone_number: tag("phone").or_else(|| tag("contact:phone")),
+        international_phone_number: None,
+        website: tag("website").or_else(|| tag("contact:website")),
+        url: None,
+        vicinity: Some(vicinity).filter(|vicinity| !vicinity.is_empty()),
+        geometry: Some(PlaceGeometry { location }),
+        rating: None,
+        user_ratings_total: None,
+        price_level: None,
+        // osm keeps opening hours as one rule string rather than a line per day
+        opening_hours: tag("opening_hours").map(|opening_hours| OpeningHours {
+            open_now: None,
+            weekday_text: vec![opening_hours],
+        }),
+        photos: Vec::new(),
+        types: amenity.iter().cloned().collect(),
+    };
+
+    Some(OfflinePlace {
+        restaurant,
+        details,
+        amenity,
+    })
+}
+
+fn matches_type(
+    amenity: Option<&str>,
+    place_type: &str,
+) -> bool {
+    match place_type.trim().to_lowercase().as_str() {
+        "" => true,
+        "restaurant" | "food" => amenity.is_none_or(|amenity| FOOD_AMENITIES.contains(&amenity)),
+        place_type => amenity == Some(place_type),
+    }
+}
+
+#[async_trait]
+impl PlaceProvider for GeoJsonPlaceProvider {
+    fn name(&self) -> &'static str {
+        "geojson"
+    }
+
+    async fn nearby_search(
+        &self,
+        search: &NearbySearch,
+    ) -> AppResult<NearbyPage> {
+        // the page token is simply the offset into the distance ordered results
+        let offset = match &search.page_token {
+            Some(page_token) => page_token
+                .parse::<usize>()
+                .map_err(|_| AppError::Validation(format!("Unknown page token: {}", page_token)))?,
+            None => 0,
+        };
+
+        let mut nearby: Vec<(f64, &Restaurant)> = self
+            .places
+            .iter()
+            .filter(|place| matches_type(place.amenity.as_deref(), &search.place_type))
+            .map(|place| (haversine_distance_metres(&search.location, &place.restaurant.geometry), &place.restaurant))
+            .filter(|(distance, _)| *distance <= search.radius_metres)
+            .collect();
+        nearby.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        let next_offset = offset + PAGE_SIZE;
+        Ok(NearbyPage {
+            restaurants: nearby
+                .iter()
+                .skip(offset)
+                .take(PAGE_SIZE)
+                .map(|(_, restaurant)| (*restaurant).clone())
+                .collect(),
+            next_page_token: (next_offset < nearby.len()).then(|| next_offset.to_string()),
+        })
+    }
+
+    async fn place_details(
+        &self,
+        place_id: &str,
+    ) -> AppResult<PlaceDetails> {
+        self.places
+            .iter()
+            .find(|place| place.details.place_id == place_id)
+            .map(|place| place.details.clone())
+            .ok_or_else(|| AppError::NotFound(format!("No place found with id: {}", place_id)))
+    }
+
+    async fn photo_url(
+        &self,
+        photo_reference: &str,
+    ) -> AppResult<String> {
+        Err(AppError::NotFound(format!(
+            "The offline place dataset has no photos, no photo found for reference: {}",
+            photo_reference,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    const MAXWELL_FOOD: &str = include_str!("../../tests/fixtures/places/maxwell_food.geojson");
+
+    fn search(place_type: &str, radius_metres: f64, page_token: Option<&str>) -> NearbySearch {
+        NearbySearch {
+            location: Location { lat: 1.2803, lng: 103.8447 },
+            radius_metres,
+            place_type: place_type.to_string(),
+            minprice: "1".to_string(),
+            page_token: page_token.map(str::to_string),
+        }
+    }
+
+    fn names(page: &NearbyPage) -> Vec<&str> {
+        page.restaurants.iter().map(|restaurant| restaurant.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn nearby_places_are_ordered_by_distance() {
+        let provider = GeoJsonPlaceProvider::from_geojson(MAXWELL_FOOD).unwrap();
+        // the unnamed node and the polygon are left out
+        assert_eq!(provider.len(), 5);
+
+        let page = provider.nearby_search(&search("restaurant", 500.0, None)).await.unwrap();
+        assert_eq!(names(&page), ["Maxwell Food Centre", "Tian Tian Hainanese Chicken Rice", "Native"]);
+        assert!(page.next_page_token.is_none());
+        assert_eq!(page.restaurants[0].place_id, "geojson:node:1318498347");
+        assert_eq!(page.restaurants[0].vicinity, "1 Kadayanallur Street, Singapore");
+
+        let page = provider.nearby_search(&search("cafe", 1000.0, None)).await.unwrap();
+        assert_eq!(names(&page), ["Nylon Coffee Roasters"]);
+    }
+
+    #[tokio::test]
+    async fn pages_are_handed_out_by_offset() {
+        let features: Vec<Value> = (0..45)
+            .map(|index| json!({
+                "type": "Feature",
+                "id": index,
+                "properties": { "amenity": "restaurant", "name": format!("Stall {}", index) },
+                "geometry": { "type": "Point", "coordinates": [103.8447 + index as f64 * 0.00001, 1.2803] },
+            }))
+            .collect();
+        let geojson = json!({ "type": "FeatureCollection", "features": features }).to_string();
+        let provider = GeoJsonPlaceProvider::from_geojson(&geojson).unwrap();
+
+        let first = provider.nearby_search(&search("restaurant", 500.0, None)).await.unwrap();
+        assert_eq!(first.restaurants.len(), PAGE_SIZE);
+        assert_eq!(first.next_page_token.as_deref(), Some("20"));
+
+        let last = provider.nearby_search(&search("restaurant", 500.0, Some("40"))).await.unwrap();
+        assert_eq!(names(&last), ["Stall 40", "Stall 41", "Stall 42", "Stall 43", "Stall 44"]);
+        assert!(last.next_page_token.is_none());
+
+        let error = provider.nearby_search(&search("restaurant", 500.0, Some("AcJnMuE"))).await.unwrap_err();
+        assert_eq!(error.code(), "validation_failed");
+    }
+
+    #[tokio::test]
+    async fn details_come_from_the_feature_tags() {
+        let provider = GeoJsonPlaceProvider::from_geojson(MAXWELL_FOOD).unwrap();
+
+        let details = provider.place_details("geojson:node:2404563981").await.unwrap();
+        assert_eq!(details.name, "Tian Tian Hainanese Chicken Rice");
+        assert_eq!(details.formatted_phone_number.as_deref(), Some("+65 9691 4852"));
+        assert_eq!(details.types, ["restaurant"]);
+
+        let details = provider.place_details("geojson:node:1318498347").await.unwrap();
+        assert_eq!(details.opening_hours.unwrap().weekday_text, ["Mo-Su 08:00-22:00"]);
+
+        assert_eq!(provider.place_details("geojson:node:0").await.unwrap_err().code(), "not_found");
+        assert_eq!(provider.photo_url("anything").await.unwrap_err().code(), "not_found");
+    }
+}
@@ -0,0 +1,141 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::Client;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::models::google_places::{NearbyPage, NearbySearchResponse, PlaceDetails, PlaceDetailsResponse, PlacesStatus};
+use crate::providers::{NearbySearch, PlaceProvider};
+
+/// Tries made with a page token before giving up, google answers INVALID_REQUEST until the token goes live.
+const PAGE_TOKEN_ATTEMPTS: u32 = 3;
+/// Width asked for when resolving a photo, big enough for a card on a phone.
+const PHOTO_MAX_WIDTH: &str = "400";
+
+/// The Google Places web service. Nearby search, details and photos all live below one base url.
+pub struct GooglePlaceProvider {
+    base_url: String,
+    api_key: String,
+    page_token_delay: Duration,
+    http_client: Client,
+}
+
+impl GooglePlaceProvider {
+    /// `base_url` is the places API root, `https://maps.googleapis.com/maps/api/place`. The nearby search url
+    /// that used to be configured is accepted too, its endpoint is stripped off.
+    pub fn new(
+        base_url: &str,
+        api_key: String,
+        page_token_delay: Duration,
+    ) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        let base_url = base_url.strip_suffix("/nearbysearch/json").unwrap_or(base_url);
+
+        GooglePlaceProvider {
+            base_url: base_url.to_string(),
+            api_key,
+            page_token_delay,
+            http_client: Client::new(),
+        }
+    }
+
+    fn endpoint(
+        &self,
+        path: &str,
+    ) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+}
+
+#[async_trait]
+impl PlaceProvider for GooglePlaceProvider {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    fn page_token_delay(&self) -> Duration {
+        self.page_token_delay
+    }
+
+    async fn nearby_search(
+        &self,
+        search: &NearbySearch,
+    ) -> AppResult<NearbyPage> {
+        let location = format!("{},{}", search.location.lat, search.location.lng);
+        let radius = search.radius_metres.to_string();
+        let params = match &search.page_token {
+            // google ignores every other parameter once a page token is given
+            Some(page_token) => vec![("pagetoken", page_token.as_str()), ("key", self.api_key.as_str())],
+            None => vec![
+                ("location", location.as_str()),
+                ("radius", radius.as_str()),
+                ("type", search.place_type.as_str()),
+                ("minprice", search.minprice.as_str()),
+                ("key", self.api_key.as_str()),
+            ],
+        };
+
+        let mut attempt = 1;
+        loop {
+            let response = self
+                .http_client
+                .get(self.endpoint("nearbysearch/json"))
+                .query(&params)
+                .send()
+                .await?
+                .json::<NearbySearchResponse>()
+                .await?;
+            if search.page_token.is_some() && response.status == PlacesStatus::InvalidRequest && attempt < PAGE_TOKEN_ATTEMPTS {
+                attempt += 1;
+                tokio::time::sleep(self.page_token_delay).await;
+                continue;
+            }
+            return response.into_page();
+        }
+    }
+
+    async fn place_details(
+        &self,
+        place_id: &str,
+    ) -> AppResult<PlaceDetails> {
+        self.http_client
+            .get(self.endpoint("details/json"))
+            .query(&[("place_id", place_id), ("key", self.api_key.as_str())])
+            .send()
+            .await?
+            .json::<PlaceDetailsResponse>()
+            .await?
+            .into_details()
+    }
+
+    async fn photo_url(
+        &self,
+        photo_reference: &str,
+    ) -> AppResult<String> {
+        let response = self
+            .http_client
+            .get(self.endpoint("photo"))
+            .query(&[
+                ("maxwidth", PHOTO_MAX_WIDTH),
+                ("photoreference", photo_reference),
+                ("key", self.api_key.as_str()),
+            ])
+            .send()
+            .await?;
+        // an unknown or expired reference is answered with a 400 instead of the redirect
+        match response.status() {
+            status if status.is_success() => {}
+            status if status.is_client_error() => {
+                return Err(AppError::NotFound(format!("No photo found for reference: {}", photo_reference)));
+            }
+            status => {
+                return Err(AppError::Upstream(format!("Google places photo returned {}", status)));
+            }
+        }
+
+        // google redirects to the actual image, which is what we hand back to the client
+        let response_url = response.url();
+        let domain = response_url
+            .domain()
+            .ok_or_else(|| AppError::Upstream(format!("Photo redirect url has no domain: {}", response_url)))?;
+        Ok(format!("{}{}", domain, response_url.path()))
+    }
+}
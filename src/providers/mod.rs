@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use tracing::info;
+use crate::config::{Config, PlaceProviderBackend};
+use crate::helpers::app_error::AppResult;
+use crate::models::google_places::{NearbyPage, PlaceDetails};
+use crate::models::restaurant::Location;
+use crate::providers::geojson::GeoJsonPlaceProvider;
+use crate::providers::google::GooglePlaceProvider;
+
+pub mod geojson;
+pub mod google;
+
+/// Where places are discovered. The controllers only talk to this trait, so every provider has to answer
+/// with the same models and map its failures onto the same `AppError` variants.
+#[async_trait]
+pub trait PlaceProvider: Send + Sync {
+    /// Short name for logs, `google` or `geojson`.
+    fn name(&self) -> &'static str;
+
+    /// How long a freshly handed out `next_page_token` needs before it can be used.
+    fn page_token_delay(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// One page of places around the search point, with the cursor for the next page if there is one.
+    async fn nearby_search(
+        &self,
+        search: &NearbySearch,
+    ) -> AppResult<NearbyPage>;
+
+    async fn place_details(
+        &self,
+        place_id: &str,
+    ) -> AppResult<PlaceDetails>;
+
+    /// Url of the image behind a photo reference handed out with a place.
+    async fn photo_url(
+        &self,
+        photo_reference: &str,
+    ) -> AppResult<String>;
+}
+
+#[derive(Clone, Debug)]
+pub struct NearbySearch {
+    pub location: Location,
+    pub radius_metres: f64,
+    pub place_type: String,
+    pub minprice: String,
+    /// Cursor from an earlier page of the same search.
+    pub page_token: Option<String>,
+}
+
+pub fn build_place_provider(
+    config: &Config,
+) -> anyhow::Result<Arc<dyn PlaceProvider>> {
+    match config.place_provider {
+        PlaceProviderBackend::Google => {
+            let api_key = config
+                .google_api_key
+                .clone()
+                .ok_or_else(|| anyhow!("GOOGLE_API_KEY must be set when using the google place provider"))?;
+            Ok(Arc::new(GooglePlaceProvider::new(
+                &config.google_maps_api_url,
+                api_key,
+                Duration::from_millis(config.google_page_token_delay_ms),
+            )))
+        }
+        PlaceProviderBackend::Geojson => {
+            let path = config
+                .places_geojson_path
+                .as_ref()
+                .ok_or_else(|| anyhow!("PLACES_GEOJSON_PATH must be set when using the geojson place provider"))?;
+            let provider = GeoJsonPlaceProvider::from_file(path)?;
+            info!("Serving {} places from {}, no requests will reach google", provider.len(), path);
+            Ok(Arc::new(provider))
+        }
+    }
+}
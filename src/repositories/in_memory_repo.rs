@@ -0,0 +1,1049 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use async_trait::async_trait;
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::geo::haversine_distance_metres;
+use crate::helpers::text_search::{contains_all_words, word_similarity, WORD_SIMILARITY_THRESHOLD};
+use crate::models::capacity::PlaceCapacity;
+use crate::models::moderation::{ReportedReview, ReviewReport};
+use crate::models::page::{Cursor, Page, PageRequest};
+use crate::models::rating::{star_index, RatingSummary, RestaurantRating, ReviewRevision, ReviewSort, ReviewStatus};
+use crate::models::reservation::{overlapping_reservation, reservation_at_unknown_place, Reservation, ReservationStatus};
+use crate::models::review_photo::ReviewPhoto;
+use crate::models::restaurant::{NearbyRestaurant, NearbyRestaurantsQuery, Restaurant, RestaurantSearchResult};
+use crate::models::user::User;
+use crate::models::vote::VoteHistory;
+use crate::repositories::{too_many_photos, Repository, SEARCH_VICINITY_WEIGHT};
+use crate::voting::tally::TallyResult;
+
+/// Process local repository used for integration tests and for running the app without postgres.
+/// Nothing is persisted, everything is lost once the server shuts down.
+#[derive(Default)]
+pub struct InMemoryRepo {
+    store: RwLock<InMemoryStore>,
+}
+
+#[derive(Default)]
+struct InMemoryStore {
+    // username -> user
+    users: HashMap<String, User>,
+    places: HashMap<String, Restaurant>,
+    // (user_id, place_id) -> bookmark timestamp
+    bookmarks: HashMap<(String, String), i64>,
+    // at most one per (user_id, place_id), removed ones included
+    reviews: Vec<RestaurantRating>,
+    // in the order they were written
+    review_revisions: Vec<ReviewRevision>,
+    // (user_id, place_id, revision) of the revisions that were published, which history is limited to
+    published_revisions: HashSet<(String, String, i64)>,
+    // in the order they were filed, the position stands in for the id
+    review_reports: Vec<ReviewReport>,
+    // (voter_id, user_id, place_id) -> whether the review helped
+    review_votes: HashMap<(String, String, String), bool>,
+    reservations: Vec<Reservation>,
+    place_capacities: HashMap<String, PlaceCapacity>,
+    // user_id -> token hash
+    calendar_feed_tokens: HashMap<String, String>,
+    // (reservation_id, reservation_timestamp, offset_secs) of the reminders sent
+    reservation_reminders: HashSet<(String, i64, i64)>,
+    vote_histories: Vec<VoteHistory>,
+}
+
+impl InMemoryRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read_store(
+        &self,
+    ) -> AppResult<RwLockReadGuard<'_, InMemoryStore>> {
+        self.store
+            .read()
+            .map_err(|_| AppError::Database("In-memory store lock was poisoned".to_string()))
+    }
+
+    fn write_store(
+        &self,
+    ) -> AppResult<RwLockWriteGuard<'_, InMemoryStore>> {
+        self.store
+            .write()
+            .map_err(|_| AppError::Database("In-memory store lock was poisoned".to_string()))
+    }
+}
+
+impl InMemoryStore {
+    fn find_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> Option<&RestaurantRating> {
+        self.reviews.iter().find(|review| review.user_id == user_id && review.place_id == place_id)
+    }
+
+    /// Writes over the review of the user for the place, restoring it if it was removed, and keeps the
+    /// new version as a revision. A `held_back` review waits for a moderator, see `add_user_review`.
+    fn write_review(
+        &mut self,
+        user_id: &str,
+        place_id: &str,
+        rating: f64,
+        description: &str,
+        held_back: Option<&str>,
+    ) {
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+        let existing = self.find_review(user_id, place_id);
+        let status = match held_back {
+            Some(_) => ReviewStatus::Pending,
+            None => existing.map(|review| review.status).unwrap_or_default(),
+        };
+        let review = RestaurantRating {
+            user_id: user_id.to_string(),
+            place_id: place_id.to_string(),
+            rating,
+            description: description.to_string(),
+            timestamp,
+            deleted_at: None,
+            status,
+            // votes stay with the review through edits
+            helpful_count: existing.map(|review| review.helpful_count).unwrap_or_default(),
+            unhelpful_count: existing.map(|review| review.unhelpful_count).unwrap_or_default(),
+            photos: existing.map(|review| review.photos.clone()).unwrap_or_default(),
+        };
+        match self.reviews.iter_mut().find(|review| review.user_id == user_id && review.place_id == place_id) {
+            Some(existing) => *existing = review,
+            None => self.reviews.push(review),
+        }
+
+        let revision = self.review_revisions
+            .iter()
+            .filter(|revision| revision.user_id == user_id && revision.place_id == place_id)
+            .count() as i64 + 1;
+        self.review_revisions.push(ReviewRevision {
+            user_id: user_id.to_string(),
+            place_id: place_id.to_string(),
+            revision,
+            rating,
+            description: description.to_string(),
+            timestamp,
+        });
+        if status == ReviewStatus::Published {
+            self.published_revisions.insert((user_id.to_string(), place_id.to_string(), revision));
+        }
+
+        // same as the unique index on open reports, the filter files one at a time
+        if let Some(reason) = held_back {
+            if !self.open_reports(user_id, place_id).any(|report| report.reporter_id.is_none()) {
+                self.file_report(user_id, place_id, None, reason, timestamp);
+            }
+        }
+    }
+
+    /// Recounts the votes on a review, the way postgres keeps the counts on the row.
+    fn count_votes(
+        &mut self,
+        user_id: &str,
+        place_id: &str,
+    ) {
+        let (mut helpful_count, mut unhelpful_count) = (0, 0);
+        for ((_, voted_user_id, voted_place_id), helpful) in &self.review_votes {
+            if voted_user_id == user_id && voted_place_id == place_id {
+                match helpful {
+                    true => helpful_count += 1,
+                    false => unhelpful_count += 1,
+                }
+            }
+        }
+        if let Some(review) = self.reviews.iter_mut().find(|review| review.user_id == user_id && review.place_id == place_id) {
+            review.helpful_count = helpful_count;
+            review.unhelpful_count = unhelpful_count;
+        }
+    }
+
+    /// Same as `check_place_and_overlap` on postgres.
+    fn check_place_and_overlap(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        reservation_timestamp: i64,
+        overlap_window_secs: i64,
+        except_reservation_id: Option<&str>,
+    ) -> AppResult<()> {
+        if !self.places.contains_key(place_id) {
+            return Err(reservation_at_unknown_place(place_id));
+        }
+        let overlaps = overlap_window_secs > 0 && self.reservations.iter().any(|reservation| {
+            reservation.user_id == user_id
+                && reservation.status.is_active()
+                && (reservation.reservation_timestamp - reservation_timestamp).abs() < overlap_window_secs
+                && Some(reservation.reservation_id.as_str()) != except_reservation_id
+        });
+        if overlaps {
+            return Err(overlapping_reservation(overlap_window_secs));
+        }
+        Ok(())
+    }
+
+    /// Same as `check_capacity` on postgres, the write lock on the store keeps bookings apart.
+    fn check_capacity(
+        &self,
+        place_id: &str,
+        reservation_timestamp: i64,
+        reservation_pax: u32,
+        except_reservation_id: Option<&str>,
+    ) -> AppResult<()> {
+        let Some(capacity) = self.place_capacities.get(place_id) else { return Ok(()) };
+        let booked = self.reservations
+            .iter()
+            .filter(|reservation| {
+                reservation.place_id == place_id
+                    && reservation.reservation_timestamp == reservation_timestamp
+                    && reservation.status.is_active()
+                    && Some(reservation.reservation_id.as_str()) != except_reservation_id
+            })
+            .map(|reservation| reservation.reservation_pax)
+            .sum();
+        capacity.check_booking(reservation_timestamp, reservation_pax, booked)
+    }
+
+    fn find_reservation_mut(
+        &mut self,
+        user_id: Option<&str>,
+        reservation_id: &str,
+    ) -> AppResult<&mut Reservation> {
+        self.reservations
+            .iter_mut()
+            .find(|reservation| {
+                reservation.reservation_id == reservation_id && user_id.is_none_or(|user_id| reservation.user_id == user_id)
+            })
+            .ok_or_else(|| AppError::NotFound(format!("No reservation found with reservation_id: {}", reservation_id)))
+    }
+
+    fn open_reports<'a>(
+        &'a self,
+        user_id: &'a str,
+        place_id: &'a str,
+    ) -> impl Iterator<Item = &'a ReviewReport> + 'a {
+        self.review_reports
+            .iter()
+            .filter(move |report| report.user_id == user_id && report.place_id == place_id && report.resolved_at.is_none())
+    }
+
+    fn file_report(
+        &mut self,
+        user_id: &str,
+        place_id: &str,
+        reporter_id: Option<&str>,
+        reason: &str,
+        created_at: i64,
+    ) {
+        self.review_reports.push(ReviewReport {
+            id: self.review_reports.len() as i64 + 1,
+            user_id: user_id.to_string(),
+            place_id: place_id.to_string(),
+            reporter_id: reporter_id.map(str::to_string),
+            reason: reason.to_string(),
+            created_at,
+            resolved_at: None,
+        });
+    }
+}
+
+#[async_trait]
+impl Repository for InMemoryRepo {
+    async fn create_user(
+        &self,
+        user: &User,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        if store.users.contains_key(&user.username) {
+            return Err(AppError::Conflict(format!("Username {} is already taken", user.username)));
+        }
+        store.users.insert(user.username.clone(), user.clone());
+        Ok(())
+    }
+
+    async fn retrieve_user_by_username(
+        &self,
+        username: &str,
+    ) -> AppResult<Option<User>> {
+        let store = self.read_store()?;
+        Ok(store.users.get(username).cloned())
+    }
+
+    async fn store_browsed_places(
+        &self,
+        list_of_restaurants: Vec<Restaurant>,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        for restaurant in list_of_restaurants {
+            store.places
+                .entry(restaurant.place_id.clone())
+                .or_insert(restaurant);
+        }
+        Ok(())
+    }
+
+    async fn retrieve_restaurant(
+        &self,
+        place_id: &str,
+    ) -> AppResult<Option<Restaurant>> {
+        let store = self.read_store()?;
+        Ok(store.places.get(place_id).cloned())
+    }
+
+    async fn search_for_restaurants(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> AppResult<Vec<RestaurantSearchResult>> {
+        let store = self.read_store()?;
+        let needle = query.to_lowercase();
+        let mut results: Vec<RestaurantSearchResult> = store.places
+            .values()
+            .filter_map(|restaurant| {
+                let searchable = format!("{} {}", restaurant.name, restaurant.vicinity);
+                let words_match = contains_all_words(query, &searchable);
+                let name_similarity = word_similarity(query, &restaurant.name);
+                let vicinity_similarity = word_similarity(query, &restaurant.vicinity);
+                let name_contains = restaurant.name.to_lowercase().contains(&needle);
+                let vicinity_contains = restaurant.vicinity.to_lowercase().contains(&needle);
+                let matched = words_match
+                    || name_similarity >= WORD_SIMILARITY_THRESHOLD
+                    || vicinity_similarity >= WORD_SIMILARITY_THRESHOLD
+                    || name_contains
+                    || vicinity_contains;
+
+                // same weights as the postgres query, ts_rank_cd is approximated by 1 for a full word match
+                matched.then(|| RestaurantSearchResult {
+                    restaurant: restaurant.clone(),
+                    score: f64::from(u8::from(words_match))
+                        + name_similarity
+                        + SEARCH_VICINITY_WEIGHT * vicinity_similarity
+                        + f64::from(u8::from(name_contains)),
+                })
+            })
+            .collect();
+        results.sort_by(|a, b| {
+            b.score.total_cmp(&a.score).then_with(|| a.restaurant.place_id.cmp(&b.restaurant.place_id))
+        });
+
+        Ok(results
+            .into_iter()
+            .skip(usize::try_from(offset).unwrap_or(0))
+            .take(usize::try_from(limit).unwrap_or(0))
+            .collect())
+    }
+
+    async fn retrieve_restaurants_near(
+        &self,
+        query: &NearbyRestaurantsQuery,
+    ) -> AppResult<Vec<NearbyRestaurant>> {
+        let store = self.read_store()?;
+        let mut nearby: Vec<(f64, &Restaurant)> = store.places
+            .values()
+            .filter(|restaurant| query.matches(restaurant))
+            .map(|restaurant| (haversine_distance_metres(&query.location, &restaurant.geometry), restaurant))
+            .filter(|(distance, _)| *distance <= query.radius_metres)
+            .collect();
+        nearby.sort_by(|(a_distance, a), (b_distance, b)| {
+            a_distance.total_cmp(b_distance).then_with(|| a.place_id.cmp(&b.place_id))
+        });
+
+        Ok(nearby
+            .into_iter()
+            .take(usize::try_from(query.limit).unwrap_or(0))
+            .map(|(distance_metres, restaurant)| NearbyRestaurant {
+                restaurant: restaurant.clone(),
+                distance_metres,
+            })
+            .collect())
+    }
+
+    async fn bookmark_place(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        // Bookmarks reference places, same as the foreign key on user_favourite_places
+        if !store.places.contains_key(place_id) {
+            return Err(AppError::NotFound(format!("No restaurant found with place_id: {}", place_id)));
+        }
+        store.bookmarks
+            .entry((user_id.to_string(), place_id.to_string()))
+            .or_insert(OffsetDateTime::now_utc().unix_timestamp());
+        Ok(())
+    }
+
+    async fn remove_bookmark(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        match store.bookmarks.remove(&(user_id.to_string(), place_id.to_string())) {
+            Some(_) => Ok(()),
+            None => Err(AppError::NotFound(format!("No bookmark found for place_id: {}", place_id))),
+        }
+    }
+
+    async fn retrieve_bookmarked_places(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Restaurant>> {
+        let store = self.read_store()?;
+        let bookmarks = store.bookmarks
+            .iter()
+            .filter(|((bookmark_user_id, _), _)| bookmark_user_id == user_id)
+            .filter_map(|((_, place_id), timestamp)| {
+                let restaurant = store.places.get(place_id)?;
+                Some((Cursor::new(*timestamp, place_id.clone()), restaurant.clone()))
+            })
+            .collect();
+        Ok(paginate(bookmarks, page, ListOrder::Descending))
+    }
+
+    async fn add_user_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        rating: f64,
+        description: &str,
+        held_back: Option<&str>,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        // Reviews reference places, same as the foreign key on user_reviews
+        if !store.places.contains_key(place_id) {
+            return Err(AppError::NotFound(format!("No restaurant found with place_id: {}", place_id)));
+        }
+        if store.find_review(user_id, place_id).is_some_and(|review| review.deleted_at.is_none()) {
+            return Err(AppError::Conflict(format!("You have already reviewed place_id: {}", place_id)));
+        }
+        store.write_review(user_id, place_id, rating, description, held_back);
+        Ok(())
+    }
+
+    async fn upsert_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        rating: f64,
+        description: &str,
+        held_back: Option<&str>,
+    ) -> AppResult<bool> {
+        let mut store = self.write_store()?;
+        if !store.places.contains_key(place_id) {
+            return Err(AppError::NotFound(format!("No restaurant found with place_id: {}", place_id)));
+        }
+        let created = store.find_review(user_id, place_id).is_none_or(|review| review.deleted_at.is_some());
+        store.write_review(user_id, place_id, rating, description, held_back);
+        Ok(created)
+    }
+
+    async fn remove_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        let review = store.reviews
+            .iter_mut()
+            .find(|review| review.user_id == user_id && review.place_id == place_id && review.deleted_at.is_none())
+            .ok_or_else(|| AppError::NotFound(format!("No review found for place_id: {}", place_id)))?;
+        review.deleted_at = Some(OffsetDateTime::now_utc().unix_timestamp());
+        Ok(())
+    }
+
+    async fn restore_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        deleted_since: i64,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        let review = store.reviews
+            .iter_mut()
+            .find(|review| {
+                review.user_id == user_id
+                    && review.place_id == place_id
+                    && review.deleted_at.is_some_and(|deleted_at| deleted_at >= deleted_since)
+            })
+            .ok_or_else(|| AppError::NotFound(format!("No recently removed review found for place_id: {}", place_id)))?;
+        review.deleted_at = None;
+        Ok(())
+    }
+
+    async fn retrieve_review_history(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<ReviewRevision>> {
+        let after_revision = page.after.as_ref().map(Cursor::numeric_key).transpose()?;
+        let store = self.read_store()?;
+        if !store.find_review(user_id, place_id).is_some_and(is_public) {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
+        }
+
+        // revisions are appended in order, so the latest ones are at the back
+        let revisions = store.review_revisions
+            .iter()
+            .rev()
+            .filter(|revision| revision.user_id == user_id && revision.place_id == place_id)
+            .filter(|revision| {
+                store.published_revisions.contains(&(user_id.to_string(), place_id.to_string(), revision.revision))
+            })
+            .filter(|revision| after_revision.is_none_or(|after_revision| revision.revision < after_revision))
+            .take(usize::try_from(page.fetch_limit()).unwrap_or(0))
+            .map(|revision| (Cursor::new(revision.timestamp, revision.revision.to_string()), revision.clone()))
+            .collect();
+        Ok(Page::from_overfetched(revisions, page.limit))
+    }
+
+    async fn retrieve_restaurant_reviews(
+        &self,
+        place_id: &str,
+        sort: ReviewSort,
+        stars: Option<u8>,
+        page: &PageRequest,
+    ) -> AppResult<Page<RestaurantRating>> {
+        let after = page.after.as_ref().map(|after| sort.position(after)).transpose()?;
+        let after = after.as_ref().map(|after| (after.sort_value, after.timestamp, after.user_id.as_str()));
+        let store = self.read_store()?;
+        let position = |review: &RestaurantRating| (sort.sort_value(review), review.timestamp, review.user_id.clone());
+        let mut reviews: Vec<&RestaurantRating> = store.reviews
+            .iter()
+            .filter(|review| review.place_id == place_id && is_public(review))
+            .filter(|review| stars.is_none_or(|stars| star_index(review.rating) + 1 == usize::from(stars)))
+            .filter(|review| {
+                after.is_none_or(|(sort_value, timestamp, user_id)| {
+                    (sort.sort_value(review), review.timestamp, review.user_id.as_str()) < (sort_value, timestamp, user_id)
+                })
+            })
+            .collect();
+        reviews.sort_by_key(|review| std::cmp::Reverse(position(review)));
+
+        let reviews = reviews
+            .into_iter()
+            .take(usize::try_from(page.fetch_limit()).unwrap_or(0))
+            .map(|review| (sort.cursor(review), review.clone()))
+            .collect();
+        Ok(Page::from_overfetched(reviews, page.limit))
+    }
+
+    async fn get_user_reviews(
+        &self,
+        user_id: &str,
+        include_deleted: bool,
+        page: &PageRequest,
+    ) -> AppResult<Page<RestaurantRating>> {
+        let store = self.read_store()?;
+        let reviews = store.reviews
+            .iter()
+            .filter(|review| review.user_id == user_id && (include_deleted || review.deleted_at.is_none()))
+            .map(|review| (Cursor::new(review.timestamp, review.place_id.clone()), review.clone()))
+            .collect();
+        Ok(paginate(reviews, page, ListOrder::Descending))
+    }
+
+    async fn retrieve_rating_summary(
+        &self,
+        place_id: &str,
+    ) -> AppResult<RatingSummary> {
+        let store = self.read_store()?;
+        // few enough reviews in memory to summarise them on every read
+        Ok(RatingSummary::from_reviews(
+            store.reviews.iter().filter(|review| review.place_id == place_id && is_public(review)),
+            OffsetDateTime::now_utc().unix_timestamp(),
+        ))
+    }
+
+    async fn add_review_photos(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        photos: &[ReviewPhoto],
+        max_photos: usize,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        let review = store.reviews
+            .iter_mut()
+            .find(|review| review.user_id == user_id && review.place_id == place_id && review.deleted_at.is_none())
+            .ok_or_else(|| AppError::NotFound(format!("No review found for place_id: {}", place_id)))?;
+        if review.photos.len() + photos.len() > max_photos {
+            return Err(too_many_photos(max_photos, review.photos.len()));
+        }
+        review.photos.extend_from_slice(photos);
+        Ok(())
+    }
+
+    async fn remove_review_photo(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        photo_id: &str,
+    ) -> AppResult<ReviewPhoto> {
+        let mut store = self.write_store()?;
+        let photos = store.reviews
+            .iter_mut()
+            .find(|review| review.user_id == user_id && review.place_id == place_id)
+            .map(|review| &mut review.photos);
+        let position = photos
+            .as_ref()
+            .and_then(|photos| photos.iter().position(|photo| photo.photo_id == photo_id));
+        match (photos, position) {
+            (Some(photos), Some(position)) => Ok(photos.remove(position)),
+            _ => Err(AppError::NotFound(format!("No photo found with photo_id: {}", photo_id))),
+        }
+    }
+
+    async fn retrieve_published_review_photo(
+        &self,
+        key: &str,
+    ) -> AppResult<Option<ReviewPhoto>> {
+        let store = self.read_store()?;
+        Ok(store.reviews
+            .iter()
+            .filter(|review| review.status == ReviewStatus::Published && review.deleted_at.is_none())
+            .flat_map(|review| review.photos.iter())
+            .find(|photo| photo.storage_key == key || photo.thumbnail_key == key)
+            .cloned())
+    }
+
+    async fn vote_on_review(
+        &self,
+        voter_id: &str,
+        user_id: &str,
+        place_id: &str,
+        helpful: bool,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        if !store.find_review(user_id, place_id).is_some_and(is_public) {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
+        }
+        store.review_votes.insert((voter_id.to_string(), user_id.to_string(), place_id.to_string()), helpful);
+        store.count_votes(user_id, place_id);
+        Ok(())
+    }
+
+    async fn remove_review_vote(
+        &self,
+        voter_id: &str,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        store.review_votes
+            .remove(&(voter_id.to_string(), user_id.to_string(), place_id.to_string()))
+            .ok_or_else(|| AppError::NotFound(format!("No vote of yours found on the review of place_id: {}", place_id)))?;
+        store.count_votes(user_id, place_id);
+        Ok(())
+    }
+
+    async fn report_review(
+        &self,
+        reporter_id: &str,
+        user_id: &str,
+        place_id: &str,
+        reason: &str,
+        hold_after_reports: i64,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        if !store.find_review(user_id, place_id).is_some_and(is_public) {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
+        }
+        if store.open_reports(user_id, place_id).any(|report| report.reporter_id.as_deref() == Some(reporter_id)) {
+            return Err(AppError::Conflict(format!("You have already reported this review of place_id: {}", place_id)));
+        }
+
+        store.file_report(user_id, place_id, Some(reporter_id), reason, OffsetDateTime::now_utc().unix_timestamp());
+        let open_reports = store.open_reports(user_id, place_id).filter(|report| report.reporter_id.is_some()).count();
+        if open_reports as i64 >= hold_after_reports {
+            if let Some(review) = store.reviews.iter_mut().find(|review| review.user_id == user_id && review.place_id == place_id) {
+                review.status = ReviewStatus::Pending;
+            }
+        }
+        Ok(())
+    }
+
+    async fn retrieve_report_queue(
+        &self,
+        page: &PageRequest,
+    ) -> AppResult<Page<ReportedReview>> {
+        let after = match &page.after {
+            Some(after) => Some((after.timestamp, after.numeric_key()?)),
+            None => None,
+        };
+        let store = self.read_store()?;
+        let mut reports: Vec<ReportedReview> = store.review_reports
+            .iter()
+            .filter(|report| report.resolved_at.is_none())
+            .filter(|report| after.is_none_or(|after| (report.created_at, report.id) > after))
+            .filter_map(|report| {
+                let review = store.find_review(&report.user_id, &report.place_id).filter(|review| review.deleted_at.is_none())?;
+                Some(ReportedReview {
+                    report: report.clone(),
+                    review: review.clone(),
+                })
+            })
+            .collect();
+        reports.sort_by_key(|reported| (reported.report.created_at, reported.report.id));
+
+        let reports = reports
+            .into_iter()
+            .take(usize::try_from(page.fetch_limit()).unwrap_or(0))
+            .map(|reported| (Cursor::new(reported.report.created_at, reported.report.id.to_string()), reported))
+            .collect();
+        Ok(Page::from_overfetched(reports, page.limit))
+    }
+
+    async fn moderate_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        status: ReviewStatus,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        let review = store.reviews
+            .iter_mut()
+            .find(|review| review.user_id == user_id && review.place_id == place_id)
+            .ok_or_else(|| AppError::NotFound(format!("No review found for place_id: {}", place_id)))?;
+        review.status = status;
+        if status == ReviewStatus::Published {
+            // the moderator approved what the review says now, not what it said while it was held back before
+            let latest = store.review_revisions
+                .iter()
+                .filter(|revision| revision.user_id == user_id && revision.place_id == place_id)
+                .map(|revision| revision.revision)
+                .max();
+            if let Some(latest) = latest {
+                store.published_revisions.insert((user_id.to_string(), place_id.to_string(), latest));
+            }
+        }
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        store.review_reports
+            .iter_mut()
+            .filter(|report| report.user_id == user_id && report.place_id == place_id && report.resolved_at.is_none())
+            .for_each(|report| report.resolved_at = Some(now));
+        Ok(())
+    }
+
+    async fn add_reservations(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        reservation_timestamp: i64,
+        reservation_pax: u32,
+        overlap_window_secs: i64,
+    ) -> AppResult<Reservation> {
+        let mut store = self.write_store()?;
+        store.check_place_and_overlap(user_id, place_id, reservation_timestamp, overlap_window_secs, None)?;
+        store.check_capacity(place_id, reservation_timestamp, reservation_pax, None)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let reservation = Reservation {
+            reservation_id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            place_id: place_id.to_string(),
+            reservation_timestamp,
+            reservation_pax,
+            status: ReservationStatus::Requested,
+            created_at: now,
+            updated_at: now,
+        };
+        store.reservations.push(reservation.clone());
+        Ok(reservation)
+    }
+
+    async fn modify_reservation(
+        &self,
+        user_id: &str,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        reservation_pax: u32,
+        overlap_window_secs: i64,
+    ) -> AppResult<Reservation> {
+        let mut store = self.write_store()?;
+        let reservation = store.find_reservation_mut(Some(user_id), reservation_id)?;
+        let status = reservation.status.after_change()?;
+        let place_id = reservation.place_id.clone();
+        store.check_place_and_overlap(user_id, &place_id, reservation_timestamp, overlap_window_secs, Some(reservation_id))?;
+        store.check_capacity(&place_id, reservation_timestamp, reservation_pax, Some(reservation_id))?;
+
+        let reservation = store.find_reservation_mut(Some(user_id), reservation_id)?;
+        reservation.status = status;
+        reservation.reservation_timestamp = reservation_timestamp;
+        reservation.reservation_pax = reservation_pax;
+        reservation.updated_at = OffsetDateTime::now_utc().unix_timestamp();
+        Ok(reservation.clone())
+    }
+
+    async fn set_reservation_status(
+        &self,
+        user_id: Option<&str>,
+        reservation_id: &str,
+        status: ReservationStatus,
+    ) -> AppResult<Reservation> {
+        let mut store = self.write_store()?;
+        let reservation = store.find_reservation_mut(user_id, reservation_id)?;
+        reservation.status = reservation.status.transition_to(status)?;
+        reservation.updated_at = OffsetDateTime::now_utc().unix_timestamp();
+        Ok(reservation.clone())
+    }
+
+    async fn set_place_capacity(
+        &self,
+        capacity: &PlaceCapacity,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        if !store.places.contains_key(&capacity.place_id) {
+            return Err(AppError::NotFound(format!("No restaurant found with place_id: {}", capacity.place_id)));
+        }
+        store.place_capacities.insert(capacity.place_id.clone(), capacity.clone());
+        Ok(())
+    }
+
+    async fn retrieve_place_capacity(
+        &self,
+        place_id: &str,
+    ) -> AppResult<Option<PlaceCapacity>> {
+        let store = self.read_store()?;
+        Ok(store.place_capacities.get(place_id).cloned())
+    }
+
+    async fn retrieve_booked_seats(
+        &self,
+        place_id: &str,
+        from: i64,
+        until: i64,
+    ) -> AppResult<HashMap<i64, u32>> {
+        let store = self.read_store()?;
+        let mut booked = HashMap::new();
+        store.reservations
+            .iter()
+            .filter(|reservation| {
+                reservation.place_id == place_id
+                    && (from..until).contains(&reservation.reservation_timestamp)
+                    && reservation.status.is_active()
+            })
+            .for_each(|reservation| *booked.entry(reservation.reservation_timestamp).or_default() += reservation.reservation_pax);
+        Ok(booked)
+    }
+
+    async fn retrieve_requested_reservations(
+        &self,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let store = self.read_store()?;
+        let reservations = store.reservations
+            .iter()
+            .filter(|reservation| reservation.status == ReservationStatus::Requested)
+            .map(|reservation| (reservation_cursor(reservation), reservation.clone()))
+            .collect();
+        Ok(paginate(reservations, page, ListOrder::Ascending))
+    }
+
+    async fn retrieve_all_user_valid_reservations(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let store = self.read_store()?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let reservations = store.reservations
+            .iter()
+            .filter(|reservation| {
+                reservation.user_id == user_id && reservation.reservation_timestamp > now && reservation.status.is_active()
+            })
+            .map(|reservation| (reservation_cursor(reservation), reservation.clone()))
+            .collect();
+        Ok(paginate(reservations, page, ListOrder::Ascending))
+    }
+
+    async fn retrieve_all_user_reservations(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let store = self.read_store()?;
+        let reservations = store.reservations
+            .iter()
+            .filter(|reservation| reservation.user_id == user_id)
+            .map(|reservation| (reservation_cursor(reservation), reservation.clone()))
+            .collect();
+        Ok(paginate(reservations, page, ListOrder::Ascending))
+    }
+
+    async fn retrieve_reservations_starting(
+        &self,
+        from: i64,
+        until: i64,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let store = self.read_store()?;
+        let reservations = store.reservations
+            .iter()
+            .filter(|reservation| {
+                reservation.reservation_timestamp > from
+                    && reservation.reservation_timestamp <= until
+                    && reservation.status.is_active()
+            })
+            .map(|reservation| (reservation_cursor(reservation), reservation.clone()))
+            .collect();
+        Ok(paginate(reservations, page, ListOrder::Ascending))
+    }
+
+    async fn claim_reservation_reminders(
+        &self,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        offsets_secs: &[i64],
+    ) -> AppResult<Vec<i64>> {
+        let mut store = self.write_store()?;
+        Ok(offsets_secs
+            .iter()
+            .copied()
+            .filter(|offset| store.reservation_reminders.insert((reservation_id.to_string(), reservation_timestamp, *offset)))
+            .collect())
+    }
+
+    async fn release_reservation_reminders(
+        &self,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        offsets_secs: &[i64],
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        for offset in offsets_secs {
+            store.reservation_reminders.remove(&(reservation_id.to_string(), reservation_timestamp, *offset));
+        }
+        Ok(())
+    }
+
+    async fn set_calendar_feed_token(
+        &self,
+        user_id: &str,
+        token_hash: &str,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        store.calendar_feed_tokens.insert(user_id.to_string(), token_hash.to_string());
+        Ok(())
+    }
+
+    async fn remove_calendar_feed_token(
+        &self,
+        user_id: &str,
+    ) -> AppResult<()> {
+        let mut store = self.write_store()?;
+        store.calendar_feed_tokens
+            .remove(user_id)
+            .map(|_| ())
+            .ok_or_else(|| AppError::NotFound("No calendar feed to remove".to_string()))
+    }
+
+    async fn retrieve_calendar_feed_user(
+        &self,
+        token_hash: &str,
+    ) -> AppResult<Option<String>> {
+        let store = self.read_store()?;
+        Ok(store.calendar_feed_tokens
+            .iter()
+            .find(|(_, hash)| hash.as_str() == token_hash)
+            .map(|(user_id, _)| user_id.clone()))
+    }
+
+    async fn store_vote_history(
+        &self,
+        user_ids: Vec<String>,
+        voted_places: Value,
+        vote_session_timestamp: i64,
+        outcome: Option<&TallyResult>,
+    ) -> AppResult<()> {
+        let voted_places = match voted_places {
+            Value::Array(places) => places,
+            other => vec![other],
+        };
+        let mut store = self.write_store()?;
+        store.vote_histories.push(VoteHistory {
+            user_ids,
+            vote_timestamp: vote_session_timestamp,
+            voted_places,
+            outcome: outcome.cloned(),
+        });
+        Ok(())
+    }
+
+    async fn retrieve_user_vote_history(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<VoteHistory>> {
+        let after = match &page.after {
+            Some(after) => Some((after.timestamp, after.numeric_key()?)),
+            None => None,
+        };
+        let store = self.read_store()?;
+        // the position in the list stands in for the id postgres assigns, it compares as a number
+        let mut vote_histories: Vec<(i64, i64, &VoteHistory)> = store.vote_histories
+            .iter()
+            .enumerate()
+            .filter(|(_, vote_history)| vote_history.user_ids.iter().any(|voter| voter == user_id))
+            .map(|(id, vote_history)| (vote_history.vote_timestamp, id as i64, vote_history))
+            .filter(|(timestamp, id, _)| after.is_none_or(|after| (*timestamp, *id) < after))
+            .collect();
+        vote_histories.sort_by(|(a_timestamp, a_id, _), (b_timestamp, b_id, _)| {
+            (b_timestamp, b_id).cmp(&(a_timestamp, a_id))
+        });
+
+        let vote_histories = vote_histories
+            .into_iter()
+            .take(usize::try_from(page.fetch_limit()).unwrap_or(0))
+            .map(|(timestamp, id, vote_history)| (Cursor::new(timestamp, id.to_string()), vote_history.clone()))
+            .collect();
+        Ok(Page::from_overfetched(vote_histories, page.limit))
+    }
+}
+
+/// Shown to everyone, the reviews postgres lists and summarises.
+fn is_public(
+    review: &RestaurantRating,
+) -> bool {
+    review.deleted_at.is_none() && review.status == ReviewStatus::Published
+}
+
+/// Listed by time and then by id, like on postgres.
+fn reservation_cursor(
+    reservation: &Reservation,
+) -> Cursor {
+    Cursor::new(reservation.reservation_timestamp, reservation.reservation_id.clone())
+}
+
+enum ListOrder {
+    Ascending,
+    Descending,
+}
+
+/// Orders items by their cursor the way the postgres queries do and cuts out the requested page.
+fn paginate<T>(
+    mut items: Vec<(Cursor, T)>,
+    page: &PageRequest,
+    order: ListOrder,
+) -> Page<T> {
+    let position = |cursor: &Cursor, other: &Cursor| match order {
+        ListOrder::Ascending => cursor.cmp_position(other.timestamp, &other.key).reverse(),
+        ListOrder::Descending => cursor.cmp_position(other.timestamp, &other.key),
+    };
+    items.sort_by(|(a, _), (b, _)| position(a, b));
+    let items = items
+        .into_iter()
+        .filter(|(cursor, _)| page.after.as_ref().is_none_or(|after| position(after, cursor) == Ordering::Less))
+        .take(usize::try_from(page.fetch_limit()).unwrap_or(0))
+        .collect();
+    Page::from_overfetched(items, page.limit)
+}
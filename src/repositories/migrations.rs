@@ -0,0 +1,184 @@
+use anyhow::Context;
+use bb8_postgres::bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use bb8_postgres::tokio_postgres::NoTls;
+use time::OffsetDateTime;
+use tracing::info;
+
+/// Arbitrary key for the postgres advisory lock that stops two instances from migrating at the same time.
+const MIGRATION_LOCK_KEY: i64 = 0x6561_7477_6865_7265;
+
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every schema change, in the order it has to be applied. New migrations go at the end with the next
+/// version number, applied migrations must never be edited.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("../../migrations/0001_initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "users",
+        sql: include_str!("../../migrations/0002_users.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "reconcile_timestamp_columns",
+        sql: include_str!("../../migrations/0003_reconcile_timestamp_columns.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "vote_outcomes",
+        sql: include_str!("../../migrations/0004_vote_outcomes.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "places_spatial_index",
+        sql: include_str!("../../migrations/0005_places_spatial_index.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "places_search",
+        sql: include_str!("../../migrations/0006_places_search.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "list_pagination",
+        sql: include_str!("../../migrations/0007_list_pagination.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "place_rating_summaries",
+        sql: include_str!("../../migrations/0008_place_rating_summaries.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "review_uniqueness",
+        sql: include_str!("../../migrations/0009_review_uniqueness.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "review_history",
+        sql: include_str!("../../migrations/0010_review_history.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "review_moderation",
+        sql: include_str!("../../migrations/0011_review_moderation.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "review_votes",
+        sql: include_str!("../../migrations/0012_review_votes.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "review_photos",
+        sql: include_str!("../../migrations/0013_review_photos.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "reservation_lifecycle",
+        sql: include_str!("../../migrations/0014_reservation_lifecycle.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "place_capacities",
+        sql: include_str!("../../migrations/0015_place_capacities.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "calendar_feeds",
+        sql: include_str!("../../migrations/0016_calendar_feeds.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "reservation_reminders",
+        sql: include_str!("../../migrations/0017_reservation_reminders.sql"),
+    },
+    Migration {
+        version: 18,
+        name: "review_photo_keys",
+        sql: include_str!("../../migrations/0018_review_photo_keys.sql"),
+    },
+    Migration {
+        version: 19,
+        name: "public_review_revisions",
+        sql: include_str!("../../migrations/0019_public_review_revisions.sql"),
+    },
+    Migration {
+        version: 20,
+        name: "photos_through_the_api",
+        sql: include_str!("../../migrations/0020_photos_through_the_api.sql"),
+    },
+];
+
+/// Applies every migration that is not yet recorded in `schema_migrations`, each in its own transaction.
+/// Returns the versions that were applied by this call.
+pub async fn run_migrations(
+    pool: &Pool<PostgresConnectionManager<NoTls>>,
+) -> anyhow::Result<Vec<i32>> {
+    let mut conn = pool
+        .get()
+        .await
+        .context("Failed to retrieve a postgres connection for migrations")?;
+
+    conn
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations \
+            (version int primary key, name varchar not null, applied_at bigint not null);",
+        )
+        .await
+        .context("Failed to create the schema_migrations table")?;
+    conn
+        .execute("SELECT pg_advisory_lock($1);", &[&MIGRATION_LOCK_KEY])
+        .await
+        .context("Failed to acquire the migration lock")?;
+
+    let mut applied_versions = Vec::new();
+    let result: anyhow::Result<()> = async {
+        for migration in MIGRATIONS {
+            let transaction = conn.transaction().await?;
+            let already_applied = transaction
+                .query_opt(
+                    "SELECT version FROM schema_migrations WHERE version = $1;",
+                    &[&migration.version],
+                )
+                .await?
+                .is_some();
+            if already_applied {
+                continue;
+            }
+
+            info!("Applying migration {:04}_{}", migration.version, migration.name);
+            transaction
+                .batch_execute(migration.sql)
+                .await
+                .with_context(|| format!("Migration {:04}_{} failed", migration.version, migration.name))?;
+            transaction
+                .execute(
+                    "INSERT INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3);",
+                    &[&migration.version, &migration.name, &OffsetDateTime::now_utc().unix_timestamp()],
+                )
+                .await?;
+            transaction.commit().await?;
+
+            applied_versions.push(migration.version);
+        }
+        Ok(())
+    }.await;
+
+    conn
+        .execute("SELECT pg_advisory_unlock($1);", &[&MIGRATION_LOCK_KEY])
+        .await
+        .context("Failed to release the migration lock")?;
+    result?;
+
+    Ok(applied_versions)
+}
@@ -1 +1,363 @@
-pub mod postgres_repo;
\ No newline at end of file
+use std::collections::HashMap;
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::models::capacity::PlaceCapacity;
+use crate::models::moderation::ReportedReview;
+use crate::models::page::{Page, PageRequest};
+use crate::models::rating::{RatingSummary, RestaurantRating, ReviewRevision, ReviewSort, ReviewStatus};
+use crate::models::reservation::{Reservation, ReservationStatus};
+use crate::models::review_photo::ReviewPhoto;
+use crate::models::restaurant::{NearbyRestaurant, NearbyRestaurantsQuery, Restaurant, RestaurantSearchResult};
+use crate::models::user::User;
+use crate::models::vote::VoteHistory;
+use crate::voting::tally::TallyResult;
+
+pub mod in_memory_repo;
+pub mod migrations;
+pub mod postgres_repo;
+
+/// How much a close spelling in the vicinity counts towards a search score compared to one in the name.
+pub const SEARCH_VICINITY_WEIGHT: f64 = 0.5;
+
+/// The error both backends give when photos would not fit on a review that has `photo_count` already.
+pub(crate) fn too_many_photos(
+    max_photos: usize,
+    photo_count: usize,
+) -> AppError {
+    AppError::Validation(format!("a review holds at most {} photos, it has {} already", max_photos, photo_count))
+}
+
+/// Storage used by the controllers. Every backend has to behave the same way from the point of view
+/// of the API, so the in-memory implementation mirrors the semantics of the postgres schema.
+#[async_trait]
+pub trait Repository: Send + Sync {
+    async fn create_user(
+        &self,
+        user: &User,
+    ) -> AppResult<()>;
+
+    async fn retrieve_user_by_username(
+        &self,
+        username: &str,
+    ) -> AppResult<Option<User>>;
+
+    async fn store_browsed_places(
+        &self,
+        list_of_restaurants: Vec<Restaurant>,
+    ) -> AppResult<()>;
+
+    async fn retrieve_restaurant(
+        &self,
+        place_id: &str,
+    ) -> AppResult<Option<Restaurant>>;
+
+    /// Stored places whose name or vicinity matches the query, by words, by a close spelling or literally,
+    /// best match first.
+    async fn search_for_restaurants(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> AppResult<Vec<RestaurantSearchResult>>;
+
+    /// Stored places within the radius of the query that pass its filters, closest first.
+    async fn retrieve_restaurants_near(
+        &self,
+        query: &NearbyRestaurantsQuery,
+    ) -> AppResult<Vec<NearbyRestaurant>>;
+
+    async fn bookmark_place(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()>;
+
+    async fn remove_bookmark(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()>;
+
+    /// Most recently bookmarked first.
+    async fn retrieve_bookmarked_places(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Restaurant>>;
+
+    /// Fails with a conflict when the user already reviewed the place, and with not found for unknown places.
+    /// A removed review counts as not there and is written over.
+    ///
+    /// With a `held_back` reason the review waits for a moderator and the reason is filed as a report,
+    /// otherwise it keeps the status it had. New reviews are published.
+    async fn add_user_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        rating: f64,
+        description: &str,
+        held_back: Option<&str>,
+    ) -> AppResult<()>;
+
+    /// Replaces the review of the user for the place, or writes it if there is none. Returns whether the
+    /// review was created. `held_back` works as for `add_user_review`.
+    async fn upsert_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        rating: f64,
+        description: &str,
+        held_back: Option<&str>,
+    ) -> AppResult<bool>;
+
+    /// Marks the review deleted, it stays restorable through `restore_review`.
+    async fn remove_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()>;
+
+    /// Brings back a review removed at or after `deleted_since`, not found for anything removed earlier.
+    async fn restore_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        deleted_since: i64,
+    ) -> AppResult<()>;
+
+    /// The versions of a published review that were published when they were written, or by a moderator later,
+    /// newest first. Not found once the review is removed.
+    async fn retrieve_review_history(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<ReviewRevision>>;
+
+    /// Only published reviews that were not removed, in the order of `sort`. With `stars` only the reviews
+    /// that round to that many stars, the same rounding as the summary histogram.
+    async fn retrieve_restaurant_reviews(
+        &self,
+        place_id: &str,
+        sort: ReviewSort,
+        stars: Option<u8>,
+        page: &PageRequest,
+    ) -> AppResult<Page<RestaurantRating>>;
+
+    /// Newest first whatever their status, removed reviews only when `include_deleted` is set.
+    async fn get_user_reviews(
+        &self,
+        user_id: &str,
+        include_deleted: bool,
+        page: &PageRequest,
+    ) -> AppResult<Page<RestaurantRating>>;
+
+    /// Aggregates of the published reviews of a place, an empty summary when it has none.
+    async fn retrieve_rating_summary(
+        &self,
+        place_id: &str,
+    ) -> AppResult<RatingSummary>;
+
+    /// Attaches photos to the review of the user for the place, after the ones it has. Not found when the user
+    /// has no review there, and rejected when the review would hold more than `max_photos`.
+    async fn add_review_photos(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        photos: &[ReviewPhoto],
+        max_photos: usize,
+    ) -> AppResult<()>;
+
+    /// Detaches a photo from the review of the user for the place and hands it back, so it can be removed
+    /// from the photo storage.
+    async fn remove_review_photo(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        photo_id: &str,
+    ) -> AppResult<ReviewPhoto>;
+
+    /// The photo stored under `key`, as the photo itself or its thumbnail, while its review is published and
+    /// not removed.
+    async fn retrieve_published_review_photo(
+        &self,
+        key: &str,
+    ) -> AppResult<Option<ReviewPhoto>>;
+
+    /// Records whether the voter found a published review helpful, replacing their earlier vote on it.
+    async fn vote_on_review(
+        &self,
+        voter_id: &str,
+        user_id: &str,
+        place_id: &str,
+        helpful: bool,
+    ) -> AppResult<()>;
+
+    /// Takes back the vote of the voter on a review, not found when they have none.
+    async fn remove_review_vote(
+        &self,
+        voter_id: &str,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()>;
+
+    /// Files a report against a published review. A user can have one open report per review, another one
+    /// is a conflict. Once `hold_after_reports` users have open reports the review waits for a moderator.
+    async fn report_review(
+        &self,
+        reporter_id: &str,
+        user_id: &str,
+        place_id: &str,
+        reason: &str,
+        hold_after_reports: i64,
+    ) -> AppResult<()>;
+
+    /// Open reports on reviews that were not removed, oldest first.
+    async fn retrieve_report_queue(
+        &self,
+        page: &PageRequest,
+    ) -> AppResult<Page<ReportedReview>>;
+
+    /// Settles the status of a review and closes its open reports. Publishing it publishes its latest version.
+    async fn moderate_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        status: ReviewStatus,
+    ) -> AppResult<()>;
+
+    /// Books a new reservation, requested until it is confirmed. A user can hold any number of reservations
+    /// at a place. At a place with a capacity the reservation has to fit in its slot, checked and booked at
+    /// once so two bookings cannot both take the last seats. The place has to be stored, and the user cannot
+    /// hold another requested or confirmed reservation less than `overlap_window_secs` away.
+    async fn add_reservations(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        reservation_timestamp: i64,
+        reservation_pax: u32,
+        overlap_window_secs: i64,
+    ) -> AppResult<Reservation>;
+
+    /// Moves a requested or confirmed reservation of the user to another time or party size, checked against
+    /// the capacity of the place and the other reservations of the user like a new one. The changed reservation
+    /// is requested again, anything else is a conflict.
+    async fn modify_reservation(
+        &self,
+        user_id: &str,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        reservation_pax: u32,
+        overlap_window_secs: i64,
+    ) -> AppResult<Reservation>;
+
+    /// Moves a reservation along its lifecycle, see `ReservationStatus::transition_to`. With a `user_id`
+    /// only a reservation of that user is found.
+    async fn set_reservation_status(
+        &self,
+        user_id: Option<&str>,
+        reservation_id: &str,
+        status: ReservationStatus,
+    ) -> AppResult<Reservation>;
+
+    /// Sets up or replaces the seats and opening hours of a place. Not found for places that were never stored.
+    async fn set_place_capacity(
+        &self,
+        capacity: &PlaceCapacity,
+    ) -> AppResult<()>;
+
+    async fn retrieve_place_capacity(
+        &self,
+        place_id: &str,
+    ) -> AppResult<Option<PlaceCapacity>>;
+
+    /// Seats taken by requested and confirmed reservations at the place starting from `from` and before
+    /// `until`, by reservation time.
+    async fn retrieve_booked_seats(
+        &self,
+        place_id: &str,
+        from: i64,
+        until: i64,
+    ) -> AppResult<HashMap<i64, u32>>;
+
+    /// Reservations of every user waiting to be confirmed, soonest first.
+    async fn retrieve_requested_reservations(
+        &self,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>>;
+
+    /// Requested and confirmed reservations still to come, soonest first.
+    async fn retrieve_all_user_valid_reservations(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>>;
+
+    /// Earliest first.
+    async fn retrieve_all_user_reservations(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>>;
+
+    /// Requested and confirmed reservations of every user starting after `from` and at the latest `until`,
+    /// soonest first.
+    async fn retrieve_reservations_starting(
+        &self,
+        from: i64,
+        until: i64,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>>;
+
+    /// Records the reminders `offsets_secs` before a reservation at `reservation_timestamp` as sent, and hands
+    /// back the offsets that were not recorded yet. Two schedulers claiming the same reminder never both get it.
+    async fn claim_reservation_reminders(
+        &self,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        offsets_secs: &[i64],
+    ) -> AppResult<Vec<i64>>;
+
+    /// Forgets claimed reminders that could not be sent, so they are claimed again.
+    async fn release_reservation_reminders(
+        &self,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        offsets_secs: &[i64],
+    ) -> AppResult<()>;
+
+    /// Sets the hash of the token the calendar feed of the user is reached with, the previous token stops working.
+    async fn set_calendar_feed_token(
+        &self,
+        user_id: &str,
+        token_hash: &str,
+    ) -> AppResult<()>;
+
+    /// Not found when the user has no calendar feed.
+    async fn remove_calendar_feed_token(
+        &self,
+        user_id: &str,
+    ) -> AppResult<()>;
+
+    /// The user whose calendar feed token hashes to `token_hash`.
+    async fn retrieve_calendar_feed_user(
+        &self,
+        token_hash: &str,
+    ) -> AppResult<Option<String>>;
+
+    async fn store_vote_history(
+        &self,
+        user_ids: Vec<String>,
+        voted_places: Value,
+        vote_session_timestamp: i64,
+        outcome: Option<&TallyResult>,
+    ) -> AppResult<()>;
+
+    /// Most recent session first.
+    async fn retrieve_user_vote_history(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<VoteHistory>>;
+}
@@ -1,17 +1,30 @@
-use anyhow::anyhow;
+use std::collections::HashMap;
+use async_trait::async_trait;
 use bb8_postgres::bb8::{Pool, PooledConnection};
 use bb8_postgres::PostgresConnectionManager;
-use bb8_postgres::tokio_postgres::{NoTls, Row};
+use bb8_postgres::tokio_postgres::{Client, NoTls, Row, Transaction};
 use serde_json::Value;
-use time::{OffsetDateTime, Time};
+use time::OffsetDateTime;
 use tracing::warn;
-use crate::models::rating::RestaurantRating;
-use crate::models::reservation::Reservation;
-use crate::models::restaurant::{Location, Photo, Restaurant};
+use uuid::Uuid;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::geo::EARTH_RADIUS_METRES;
+use crate::helpers::text_search::WORD_SIMILARITY_THRESHOLD;
+use crate::models::capacity::PlaceCapacity;
+use crate::models::moderation::{ReportedReview, ReviewReport};
+use crate::models::page::{Cursor, Page, PageRequest};
+use crate::models::rating::{RatingSummary, RestaurantRating, ReviewRevision, ReviewSort, ReviewStatus, RECENT_RATING_WINDOW_SECS};
+use crate::models::reservation::{overlapping_reservation, reservation_at_unknown_place, Reservation, ReservationStatus};
+use crate::models::review_photo::ReviewPhoto;
+use crate::models::restaurant::{Location, NearbyRestaurant, NearbyRestaurantsQuery, Photo, Restaurant, RestaurantSearchResult};
+use crate::models::user::User;
 use crate::models::vote::VoteHistory;
+use crate::repositories::{too_many_photos, Repository, SEARCH_VICINITY_WEIGHT};
+use crate::voting::tally::TallyResult;
 
 pub const RETRY_LIMIT: usize = 5;
 
+#[derive(Clone)]
 pub struct PostgresConnectionRepo {
     postgres_connection: Pool<PostgresConnectionManager<NoTls>>,
 }
@@ -27,7 +40,7 @@ impl PostgresConnectionRepo {
 
     async fn get_postgres_connection(
         &self,
-    ) -> anyhow::Result<PooledConnection<PostgresConnectionManager<NoTls>>> {
+    ) -> AppResult<PooledConnection<'_, PostgresConnectionManager<NoTls>>> {
         for _ in 0..RETRY_LIMIT {
             match self.postgres_connection.get().await {
                 Ok(conn) => return Ok(conn),
@@ -39,541 +52,1535 @@ impl PostgresConnectionRepo {
             }
         }
 
-        return Err(anyhow!("Failed to retrieve a valid connection from postgres pool, BAILING"));
+        Err(AppError::Database("Failed to retrieve a valid connection from postgres pool, BAILING".to_string()))
     }
+}
 
-    pub async fn store_browsed_places(
+#[async_trait]
+impl Repository for PostgresConnectionRepo {
+    async fn create_user(
         &self,
-        list_of_restaurants: Vec<Restaurant>,
-    ) -> anyhow::Result<()> {
+        user: &User,
+    ) -> AppResult<()> {
         let conn = self.get_postgres_connection().await?;
-        let mut stmt = String::from("INSERT INTO places \
-            (place_id, name, photo_height, photo_width, photo_reference, rating, vicinity, lat, lng) \
-            VALUES ");
+        conn
+            .execute(
+                "INSERT INTO users (user_id, username, password_hash, created_at) VALUES ($1, $2, $3, $4);",
+                &[&user.user_id, &user.username, &user.password_hash, &user.created_at],
+            )
+            .await
+            .map_err(|e| match AppError::from(e) {
+                AppError::Conflict(_) => AppError::Conflict(format!("Username {} is already taken", user.username)),
+                other => other,
+            })?;
 
-        for restaurant in list_of_restaurants {
-            let item = format!(
-                "('{}', '{}', {}, {}, '{}', {}, '{}', {}, {}),",
-                restaurant.place_id,
-                restaurant.name,
-                restaurant.photos.height,
-                restaurant.photos.width,
-                restaurant.photos.photo_reference,
-                restaurant.rating,
-                restaurant.vicinity,
-                restaurant.geometry.lat,
-                restaurant.geometry.lng,
-            );
-            stmt.push_str(&item);
-        }
-        stmt.remove(stmt.len() - 1);
-        stmt.push_str(" ON CONFLICT DO NOTHING;");
-
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to insert places into table due to: {}", e);
-            }
-        }
         Ok(())
     }
 
-    pub async fn retrieve_restaurant(
+    async fn retrieve_user_by_username(
         &self,
-        place_id: &String,
-    ) -> anyhow::Result<Option<Restaurant>> {
+        username: &str,
+    ) -> AppResult<Option<User>> {
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * FROM places where place_id = '{}' limit 1;",
-            place_id
-        );
+        let row = conn
+            .query_opt(
+                "SELECT * FROM users WHERE username = $1;",
+                &[&username],
+            )
+            .await?;
 
-        let res = conn
-            .query(&stmt, &[])
-            .await;
-
-        match res {
-            Ok(rows) => {
-                if rows.len() > 0 {
-                    for row in rows {
-                        let restaurant = parse_row_into_restaurant(row);
-                        return Ok(Some(restaurant));
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("Ran into an issue retrieving the restaurant with id: {}, due to: {}", place_id, e);
-            }
-        }
-        Ok(None)
+        Ok(row.map(parse_row_into_user))
     }
 
-    pub async fn search_for_restaurants(
+    async fn store_browsed_places(
         &self,
-        restaurant_name: &String,
-    ) -> anyhow::Result<Vec<Restaurant>> {
+        list_of_restaurants: Vec<Restaurant>,
+    ) -> AppResult<()> {
+        if list_of_restaurants.is_empty() {
+            return Ok(());
+        }
+
+        // Every column is sent as an array parameter and unnested server side, so the whole
+        // batch goes through a single prepared statement regardless of how many places we got.
+        let mut place_ids = Vec::with_capacity(list_of_restaurants.len());
+        let mut names = Vec::with_capacity(list_of_restaurants.len());
+        let mut photo_heights = Vec::with_capacity(list_of_restaurants.len());
+        let mut photo_widths = Vec::with_capacity(list_of_restaurants.len());
+        let mut photo_references = Vec::with_capacity(list_of_restaurants.len());
+        let mut ratings = Vec::with_capacity(list_of_restaurants.len());
+        let mut price_levels = Vec::with_capacity(list_of_restaurants.len());
+        let mut vicinities = Vec::with_capacity(list_of_restaurants.len());
+        let mut lats = Vec::with_capacity(list_of_restaurants.len());
+        let mut lngs = Vec::with_capacity(list_of_restaurants.len());
+        for restaurant in list_of_restaurants {
+            place_ids.push(restaurant.place_id);
+            names.push(restaurant.name);
+            // places without a photo keep all three photo columns null
+            photo_heights.push(restaurant.photos.as_ref().map(|photo| photo.height as i32));
+            photo_widths.push(restaurant.photos.as_ref().map(|photo| photo.width as i32));
+            photo_references.push(restaurant.photos.map(|photo| photo.photo_reference));
+            ratings.push(restaurant.rating);
+            price_levels.push(restaurant.price_level.map(i16::from));
+            vicinities.push(restaurant.vicinity);
+            lats.push(restaurant.geometry.lat);
+            lngs.push(restaurant.geometry.lng);
+        }
+
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * FROM places WHERE name ILIKE '%{}%'",
-            restaurant_name
-        );
+        conn
+            .execute(
+                "INSERT INTO places \
+                (place_id, name, photo_height, photo_width, photo_reference, rating, vicinity, lat, lng, price_level) \
+                SELECT * FROM UNNEST(\
+                $1::varchar[], $2::varchar[], $3::int[], $4::int[], $5::varchar[], \
+                $6::double precision[], $7::varchar[], $8::double precision[], $9::double precision[], \
+                $10::smallint[]) \
+                ON CONFLICT DO NOTHING;",
+                &[
+                    &place_ids,
+                    &names,
+                    &photo_heights,
+                    &photo_widths,
+                    &photo_references,
+                    &ratings,
+                    &vicinities,
+                    &lats,
+                    &lngs,
+                    &price_levels,
+                ],
+            )
+            .await?;
 
-        let res = conn
-            .query(&stmt, &[])
-            .await;
+        Ok(())
+    }
 
-        let mut restaurants = Vec::new();
-        match res {
-            Ok(rows) => {
-                for row in rows {
-                    let restaurant = parse_row_into_restaurant(row);
+    async fn retrieve_restaurant(
+        &self,
+        place_id: &str,
+    ) -> AppResult<Option<Restaurant>> {
+        let conn = self.get_postgres_connection().await?;
+        let row = conn
+            .query_opt(
+                "SELECT * FROM places where place_id = $1 limit 1;",
+                &[&place_id],
+            )
+            .await?;
 
-                    restaurants.push(restaurant);
-                }
-            }
-            Err(e) => {
-                warn!("Ran into an error retrieving restaurants due to: {}", e);
-            }
-        }
+        Ok(row.map(parse_row_into_restaurant))
+    }
 
-        Ok(restaurants)
+    async fn search_for_restaurants(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> AppResult<Vec<RestaurantSearchResult>> {
+        let mut conn = self.get_postgres_connection().await?;
+        let pattern = format!("%{}%", escape_like_pattern(query));
+        // the threshold behind <% is a setting, set_config(.., true) keeps it to this transaction
+        let transaction = conn.transaction().await?;
+        transaction
+            .execute(
+                "SELECT set_config('pg_trgm.word_similarity_threshold', $1, true);",
+                &[&WORD_SIMILARITY_THRESHOLD.to_string()],
+            )
+            .await?;
+        // every condition can use an index: search_vector and the trigram indexes on name and vicinity
+        let rows = transaction
+            .query(
+                "SELECT * FROM ( \
+                    SELECT *, \
+                        ts_rank_cd(search_vector, websearch_to_tsquery('simple', $1)) \
+                        + word_similarity($1, name) \
+                        + $5::float8 * word_similarity($1, coalesce(vicinity, '')) \
+                        + CASE WHEN name ILIKE $2 THEN 1 ELSE 0 END AS score \
+                    FROM places \
+                    WHERE search_vector @@ websearch_to_tsquery('simple', $1) \
+                    OR $1 <% name OR $1 <% vicinity \
+                    OR name ILIKE $2 OR vicinity ILIKE $2 \
+                ) matches ORDER BY score DESC, place_id LIMIT $3 OFFSET $4",
+                &[&query, &pattern, &limit, &offset, &SEARCH_VICINITY_WEIGHT],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| RestaurantSearchResult {
+                score: row.get("score"),
+                restaurant: parse_row_into_restaurant(row),
+            })
+            .collect())
     }
 
-    pub async fn bookmark_place(
+    async fn retrieve_restaurants_near(
         &self,
-        user_id: &String,
-        place_id: &String,
-    ) -> anyhow::Result<()> {
+        query: &NearbyRestaurantsQuery,
+    ) -> AppResult<Vec<NearbyRestaurant>> {
         let conn = self.get_postgres_connection().await?;
-        let mut stmt = String::from("INSERT INTO user_favourite_places (user_id, place_id, timestamp) VALUES ");
-        let params = format!(
-            "('{}', '{}', '{}')",
-            user_id,
-            place_id,
-            OffsetDateTime::now_utc()
-        );
-        stmt.push_str(&params);
-        stmt.push_str(" ON CONFLICT DO NOTHING;");
-
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to bookmark restaurant for user: {}, due to: {}", user_id, e);
-            }
-        }
+        // earth_box goes through the places_location_idx gist index to narrow the search down to a box around
+        // the point, earthdistance measures on a slightly larger sphere so the radius is scaled up to match.
+        // The exact distance is haversine like everywhere else, least() guards asin against rounding above 1.
+        let rows = conn
+            .query(
+                "SELECT * FROM ( \
+                    SELECT *, 2 * $3::float8 * asin(least(1, sqrt( \
+                        power(sin(radians(lat - $1) / 2), 2) \
+                        + cos(radians($1)) * cos(radians(lat)) * power(sin(radians(lng - $2) / 2), 2) \
+                    ))) AS distance_metres \
+                    FROM places \
+                    WHERE earth_box(ll_to_earth($1, $2), $4 * earth() / $3) @> ll_to_earth(lat, lng) \
+                    AND ($6::float8 IS NULL OR rating >= $6) \
+                    AND ($7::smallint IS NULL OR price_level >= $7) \
+                    AND ($8::smallint IS NULL OR price_level <= $8) \
+                ) nearby WHERE distance_metres <= $4 ORDER BY distance_metres, place_id LIMIT $5",
+                &[
+                    &query.location.lat,
+                    &query.location.lng,
+                    &EARTH_RADIUS_METRES,
+                    &query.radius_metres,
+                    &query.limit,
+                    &query.min_rating,
+                    &query.min_price_level.map(i16::from),
+                    &query.max_price_level.map(i16::from),
+                ],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| NearbyRestaurant {
+                distance_metres: row.get("distance_metres"),
+                restaurant: parse_row_into_restaurant(row),
+            })
+            .collect())
+    }
+
+    async fn bookmark_place(
+        &self,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
+        let conn = self.get_postgres_connection().await?;
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+
+        conn
+            .execute(
+                "INSERT INTO user_favourite_places (user_id, place_id, timestamp) VALUES ($1, $2, $3) \
+                ON CONFLICT DO NOTHING;",
+                &[&user_id, &place_id, &timestamp],
+            )
+            .await
+            .map_err(|e| unknown_place(e, place_id))?;
 
         Ok(())
     }
 
-    pub async fn remove_bookmark(
+    async fn remove_bookmark(
         &self,
-        user_id: &String,
-        place_id: &String,
-    ) -> anyhow::Result<()> {
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "DELETE FROM user_favourite_places where user_id = '{}' and place_id = '{}';",
-            user_id,
-            place_id
-        );
+        let removed = conn
+            .execute(
+                "DELETE FROM user_favourite_places where user_id = $1 and place_id = $2;",
+                &[&user_id, &place_id],
+            )
+            .await?;
 
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to remove bookmarked restaurant for user: {}, due to: {}", user_id, e);
-            }
+        if removed == 0 {
+            return Err(AppError::NotFound(format!("No bookmark found for place_id: {}", place_id)));
         }
-
         Ok(())
     }
 
-    pub async fn retrieve_bookmarked_places(
+    async fn retrieve_bookmarked_places(
         &self,
-        user_id: &String,
-    ) -> anyhow::Result<Vec<Restaurant>> {
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Restaurant>> {
+        let (after_timestamp, after_key) = cursor_params(page);
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * from places where place_id in (SELECT place_id FROM user_favourite_places where user_id = '{}');",
-            user_id,
-        );
+        let rows = conn
+            .query(
+                "SELECT p.*, f.timestamp AS bookmarked_at FROM user_favourite_places f \
+                JOIN places p ON p.place_id = f.place_id \
+                WHERE f.user_id = $1 AND ($3::bigint IS NULL OR (f.timestamp, f.place_id) < ($3, $4)) \
+                ORDER BY f.timestamp DESC, f.place_id DESC LIMIT $2;",
+                &[&user_id, &page.fetch_limit(), &after_timestamp, &after_key],
+            )
+            .await?;
+
+        let bookmarks = rows
+            .into_iter()
+            .map(|row| {
+                let bookmarked_at = row.get::<&str, i64>("bookmarked_at");
+                let restaurant = parse_row_into_restaurant(row);
+                (Cursor::new(bookmarked_at, restaurant.place_id.clone()), restaurant)
+            })
+            .collect();
+        Ok(Page::from_overfetched(bookmarks, page.limit))
+    }
 
-        let mut favourite_restaurants: Vec<Restaurant> = Vec::new();
-        let res = conn
-            .query(&stmt, &[])
-            .await;
-        match res {
-            Ok(rows) => {
-                for row in rows {
-                    let restaurant = parse_row_into_restaurant(row);
+    async fn add_user_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        rating: f64,
+        description: &str,
+        held_back: Option<&str>,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+        let status = written_status(held_back);
+
+        let transaction = conn.transaction().await?;
+        lock_rating_summary(&transaction, place_id).await?;
+        // a removed review is written over, one that is still up is a conflict
+        let written = transaction
+            .execute(
+                "INSERT INTO user_reviews (user_id, place_id, rating, description, timestamp, status) \
+                VALUES ($1, $2, $3, $4, $5, $6) \
+                ON CONFLICT (user_id, place_id) DO UPDATE \
+                SET rating = excluded.rating, description = excluded.description, timestamp = excluded.timestamp, \
+                    deleted_at = NULL, status = CASE WHEN excluded.status = 'pending' THEN 'pending' ELSE user_reviews.status END \
+                WHERE user_reviews.deleted_at IS NOT NULL;",
+                &[&user_id, &place_id, &rating, &description, &timestamp, &status.as_str()],
+            )
+            .await
+            .map_err(|e| unknown_place(e, place_id))?;
 
-                    favourite_restaurants.push(restaurant);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to retrieve bookmarked restaurant for user: {}, due to: {}", user_id, e);
-            }
+        if written == 0 {
+            return Err(AppError::Conflict(format!("You have already reviewed place_id: {}", place_id)));
+        }
+        record_revision(&transaction, user_id, place_id, rating, description, timestamp).await?;
+        if let Some(reason) = held_back {
+            file_filter_report(&transaction, user_id, place_id, reason, timestamp).await?;
         }
+        refresh_rating_summary(&transaction, place_id).await?;
+        transaction.commit().await?;
 
-        Ok(favourite_restaurants)
+        Ok(())
     }
 
-    pub async fn add_user_review(
+    async fn upsert_review(
         &self,
-        user_id: &String,
-        place_id: &String,
+        user_id: &str,
+        place_id: &str,
         rating: f64,
-        description: &String,
-    ) -> anyhow::Result<()> {
-        let conn = self.get_postgres_connection().await?;
-        let mut stmt = String::from("INSERT INTO user_reviews (user_id, place_id, rating, description, timestamp) VALUES ");
-        let params = format!(
-            "('{}', '{}', '{}', '{}', '{}')",
-            user_id,
-            place_id,
-            rating,
-            description,
-            OffsetDateTime::now_utc().unix_timestamp(),
-        );
-        stmt.push_str(&params);
-        stmt.push_str(" ON CONFLICT DO NOTHING;");
-
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to add review to restaurant for user: {}, due to: {}", user_id, e);
-            }
+        description: &str,
+        held_back: Option<&str>,
+    ) -> AppResult<bool> {
+        let mut conn = self.get_postgres_connection().await?;
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+        let status = written_status(held_back);
+
+        let transaction = conn.transaction().await?;
+        lock_rating_summary(&transaction, place_id).await?;
+        let existing = transaction
+            .query_opt(
+                "SELECT deleted_at FROM user_reviews WHERE user_id = $1 AND place_id = $2;",
+                &[&user_id, &place_id],
+            )
+            .await?;
+        let created = existing.is_none_or(|row| row.get::<&str, Option<i64>>("deleted_at").is_some());
+
+        transaction
+            .execute(
+                "INSERT INTO user_reviews (user_id, place_id, rating, description, timestamp, status) \
+                VALUES ($1, $2, $3, $4, $5, $6) \
+                ON CONFLICT (user_id, place_id) DO UPDATE \
+                SET rating = excluded.rating, description = excluded.description, timestamp = excluded.timestamp, \
+                    deleted_at = NULL, status = CASE WHEN excluded.status = 'pending' THEN 'pending' ELSE user_reviews.status END;",
+                &[&user_id, &place_id, &rating, &description, &timestamp, &status.as_str()],
+            )
+            .await
+            .map_err(|e| unknown_place(e, place_id))?;
+        record_revision(&transaction, user_id, place_id, rating, description, timestamp).await?;
+        if let Some(reason) = held_back {
+            file_filter_report(&transaction, user_id, place_id, reason, timestamp).await?;
         }
+        refresh_rating_summary(&transaction, place_id).await?;
+        transaction.commit().await?;
 
-        Ok(())
+        Ok(created)
     }
 
-    pub async fn update_review(
+    async fn remove_review(
         &self,
-        user_id: &String,
-        place_id: &String,
-        rating: f64,
-        description: &String,
-    ) -> anyhow::Result<()> {
-        let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "UPDATE user_reviews SET rating = {}, timestamp = {}, description = '{}' where user_id = '{}' and place_id = '{}';",
-            rating,
-            OffsetDateTime::now_utc().unix_timestamp(),
-            description,
-            user_id,
-            place_id
-        );
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let transaction = conn.transaction().await?;
+        lock_rating_summary(&transaction, place_id).await?;
+        let removed = transaction
+            .execute(
+                "UPDATE user_reviews SET deleted_at = $3 \
+                where user_id = $1 and place_id = $2 and deleted_at IS NULL;",
+                &[&user_id, &place_id, &now],
+            )
+            .await?;
 
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to update review of restaurant for user: {}, due to: {}", user_id, e);
-            }
+        if removed == 0 {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
         }
+        refresh_rating_summary(&transaction, place_id).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
 
+    async fn restore_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        deleted_since: i64,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let transaction = conn.transaction().await?;
+        lock_rating_summary(&transaction, place_id).await?;
+        let restored = transaction
+            .execute(
+                "UPDATE user_reviews SET deleted_at = NULL \
+                where user_id = $1 and place_id = $2 and deleted_at >= $3;",
+                &[&user_id, &place_id, &deleted_since],
+            )
+            .await?;
+
+        if restored == 0 {
+            return Err(AppError::NotFound(format!("No recently removed review found for place_id: {}", place_id)));
+        }
+        refresh_rating_summary(&transaction, place_id).await?;
+        transaction.commit().await?;
         Ok(())
     }
 
-    pub async fn remove_review(
+    async fn retrieve_review_history(
         &self,
-        user_id: &String,
-        place_id: &String,
-    ) -> anyhow::Result<()> {
+        user_id: &str,
+        place_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<ReviewRevision>> {
+        let after_revision = page.after.as_ref().map(Cursor::numeric_key).transpose()?;
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "DELETE FROM user_reviews where user_id = '{}' and place_id = '{}';",
-            user_id,
-            place_id
-        );
+        let review = conn
+            .query_opt(
+                "SELECT 1 FROM user_reviews \
+                where user_id = $1 and place_id = $2 and deleted_at IS NULL and status = 'published';",
+                &[&user_id, &place_id],
+            )
+            .await?;
+        if review.is_none() {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
+        }
 
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
+        // revisions only ever count up, so the revision alone orders them
+        let rows = conn
+            .query(
+                "SELECT * FROM user_review_revisions WHERE user_id = $1 AND place_id = $2 AND published \
+                AND ($4::int IS NULL OR revision < $4) \
+                ORDER BY revision DESC LIMIT $3;",
+                &[&user_id, &place_id, &page.fetch_limit(), &after_revision.map(|revision| revision as i32)],
+            )
+            .await?;
+
+        let revisions = rows
+            .into_iter()
+            .map(parse_row_into_review_revision)
+            .map(|revision| (Cursor::new(revision.timestamp, revision.revision.to_string()), revision))
+            .collect();
+        Ok(Page::from_overfetched(revisions, page.limit))
+    }
 
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to remove review on restaurant for user: {}, due to: {}", user_id, e);
-            }
+    async fn retrieve_restaurant_reviews(
+        &self,
+        place_id: &str,
+        sort: ReviewSort,
+        stars: Option<u8>,
+        page: &PageRequest,
+    ) -> AppResult<Page<RestaurantRating>> {
+        let after = page.after.as_ref().map(|after| sort.position(after)).transpose()?;
+        let conn = self.get_postgres_connection().await?;
+        // the sort expression comes from a fixed set, nothing the client sends ends up in the statement
+        let rows = conn
+            .query(
+                &format!(
+                    "SELECT * from user_reviews where place_id = $1 AND deleted_at IS NULL AND status = 'published' \
+                    AND ($6::int IS NULL OR least(greatest(floor(rating + 0.5), 1), 5) = $6) \
+                    AND ($3::bigint IS NULL OR ({sort}, timestamp, user_id) < ($3, $4::bigint, $5::varchar)) \
+                    ORDER BY {sort} DESC, timestamp DESC, user_id DESC LIMIT $2;",
+                    sort = review_sort_expression(sort),
+                ),
+                &[
+                    &place_id,
+                    &page.fetch_limit(),
+                    &after.as_ref().map(|after| after.sort_value),
+                    &after.as_ref().map(|after| after.timestamp),
+                    &after.as_ref().map(|after| after.user_id.as_str()),
+                    &stars.map(i32::from),
+                ],
+            )
+            .await?;
+
+        let reviews = rows
+            .into_iter()
+            .map(parse_row_into_restaurant_rating)
+            .map(|review| (sort.cursor(&review), review))
+            .collect();
+        let mut reviews = Page::from_overfetched(reviews, page.limit);
+        attach_review_photos(&conn, reviews.items.iter_mut().collect()).await?;
+        Ok(reviews)
+    }
+
+    async fn get_user_reviews(
+        &self,
+        user_id: &str,
+        include_deleted: bool,
+        page: &PageRequest,
+    ) -> AppResult<Page<RestaurantRating>> {
+        let (after_timestamp, after_key) = cursor_params(page);
+        let conn = self.get_postgres_connection().await?;
+        let rows = conn
+            .query(
+                "SELECT * from user_reviews where user_id = $1 AND ($5 OR deleted_at IS NULL) \
+                AND ($3::bigint IS NULL OR (timestamp, place_id) < ($3, $4)) \
+                ORDER BY timestamp DESC, place_id DESC LIMIT $2;",
+                &[&user_id, &page.fetch_limit(), &after_timestamp, &after_key, &include_deleted],
+            ).await?;
+
+        let reviews = rows
+            .into_iter()
+            .map(parse_row_into_restaurant_rating)
+            .map(|review| (Cursor::new(review.timestamp, review.place_id.clone()), review))
+            .collect();
+        let mut reviews = Page::from_overfetched(reviews, page.limit);
+        attach_review_photos(&conn, reviews.items.iter_mut().collect()).await?;
+        Ok(reviews)
+    }
+
+    async fn retrieve_rating_summary(
+        &self,
+        place_id: &str,
+    ) -> AppResult<RatingSummary> {
+        let conn = self.get_postgres_connection().await?;
+        let recent_since = OffsetDateTime::now_utc().unix_timestamp() - RECENT_RATING_WINDOW_SECS;
+        // the recent reviews come off the (place_id, timestamp) index, everything else is kept in the summary
+        let row = conn
+            .query_one(
+                "SELECT s.review_count, s.rating_total, \
+                    s.one_star, s.two_stars, s.three_stars, s.four_stars, s.five_stars, \
+                    recent.recent_count, recent.recent_total \
+                FROM (SELECT count(*) AS recent_count, coalesce(sum(rating), 0) AS recent_total \
+                    FROM user_reviews WHERE place_id = $1 AND timestamp > $2 AND deleted_at IS NULL \
+                    AND status = 'published') recent \
+                LEFT JOIN place_rating_summaries s ON s.place_id = $1;",
+                &[&place_id, &recent_since],
+            )
+            .await?;
+
+        let count = |column: &str| row.get::<&str, Option<i64>>(column).unwrap_or_default();
+        Ok(RatingSummary::from_totals(
+            count("review_count"),
+            row.get::<&str, Option<f64>>("rating_total").unwrap_or_default(),
+            ["one_star", "two_stars", "three_stars", "four_stars", "five_stars"].map(count),
+            count("recent_count"),
+            row.get::<&str, f64>("recent_total"),
+        ))
+    }
+
+    async fn add_review_photos(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        photos: &[ReviewPhoto],
+        max_photos: usize,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let transaction = conn.transaction().await?;
+        let review = transaction
+            .query_opt(
+                "SELECT 1 FROM user_reviews WHERE user_id = $1 AND place_id = $2 AND deleted_at IS NULL FOR UPDATE;",
+                &[&user_id, &place_id],
+            )
+            .await?;
+        if review.is_none() {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
+        }
+
+        let attached = transaction
+            .query_one(
+                "SELECT count(*) AS photo_count, coalesce(max(position), 0) AS last_position \
+                FROM review_photos WHERE user_id = $1 AND place_id = $2;",
+                &[&user_id, &place_id],
+            )
+            .await?;
+        let photo_count = attached.get::<&str, i64>("photo_count") as usize;
+        if photo_count + photos.len() > max_photos {
+            return Err(too_many_photos(max_photos, photo_count));
+        }
+
+        let last_position = attached.get::<&str, i32>("last_position");
+        for (offset, photo) in photos.iter().enumerate() {
+            transaction
+                .execute(
+                    "INSERT INTO review_photos (photo_id, user_id, place_id, position, storage_key, thumbnail_key, \
+                        url, thumbnail_url, content_type, width, height, created_at) \
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12);",
+                    &[
+                        &photo.photo_id,
+                        &user_id,
+                        &place_id,
+                        &(last_position + 1 + offset as i32),
+                        &photo.storage_key,
+                        &photo.thumbnail_key,
+                        &photo.url,
+                        &photo.thumbnail_url,
+                        &photo.content_type,
+                        &(photo.width as i32),
+                        &(photo.height as i32),
+                        &photo.created_at,
+                    ],
+                )
+                .await?;
         }
+        transaction.commit().await?;
         Ok(())
     }
 
-    pub async fn retrieve_restaurant_reviews(
+    async fn remove_review_photo(
         &self,
-        place_id: &String,
-    ) -> anyhow::Result<Vec<RestaurantRating>> {
+        user_id: &str,
+        place_id: &str,
+        photo_id: &str,
+    ) -> AppResult<ReviewPhoto> {
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * from user_reviews where place_id = '{}';",
-            place_id
-        );
+        let removed = conn
+            .query_opt(
+                "DELETE FROM review_photos WHERE photo_id = $1 AND user_id = $2 AND place_id = $3 RETURNING *;",
+                &[&photo_id, &user_id, &place_id],
+            )
+            .await?;
 
-        let mut restaurant_reviews: Vec<RestaurantRating> = Vec::new();
-        let res = conn.query(&stmt, &[]).await;
+        removed
+            .as_ref()
+            .map(parse_row_into_review_photo)
+            .ok_or_else(|| AppError::NotFound(format!("No photo found with photo_id: {}", photo_id)))
+    }
 
-        match res {
-            Ok(rows) => {
-                for row in rows {
-                    let restaurant_rating = parse_row_into_restaurant_rating(row);
+    async fn retrieve_published_review_photo(
+        &self,
+        key: &str,
+    ) -> AppResult<Option<ReviewPhoto>> {
+        let conn = self.get_postgres_connection().await?;
+        let row = conn
+            .query_opt(
+                "SELECT review_photos.* FROM review_photos \
+                JOIN user_reviews ON user_reviews.user_id = review_photos.user_id \
+                AND user_reviews.place_id = review_photos.place_id \
+                WHERE (review_photos.storage_key = $1 OR review_photos.thumbnail_key = $1) \
+                AND user_reviews.status = 'published' AND user_reviews.deleted_at IS NULL;",
+                &[&key],
+            )
+            .await?;
 
-                    restaurant_reviews.push(restaurant_rating);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to retrieve restaurant rating for place_id: {} due to: {}", place_id, e);
-            }
+        Ok(row.as_ref().map(parse_row_into_review_photo))
+    }
+
+    async fn vote_on_review(
+        &self,
+        voter_id: &str,
+        user_id: &str,
+        place_id: &str,
+        helpful: bool,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let transaction = conn.transaction().await?;
+        let review = transaction
+            .query_opt(
+                "SELECT 1 FROM user_reviews \
+                WHERE user_id = $1 AND place_id = $2 AND deleted_at IS NULL AND status = 'published' FOR UPDATE;",
+                &[&user_id, &place_id],
+            )
+            .await?;
+        if review.is_none() {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
         }
 
-        Ok(restaurant_reviews)
+        transaction
+            .execute(
+                "INSERT INTO review_votes (voter_id, user_id, place_id, helpful, created_at) \
+                VALUES ($1, $2, $3, $4, $5) \
+                ON CONFLICT (voter_id, user_id, place_id) DO UPDATE \
+                SET helpful = excluded.helpful, created_at = excluded.created_at;",
+                &[&voter_id, &user_id, &place_id, &helpful, &now],
+            )
+            .await?;
+        refresh_vote_counts(&transaction, user_id, place_id).await?;
+        transaction.commit().await?;
+        Ok(())
     }
 
-    pub async fn get_user_reviews(
+    async fn remove_review_vote(
         &self,
-        user_id: &String,
-    ) -> anyhow::Result<Vec<RestaurantRating>> {
-        let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * from user_reviews where user_id = '{}';",
-            user_id
-        );
+        voter_id: &str,
+        user_id: &str,
+        place_id: &str,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let transaction = conn.transaction().await?;
+        transaction
+            .execute(
+                "SELECT 1 FROM user_reviews WHERE user_id = $1 AND place_id = $2 FOR UPDATE;",
+                &[&user_id, &place_id],
+            )
+            .await?;
+        let removed = transaction
+            .execute(
+                "DELETE FROM review_votes WHERE voter_id = $1 AND user_id = $2 AND place_id = $3;",
+                &[&voter_id, &user_id, &place_id],
+            )
+            .await?;
+
+        if removed == 0 {
+            return Err(AppError::NotFound(format!("No vote of yours found on the review of place_id: {}", place_id)));
+        }
+        refresh_vote_counts(&transaction, user_id, place_id).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn report_review(
+        &self,
+        reporter_id: &str,
+        user_id: &str,
+        place_id: &str,
+        reason: &str,
+        hold_after_reports: i64,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let transaction = conn.transaction().await?;
+        lock_rating_summary(&transaction, place_id).await?;
+        let review = transaction
+            .query_opt(
+                "SELECT 1 FROM user_reviews \
+                WHERE user_id = $1 AND place_id = $2 AND deleted_at IS NULL AND status = 'published';",
+                &[&user_id, &place_id],
+            )
+            .await?;
+        if review.is_none() {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
+        }
 
-        let mut restaurant_reviews: Vec<RestaurantRating> = Vec::new();
-        let res = conn
+        transaction
+            .execute(
+                "INSERT INTO review_reports (user_id, place_id, reporter_id, reason, created_at) \
+                VALUES ($1, $2, $3, $4, $5);",
+                &[&user_id, &place_id, &reporter_id, &reason, &now],
+            )
+            .await
+            .map_err(|e| match AppError::from(e) {
+                AppError::Conflict(_) => AppError::Conflict(format!("You have already reported this review of place_id: {}", place_id)),
+                other => other,
+            })?;
+        let open_reports = transaction
+            .query_one(
+                "SELECT count(*) AS open_reports FROM review_reports \
+                WHERE user_id = $1 AND place_id = $2 AND reporter_id IS NOT NULL AND resolved_at IS NULL;",
+                &[&user_id, &place_id],
+            )
+            .await?
+            .get::<&str, i64>("open_reports");
+
+        if open_reports >= hold_after_reports {
+            transaction
+                .execute(
+                    "UPDATE user_reviews SET status = 'pending' WHERE user_id = $1 AND place_id = $2;",
+                    &[&user_id, &place_id],
+                )
+                .await?;
+            refresh_rating_summary(&transaction, place_id).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn retrieve_report_queue(
+        &self,
+        page: &PageRequest,
+    ) -> AppResult<Page<ReportedReview>> {
+        let after = match &page.after {
+            Some(after) => Some((after.timestamp, after.numeric_key()?)),
+            None => None,
+        };
+        let conn = self.get_postgres_connection().await?;
+        let rows = conn
             .query(
-                &stmt,
-                &[],
-            ).await;
+                "SELECT r.id, r.user_id, r.place_id, r.reporter_id, r.reason, r.created_at, r.resolved_at, \
+                    u.rating, u.description, u.timestamp, u.deleted_at, u.status, u.helpful_count, u.unhelpful_count \
+                FROM review_reports r \
+                JOIN user_reviews u ON u.user_id = r.user_id AND u.place_id = r.place_id \
+                WHERE r.resolved_at IS NULL AND u.deleted_at IS NULL \
+                AND ($2::bigint IS NULL OR (r.created_at, r.id) > ($2, $3)) \
+                ORDER BY r.created_at, r.id LIMIT $1;",
+                &[&page.fetch_limit(), &after.map(|(timestamp, _)| timestamp), &after.map(|(_, id)| id)],
+            )
+            .await?;
+
+        let reports = rows
+            .into_iter()
+            .map(|row| ReportedReview {
+                report: parse_row_into_review_report(&row),
+                review: parse_row_into_restaurant_rating(row),
+            })
+            .map(|reported| (Cursor::new(reported.report.created_at, reported.report.id.to_string()), reported))
+            .collect();
+        let mut reports = Page::from_overfetched(reports, page.limit);
+        attach_review_photos(&conn, reports.items.iter_mut().map(|reported| &mut reported.review).collect()).await?;
+        Ok(reports)
+    }
 
-        match res {
-            Ok(rows) => {
-                for row in rows {
-                    let restaurant_rating = parse_row_into_restaurant_rating(row);
+    async fn moderate_review(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        status: ReviewStatus,
+    ) -> AppResult<()> {
+        let mut conn = self.get_postgres_connection().await?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let transaction = conn.transaction().await?;
+        lock_rating_summary(&transaction, place_id).await?;
+        let moderated = transaction
+            .execute(
+                "UPDATE user_reviews SET status = $3 WHERE user_id = $1 AND place_id = $2;",
+                &[&user_id, &place_id, &status.as_str()],
+            )
+            .await?;
 
-                    restaurant_reviews.push(restaurant_rating);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to retrieve restaurant rating for user_id: {} due to: {}", user_id, e);
-            }
+        if moderated == 0 {
+            return Err(AppError::NotFound(format!("No review found for place_id: {}", place_id)));
+        }
+        if status == ReviewStatus::Published {
+            // the moderator approved what the review says now, not what it said while it was held back before
+            transaction
+                .execute(
+                    "UPDATE user_review_revisions SET published = true \
+                    WHERE user_id = $1 AND place_id = $2 \
+                    AND revision = (SELECT max(revision) FROM user_review_revisions WHERE user_id = $1 AND place_id = $2);",
+                    &[&user_id, &place_id],
+                )
+                .await?;
         }
+        transaction
+            .execute(
+                "UPDATE review_reports SET resolved_at = $3 \
+                WHERE user_id = $1 AND place_id = $2 AND resolved_at IS NULL;",
+                &[&user_id, &place_id, &now],
+            )
+            .await?;
+        refresh_rating_summary(&transaction, place_id).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn add_reservations(
+        &self,
+        user_id: &str,
+        place_id: &str,
+        reservation_timestamp: i64,
+        reservation_pax: u32,
+        overlap_window_secs: i64,
+    ) -> AppResult<Reservation> {
+        let pax = pax_column(reservation_pax)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut conn = self.get_postgres_connection().await?;
+        let transaction = conn.transaction().await?;
+
+        check_place_and_overlap(&transaction, user_id, place_id, reservation_timestamp, overlap_window_secs, None).await?;
+        check_capacity(&transaction, place_id, reservation_timestamp, reservation_pax, None).await?;
+        let row = transaction
+            .query_one(
+                "INSERT INTO user_reservations (reservation_id, user_id, place_id, reservation_timestamp, reservation_pax, \
+                    status, created_at, updated_at) \
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $7) RETURNING *;",
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &user_id,
+                    &place_id,
+                    &reservation_timestamp,
+                    &pax,
+                    &ReservationStatus::Requested.as_str(),
+                    &now,
+                ],
+            )
+            .await?;
+        transaction.commit().await?;
 
-        Ok(restaurant_reviews)
+        Ok(parse_row_into_restaurant_reservation(&row))
     }
 
-    pub async fn add_reservations(
+    async fn modify_reservation(
         &self,
-        user_id: &String,
-        place_id: &String,
+        user_id: &str,
+        reservation_id: &str,
         reservation_timestamp: i64,
         reservation_pax: u32,
-    ) -> anyhow::Result<()> {
-        let conn = self.get_postgres_connection().await?;
-        let mut stmt = String::from("INSERT INTO user_reservations (user_id, place_id, reservation_timestamp, reservation_pax) VALUES ");
-        let params = format!(
-            "('{}', '{}', {}, {})",
+        overlap_window_secs: i64,
+    ) -> AppResult<Reservation> {
+        let pax = pax_column(reservation_pax)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut conn = self.get_postgres_connection().await?;
+        let transaction = conn.transaction().await?;
+
+        let current = lock_reservation(&transaction, Some(user_id), reservation_id).await?;
+        let status = current.status.after_change()?;
+        check_place_and_overlap(
+            &transaction,
             user_id,
-            place_id,
+            &current.place_id,
             reservation_timestamp,
-            reservation_pax,
-        );
-        stmt.push_str(&params);
-        stmt.push_str("ON CONFLICT DO NOTHING;");
-
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to add reservation for user: {}, due to: {}", user_id, e);
-            }
-        }
+            overlap_window_secs,
+            Some(reservation_id),
+        ).await?;
+        check_capacity(&transaction, &current.place_id, reservation_timestamp, reservation_pax, Some(reservation_id)).await?;
+        let row = transaction
+            .query_one(
+                "UPDATE user_reservations SET reservation_timestamp = $2, reservation_pax = $3, status = $4, updated_at = $5 \
+                WHERE reservation_id = $1 RETURNING *;",
+                &[&reservation_id, &reservation_timestamp, &pax, &status.as_str(), &now],
+            )
+            .await?;
+        transaction.commit().await?;
 
-        Ok(())
+        Ok(parse_row_into_restaurant_reservation(&row))
+    }
+
+    async fn set_reservation_status(
+        &self,
+        user_id: Option<&str>,
+        reservation_id: &str,
+        status: ReservationStatus,
+    ) -> AppResult<Reservation> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut conn = self.get_postgres_connection().await?;
+        let transaction = conn.transaction().await?;
+
+        let current = lock_reservation(&transaction, user_id, reservation_id).await?;
+        let status = current.status.transition_to(status)?;
+        let row = transaction
+            .query_one(
+                "UPDATE user_reservations SET status = $2, updated_at = $3 WHERE reservation_id = $1 RETURNING *;",
+                &[&reservation_id, &status.as_str(), &now],
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(parse_row_into_restaurant_reservation(&row))
     }
 
-    pub async fn remove_reservation(
+    async fn set_place_capacity(
         &self,
-        user_id: &String,
-        place_id: &String,
-    ) -> anyhow::Result<()> {
+        capacity: &PlaceCapacity,
+    ) -> AppResult<()> {
+        let seats_per_slot = i32::try_from(capacity.seats_per_slot)
+            .map_err(|_| AppError::Validation("seats_per_slot is out of range".to_string()))?;
+        let opening_hours = serde_json::to_value(&capacity.opening_hours)
+            .map_err(|e| AppError::Database(format!("Failed to serialise opening hours: {}", e)))?;
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "DELETE FROM user_reservations where user_id = '{}' and place_id = '{}';",
-            user_id,
-            place_id,
-        );
 
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
+        conn
+            .execute(
+                "INSERT INTO place_capacities (place_id, seats_per_slot, slot_minutes, utc_offset_minutes, opening_hours) \
+                VALUES ($1, $2, $3, $4, $5) \
+                ON CONFLICT (place_id) DO UPDATE SET seats_per_slot = excluded.seats_per_slot, \
+                    slot_minutes = excluded.slot_minutes, utc_offset_minutes = excluded.utc_offset_minutes, \
+                    opening_hours = excluded.opening_hours;",
+                &[
+                    &capacity.place_id,
+                    &seats_per_slot,
+                    &(capacity.slot_minutes as i32),
+                    &capacity.utc_offset_minutes,
+                    &opening_hours,
+                ],
+            )
+            .await
+            .map_err(|e| unknown_place(e, &capacity.place_id))?;
 
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to remove restaurant reservation for user: {}, due to: {}", user_id, e);
-            }
-        }
         Ok(())
     }
 
-    pub async fn retrieve_all_user_valid_reservations(
+    async fn retrieve_place_capacity(
         &self,
-        user_id: &String,
-    ) -> anyhow::Result<Vec<Reservation>> {
+        place_id: &str,
+    ) -> AppResult<Option<PlaceCapacity>> {
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * FROM user_reservations where user_id = '{}' and reservation_timestamp > {};",
-            user_id,
-            OffsetDateTime::now_utc().unix_timestamp()
-        );
+        let row = conn
+            .query_opt("SELECT * FROM place_capacities WHERE place_id = $1;", &[&place_id])
+            .await?;
 
-        let res = conn
-            .query(&stmt, &[])
-            .await;
+        row.as_ref().map(parse_row_into_place_capacity).transpose()
+    }
 
-        let mut reservations: Vec<Reservation> = Vec::new();
-        match res {
-            Ok(rows) => {
-                for row in rows {
-                    let user_reservation = parse_row_into_restaurant_reservation(row);
+    async fn retrieve_booked_seats(
+        &self,
+        place_id: &str,
+        from: i64,
+        until: i64,
+    ) -> AppResult<HashMap<i64, u32>> {
+        let conn = self.get_postgres_connection().await?;
+        let rows = conn
+            .query(
+                "SELECT reservation_timestamp, sum(reservation_pax)::bigint AS booked FROM user_reservations \
+                WHERE place_id = $1 AND reservation_timestamp >= $2 AND reservation_timestamp < $3 \
+                AND status IN ('requested', 'confirmed') \
+                GROUP BY reservation_timestamp;",
+                &[&place_id, &from, &until],
+            )
+            .await?;
 
-                    reservations.push(user_reservation);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to retrieve user reservations for user: {}, due to: {}", user_id, e);
-            }
-        }
-        Ok(reservations)
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("reservation_timestamp"), seat_count(row.get("booked"))))
+            .collect())
     }
 
-    pub async fn retrieve_all_user_reservations(
+    async fn retrieve_requested_reservations(
         &self,
-        user_id: &String,
-    ) -> anyhow::Result<Vec<Reservation>> {
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let (after_timestamp, after_key) = cursor_params(page);
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * FROM user_reservations where user_id = '{}'",
-            user_id
-        );
+        let rows = conn
+            .query(
+                "SELECT * FROM user_reservations WHERE status = 'requested' \
+                AND ($2::bigint IS NULL OR (reservation_timestamp, reservation_id) > ($2, $3)) \
+                ORDER BY reservation_timestamp, reservation_id LIMIT $1;",
+                &[&page.fetch_limit(), &after_timestamp, &after_key],
+            )
+            .await?;
 
-        let res = conn
-            .query(&stmt, &[])
-            .await;
+        Ok(Page::from_overfetched(reservations_with_cursors(rows), page.limit))
+    }
 
-        let mut reservations: Vec<Reservation> = Vec::new();
-        match res {
-            Ok(rows) => {
-                for row in rows {
-                    let user_reservation = parse_row_into_restaurant_reservation(row);
-                    reservations.push(user_reservation);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to retrieve user reservations for user: {}, due to: {}", user_id, e);
-            }
+    async fn retrieve_all_user_valid_reservations(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let (after_timestamp, after_key) = cursor_params(page);
+        let conn = self.get_postgres_connection().await?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let rows = conn
+            .query(
+                "SELECT * FROM user_reservations where user_id = $1 and reservation_timestamp > $2 \
+                AND status IN ('requested', 'confirmed') \
+                AND ($4::bigint IS NULL OR (reservation_timestamp, reservation_id) > ($4, $5)) \
+                ORDER BY reservation_timestamp, reservation_id LIMIT $3;",
+                &[&user_id, &now, &page.fetch_limit(), &after_timestamp, &after_key],
+            )
+            .await?;
+
+        Ok(Page::from_overfetched(reservations_with_cursors(rows), page.limit))
+    }
+
+    async fn retrieve_all_user_reservations(
+        &self,
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let (after_timestamp, after_key) = cursor_params(page);
+        let conn = self.get_postgres_connection().await?;
+        let rows = conn
+            .query(
+                "SELECT * FROM user_reservations where user_id = $1 \
+                AND ($3::bigint IS NULL OR (reservation_timestamp, reservation_id) > ($3, $4)) \
+                ORDER BY reservation_timestamp, reservation_id LIMIT $2;",
+                &[&user_id, &page.fetch_limit(), &after_timestamp, &after_key],
+            )
+            .await?;
+
+        Ok(Page::from_overfetched(reservations_with_cursors(rows), page.limit))
+    }
+
+    async fn retrieve_reservations_starting(
+        &self,
+        from: i64,
+        until: i64,
+        page: &PageRequest,
+    ) -> AppResult<Page<Reservation>> {
+        let (after_timestamp, after_key) = cursor_params(page);
+        let conn = self.get_postgres_connection().await?;
+        let rows = conn
+            .query(
+                "SELECT * FROM user_reservations WHERE reservation_timestamp > $1 AND reservation_timestamp <= $2 \
+                AND status IN ('requested', 'confirmed') \
+                AND ($4::bigint IS NULL OR (reservation_timestamp, reservation_id) > ($4, $5)) \
+                ORDER BY reservation_timestamp, reservation_id LIMIT $3;",
+                &[&from, &until, &page.fetch_limit(), &after_timestamp, &after_key],
+            )
+            .await?;
+
+        Ok(Page::from_overfetched(reservations_with_cursors(rows), page.limit))
+    }
+
+    async fn claim_reservation_reminders(
+        &self,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        offsets_secs: &[i64],
+    ) -> AppResult<Vec<i64>> {
+        let conn = self.get_postgres_connection().await?;
+        let rows = conn
+            .query(
+                "INSERT INTO reservation_reminders (reservation_id, reservation_timestamp, offset_secs, sent_at) \
+                SELECT $1, $2, offset_secs, $4 FROM unnest($3::bigint[]) AS offset_secs \
+                ON CONFLICT DO NOTHING RETURNING offset_secs;",
+                &[&reservation_id, &reservation_timestamp, &offsets_secs, &OffsetDateTime::now_utc().unix_timestamp()],
+            )
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("offset_secs")).collect())
+    }
+
+    async fn release_reservation_reminders(
+        &self,
+        reservation_id: &str,
+        reservation_timestamp: i64,
+        offsets_secs: &[i64],
+    ) -> AppResult<()> {
+        let conn = self.get_postgres_connection().await?;
+        conn
+            .execute(
+                "DELETE FROM reservation_reminders WHERE reservation_id = $1 AND reservation_timestamp = $2 \
+                AND offset_secs = ANY($3::bigint[]);",
+                &[&reservation_id, &reservation_timestamp, &offsets_secs],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_calendar_feed_token(
+        &self,
+        user_id: &str,
+        token_hash: &str,
+    ) -> AppResult<()> {
+        let conn = self.get_postgres_connection().await?;
+        conn
+            .execute(
+                "INSERT INTO calendar_feed_tokens (user_id, token_hash, created_at) VALUES ($1, $2, $3) \
+                ON CONFLICT (user_id) DO UPDATE SET token_hash = excluded.token_hash, created_at = excluded.created_at;",
+                &[&user_id, &token_hash, &OffsetDateTime::now_utc().unix_timestamp()],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_calendar_feed_token(
+        &self,
+        user_id: &str,
+    ) -> AppResult<()> {
+        let conn = self.get_postgres_connection().await?;
+        let removed = conn
+            .execute("DELETE FROM calendar_feed_tokens WHERE user_id = $1;", &[&user_id])
+            .await?;
+
+        if removed == 0 {
+            return Err(AppError::NotFound("No calendar feed to remove".to_string()));
         }
-        Ok(reservations)
+        Ok(())
     }
 
-    pub async fn store_vote_history(
+    async fn retrieve_calendar_feed_user(
+        &self,
+        token_hash: &str,
+    ) -> AppResult<Option<String>> {
+        let conn = self.get_postgres_connection().await?;
+        let row = conn
+            .query_opt("SELECT user_id FROM calendar_feed_tokens WHERE token_hash = $1;", &[&token_hash])
+            .await?;
+
+        Ok(row.map(|row| row.get("user_id")))
+    }
+
+    async fn store_vote_history(
         &self,
         user_ids: Vec<String>,
         voted_places: Value,
         vote_session_timestamp: i64,
-    ) -> anyhow::Result<()> {
+        outcome: Option<&TallyResult>,
+    ) -> AppResult<()> {
+        let voted_places = match voted_places {
+            Value::Array(places) => places,
+            other => vec![other],
+        };
+        let outcome = outcome
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(|e| AppError::Database(format!("Failed to serialize vote outcome: {}", e)))?;
         let conn = self.get_postgres_connection().await?;
-        let mut stmt = String::from("INSERT INTO voting_history (voted_places, vote_timestamp, voters) VALUES ");
-        let mut voted_places_stmt = format!("ARRAY[");
-        if let Value::Array(places) = voted_places {
-            for place in places {
-                voted_places_stmt.push_str(
-                    &format!(
-                        "'{}'::jsonb,",
-                        place
-                    )
-                )
-            }
-        }
-        voted_places_stmt.remove(voted_places_stmt.len() - 1);
-        voted_places_stmt.push_str("]");
 
-        let mut user_ids_stmt = format!("ARRAY[");
-        for user_id in user_ids {
-            user_ids_stmt.push_str(
-                &format!(
-                    "'{}',",
-                    user_id
-                )
+        conn
+            .execute(
+                "INSERT INTO voting_history (voted_places, vote_timestamp, voters, outcome) VALUES ($1, $2, $3, $4);",
+                &[&voted_places, &vote_session_timestamp, &user_ids, &outcome],
             )
-        }
-        user_ids_stmt.remove(user_ids_stmt.len() - 1);
-        user_ids_stmt.push_str("]");
-        let params = format!(
-            "({}, {}, {});",
-            voted_places_stmt,
-            vote_session_timestamp,
-            user_ids_stmt,
-        );
-        stmt.push_str(&params);
-
-        let res = conn
-            .execute(&stmt, &[])
-            .await;
-        match res {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("Failed to store voting history due to: {}", e);
-            }
-        }
+            .await?;
+
         Ok(())
     }
 
-    pub async fn retrieve_user_vote_history(
+    async fn retrieve_user_vote_history(
         &self,
-        user_id: &String,
-    ) -> anyhow::Result<Vec<VoteHistory>> {
+        user_id: &str,
+        page: &PageRequest,
+    ) -> AppResult<Page<VoteHistory>> {
+        let (after_timestamp, after_id) = match &page.after {
+            Some(after) => (Some(after.timestamp), Some(after.numeric_key()?)),
+            None => (None, None),
+        };
         let conn = self.get_postgres_connection().await?;
-        let stmt = format!(
-            "SELECT * FROM voting_history WHERE '{}' = ANY(voters)",
-            user_id
-        );
+        // containment rather than `= ANY(voters)` so the gin index on voters can be used
+        let rows = conn
+            .query(
+                "SELECT * FROM voting_history WHERE voters @> ARRAY[$1]::text[] \
+                AND ($3::bigint IS NULL OR (vote_timestamp, id) < ($3, $4)) \
+                ORDER BY vote_timestamp DESC, id DESC LIMIT $2;",
+                &[&user_id, &page.fetch_limit(), &after_timestamp, &after_id],
+            )
+            .await?;
+
+        let vote_histories = rows
+            .into_iter()
+            .map(|row| {
+                let id = row.get::<&str, i64>("id");
+                let vote_history = parse_row_into_vote_history(row);
+                (Cursor::new(vote_history.vote_timestamp, id.to_string()), vote_history)
+            })
+            .collect();
+        Ok(Page::from_overfetched(vote_histories, page.limit))
+    }
+}
 
-        let res = conn
-            .query(&stmt, &[])
-            .await;
+/// Names the place when a write failed on the foreign key to places.
+fn unknown_place(
+    e: bb8_postgres::tokio_postgres::Error,
+    place_id: &str,
+) -> AppError {
+    match AppError::from(e) {
+        AppError::NotFound(_) => AppError::NotFound(format!("No restaurant found with place_id: {}", place_id)),
+        other => other,
+    }
+}
 
-        let mut vote_histories: Vec<VoteHistory> = Vec::new();
-        match res {
-            Ok(rows) => {
-                for row in rows {
-                    let vote_history = parse_row_into_vote_history(row);
+/// Takes the row lock on the summary of a place before its reviews change, so concurrent review changes
+/// to one place are counted one after the other.
+async fn lock_rating_summary(
+    transaction: &Transaction<'_>,
+    place_id: &str,
+) -> AppResult<()> {
+    transaction
+        .execute(
+            "INSERT INTO place_rating_summaries (place_id) VALUES ($1) ON CONFLICT (place_id) DO NOTHING;",
+            &[&place_id],
+        )
+        .await?;
+    transaction
+        .execute(
+            "SELECT 1 FROM place_rating_summaries WHERE place_id = $1 FOR UPDATE;",
+            &[&place_id],
+        )
+        .await?;
+    Ok(())
+}
 
-                    vote_histories.push(vote_history);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to retrieve user's voting histories: {}", e);
-            }
-        }
-        Ok(vote_histories)
+/// What a review write leaves the status at when it is new, written over reviews keep theirs unless held back.
+fn written_status(
+    held_back: Option<&str>,
+) -> ReviewStatus {
+    match held_back {
+        Some(_) => ReviewStatus::Pending,
+        None => ReviewStatus::Published,
+    }
+}
+
+/// Puts a review the banned term filter held back in the moderation queue. The filter keeps at most one
+/// open report per review, a review that is still waiting keeps the reason it was first held back for.
+async fn file_filter_report(
+    transaction: &Transaction<'_>,
+    user_id: &str,
+    place_id: &str,
+    reason: &str,
+    created_at: i64,
+) -> AppResult<()> {
+    transaction
+        .execute(
+            "INSERT INTO review_reports (user_id, place_id, reason, created_at) \
+            VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING;",
+            &[&user_id, &place_id, &reason, &created_at],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Keeps the version of a review that was just written, numbered one past the latest one, and whether the
+/// review is published with it.
+async fn record_revision(
+    transaction: &Transaction<'_>,
+    user_id: &str,
+    place_id: &str,
+    rating: f64,
+    description: &str,
+    timestamp: i64,
+) -> AppResult<()> {
+    transaction
+        .execute(
+            "INSERT INTO user_review_revisions (user_id, place_id, revision, rating, description, timestamp, published) \
+            SELECT $1::varchar, $2::varchar, coalesce(max(revision), 0) + 1, $3::float8, $4::varchar, $5::bigint, \
+                (SELECT status = 'published' FROM user_reviews WHERE user_id = $1 AND place_id = $2) \
+            FROM user_review_revisions \
+            WHERE user_id = $1 AND place_id = $2;",
+            &[&user_id, &place_id, &rating, &description, &timestamp],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Recounts the summary of a place from its reviews, the same rounding as the place_rating_summaries migration.
+async fn refresh_rating_summary(
+    transaction: &Transaction<'_>,
+    place_id: &str,
+) -> AppResult<()> {
+    transaction
+        .execute(
+            "UPDATE place_rating_summaries s SET \
+                review_count = totals.review_count, rating_total = totals.rating_total, \
+                one_star = totals.one_star, two_stars = totals.two_stars, three_stars = totals.three_stars, \
+                four_stars = totals.four_stars, five_stars = totals.five_stars \
+            FROM (SELECT count(*) AS review_count, coalesce(sum(rating), 0) AS rating_total, \
+                count(*) FILTER (WHERE least(greatest(floor(rating + 0.5), 1), 5) = 1) AS one_star, \
+                count(*) FILTER (WHERE least(greatest(floor(rating + 0.5), 1), 5) = 2) AS two_stars, \
+                count(*) FILTER (WHERE least(greatest(floor(rating + 0.5), 1), 5) = 3) AS three_stars, \
+                count(*) FILTER (WHERE least(greatest(floor(rating + 0.5), 1), 5) = 4) AS four_stars, \
+                count(*) FILTER (WHERE least(greatest(floor(rating + 0.5), 1), 5) = 5) AS five_stars \
+                FROM user_reviews WHERE place_id = $1 AND rating IS NOT NULL AND deleted_at IS NULL \
+                    AND status = 'published') totals \
+            WHERE s.place_id = $1;",
+            &[&place_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Fills in the photos of reviews read without them, with one query for all of them.
+async fn attach_review_photos(
+    conn: &Client,
+    reviews: Vec<&mut RestaurantRating>,
+) -> AppResult<()> {
+    if reviews.is_empty() {
+        return Ok(());
+    }
+
+    let user_ids: Vec<String> = reviews.iter().map(|review| review.user_id.clone()).collect();
+    let place_ids: Vec<String> = reviews.iter().map(|review| review.place_id.clone()).collect();
+    let rows = conn
+        .query(
+            "SELECT p.* FROM review_photos p \
+            JOIN unnest($1::varchar[], $2::varchar[]) AS r (user_id, place_id) \
+                ON p.user_id = r.user_id AND p.place_id = r.place_id \
+            ORDER BY p.position;",
+            &[&user_ids, &place_ids],
+        )
+        .await?;
+
+    let mut photos: HashMap<(String, String), Vec<ReviewPhoto>> = HashMap::new();
+    for row in rows {
+        photos
+            .entry((row.get("user_id"), row.get("place_id")))
+            .or_default()
+            .push(parse_row_into_review_photo(&row));
+    }
+    for review in reviews {
+        review.photos = photos.remove(&(review.user_id.clone(), review.place_id.clone())).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Recounts the votes on a review, under the row lock its voters take.
+async fn refresh_vote_counts(
+    transaction: &Transaction<'_>,
+    user_id: &str,
+    place_id: &str,
+) -> AppResult<()> {
+    transaction
+        .execute(
+            "UPDATE user_reviews SET helpful_count = votes.helpful, unhelpful_count = votes.unhelpful \
+            FROM (SELECT count(*) FILTER (WHERE helpful) AS helpful, count(*) FILTER (WHERE NOT helpful) AS unhelpful \
+                FROM review_votes WHERE user_id = $1 AND place_id = $2) votes \
+            WHERE user_id = $1 AND place_id = $2;",
+            &[&user_id, &place_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// What the reviews of a place are ordered by first, matches `ReviewSort::sort_value`.
+fn review_sort_expression(
+    sort: ReviewSort,
+) -> &'static str {
+    match sort {
+        ReviewSort::Newest => "timestamp",
+        ReviewSort::Highest => "round(rating * 2)::bigint",
+        ReviewSort::Lowest => "-round(rating * 2)::bigint",
+        ReviewSort::MostHelpful => "helpful_count::bigint",
+    }
+}
+
+/// The cursor of a page request as query parameters, both null for the first page.
+fn cursor_params(
+    page: &PageRequest,
+) -> (Option<i64>, Option<&str>) {
+    match &page.after {
+        Some(after) => (Some(after.timestamp), Some(after.key.as_str())),
+        None => (None, None),
+    }
+}
+
+/// Reservations are listed by time and then by id, which is also their cursor.
+fn reservations_with_cursors(
+    rows: Vec<Row>,
+) -> Vec<(Cursor, Reservation)> {
+    rows
+        .iter()
+        .map(parse_row_into_restaurant_reservation)
+        .map(|reservation| (Cursor::new(reservation.reservation_timestamp, reservation.reservation_id.clone()), reservation))
+        .collect()
+}
+
+fn pax_column(
+    reservation_pax: u32,
+) -> AppResult<i32> {
+    i32::try_from(reservation_pax).map_err(|_| AppError::Validation("reservation_pax is out of range".to_string()))
+}
+
+fn seat_count(
+    booked: i64,
+) -> u32 {
+    u32::try_from(booked).unwrap_or(u32::MAX)
+}
+
+/// Rejects reservations at places that were never stored, and ones starting less than `overlap_window_secs` from
+/// another reservation of the user. Bookings of a user wait on each other until the transaction ends, so two
+/// overlapping ones cannot both get in.
+async fn check_place_and_overlap(
+    transaction: &Transaction<'_>,
+    user_id: &str,
+    place_id: &str,
+    reservation_timestamp: i64,
+    overlap_window_secs: i64,
+    except_reservation_id: Option<&str>,
+) -> AppResult<()> {
+    let place = transaction
+        .query_opt("SELECT 1 FROM places WHERE place_id = $1;", &[&place_id])
+        .await?;
+    if place.is_none() {
+        return Err(reservation_at_unknown_place(place_id));
+    }
+    if overlap_window_secs <= 0 {
+        return Ok(());
+    }
+
+    transaction
+        .execute("SELECT pg_advisory_xact_lock(hashtext('user_reservations:' || $1::varchar));", &[&user_id])
+        .await?;
+    let overlapping = transaction
+        .query_opt(
+            "SELECT 1 FROM user_reservations WHERE user_id = $1 AND status IN ('requested', 'confirmed') \
+            AND reservation_timestamp > $2::bigint - $3::bigint AND reservation_timestamp < $2::bigint + $3::bigint \
+            AND ($4::varchar IS NULL OR reservation_id <> $4) LIMIT 1;",
+            &[&user_id, &reservation_timestamp, &overlap_window_secs, &except_reservation_id],
+        )
+        .await?;
+    if overlapping.is_some() {
+        return Err(overlapping_reservation(overlap_window_secs));
+    }
+    Ok(())
+}
+
+/// Rejects a reservation that does not fit in its slot at a place with a capacity. The capacity row stays
+/// locked until the transaction ends, so bookings at the place are checked one at a time.
+async fn check_capacity(
+    transaction: &Transaction<'_>,
+    place_id: &str,
+    reservation_timestamp: i64,
+    reservation_pax: u32,
+    except_reservation_id: Option<&str>,
+) -> AppResult<()> {
+    let Some(row) = transaction
+        .query_opt("SELECT * FROM place_capacities WHERE place_id = $1 FOR UPDATE;", &[&place_id])
+        .await?
+    else {
+        return Ok(());
+    };
+    let capacity = parse_row_into_place_capacity(&row)?;
+
+    let booked = transaction
+        .query_one(
+            "SELECT coalesce(sum(reservation_pax), 0)::bigint AS booked FROM user_reservations \
+            WHERE place_id = $1 AND reservation_timestamp = $2 AND status IN ('requested', 'confirmed') \
+            AND ($3::varchar IS NULL OR reservation_id <> $3);",
+            &[&place_id, &reservation_timestamp, &except_reservation_id],
+        )
+        .await?;
+    capacity.check_booking(reservation_timestamp, reservation_pax, seat_count(booked.get("booked")))
+}
+
+/// Reads a reservation and holds on to its row until the transaction ends, so status changes apply one at a time.
+async fn lock_reservation(
+    transaction: &Transaction<'_>,
+    user_id: Option<&str>,
+    reservation_id: &str,
+) -> AppResult<Reservation> {
+    let row = transaction
+        .query_opt(
+            "SELECT * FROM user_reservations WHERE reservation_id = $1 AND ($2::varchar IS NULL OR user_id = $2) FOR UPDATE;",
+            &[&reservation_id, &user_id],
+        )
+        .await?;
+
+    row
+        .as_ref()
+        .map(parse_row_into_restaurant_reservation)
+        .ok_or_else(|| AppError::NotFound(format!("No reservation found with reservation_id: {}", reservation_id)))
+}
+
+/// Escapes the wildcard characters of a LIKE pattern so user input is always matched literally.
+fn escape_like_pattern(
+    input: &str,
+) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn parse_row_into_user(
+    row: Row,
+) -> User {
+    User {
+        user_id: row.get("user_id"),
+        username: row.get("username"),
+        password_hash: row.get("password_hash"),
+        created_at: row.get::<&str, i64>("created_at"),
     }
 }
 
@@ -583,12 +1590,15 @@ fn parse_row_into_restaurant(
     Restaurant {
         place_id: row.get("place_id"),
         name: row.get("name"),
-        photos: Photo {
-            height: row.get::<&str, i32>("photo_height") as i64,
-            photo_reference: row.get("photo_reference"),
-            width: row.get::<&str, i32>("photo_width") as i64,
-        },
-        rating: row.get::<&str, f64>("rating"),
+        photos: row
+            .get::<&str, Option<String>>("photo_reference")
+            .map(|photo_reference| Photo {
+                height: row.get::<&str, Option<i32>>("photo_height").unwrap_or_default() as i64,
+                photo_reference,
+                width: row.get::<&str, Option<i32>>("photo_width").unwrap_or_default() as i64,
+            }),
+        rating: row.get::<&str, Option<f64>>("rating"),
+        price_level: row.get::<&str, Option<i16>>("price_level").map(|price_level| price_level as u8),
         vicinity: row.get("vicinity"),
         geometry: Location {
             lat: row.get::<&str, f64>("lat"),
@@ -605,23 +1615,91 @@ fn parse_row_into_restaurant_rating(
         place_id: row.get("place_id"),
         rating: row.get::<&str, f64>("rating"),
         description: row.get("description"),
-        timestamp: row.get::<&str, i32>("timestamp") as i64,
+        timestamp: row.get::<&str, i64>("timestamp"),
+        deleted_at: row.get::<&str, Option<i64>>("deleted_at"),
+        status: ReviewStatus::from_column(row.get("status")),
+        helpful_count: i64::from(row.get::<&str, i32>("helpful_count")),
+        unhelpful_count: i64::from(row.get::<&str, i32>("unhelpful_count")),
+        // filled in by attach_review_photos
+        photos: Vec::new(),
     }
 }
 
-fn parse_row_into_restaurant_reservation(
+fn parse_row_into_review_report(
+    row: &Row,
+) -> ReviewReport {
+    ReviewReport {
+        id: row.get::<&str, i64>("id"),
+        user_id: row.get("user_id"),
+        place_id: row.get("place_id"),
+        reporter_id: row.get::<&str, Option<String>>("reporter_id"),
+        reason: row.get("reason"),
+        created_at: row.get::<&str, i64>("created_at"),
+        resolved_at: row.get::<&str, Option<i64>>("resolved_at"),
+    }
+}
+
+fn parse_row_into_review_photo(
+    row: &Row,
+) -> ReviewPhoto {
+    ReviewPhoto {
+        photo_id: row.get("photo_id"),
+        url: row.get("url"),
+        thumbnail_url: row.get("thumbnail_url"),
+        content_type: row.get("content_type"),
+        width: i64::from(row.get::<&str, i32>("width")),
+        height: i64::from(row.get::<&str, i32>("height")),
+        created_at: row.get::<&str, i64>("created_at"),
+        storage_key: row.get("storage_key"),
+        thumbnail_key: row.get("thumbnail_key"),
+    }
+}
+
+fn parse_row_into_review_revision(
     row: Row,
+) -> ReviewRevision {
+    ReviewRevision {
+        user_id: row.get("user_id"),
+        place_id: row.get("place_id"),
+        revision: i64::from(row.get::<&str, i32>("revision")),
+        rating: row.get::<&str, f64>("rating"),
+        description: row.get("description"),
+        timestamp: row.get::<&str, i64>("timestamp"),
+    }
+}
+
+fn parse_row_into_place_capacity(
+    row: &Row,
+) -> AppResult<PlaceCapacity> {
+    let opening_hours = serde_json::from_value(row.get::<&str, Value>("opening_hours"))
+        .map_err(|e| AppError::Database(format!("Failed to parse opening hours: {}", e)))?;
+
+    Ok(PlaceCapacity {
+        place_id: row.get("place_id"),
+        seats_per_slot: row.get::<&str, i32>("seats_per_slot") as u32,
+        slot_minutes: row.get::<&str, i32>("slot_minutes") as u32,
+        utc_offset_minutes: row.get("utc_offset_minutes"),
+        opening_hours,
+    })
+}
+
+fn parse_row_into_restaurant_reservation(
+    row: &Row,
 ) -> Reservation {
-    let epoch_time = row.get::<&str, i32>("reservation_timestamp");
+    let epoch_time = row.get::<&str, i64>("reservation_timestamp");
     let pax = row.get::<&str, i32>("reservation_pax");
     let user_id = row.get::<&str, &str>("user_id");
     let place_id = row.get::<&str, &str>("place_id");
 
     Reservation {
+        reservation_id: row.get("reservation_id"),
         user_id: user_id.to_string(),
         place_id: place_id.to_string(),
-        reservation_timestamp: epoch_time as i64,
+        reservation_timestamp: epoch_time,
         reservation_pax: pax as u32,
+        status: ReservationStatus::from_column(row.get("status")),
+        created_at: row.get::<&str, i64>("created_at"),
+        updated_at: row.get::<&str, i64>("updated_at"),
     }
 }
 
@@ -629,11 +1707,1040 @@ fn parse_row_into_vote_history(
     row: Row,
 ) -> VoteHistory {
     let user_ids = row.get::<&str, Vec<String>>("voters");
-    let vote_timestamp = row.get::<&str, i32>("vote_timestamp");
+    let vote_timestamp = row.get::<&str, i64>("vote_timestamp");
     let voted_places = row.get::<&str, Vec<Value>>("voted_places");
+    let outcome = row
+        .get::<&str, Option<Value>>("outcome")
+        .and_then(|outcome| serde_json::from_value(outcome).ok());
     VoteHistory {
         user_ids,
-        vote_timestamp: vote_timestamp as i64,
+        vote_timestamp,
         voted_places,
+        outcome,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    //! Regression suite for user supplied input reaching SQL. These run against a real postgres
+    //! instance pointed to by `TEST_POSTGRES_URL`; every test migrates its own schema and drops it
+    //! afterwards. They are ignored unless asked for with `cargo test -- --include-ignored`, and fail
+    //! when the variable is not set.
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use bb8_postgres::bb8::Pool;
+    use bb8_postgres::PostgresConnectionManager;
+    use bb8_postgres::tokio_postgres::{Config, NoTls};
+    use serde_json::json;
+    use crate::models::capacity::{DayOfWeek, OpeningHours};
+    use crate::models::reservation::{RESERVATION_OVERLAPS, RESERVATION_UNKNOWN_PLACE};
+    use crate::repositories::migrations::{run_migrations, MIGRATIONS};
+    use crate::voting::tally::{tally, Ballot, VotingMethod};
+    use super::*;
+
+    pub const HOSTILE_INPUTS: &[&str] = &[
+        "O'Brien's Chicken Rice",
+        "'); DROP TABLE places; --",
+        "Robert'); DELETE FROM user_reviews; --",
+        "\" OR 1=1 --",
+        "' OR '1'='1",
+        "$1 $$ dollar $$ quoting",
+        "鸡饭 🍗 Café Zoë",
+        "100% _real_ \\ backslash",
+    ];
+
+    static SCHEMA_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    pub struct TestDatabase {
+        admin_config: Config,
+        schema: String,
+        pub repo: PostgresConnectionRepo,
+    }
+
+    impl TestDatabase {
+        pub async fn setup() -> Self {
+            Self::setup_from_legacy_schema(None).await
+        }
+
+        /// `legacy_sql` runs inside the fresh schema before any migration, to emulate databases that
+        /// were set up by hand before migrations existed.
+        async fn setup_from_legacy_schema(legacy_sql: Option<&str>) -> Self {
+            let url = std::env::var("TEST_POSTGRES_URL").expect("TEST_POSTGRES_URL has to point at a postgres database");
+            let schema = format!(
+                "repo_test_{}_{}",
+                std::process::id(),
+                SCHEMA_COUNTER.fetch_add(1, Ordering::SeqCst),
+            );
+
+            let admin_config = Config::from_str(&url).expect("invalid TEST_POSTGRES_URL");
+            let (client, connection) = admin_config.connect(NoTls).await.expect("failed to connect to test postgres");
+            tokio::spawn(connection);
+            client
+                .batch_execute(&format!("CREATE SCHEMA {};", schema))
+                .await
+                .expect("failed to create test schema");
+            if let Some(legacy_sql) = legacy_sql {
+                client
+                    .batch_execute(&format!("SET search_path TO {}; {}", schema, legacy_sql))
+                    .await
+                    .expect("failed to apply legacy schema");
+            }
+
+            let mut repo_config = admin_config.clone();
+            // public stays on the path for the extensions migrations create there
+            repo_config.options(&format!("-c search_path={},public", schema));
+            let pool = Pool::builder()
+                .max_size(2)
+                .build(PostgresConnectionManager::new(repo_config, NoTls))
+                .await
+                .expect("failed to build test pool");
+            run_migrations(&pool).await.expect("failed to migrate test schema");
+
+            Self {
+                admin_config,
+                schema,
+                repo: PostgresConnectionRepo::new(pool),
+            }
+        }
+
+        pub async fn count(&self, table: &str) -> i64 {
+            let (client, connection) = self.admin_config.connect(NoTls).await.unwrap();
+            tokio::spawn(connection);
+            let row = client
+                .query_one(&format!("SELECT count(*) FROM {}.{}", self.schema, table), &[])
+                .await
+                .unwrap();
+            row.get(0)
+        }
+
+        pub async fn teardown(self) {
+            let (client, connection) = self.admin_config.connect(NoTls).await.unwrap();
+            tokio::spawn(connection);
+            client
+                .batch_execute(&format!("DROP SCHEMA {} CASCADE;", self.schema))
+                .await
+                .unwrap();
+        }
+    }
+
+    fn hostile_restaurant(index: usize, input: &str) -> Restaurant {
+        Restaurant {
+            place_id: format!("{}-{}", input, index),
+            name: input.to_string(),
+            photos: Some(Photo {
+                height: 400,
+                photo_reference: input.to_string(),
+                width: 300,
+            }),
+            rating: Some(4.5),
+            price_level: Some(1),
+            vicinity: input.to_string(),
+            geometry: Location {
+                lat: 1.3521,
+                lng: 103.8198,
+            },
+        }
+    }
+
+    pub async fn seed_places(db: &TestDatabase) -> Vec<Restaurant> {
+        let restaurants: Vec<Restaurant> = HOSTILE_INPUTS
+            .iter()
+            .enumerate()
+            .map(|(index, input)| hostile_restaurant(index, input))
+            .collect();
+        db.repo.store_browsed_places(restaurants.clone()).await.unwrap();
+        restaurants
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn migrations_adopt_hand_applied_schema() {
+        let legacy_schema = format!(
+            "{} {}",
+            include_str!("../../migrations/0001_initial_schema.sql"),
+            "INSERT INTO places (place_id, name) VALUES ('legacy-place', 'Legacy Place'); \
+            INSERT INTO user_reviews (user_id, place_id, rating, description, timestamp) \
+            VALUES ('legacy-user', 'legacy-place', 4.0, 'Still here', '2023-07-01 12:00:00'), \
+            ('legacy-user', 'legacy-place', 1.0, 'Posted twice', '2023-06-01 12:00:00'), \
+            ('legacy-other', 'closed-place', 3.0, 'Never stored', '2023-06-01 12:00:00');",
+        );
+        let db = TestDatabase::setup_from_legacy_schema(Some(&legacy_schema)).await;
+
+        let reviews = db.repo.get_user_reviews("legacy-user", false, &PageRequest::default()).await.unwrap().items;
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(reviews[0].description, "Still here");
+        assert_eq!(reviews[0].timestamp, 1_688_212_800);
+        let summary = db.repo.retrieve_rating_summary("legacy-place").await.unwrap();
+        assert_eq!((summary.count, summary.average), (1, Some(4.0)));
+        // reviews of places that were never stored survive the foreign key
+        assert_eq!(db.repo.get_user_reviews("legacy-other", false, &PageRequest::default()).await.unwrap().items.len(), 1);
+
+        let pool = &db.repo.postgres_connection;
+        assert!(run_migrations(pool).await.unwrap().is_empty());
+        assert_eq!(db.count("schema_migrations").await, MIGRATIONS.len() as i64);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn users_round_trip_hostile_input() {
+        let db = TestDatabase::setup().await;
+
+        for (index, input) in HOSTILE_INPUTS.iter().enumerate() {
+            let user = User {
+                user_id: format!("{}-{}", input, index),
+                username: input.to_string(),
+                password_hash: input.to_string(),
+                created_at: 1_700_000_000,
+            };
+            db.repo.create_user(&user).await.unwrap();
+
+            let stored = db.repo
+                .retrieve_user_by_username(input)
+                .await
+                .unwrap()
+                .expect("user should have been stored");
+            assert_eq!(stored.user_id, user.user_id);
+            assert_eq!(stored.password_hash, user.password_hash);
+
+            let duplicate = db.repo.create_user(&user).await;
+            assert!(matches!(duplicate, Err(AppError::Conflict(_))));
+        }
+        assert_eq!(db.count("users").await, HOSTILE_INPUTS.len() as i64);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn places_round_trip_hostile_input() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+
+        assert_eq!(db.count("places").await, HOSTILE_INPUTS.len() as i64);
+        for restaurant in &restaurants {
+            let stored = db.repo
+                .retrieve_restaurant(&restaurant.place_id)
+                .await
+                .unwrap()
+                .expect("restaurant should have been stored");
+            assert_eq!(stored.name, restaurant.name);
+            assert_eq!(stored.vicinity, restaurant.vicinity);
+            assert_eq!(stored.photos, restaurant.photos);
+        }
+
+        db.teardown().await;
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn places_without_photos_or_rating_round_trip() {
+        let db = TestDatabase::setup().await;
+        let mut restaurant = hostile_restaurant(0, "Unrated Stall");
+        restaurant.photos = None;
+        restaurant.rating = None;
+        db.repo.store_browsed_places(vec![restaurant.clone()]).await.unwrap();
+
+        let stored = db.repo.retrieve_restaurant(&restaurant.place_id).await.unwrap().unwrap();
+        assert!(stored.photos.is_none());
+        assert!(stored.rating.is_none());
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn search_ranks_words_and_forgives_typos() {
+        let db = TestDatabase::setup().await;
+        let places = [
+            ("tian-tian", "Tian Tian Hainanese Chicken Rice", "1 Kadayanallur St, Maxwell Food Centre"),
+            ("wee-nam-kee", "Wee Nam Kee Chicken Rice", "101 Thomson Rd, United Square"),
+            ("zhen-zhen", "Zhen Zhen Porridge", "1 Kadayanallur St, Maxwell Food Centre"),
+            ("rice-garden", "Rice Garden", "Tampines Mall"),
+        ];
+        let restaurants: Vec<Restaurant> = places
+            .iter()
+            .enumerate()
+            .map(|(index, (place_id, name, vicinity))| Restaurant {
+                place_id: place_id.to_string(),
+                name: name.to_string(),
+                vicinity: vicinity.to_string(),
+                ..hostile_restaurant(index, name)
+            })
+            .collect();
+        db.repo.store_browsed_places(restaurants).await.unwrap();
+        let place_ids = |results: Vec<RestaurantSearchResult>| -> Vec<String> {
+            results.into_iter().map(|result| result.restaurant.place_id).collect()
+        };
+
+        let typo = db.repo.search_for_restaurants("chikn rice", 20, 0).await.unwrap();
+        assert!(typo[0].score > 0.0);
+        assert_eq!(place_ids(typo)[..2], ["tian-tian", "wee-nam-kee"]);
+
+        // words can come from the name and the vicinity
+        let multi_word = db.repo.search_for_restaurants("maxwell chicken", 20, 0).await.unwrap();
+        assert_eq!(place_ids(multi_word)[0], "tian-tian");
+
+        let by_vicinity = db.repo.search_for_restaurants("Kadayanallur", 20, 0).await.unwrap();
+        assert_eq!(place_ids(by_vicinity), ["tian-tian", "zhen-zhen"]);
+
+        let all_rice = db.repo.search_for_restaurants("rice", 20, 0).await.unwrap();
+        assert!(all_rice.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        let second_page = db.repo.search_for_restaurants("rice", 1, 1).await.unwrap();
+        assert_eq!(place_ids(second_page), [all_rice[1].restaurant.place_id.clone()]);
+
+        assert!(db.repo.search_for_restaurants("laksa", 20, 0).await.unwrap().is_empty());
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn search_matches_hostile_input_literally() {
+        let db = TestDatabase::setup().await;
+        seed_places(&db).await;
+
+        for input in HOSTILE_INPUTS {
+            // similar looking inputs can show up as fuzzy matches, but only below the literal one
+            let found = db.repo.search_for_restaurants(input, 20, 0).await.unwrap();
+            assert_eq!(found[0].restaurant.name, *input, "search for {:?} returned {:?}", input, found);
+            let literal_matches = found.iter().filter(|result| result.restaurant.name.contains(input)).count();
+            assert_eq!(literal_matches, 1, "search for {:?} returned {:?}", input, found);
+        }
+
+        let wildcard = db.repo.search_for_restaurants("%", 20, 0).await.unwrap();
+        assert_eq!(wildcard.len(), 1);
+        assert_eq!(wildcard[0].restaurant.name, "100% _real_ \\ backslash");
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn restaurants_near_are_closest_first() {
+        let db = TestDatabase::setup().await;
+        let offsets = [("far", 0.02, Some(4.8), Some(1)), ("near", 0.001, Some(3.9), None), ("nearer", 0.0005, None, Some(3))];
+        let restaurants: Vec<Restaurant> = offsets
+            .iter()
+            .enumerate()
+            .map(|(index, (name, offset, rating, price_level))| {
+                let mut restaurant = hostile_restaurant(index, name);
+                restaurant.geometry.lat += offset;
+                restaurant.rating = *rating;
+                restaurant.price_level = *price_level;
+                restaurant
+            })
+            .collect();
+        db.repo.store_browsed_places(restaurants).await.unwrap();
+
+        let centre = Location { lat: 1.3521, lng: 103.8198 };
+        let names = |nearby: Vec<NearbyRestaurant>| -> Vec<String> {
+            nearby.into_iter().map(|nearby| nearby.restaurant.name).collect()
+        };
+        let nearby = db.repo.retrieve_restaurants_near(&NearbyRestaurantsQuery::new(centre.clone(), 500.0, 20)).await.unwrap();
+        assert!((nearby[0].distance_metres - 55.6).abs() < 0.1, "{}", nearby[0].distance_metres);
+        assert_eq!(names(nearby), vec!["nearer", "near"]);
+
+        let limited = db.repo.retrieve_restaurants_near(&NearbyRestaurantsQuery::new(centre.clone(), 5_000.0, 1)).await.unwrap();
+        assert_eq!(names(limited), vec!["nearer"]);
+
+        let mut filtered = NearbyRestaurantsQuery::new(centre.clone(), 5_000.0, 20);
+        filtered.min_rating = Some(3.5);
+        assert_eq!(names(db.repo.retrieve_restaurants_near(&filtered).await.unwrap()), vec!["near", "far"]);
+        filtered.max_price_level = Some(2);
+        assert_eq!(names(db.repo.retrieve_restaurants_near(&filtered).await.unwrap()), vec!["far"]);
+        filtered.min_rating = None;
+        filtered.min_price_level = Some(2);
+        filtered.max_price_level = None;
+        assert_eq!(names(db.repo.retrieve_restaurants_near(&filtered).await.unwrap()), vec!["nearer"]);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn restaurants_near_can_use_the_spatial_index() {
+        let db = TestDatabase::setup().await;
+        let (client, connection) = db.admin_config.connect(NoTls).await.unwrap();
+        tokio::spawn(connection);
+
+        // the table is far too small for the planner to pick the index on its own
+        let plan = client
+            .simple_query(&format!(
+                "SET search_path TO {}, public; SET enable_seqscan TO off; \
+                EXPLAIN SELECT * FROM places WHERE earth_box(ll_to_earth(1.3521, 103.8198), 500) @> ll_to_earth(lat, lng);",
+                db.schema,
+            ))
+            .await
+            .unwrap();
+        let plan: Vec<String> = plan
+            .into_iter()
+            .filter_map(|message| match message {
+                bb8_postgres::tokio_postgres::SimpleQueryMessage::Row(row) => row.get(0).map(str::to_string),
+                _ => None,
+            })
+            .collect();
+        assert!(plan.iter().any(|line| line.contains("places_location_idx")), "{:?}", plan);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn bookmarks_round_trip_hostile_input() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+
+        for (input, restaurant) in HOSTILE_INPUTS.iter().zip(&restaurants) {
+            let user_id = input.to_string();
+            db.repo.bookmark_place(&user_id, &restaurant.place_id).await.unwrap();
+
+            let bookmarks = db.repo.retrieve_bookmarked_places(&user_id, &PageRequest::default()).await.unwrap().items;
+            assert_eq!(bookmarks.len(), 1);
+            assert_eq!(bookmarks[0].place_id, restaurant.place_id);
+        }
+        assert_eq!(db.count("user_favourite_places").await, HOSTILE_INPUTS.len() as i64);
+
+        for (input, restaurant) in HOSTILE_INPUTS.iter().zip(&restaurants) {
+            db.repo.remove_bookmark(input, &restaurant.place_id).await.unwrap();
+        }
+        assert_eq!(db.count("user_favourite_places").await, 0);
+        assert_eq!(db.count("places").await, HOSTILE_INPUTS.len() as i64);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn list_pages_follow_their_cursors() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+
+        // bookmarked within the same second, so the place id has to break the ties
+        for restaurant in &restaurants {
+            db.repo.bookmark_place("alice", &restaurant.place_id).await.unwrap();
+        }
+        let mut page = PageRequest::first(3);
+        let mut bookmarked = Vec::new();
+        loop {
+            let bookmarks = db.repo.retrieve_bookmarked_places("alice", &page).await.unwrap();
+            assert!(bookmarks.items.len() <= 3);
+            bookmarked.extend(bookmarks.items.into_iter().map(|restaurant| restaurant.place_id));
+            match bookmarks.next_cursor {
+                Some(cursor) => page.after = Some(Cursor::decode(&cursor).unwrap()),
+                None => break,
+            }
+        }
+        let mut expected: Vec<String> = restaurants.iter().map(|restaurant| restaurant.place_id.clone()).collect();
+        expected.sort_by(|a, b| b.cmp(a));
+        assert_eq!(bookmarked, expected);
+
+        for vote_timestamp in [1_700_000_000, 1_700_000_000, 1_700_000_100] {
+            db.repo
+                .store_vote_history(vec!["alice".to_string()], json!([]), vote_timestamp, None)
+                .await
+                .unwrap();
+        }
+        let first = db.repo.retrieve_user_vote_history("alice", &PageRequest::first(2)).await.unwrap();
+        assert_eq!(first.items.iter().map(|history| history.vote_timestamp).collect::<Vec<_>>(), [1_700_000_100, 1_700_000_000]);
+        let after = Cursor::decode(&first.next_cursor.unwrap()).unwrap();
+        let last = db.repo
+            .retrieve_user_vote_history("alice", &PageRequest { after: Some(after), limit: 2 })
+            .await
+            .unwrap();
+        assert_eq!(last.items.len(), 1);
+        assert!(last.next_cursor.is_none());
+
+        let foreign_cursor = PageRequest { after: Some(Cursor::new(1_700_000_000, "tian-tian")), limit: 2 };
+        let error = db.repo.retrieve_user_vote_history("alice", &foreign_cursor).await.unwrap_err();
+        assert_eq!(error.code(), "validation_failed");
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn reviews_round_trip_hostile_input() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+
+        for (input, restaurant) in HOSTILE_INPUTS.iter().zip(&restaurants) {
+            let user_id = input.to_string();
+            db.repo
+                .add_user_review(&user_id, &restaurant.place_id, 4.0, &user_id, None)
+                .await
+                .unwrap();
+
+            let user_reviews = db.repo.get_user_reviews(&user_id, false, &PageRequest::default()).await.unwrap().items;
+            assert_eq!(user_reviews.len(), 1);
+            assert_eq!(user_reviews[0].description, *input);
+
+            let updated_description = format!("{} -- updated '", input);
+            let created = db.repo
+                .upsert_review(&user_id, &restaurant.place_id, 2.5, &updated_description, None)
+                .await
+                .unwrap();
+            assert!(!created);
+
+            let place_reviews = db.repo
+                .retrieve_restaurant_reviews(&restaurant.place_id, ReviewSort::Newest, None, &PageRequest::default())
+                .await
+                .unwrap()
+                .items;
+            assert_eq!(place_reviews.len(), 1);
+            assert_eq!(place_reviews[0].description, updated_description);
+            assert_eq!(place_reviews[0].rating, 2.5);
+        }
+        assert_eq!(db.count("user_reviews").await, HOSTILE_INPUTS.len() as i64);
+
+        // removed reviews are only marked deleted
+        for (input, restaurant) in HOSTILE_INPUTS.iter().zip(&restaurants) {
+            db.repo.remove_review(input, &restaurant.place_id).await.unwrap();
+            assert!(db.repo.get_user_reviews(input, false, &PageRequest::default()).await.unwrap().items.is_empty());
+        }
+        assert_eq!(db.count("user_reviews").await, HOSTILE_INPUTS.len() as i64);
+        assert_eq!(db.count("user_review_revisions").await, 2 * HOSTILE_INPUTS.len() as i64);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn one_review_per_user_and_place() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+
+        db.repo.add_user_review("alice", place_id, 4.0, "first", None).await.unwrap();
+        let error = db.repo.add_user_review("alice", place_id, 1.0, "second", None).await.unwrap_err();
+        assert_eq!(error.code(), "conflict");
+        let error = db.repo.add_user_review("alice", "nowhere", 1.0, "", None).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+
+        assert!(!db.repo.upsert_review("alice", place_id, 2.0, "changed", None).await.unwrap());
+        assert!(db.repo.upsert_review("bob", place_id, 5.0, "", None).await.unwrap());
+        assert_eq!(db.repo.upsert_review("bob", "nowhere", 5.0, "", None).await.unwrap_err().code(), "not_found");
+
+        let reviews = db.repo.retrieve_restaurant_reviews(place_id, ReviewSort::Newest, None, &PageRequest::default()).await.unwrap().items;
+        assert_eq!(reviews.len(), 2);
+        assert_eq!(db.repo.retrieve_rating_summary(place_id).await.unwrap().average, Some(3.5));
+        assert_eq!(db.count("place_rating_summaries").await, 1);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn removed_reviews_keep_their_revisions() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+
+        db.repo.add_user_review("alice", place_id, 3.0, HOSTILE_INPUTS[1], None).await.unwrap();
+        db.repo.upsert_review("alice", place_id, 4.0, HOSTILE_INPUTS[2], None).await.unwrap();
+        let history = db.repo.retrieve_review_history("alice", place_id, &PageRequest::first(1)).await.unwrap();
+        assert_eq!((history.items[0].revision, history.items[0].description.as_str()), (2, HOSTILE_INPUTS[2]));
+        let after = Cursor::decode(&history.next_cursor.unwrap()).unwrap();
+        let history = db.repo
+            .retrieve_review_history("alice", place_id, &PageRequest { after: Some(after), limit: 1 })
+            .await
+            .unwrap();
+        assert_eq!((history.items[0].revision, history.items[0].description.as_str()), (1, HOSTILE_INPUTS[1]));
+        assert!(history.next_cursor.is_none());
+
+        let removed_at = OffsetDateTime::now_utc().unix_timestamp();
+        db.repo.remove_review("alice", place_id).await.unwrap();
+        assert_eq!(db.repo.remove_review("alice", place_id).await.unwrap_err().code(), "not_found");
+        let reviews = db.repo.retrieve_restaurant_reviews(place_id, ReviewSort::Newest, None, &PageRequest::default()).await.unwrap();
+        assert!(reviews.items.is_empty());
+        assert!(db.repo.get_user_reviews("alice", false, &PageRequest::default()).await.unwrap().items.is_empty());
+        assert_eq!(db.repo.get_user_reviews("alice", true, &PageRequest::default()).await.unwrap().items.len(), 1);
+        assert_eq!(db.repo.retrieve_rating_summary(place_id).await.unwrap().count, 0);
+        let error = db.repo.retrieve_review_history("alice", place_id, &PageRequest::default()).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+
+        // only removals at or after deleted_since can be undone
+        let error = db.repo.restore_review("alice", place_id, removed_at + 60).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        db.repo.restore_review("alice", place_id, removed_at - 60).await.unwrap();
+        assert_eq!(db.repo.retrieve_rating_summary(place_id).await.unwrap().average, Some(4.0));
+
+        // writing over a removed review counts as creating it and continues its history
+        db.repo.remove_review("alice", place_id).await.unwrap();
+        db.repo.add_user_review("alice", place_id, 5.0, "back again", None).await.unwrap();
+        let history = db.repo.retrieve_review_history("alice", place_id, &PageRequest::default()).await.unwrap();
+        assert_eq!(history.items.len(), 3);
+        db.repo.remove_review("alice", place_id).await.unwrap();
+        assert!(db.repo.upsert_review("alice", place_id, 5.0, "and again", None).await.unwrap());
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn rating_summaries_follow_review_changes() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+
+        for (user_id, rating) in [("alice", 5.0), ("bob", 4.5), ("carol", 1.0)] {
+            db.repo.add_user_review(user_id, place_id, rating, "", None).await.unwrap();
+        }
+        let summary = db.repo.retrieve_rating_summary(place_id).await.unwrap();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.average, Some(3.5));
+        assert_eq!(summary.histogram, [1, 0, 0, 0, 2]);
+        assert_eq!(summary.recent_count, 3);
+        assert_eq!(summary.trend, Some(0.0));
+
+        db.repo.upsert_review("carol", place_id, 2.5, "", None).await.unwrap();
+        db.repo.remove_review("alice", place_id).await.unwrap();
+        let summary = db.repo.retrieve_rating_summary(place_id).await.unwrap();
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.average, Some(3.5));
+        assert_eq!(summary.histogram, [0, 0, 1, 0, 1]);
+
+        // reviews written long ago count overall but not towards the recent average
+        db.repo
+            .get_postgres_connection()
+            .await
+            .unwrap()
+            .execute("UPDATE user_reviews SET timestamp = 0 WHERE user_id = 'carol';", &[])
+            .await
+            .unwrap();
+        let summary = db.repo.retrieve_rating_summary(place_id).await.unwrap();
+        assert_eq!(summary.recent_count, 1);
+        assert_eq!(summary.recent_average, Some(4.5));
+        assert_eq!(summary.trend, Some(1.0));
+
+        assert_eq!(db.repo.remove_review("alice", place_id).await.unwrap_err().code(), "not_found");
+        assert_eq!(db.repo.retrieve_rating_summary("nowhere").await.unwrap(), RatingSummary::default());
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn reviews_sort_and_count_helpful_votes() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+        for (user_id, rating) in [("alice", 4.5), ("bob", 1.0), ("carol", 3.0), ("dave", 4.0)] {
+            db.repo.add_user_review(user_id, place_id, rating, "", None).await.unwrap();
+        }
+
+        for voter in HOSTILE_INPUTS {
+            db.repo.vote_on_review(voter, "carol", place_id, true).await.unwrap();
+        }
+        db.repo.vote_on_review(HOSTILE_INPUTS[0], "bob", place_id, true).await.unwrap();
+        db.repo.vote_on_review(HOSTILE_INPUTS[0], "bob", place_id, false).await.unwrap();
+        db.repo.remove_review_vote(HOSTILE_INPUTS[1], "carol", place_id).await.unwrap();
+        let error = db.repo.remove_review_vote(HOSTILE_INPUTS[1], "carol", place_id).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        let error = db.repo.vote_on_review("alice", "carol", "nowhere", true).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+
+        let user_ids = |reviews: &Page<RestaurantRating>| -> Vec<String> {
+            reviews.items.iter().map(|review| review.user_id.clone()).collect()
+        };
+        let highest = db.repo.retrieve_restaurant_reviews(place_id, ReviewSort::Highest, None, &PageRequest::default()).await.unwrap();
+        assert_eq!(user_ids(&highest), ["alice", "dave", "carol", "bob"]);
+        let lowest = db.repo.retrieve_restaurant_reviews(place_id, ReviewSort::Lowest, None, &PageRequest::first(3)).await.unwrap();
+        assert_eq!(user_ids(&lowest), ["bob", "carol", "dave"]);
+        let after = Cursor::decode(&lowest.next_cursor.unwrap()).unwrap();
+        let rest = db.repo
+            .retrieve_restaurant_reviews(place_id, ReviewSort::Lowest, None, &PageRequest { after: Some(after.clone()), limit: 3 })
+            .await
+            .unwrap();
+        assert_eq!(user_ids(&rest), ["alice"]);
+        let error = db.repo
+            .retrieve_restaurant_reviews(place_id, ReviewSort::Newest, None, &PageRequest { after: Some(after), limit: 3 })
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "validation_failed");
+
+        let most_helpful = db.repo
+            .retrieve_restaurant_reviews(place_id, ReviewSort::MostHelpful, None, &PageRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(most_helpful.items[0].user_id, "carol");
+        assert_eq!(most_helpful.items[0].helpful_count, HOSTILE_INPUTS.len() as i64 - 1);
+        let bob = most_helpful.items.iter().find(|review| review.user_id == "bob").unwrap();
+        assert_eq!((bob.helpful_count, bob.unhelpful_count), (0, 1));
+
+        let four_stars = db.repo.retrieve_restaurant_reviews(place_id, ReviewSort::Newest, Some(4), &PageRequest::default()).await.unwrap();
+        assert_eq!(user_ids(&four_stars), ["dave"]);
+
+        db.teardown().await;
+    }
+
+    fn review_photo(photo_id: &str) -> ReviewPhoto {
+        ReviewPhoto {
+            photo_id: photo_id.to_string(),
+            url: format!("/review/photos/reviews/{}.png", photo_id),
+            thumbnail_url: format!("/review/photos/reviews/{}_thumb.jpg", photo_id),
+            content_type: "image/png".to_string(),
+            width: 40,
+            height: 20,
+            created_at: 1_700_000_000,
+            storage_key: format!("reviews/{}.png", photo_id),
+            thumbnail_key: format!("reviews/{}_thumb.jpg", photo_id),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn review_photos_attach_in_order_up_to_the_limit() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+        let error = db.repo.add_review_photos("alice", place_id, &[review_photo("first")], 3).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+
+        db.repo.add_user_review("alice", place_id, 4.0, "", None).await.unwrap();
+        db.repo.add_user_review("bob", place_id, 3.0, "", None).await.unwrap();
+        db.repo.add_review_photos("alice", place_id, &[review_photo("first"), review_photo("second")], 3).await.unwrap();
+        let error = db.repo
+            .add_review_photos("alice", place_id, &[review_photo("third"), review_photo("fourth")], 3)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "validation_failed");
+        db.repo.add_review_photos("alice", place_id, &[review_photo("third")], 3).await.unwrap();
+
+        let removed = db.repo.remove_review_photo("alice", place_id, "first").await.unwrap();
+        assert_eq!(removed, review_photo("first"));
+        let error = db.repo.remove_review_photo("bob", place_id, "second").await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        db.repo.add_review_photos("alice", place_id, &[review_photo(HOSTILE_INPUTS[0])], 3).await.unwrap();
+
+        let photo_ids = |review: &RestaurantRating| -> Vec<String> {
+            review.photos.iter().map(|photo| photo.photo_id.clone()).collect()
+        };
+        let reviews = db.repo.retrieve_restaurant_reviews(place_id, ReviewSort::Highest, None, &PageRequest::default()).await.unwrap();
+        assert_eq!(photo_ids(&reviews.items[0]), ["second", "third", HOSTILE_INPUTS[0]]);
+        assert!(reviews.items[1].photos.is_empty());
+        // photos stay with the review through edits
+        db.repo.upsert_review("alice", place_id, 4.5, "edited", None).await.unwrap();
+        let reviews = db.repo.get_user_reviews("alice", false, &PageRequest::default()).await.unwrap();
+        assert_eq!(photo_ids(&reviews.items[0]), ["second", "third", HOSTILE_INPUTS[0]]);
+        assert_eq!(reviews.items[0].photos[0], review_photo("second"));
+
+        // photos are only found while their review is published and not removed
+        let published = |key: &'static str| db.repo.retrieve_published_review_photo(key);
+        assert_eq!(published("reviews/second.png").await.unwrap(), Some(review_photo("second")));
+        assert_eq!(published("reviews/third_thumb.jpg").await.unwrap(), Some(review_photo("third")));
+        assert_eq!(published("reviews/first.png").await.unwrap(), None);
+        db.repo.moderate_review("alice", place_id, ReviewStatus::Hidden).await.unwrap();
+        assert_eq!(published("reviews/second.png").await.unwrap(), None);
+        db.repo.moderate_review("alice", place_id, ReviewStatus::Published).await.unwrap();
+        db.repo.remove_review("alice", place_id).await.unwrap();
+        assert_eq!(published("reviews/second.png").await.unwrap(), None);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn moderation_holds_back_and_settles_reviews() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+
+        db.repo.add_user_review("alice", place_id, 1.0, HOSTILE_INPUTS[2], Some(HOSTILE_INPUTS[1])).await.unwrap();
+        // a held back review stays held back through further writes, the filter files one report
+        assert!(!db.repo.upsert_review("alice", place_id, 2.0, "clean now", None).await.unwrap());
+        db.repo.upsert_review("alice", place_id, 1.0, HOSTILE_INPUTS[2], Some("again")).await.unwrap();
+        db.repo.add_user_review("bob", place_id, 4.0, "fine", None).await.unwrap();
+        assert_eq!(db.repo.retrieve_rating_summary(place_id).await.unwrap().count, 1);
+        let reviews = db.repo.retrieve_restaurant_reviews(place_id, ReviewSort::Newest, None, &PageRequest::default()).await.unwrap().items;
+        assert_eq!(reviews.iter().map(|review| review.user_id.as_str()).collect::<Vec<_>>(), ["bob"]);
+        let own = db.repo.get_user_reviews("alice", false, &PageRequest::default()).await.unwrap().items;
+        assert_eq!(own[0].status, ReviewStatus::Pending);
+        let error = db.repo.retrieve_review_history("alice", place_id, &PageRequest::default()).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        let error = db.repo.report_review("carol", "alice", place_id, "spam", 2).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+
+        db.repo.report_review("carol", "bob", place_id, HOSTILE_INPUTS[3], 2).await.unwrap();
+        let error = db.repo.report_review("carol", "bob", place_id, "twice", 2).await.unwrap_err();
+        assert_eq!(error.code(), "conflict");
+        assert_eq!(db.repo.retrieve_rating_summary(place_id).await.unwrap().count, 1);
+        db.repo.report_review("dave", "bob", place_id, "spam", 2).await.unwrap();
+        assert_eq!(db.repo.retrieve_rating_summary(place_id).await.unwrap().count, 0);
+
+        let queue = db.repo.retrieve_report_queue(&PageRequest::first(2)).await.unwrap();
+        assert_eq!(queue.items[0].report.reporter_id, None);
+        assert_eq!(queue.items[0].report.reason, HOSTILE_INPUTS[1]);
+        assert_eq!(queue.items[0].review.description, HOSTILE_INPUTS[2]);
+        assert_eq!(queue.items[1].report.reason, HOSTILE_INPUTS[3]);
+        let after = Cursor::decode(&queue.next_cursor.unwrap()).unwrap();
+        let queue = db.repo.retrieve_report_queue(&PageRequest { after: Some(after), limit: 2 }).await.unwrap();
+        assert_eq!(queue.items.len(), 1);
+        assert!(queue.next_cursor.is_none());
+
+        db.repo.moderate_review("alice", place_id, ReviewStatus::Published).await.unwrap();
+        db.repo.moderate_review("bob", place_id, ReviewStatus::Hidden).await.unwrap();
+        assert!(db.repo.retrieve_report_queue(&PageRequest::default()).await.unwrap().items.is_empty());
+        let summary = db.repo.retrieve_rating_summary(place_id).await.unwrap();
+        assert_eq!((summary.count, summary.average), (1, Some(1.0)));
+        let error = db.repo.moderate_review("alice", "nowhere", ReviewStatus::Hidden).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        // history starts at the version the moderator approved, the ones written while held back stay out
+        let history = db.repo.retrieve_review_history("alice", place_id, &PageRequest::default()).await.unwrap();
+        assert_eq!(history.items.iter().map(|revision| revision.revision).collect::<Vec<_>>(), [3]);
+        db.repo.upsert_review("alice", place_id, 1.5, "edited", None).await.unwrap();
+        let history = db.repo.retrieve_review_history("alice", place_id, &PageRequest::default()).await.unwrap();
+        assert_eq!(history.items.iter().map(|revision| revision.revision).collect::<Vec<_>>(), [4, 3]);
+
+        // settled reports no longer count, the review can be reported afresh
+        db.repo.report_review("carol", "alice", place_id, "spam", 2).await.unwrap();
+        assert_eq!(db.repo.retrieve_report_queue(&PageRequest::default()).await.unwrap().items.len(), 1);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn reservations_round_trip_hostile_input() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let future_timestamp = OffsetDateTime::now_utc().unix_timestamp() + 3600;
+
+        for (input, restaurant) in HOSTILE_INPUTS.iter().zip(&restaurants) {
+            let user_id = input.to_string();
+            db.repo
+                .add_reservations(&user_id, &restaurant.place_id, future_timestamp, 4, 3600)
+                .await
+                .unwrap();
+
+            let valid = db.repo.retrieve_all_user_valid_reservations(&user_id, &PageRequest::default()).await.unwrap().items;
+            assert_eq!(valid.len(), 1);
+            assert_eq!(valid[0].place_id, restaurant.place_id);
+
+            let all = db.repo.retrieve_all_user_reservations(&user_id, &PageRequest::default()).await.unwrap().items;
+            assert_eq!(all.len(), 1);
+            assert_eq!(all[0].user_id, *input);
+        }
+
+        for input in HOSTILE_INPUTS {
+            let reservation = db.repo.retrieve_all_user_reservations(input, &PageRequest::default()).await.unwrap().items.remove(0);
+            let cancelled = db.repo.set_reservation_status(Some(input), &reservation.reservation_id, ReservationStatus::Cancelled).await.unwrap();
+            assert_eq!(cancelled.status, ReservationStatus::Cancelled);
+            assert!(db.repo.retrieve_all_user_valid_reservations(input, &PageRequest::default()).await.unwrap().items.is_empty());
+        }
+        assert_eq!(db.count("user_reservations").await, HOSTILE_INPUTS.len() as i64);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn reservations_follow_their_lifecycle() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+        let soon = OffsetDateTime::now_utc().unix_timestamp() + 3600;
+
+        let first = db.repo.add_reservations("alice", place_id, soon, 2, 3600).await.unwrap();
+        let second = db.repo.add_reservations("alice", place_id, soon + 86_400, 4, 3600).await.unwrap();
+        assert_ne!(first.reservation_id, second.reservation_id);
+        assert_eq!(first.status, ReservationStatus::Requested);
+        let requested = db.repo.retrieve_requested_reservations(&PageRequest::first(1)).await.unwrap();
+        assert_eq!(requested.items[0].reservation_id, first.reservation_id);
+        let after = Cursor::decode(&requested.next_cursor.unwrap()).unwrap();
+        let rest = db.repo.retrieve_requested_reservations(&PageRequest { after: Some(after), limit: 5 }).await.unwrap();
+        assert_eq!(rest.items[0].reservation_id, second.reservation_id);
+
+        let error = db.repo.set_reservation_status(Some("bob"), &first.reservation_id, ReservationStatus::Cancelled).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        let confirmed = db.repo.set_reservation_status(None, &first.reservation_id, ReservationStatus::Confirmed).await.unwrap();
+        assert_eq!(confirmed.status, ReservationStatus::Confirmed);
+        let error = db.repo.set_reservation_status(None, &second.reservation_id, ReservationStatus::NoShow).await.unwrap_err();
+        assert_eq!(error.code(), "conflict");
+
+        let changed = db.repo.modify_reservation("alice", &first.reservation_id, soon + 1800, 3, 3600).await.unwrap();
+        assert_eq!((changed.reservation_timestamp, changed.reservation_pax), (soon + 1800, 3));
+        assert_eq!(changed.status, ReservationStatus::Requested);
+        assert_eq!(changed.created_at, first.created_at);
+        db.repo.set_reservation_status(None, &second.reservation_id, ReservationStatus::Cancelled).await.unwrap();
+        let error = db.repo.modify_reservation("alice", &second.reservation_id, soon, 2, 3600).await.unwrap_err();
+        assert_eq!(error.code(), "conflict");
+
+        let valid = db.repo.retrieve_all_user_valid_reservations("alice", &PageRequest::default()).await.unwrap();
+        assert_eq!(valid.items.len(), 1);
+        assert_eq!(valid.items[0].reservation_id, first.reservation_id);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn bookings_never_overfill_a_slot() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let place_id = restaurants[0].place_id.as_str();
+        let mut capacity = PlaceCapacity {
+            place_id: place_id.to_string(),
+            seats_per_slot: 4,
+            slot_minutes: 60,
+            utc_offset_minutes: 0,
+            opening_hours: Vec::new(),
+        };
+        let error = db.repo.set_place_capacity(&PlaceCapacity { place_id: "nowhere".to_string(), ..capacity.clone() }).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        db.repo.set_place_capacity(&capacity).await.unwrap();
+        // replacing the capacity opens the place every day
+        capacity.opening_hours = [
+            DayOfWeek::Monday, DayOfWeek::Tuesday, DayOfWeek::Wednesday, DayOfWeek::Thursday,
+            DayOfWeek::Friday, DayOfWeek::Saturday, DayOfWeek::Sunday,
+        ]
+            .into_iter()
+            .map(|day| OpeningHours { day, opens: "10:00".to_string(), closes: "12:00".to_string() })
+            .collect();
+        db.repo.set_place_capacity(&capacity).await.unwrap();
+        assert_eq!(db.repo.retrieve_place_capacity(place_id).await.unwrap(), Some(capacity.clone()));
+        assert_eq!(db.repo.retrieve_place_capacity(&restaurants[1].place_id).await.unwrap(), None);
+
+        let date = (OffsetDateTime::now_utc() + time::Duration::days(2)).date();
+        let ten = date.with_hms(10, 0, 0).unwrap().assume_utc().unix_timestamp();
+        let bookings = HOSTILE_INPUTS.iter().map(|user_id| db.repo.add_reservations(user_id, place_id, ten, 1, 0));
+        let booked = futures::future::join_all(bookings).await;
+        assert_eq!(booked.iter().filter(|booking| booking.is_ok()).count(), 4);
+        assert!(booked.iter().filter_map(|booking| booking.as_ref().err()).all(|error| error.code() == "conflict"));
+        let error = db.repo.add_reservations("alice", place_id, ten + 60, 1, 0).await.unwrap_err();
+        assert_eq!(error.code(), "validation_failed");
+
+        let later = db.repo.add_reservations("alice", place_id, ten + 3600, 3, 0).await.unwrap();
+        let error = db.repo.modify_reservation("alice", &later.reservation_id, ten, 1, 0).await.unwrap_err();
+        assert_eq!(error.code(), "conflict");
+        db.repo.modify_reservation("alice", &later.reservation_id, ten + 3600, 4, 0).await.unwrap();
+        let seats = db.repo.retrieve_booked_seats(place_id, ten, ten + 7200).await.unwrap();
+        assert_eq!(seats, HashMap::from([(ten, 4), (ten + 3600, 4)]));
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn reservations_are_checked_against_places_and_each_other() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let soon = OffsetDateTime::now_utc().unix_timestamp() + 3600;
+
+        let error = db.repo.add_reservations("alice", "nowhere", soon, 2, 3600).await.unwrap_err();
+        assert_eq!(error.code(), RESERVATION_UNKNOWN_PLACE);
+        let first = db.repo.add_reservations("alice", &restaurants[0].place_id, soon, 2, 3600).await.unwrap();
+        // a reservation elsewhere counts as much as one at the same place
+        let error = db.repo.add_reservations("alice", &restaurants[1].place_id, soon + 3599, 2, 3600).await.unwrap_err();
+        assert_eq!(error.code(), RESERVATION_OVERLAPS);
+        let error = db.repo.add_reservations("alice", &restaurants[1].place_id, soon - 3599, 2, 3600).await.unwrap_err();
+        assert_eq!(error.code(), RESERVATION_OVERLAPS);
+        db.repo.add_reservations(HOSTILE_INPUTS[1], &restaurants[1].place_id, soon, 2, 3600).await.unwrap();
+        db.repo.add_reservations("alice", &restaurants[1].place_id, soon + 3599, 2, 0).await.unwrap();
+        let second = db.repo.add_reservations("alice", &restaurants[1].place_id, soon + 7200, 2, 3600).await.unwrap();
+
+        // moving a reservation does not overlap with itself
+        db.repo.modify_reservation("alice", &first.reservation_id, soon + 60, 2, 60).await.unwrap();
+        let error = db.repo.modify_reservation("alice", &second.reservation_id, soon + 3600, 2, 3600).await.unwrap_err();
+        assert_eq!(error.code(), RESERVATION_OVERLAPS);
+        db.repo.set_reservation_status(Some("alice"), &first.reservation_id, ReservationStatus::Cancelled).await.unwrap();
+        let error = db.repo.add_reservations("alice", &restaurants[0].place_id, soon, 2, 3600).await.unwrap_err();
+        assert_eq!(error.code(), RESERVATION_OVERLAPS);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn reservation_reminders_are_claimed_once() {
+        let db = TestDatabase::setup().await;
+        let restaurants = seed_places(&db).await;
+        let soon = OffsetDateTime::now_utc().unix_timestamp() + 3600;
+        let first = db.repo.add_reservations("alice", &restaurants[0].place_id, soon, 2, 0).await.unwrap();
+        let second = db.repo.add_reservations(HOSTILE_INPUTS[1], &restaurants[1].place_id, soon + 60, 2, 0).await.unwrap();
+        let cancelled = db.repo.add_reservations("bob", &restaurants[0].place_id, soon, 2, 0).await.unwrap();
+        db.repo.set_reservation_status(None, &cancelled.reservation_id, ReservationStatus::Cancelled).await.unwrap();
+        db.repo.add_reservations("carol", &restaurants[0].place_id, soon + 7200, 2, 0).await.unwrap();
+
+        let starting = db.repo.retrieve_reservations_starting(soon - 1, soon + 60, &PageRequest::first(1)).await.unwrap();
+        assert_eq!(starting.items[0].reservation_id, first.reservation_id);
+        let cursor = Cursor::decode(&starting.next_cursor.unwrap()).unwrap();
+        let page = PageRequest { after: Some(cursor), limit: 10 };
+        let starting = db.repo.retrieve_reservations_starting(soon - 1, soon + 60, &page).await.unwrap();
+        assert_eq!(starting.items.iter().map(|reservation| &reservation.reservation_id).collect::<Vec<_>>(), [&second.reservation_id]);
+        assert!(starting.next_cursor.is_none());
+
+        let claim = |offsets: &'static [i64]| db.repo.claim_reservation_reminders(&first.reservation_id, soon, offsets);
+        let mut claimed = claim(&[3600, 86400]).await.unwrap();
+        claimed.sort();
+        assert_eq!(claimed, [3600, 86400]);
+        assert!(claim(&[3600, 86400]).await.unwrap().is_empty());
+        // claiming at once hands every reminder out once
+        let claims = futures::future::join_all((0..8).map(|_| claim(&[600]))).await;
+        assert_eq!(claims.into_iter().map(|claimed| claimed.unwrap().len()).sum::<usize>(), 1);
+
+        db.repo.release_reservation_reminders(&first.reservation_id, soon, &[3600]).await.unwrap();
+        assert_eq!(claim(&[3600, 600]).await.unwrap(), [3600]);
+        // a moved reservation is reminded of again
+        assert_eq!(db.repo.claim_reservation_reminders(&first.reservation_id, soon + 60, &[3600]).await.unwrap(), [3600]);
+        assert_eq!(db.count("reservation_reminders").await, 4);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn calendar_feed_tokens_belong_to_one_user_at_a_time() {
+        let db = TestDatabase::setup().await;
+
+        for input in HOSTILE_INPUTS {
+            db.repo.set_calendar_feed_token(input, &format!("first {}", input)).await.unwrap();
+            db.repo.set_calendar_feed_token(input, &format!("second {}", input)).await.unwrap();
+            assert_eq!(db.repo.retrieve_calendar_feed_user(&format!("first {}", input)).await.unwrap(), None);
+            assert_eq!(db.repo.retrieve_calendar_feed_user(&format!("second {}", input)).await.unwrap().as_deref(), Some(*input));
+        }
+        assert_eq!(db.count("calendar_feed_tokens").await, HOSTILE_INPUTS.len() as i64);
+
+        db.repo.remove_calendar_feed_token(HOSTILE_INPUTS[0]).await.unwrap();
+        let error = db.repo.remove_calendar_feed_token(HOSTILE_INPUTS[0]).await.unwrap_err();
+        assert_eq!(error.code(), "not_found");
+        assert_eq!(db.repo.retrieve_calendar_feed_user(&format!("second {}", HOSTILE_INPUTS[0])).await.unwrap(), None);
+
+        db.teardown().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn vote_history_round_trip_hostile_input() {
+        let db = TestDatabase::setup().await;
+
+        let user_ids: Vec<String> = HOSTILE_INPUTS.iter().map(|input| input.to_string()).collect();
+        let voted_places = json!(HOSTILE_INPUTS
+            .iter()
+            .map(|input| json!({ "place_id": input, "votes": 1 }))
+            .collect::<Vec<_>>());
+        let ballots: Vec<Ballot> = user_ids
+            .iter()
+            .map(|user_id| Ballot {
+                user_id: Some(user_id.clone()),
+                choices: vec![user_id.clone()],
+            })
+            .collect();
+        let outcome = tally(VotingMethod::Plurality, &user_ids, &ballots).unwrap();
+        db.repo
+            .store_vote_history(user_ids.clone(), voted_places.clone(), 1_700_000_000, Some(&outcome))
+            .await
+            .unwrap();
+
+        for user_id in &user_ids {
+            let histories = db.repo.retrieve_user_vote_history(user_id, &PageRequest::default()).await.unwrap().items;
+            assert_eq!(histories.len(), 1);
+            assert_eq!(histories[0].user_ids, user_ids);
+            assert_eq!(json!(histories[0].voted_places), voted_places);
+            assert_eq!(histories[0].outcome.as_ref(), Some(&outcome));
+        }
+
+        db.teardown().await;
+    }
+}
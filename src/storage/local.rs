@@ -0,0 +1,75 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use async_trait::async_trait;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::storage::{validate_key, PhotoStorage};
+
+/// Photos kept as files below a directory.
+pub struct LocalPhotoStorage {
+    root: PathBuf,
+}
+
+impl LocalPhotoStorage {
+    pub fn new(
+        root: &str,
+    ) -> Self {
+        LocalPhotoStorage {
+            root: PathBuf::from(root),
+        }
+    }
+
+    fn path(
+        &self,
+        key: &str,
+    ) -> AppResult<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+fn storage_error(
+    e: std::io::Error,
+) -> AppError {
+    AppError::Database(format!("Photo storage failed: {}", e))
+}
+
+#[async_trait]
+impl PhotoStorage for LocalPhotoStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        _content_type: &str,
+        bytes: Vec<u8>,
+    ) -> AppResult<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(storage_error)?;
+        }
+        tokio::fs::write(&path, bytes).await.map_err(storage_error)
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+    ) -> AppResult<Vec<u8>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(AppError::NotFound(format!("No photo found for key: {}", key))),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn delete(
+        &self,
+        key: &str,
+    ) -> AppResult<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(storage_error(e)),
+            _ => Ok(()),
+        }
+    }
+}
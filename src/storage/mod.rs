@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use tracing::info;
+use crate::config::{Config, PhotoStorageBackend};
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::storage::local::LocalPhotoStorage;
+use crate::storage::s3::S3PhotoStorage;
+
+pub mod local;
+pub mod s3;
+
+/// Where the API serves photos from, whichever storage keeps them. Photos are never linked to the storage
+/// itself, the API only hands them out while their review is published.
+pub const PHOTO_ROUTE: &str = "/review/photos";
+
+/// Where uploaded photos are kept. Keys are generated by the server and only ever contain ascii letters,
+/// digits, `-`, `_`, `.` and `/`, see `validate_key`.
+#[async_trait]
+pub trait PhotoStorage: Send + Sync {
+    /// Short name for logs, `local` or `s3`.
+    fn name(&self) -> &'static str;
+
+    async fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        bytes: Vec<u8>,
+    ) -> AppResult<()>;
+
+    /// Not found for keys that were never stored or were deleted.
+    async fn get(
+        &self,
+        key: &str,
+    ) -> AppResult<Vec<u8>>;
+
+    /// Deleting a key that is not there is not an error.
+    async fn delete(
+        &self,
+        key: &str,
+    ) -> AppResult<()>;
+}
+
+/// Where clients fetch the photo stored under `key`.
+pub fn photo_url(
+    key: &str,
+) -> String {
+    format!("{}/{}", PHOTO_ROUTE, key)
+}
+
+/// Rejects keys that could climb out of the storage root or need escaping.
+pub fn validate_key(
+    key: &str,
+) -> AppResult<()> {
+    let valid = !key.is_empty()
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+        && key.split('/').all(|segment| !segment.is_empty() && !segment.starts_with('.'));
+    if !valid {
+        return Err(AppError::NotFound(format!("No photo found for key: {}", key)));
+    }
+    Ok(())
+}
+
+/// The content type of a stored photo, from the extension its key was given on upload.
+pub fn content_type_of(
+    key: &str,
+) -> &'static str {
+    match key.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("jpg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn build_photo_storage(
+    config: &Config,
+) -> anyhow::Result<Arc<dyn PhotoStorage>> {
+    match config.photo_storage {
+        PhotoStorageBackend::Local => {
+            info!("Storing review photos below {}", config.photo_storage_dir);
+            Ok(Arc::new(LocalPhotoStorage::new(&config.photo_storage_dir)))
+        }
+        PhotoStorageBackend::S3 => {
+            let setting = |value: &Option<String>, name: &str| {
+                value.clone().ok_or_else(|| anyhow!("{} must be set when using the s3 photo storage", name))
+            };
+            Ok(Arc::new(S3PhotoStorage::new(
+                &setting(&config.s3_endpoint, "S3_ENDPOINT")?,
+                setting(&config.s3_bucket, "S3_BUCKET")?,
+                config.s3_region.clone(),
+                setting(&config.s3_access_key_id, "S3_ACCESS_KEY_ID")?,
+                setting(&config.s3_secret_access_key, "S3_SECRET_ACCESS_KEY")?,
+            )?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_stay_inside_the_storage_root() {
+        assert!(validate_key("reviews/0b0e-4f.jpg").is_ok());
+        for key in ["", "../etc/passwd", "reviews/../../x", "/absolute.jpg", "reviews//x.jpg", "spaced key.jpg", "%2e%2e/x"] {
+            assert_eq!(validate_key(key).unwrap_err().code(), "not_found", "{}", key);
+        }
+        assert_eq!(content_type_of("reviews/a_thumb.jpg"), "image/jpeg");
+        assert_eq!(content_type_of("reviews/a"), "application/octet-stream");
+        assert_eq!(photo_url("reviews/a_thumb.jpg"), "/review/photos/reviews/a_thumb.jpg");
+    }
+}
@@ -0,0 +1,104 @@
+//! Live group voting. A host opens a room with a set of candidate restaurants and shares its join code,
+//! everyone in the room votes over a WebSocket and the host closes the round to settle on a winner.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use time::OffsetDateTime;
+use uuid::Uuid;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::auth::AuthUser;
+use crate::models::restaurant::Restaurant;
+use crate::voting::room::Room;
+use crate::voting::tally::VotingMethod;
+
+pub mod room;
+pub mod tally;
+
+pub const JOIN_CODE_LENGTH: usize = 6;
+/// Rooms that were never closed are dropped after this long, so abandoned sessions do not pile up.
+pub const ROOM_TTL_SECS: i64 = 6 * 60 * 60;
+
+// no 0/O or 1/I so codes survive being read out loud
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+pub type SharedRoom = Arc<Mutex<Room>>;
+
+/// Registry of the open voting rooms on this instance, keyed by join code.
+#[derive(Clone, Default)]
+pub struct VotingRooms {
+    rooms: Arc<Mutex<HashMap<String, SharedRoom>>>,
+}
+
+impl VotingRooms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_room(
+        &self,
+        host: &AuthUser,
+        method: VotingMethod,
+        candidates: Vec<Restaurant>,
+    ) -> AppResult<SharedRoom> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut rooms = self.lock_rooms()?;
+        rooms.retain(|_, room| {
+            lock_room(room)
+                .map(|room| now - room.created_at < ROOM_TTL_SECS)
+                .unwrap_or(false)
+        });
+
+        let join_code = loop {
+            let join_code = generate_join_code();
+            if !rooms.contains_key(&join_code) {
+                break join_code;
+            }
+        };
+        let room = Arc::new(Mutex::new(Room::new(join_code.clone(), host, method, candidates, now)));
+        rooms.insert(join_code, room.clone());
+
+        Ok(room)
+    }
+
+    pub fn get_room(
+        &self,
+        join_code: &str,
+    ) -> AppResult<SharedRoom> {
+        self.lock_rooms()?
+            .get(&join_code.trim().to_uppercase())
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("No voting room found with join code: {}", join_code)))
+    }
+
+    pub fn remove_room(
+        &self,
+        join_code: &str,
+    ) -> AppResult<()> {
+        self.lock_rooms()?.remove(join_code);
+        Ok(())
+    }
+
+    fn lock_rooms(
+        &self,
+    ) -> AppResult<MutexGuard<'_, HashMap<String, SharedRoom>>> {
+        self.rooms
+            .lock()
+            .map_err(|_| AppError::Database("Voting room registry lock was poisoned".to_string()))
+    }
+}
+
+pub fn lock_room(
+    room: &SharedRoom,
+) -> AppResult<MutexGuard<'_, Room>> {
+    room
+        .lock()
+        .map_err(|_| AppError::Database("Voting room lock was poisoned".to_string()))
+}
+
+fn generate_join_code() -> String {
+    Uuid::new_v4()
+        .as_bytes()
+        .iter()
+        .take(JOIN_CODE_LENGTH)
+        .map(|byte| JOIN_CODE_ALPHABET[*byte as usize % JOIN_CODE_ALPHABET.len()] as char)
+        .collect()
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use crate::helpers::app_error::{AppError, AppResult};
+use crate::helpers::auth::AuthUser;
+use crate::models::restaurant::Restaurant;
+use crate::voting::tally::{tally, validate_ballot, Ballot, TallyResult, VotingMethod};
+
+const EVENT_BUFFER_SIZE: usize = 64;
+
+/// Messages clients send over the room's WebSocket.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Host only, replaces the candidates and clears the votes cast so far.
+    SetCandidates { place_ids: Vec<String> },
+    /// Shorthand for a ballot with a single choice.
+    Vote { place_id: String },
+    /// Members may replace their ballot until the round is closed. Choices are in order of preference for
+    /// ranked choice rooms.
+    Ballot { choices: Vec<String> },
+    /// Host only, ends the round and records the winner.
+    Close,
+}
+
+/// Messages the server pushes to clients. Everything except `state` and `error` is broadcast to the
+/// whole room.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RoomEvent {
+    State(RoomSnapshot),
+    MemberJoined { user_id: String, username: String },
+    MemberLeft { user_id: String, username: String },
+    Candidates { candidates: Vec<Restaurant>, counts: Vec<CandidateVotes> },
+    VoteCast { user_id: String, choices: Vec<String>, counts: Vec<CandidateVotes> },
+    Closed { winner: Restaurant, counts: Vec<CandidateVotes>, outcome: TallyResult },
+    Error { code: String, message: String },
+}
+
+impl From<AppError> for RoomEvent {
+    fn from(e: AppError) -> Self {
+        RoomEvent::Error {
+            code: e.code().to_string(),
+            message: e.public_message(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RoomSnapshot {
+    pub join_code: String,
+    pub host_user_id: String,
+    pub method: VotingMethod,
+    pub created_at: i64,
+    pub candidates: Vec<Restaurant>,
+    pub members: Vec<Member>,
+    pub counts: Vec<CandidateVotes>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Member {
+    pub user_id: String,
+    pub username: String,
+    pub connected: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CandidateVotes {
+    pub place_id: String,
+    pub name: String,
+    pub votes: u32,
+}
+
+/// Result of closing a round, `counts` runs from first to last place.
+#[derive(Clone, Debug)]
+pub struct VoteOutcome {
+    pub winner: Restaurant,
+    pub counts: Vec<CandidateVotes>,
+    pub result: TallyResult,
+    pub voter_ids: Vec<String>,
+}
+
+pub struct Room {
+    pub join_code: String,
+    pub host_user_id: String,
+    pub method: VotingMethod,
+    pub created_at: i64,
+    pub candidates: Vec<Restaurant>,
+    // everyone who joined, in join order, along with their open connection count
+    members: Vec<(Member, usize)>,
+    // user_id -> choices
+    ballots: HashMap<String, Vec<String>>,
+    closing: bool,
+    events: broadcast::Sender<RoomEvent>,
+}
+
+impl Room {
+    pub fn new(
+        join_code: String,
+        host: &AuthUser,
+        method: VotingMethod,
+        candidates: Vec<Restaurant>,
+        created_at: i64,
+    ) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        Room {
+            join_code,
+            host_user_id: host.user_id.clone(),
+            method,
+            created_at,
+            candidates,
+            members: Vec::new(),
+            ballots: HashMap::new(),
+            closing: false,
+            events,
+        }
+    }
+
+    pub fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            join_code: self.join_code.clone(),
+            host_user_id: self.host_user_id.clone(),
+            method: self.method,
+            created_at: self.created_at,
+            candidates: self.candidates.clone(),
+            members: self.members.iter().map(|(member, _)| member.clone()).collect(),
+            counts: self.counts(),
+        }
+    }
+
+    /// Registers a new connection for `user` and subscribes it to the room's events.
+    pub fn join(
+        &mut self,
+        user: &AuthUser,
+    ) -> AppResult<broadcast::Receiver<RoomEvent>> {
+        self.ensure_open()?;
+        let receiver = self.events.subscribe();
+
+        match self.members.iter_mut().find(|(member, _)| member.user_id == user.user_id) {
+            Some((member, connections)) => {
+                *connections += 1;
+                member.connected = true;
+            }
+            None => self.members.push((
+                Member {
+                    user_id: user.user_id.clone(),
+                    username: user.username.clone(),
+                    connected: true,
+                },
+                1,
+            )),
+        }
+        self.broadcast(RoomEvent::MemberJoined {
+            user_id: user.user_id.clone(),
+            username: user.username.clone(),
+        });
+
+        Ok(receiver)
+    }
+
+    /// Drops one of `user_id`'s connections. Members stay on the roster, and keep their ballot, after they
+    /// disconnect since they still took part in the round.
+    pub fn leave(
+        &mut self,
+        user_id: &str,
+    ) {
+        let Some((member, connections)) = self.members
+            .iter_mut()
+            .find(|(member, _)| member.user_id == user_id) else {
+            return;
+        };
+        *connections = connections.saturating_sub(1);
+        if *connections == 0 {
+            member.connected = false;
+            let event = RoomEvent::MemberLeft {
+                user_id: member.user_id.clone(),
+                username: member.username.clone(),
+            };
+            self.broadcast(event);
+        }
+    }
+
+    pub fn set_candidates(
+        &mut self,
+        user_id: &str,
+        candidates: Vec<Restaurant>,
+    ) -> AppResult<()> {
+        self.ensure_open()?;
+        self.ensure_host(user_id, "change the candidates")?;
+
+        self.candidates = candidates;
+        self.ballots.clear();
+        self.broadcast(RoomEvent::Candidates {
+            candidates: self.candidates.clone(),
+            counts: self.counts(),
+        });
+        Ok(())
+    }
+
+    pub fn cast_ballot(
+        &mut self,
+        user_id: &str,
+        choices: Vec<String>,
+    ) -> AppResult<()> {
+        self.ensure_open()?;
+        if !self.members.iter().any(|(member, _)| member.user_id == user_id) {
+            return Err(AppError::Forbidden("Only members of the room can vote".to_string()));
+        }
+        validate_ballot(self.method, &self.candidate_ids(), &choices)?;
+
+        self.ballots.insert(user_id.to_string(), choices.clone());
+        self.broadcast(RoomEvent::VoteCast {
+            user_id: user_id.to_string(),
+            choices,
+            counts: self.counts(),
+        });
+        Ok(())
+    }
+
+    /// Stops accepting votes and works out the winner. The room stays in this state until the outcome
+    /// has been stored and `finish_close` is called, or `abort_close` reopens it if storing failed.
+    pub fn begin_close(
+        &mut self,
+        user_id: &str,
+    ) -> AppResult<VoteOutcome> {
+        self.ensure_open()?;
+        self.ensure_host(user_id, "close the vote")?;
+        if self.ballots.is_empty() {
+            return Err(AppError::Validation("No votes have been cast yet".to_string()));
+        }
+
+        let ballots: Vec<Ballot> = self.ballots
+            .iter()
+            .map(|(user_id, choices)| Ballot {
+                user_id: Some(user_id.clone()),
+                choices: choices.clone(),
+            })
+            .collect();
+        let result = tally(self.method, &self.candidate_ids(), &ballots)?;
+        let name_of = |place_id: &str| self.candidates
+            .iter()
+            .find(|candidate| candidate.place_id == place_id)
+            .map(|candidate| candidate.name.clone());
+        let winner = self.candidates
+            .iter()
+            .find(|candidate| candidate.place_id == result.winner)
+            .cloned()
+            .ok_or_else(|| AppError::Database("Winning candidate missing from the room".to_string()))?;
+        let counts = result
+            .standings()
+            .into_iter()
+            .map(|count| CandidateVotes {
+                name: name_of(&count.place_id).unwrap_or_default(),
+                place_id: count.place_id,
+                votes: count.votes,
+            })
+            .collect();
+
+        self.closing = true;
+        Ok(VoteOutcome {
+            winner,
+            counts,
+            result,
+            voter_ids: self.members.iter().map(|(member, _)| member.user_id.clone()).collect(),
+        })
+    }
+
+    pub fn finish_close(
+        &mut self,
+        outcome: VoteOutcome,
+    ) {
+        self.broadcast(RoomEvent::Closed {
+            winner: outcome.winner,
+            counts: outcome.counts,
+            outcome: outcome.result,
+        });
+    }
+
+    pub fn abort_close(&mut self) {
+        self.closing = false;
+    }
+
+    /// Live counts while the round is open. Ranked ballots only count their first preference here, the
+    /// runoff happens when the round closes.
+    fn counts(&self) -> Vec<CandidateVotes> {
+        self.candidates
+            .iter()
+            .map(|candidate| CandidateVotes {
+                place_id: candidate.place_id.clone(),
+                name: candidate.name.clone(),
+                votes: self.ballots
+                    .values()
+                    .filter(|choices| match self.method {
+                        VotingMethod::RankedChoice => choices.first() == Some(&candidate.place_id),
+                        _ => choices.contains(&candidate.place_id),
+                    })
+                    .count() as u32,
+            })
+            .collect()
+    }
+
+    fn candidate_ids(&self) -> Vec<String> {
+        self.candidates.iter().map(|candidate| candidate.place_id.clone()).collect()
+    }
+
+    fn ensure_open(&self) -> AppResult<()> {
+        if self.closing {
+            return Err(AppError::Conflict("Voting in this room has already closed".to_string()));
+        }
+        Ok(())
+    }
+
+    fn ensure_host(
+        &self,
+        user_id: &str,
+        action: &str,
+    ) -> AppResult<()> {
+        if self.host_user_id != user_id {
+            return Err(AppError::Forbidden(format!("Only the host can {}", action)));
+        }
+        Ok(())
+    }
+
+    fn broadcast(
+        &self,
+        event: RoomEvent,
+    ) {
+        // nobody listening is fine, the event is simply dropped
+        let _ = self.events.send(event);
+    }
+}
@@ -0,0 +1,408 @@
+//! Ballot counting for group decisions. Every method is deterministic: the same candidates and ballots
+//! always produce the same winner, whatever order the ballots arrive in.
+use std::cmp::Reverse;
+use serde::{Deserialize, Serialize};
+use crate::helpers::app_error::{AppError, AppResult};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VotingMethod {
+    /// One choice per ballot, most votes wins.
+    #[default]
+    Plurality,
+    /// Any number of choices per ballot, the most approved candidate wins.
+    Approval,
+    /// Choices in order of preference, counted as an instant runoff.
+    RankedChoice,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ballot {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    pub choices: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoundCount {
+    pub place_id: String,
+    pub votes: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TallyRound {
+    pub round: u32,
+    /// Candidates still standing in this round, in the order they were proposed.
+    pub counts: Vec<RoundCount>,
+    /// Ranked ballots whose remaining choices have all been eliminated.
+    pub exhausted_ballots: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eliminated: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TallyResult {
+    pub method: VotingMethod,
+    pub winner: String,
+    pub ballot_count: u32,
+    pub rounds: Vec<TallyRound>,
+    /// Whether the winner, or an elimination on the way there, was settled by the tie-break rules.
+    pub tie_broken: bool,
+}
+
+impl TallyResult {
+    /// Every candidate from first to last place: the winner, the rest of the final round by votes and then
+    /// the eliminated candidates, latest elimination first, with the votes they had when they went out.
+    pub fn standings(&self) -> Vec<RoundCount> {
+        let mut standings = self.rounds
+            .last()
+            .map(|round| round.counts.clone())
+            .unwrap_or_default();
+        standings.sort_by_key(|count| (count.place_id != self.winner, Reverse(count.votes)));
+
+        for round in self.rounds.iter().rev() {
+            let eliminated = round
+                .eliminated
+                .as_ref()
+                .and_then(|eliminated| round.counts.iter().find(|count| &count.place_id == eliminated));
+            if let Some(eliminated) = eliminated {
+                standings.push(eliminated.clone());
+            }
+        }
+        standings
+    }
+}
+
+/// Checks a single ballot against the candidates and the rules of `method`.
+pub fn validate_ballot(
+    method: VotingMethod,
+    candidates: &[String],
+    choices: &[String],
+) -> AppResult<()> {
+    if choices.is_empty() {
+        return Err(AppError::Validation("A ballot needs at least one choice".to_string()));
+    }
+    if method == VotingMethod::Plurality && choices.len() > 1 {
+        return Err(AppError::Validation("Plurality ballots take exactly one choice".to_string()));
+    }
+    for (position, choice) in choices.iter().enumerate() {
+        if !candidates.contains(choice) {
+            return Err(AppError::Validation(format!("{} is not a candidate", choice)));
+        }
+        if choices[..position].contains(choice) {
+            return Err(AppError::Validation(format!("{} appears on the ballot more than once", choice)));
+        }
+    }
+    Ok(())
+}
+
+/// Counts `ballots` for `candidates`. Ties are settled the same way every time:
+/// - the leading candidate listed first in `candidates` wins a tied round
+/// - an instant runoff eliminates, among the tied last placed candidates, the one with the fewest votes
+///   in the latest earlier round where they differed, and failing that the one listed last
+pub fn tally(
+    method: VotingMethod,
+    candidates: &[String],
+    ballots: &[Ballot],
+) -> AppResult<TallyResult> {
+    if candidates.is_empty() {
+        return Err(AppError::Validation("At least one candidate is needed".to_string()));
+    }
+    for (position, candidate) in candidates.iter().enumerate() {
+        if candidates[..position].contains(candidate) {
+            return Err(AppError::Validation(format!("{} is listed as a candidate more than once", candidate)));
+        }
+    }
+    if ballots.is_empty() {
+        return Err(AppError::Validation("At least one ballot is needed".to_string()));
+    }
+    for ballot in ballots {
+        validate_ballot(method, candidates, &ballot.choices)?;
+    }
+
+    let (winner, rounds, tie_broken) = match method {
+        VotingMethod::Plurality | VotingMethod::Approval => single_round(candidates, ballots),
+        VotingMethod::RankedChoice => instant_runoff(candidates, ballots),
+    };
+
+    Ok(TallyResult {
+        method,
+        winner,
+        ballot_count: ballots.len() as u32,
+        rounds,
+        tie_broken,
+    })
+}
+
+fn single_round(
+    candidates: &[String],
+    ballots: &[Ballot],
+) -> (String, Vec<TallyRound>, bool) {
+    let counts: Vec<RoundCount> = candidates
+        .iter()
+        .map(|candidate| RoundCount {
+            place_id: candidate.clone(),
+            votes: ballots.iter().filter(|ballot| ballot.choices.contains(candidate)).count() as u32,
+        })
+        .collect();
+    let (winner, tie_broken) = leader(&counts);
+
+    let round = TallyRound {
+        round: 1,
+        counts,
+        exhausted_ballots: 0,
+        eliminated: None,
+    };
+    (winner, vec![round], tie_broken)
+}
+
+fn instant_runoff(
+    candidates: &[String],
+    ballots: &[Ballot],
+) -> (String, Vec<TallyRound>, bool) {
+    let mut standing: Vec<String> = candidates.to_vec();
+    let mut rounds: Vec<TallyRound> = Vec::new();
+    let mut tie_broken = false;
+
+    loop {
+        let mut counts: Vec<RoundCount> = standing
+            .iter()
+            .map(|candidate| RoundCount {
+                place_id: candidate.clone(),
+                votes: 0,
+            })
+            .collect();
+        let mut exhausted_ballots = 0;
+        for ballot in ballots {
+            let preference = ballot.choices.iter().find(|choice| standing.contains(choice));
+            match preference.and_then(|choice| counts.iter_mut().find(|count| &count.place_id == choice)) {
+                Some(count) => count.votes += 1,
+                None => exhausted_ballots += 1,
+            }
+        }
+
+        let active_ballots = ballots.len() as u32 - exhausted_ballots;
+        let (winner, leader_tied) = leader(&counts);
+        let winner_votes = counts.iter().find(|count| count.place_id == winner).map_or(0, |count| count.votes);
+        if standing.len() == 1 || winner_votes * 2 > active_ballots {
+            tie_broken |= leader_tied;
+            rounds.push(TallyRound {
+                round: rounds.len() as u32 + 1,
+                counts,
+                exhausted_ballots,
+                eliminated: None,
+            });
+            return (winner, rounds, tie_broken);
+        }
+
+        let (eliminated, elimination_tied) = last_place(&counts, &rounds);
+        tie_broken |= elimination_tied;
+        standing.retain(|candidate| *candidate != eliminated);
+        rounds.push(TallyRound {
+            round: rounds.len() as u32 + 1,
+            counts,
+            exhausted_ballots,
+            eliminated: Some(eliminated),
+        });
+    }
+}
+
+/// Candidate with the most votes, the earliest listed one on a tie.
+fn leader(
+    counts: &[RoundCount],
+) -> (String, bool) {
+    let most_votes = counts.iter().map(|count| count.votes).max().unwrap_or(0);
+    let mut leaders = counts.iter().filter(|count| count.votes == most_votes);
+    let winner = leaders.next().map(|count| count.place_id.clone()).unwrap_or_default();
+    (winner, leaders.next().is_some())
+}
+
+/// Candidate to eliminate this round, see `tally` for how ties are settled.
+fn last_place(
+    counts: &[RoundCount],
+    earlier_rounds: &[TallyRound],
+) -> (String, bool) {
+    let fewest_votes = counts.iter().map(|count| count.votes).min().unwrap_or(0);
+    let mut tied: Vec<&str> = counts
+        .iter()
+        .filter(|count| count.votes == fewest_votes)
+        .map(|count| count.place_id.as_str())
+        .collect();
+    if tied.len() == 1 {
+        return (tied[0].to_string(), false);
+    }
+
+    for round in earlier_rounds.iter().rev() {
+        let votes_in_round = |place_id: &str| round
+            .counts
+            .iter()
+            .find(|count| count.place_id == place_id)
+            .map_or(0, |count| count.votes);
+        let fewest_in_round = tied.iter().map(|place_id| votes_in_round(place_id)).min().unwrap_or(0);
+        tied.retain(|place_id| votes_in_round(place_id) == fewest_in_round);
+        if tied.len() == 1 {
+            break;
+        }
+    }
+
+    // counts keep the proposal order, so the last one left is the one listed last
+    (tied.last().map(|place_id| place_id.to_string()).unwrap_or_default(), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(place_ids: &[&str]) -> Vec<String> {
+        place_ids.iter().map(|place_id| place_id.to_string()).collect()
+    }
+
+    fn ballots(choices: &[&[&str]]) -> Vec<Ballot> {
+        choices
+            .iter()
+            .map(|choices| Ballot {
+                user_id: None,
+                choices: candidates(choices),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plurality_picks_the_most_votes() {
+        let result = tally(
+            VotingMethod::Plurality,
+            &candidates(&["a", "b", "c"]),
+            &ballots(&[&["b"], &["c"], &["b"]]),
+        ).unwrap();
+
+        assert_eq!(result.winner, "b");
+        assert_eq!(result.rounds.len(), 1);
+        assert!(!result.tie_broken);
+        assert_eq!(result.standings()[0], RoundCount { place_id: "b".to_string(), votes: 2 });
+    }
+
+    #[test]
+    fn plurality_ties_go_to_the_first_listed_candidate() {
+        let result = tally(
+            VotingMethod::Plurality,
+            &candidates(&["a", "b", "c"]),
+            &ballots(&[&["c"], &["b"]]),
+        ).unwrap();
+
+        assert_eq!(result.winner, "b");
+        assert!(result.tie_broken);
+    }
+
+    #[test]
+    fn plurality_rejects_multiple_choices() {
+        let error = tally(
+            VotingMethod::Plurality,
+            &candidates(&["a", "b"]),
+            &ballots(&[&["a", "b"]]),
+        ).unwrap_err();
+        assert_eq!(error.code(), "validation_failed");
+    }
+
+    #[test]
+    fn approval_counts_every_choice() {
+        let result = tally(
+            VotingMethod::Approval,
+            &candidates(&["a", "b", "c"]),
+            &ballots(&[&["a", "c"], &["c"], &["a", "b", "c"]]),
+        ).unwrap();
+
+        assert_eq!(result.winner, "c");
+        let votes: Vec<u32> = result.rounds[0].counts.iter().map(|count| count.votes).collect();
+        assert_eq!(votes, vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn ranked_choice_transfers_eliminated_votes() {
+        // a leads on first preferences, but c's supporters prefer b over a
+        let result = tally(
+            VotingMethod::RankedChoice,
+            &candidates(&["a", "b", "c"]),
+            &ballots(&[&["a"], &["a"], &["a"], &["b"], &["b"], &["c", "b"], &["c", "b"]]),
+        ).unwrap();
+
+        assert_eq!(result.rounds.len(), 2);
+        assert_eq!(result.rounds[0].eliminated.as_deref(), Some("c"));
+        assert_eq!(result.winner, "b");
+        assert_eq!(result.rounds[1].counts[1], RoundCount { place_id: "b".to_string(), votes: 4 });
+        assert!(result.tie_broken);
+    }
+
+    #[test]
+    fn ranked_choice_stops_at_a_majority() {
+        let result = tally(
+            VotingMethod::RankedChoice,
+            &candidates(&["a", "b", "c"]),
+            &ballots(&[&["b", "a"], &["b"], &["a", "b"]]),
+        ).unwrap();
+
+        assert_eq!(result.winner, "b");
+        assert_eq!(result.rounds.len(), 1);
+        assert!(!result.tie_broken);
+    }
+
+    #[test]
+    fn ranked_choice_breaks_elimination_ties_on_earlier_rounds() {
+        let result = tally(
+            VotingMethod::RankedChoice,
+            &candidates(&["a", "c", "b", "d"]),
+            &ballots(&[
+                &["a"],
+                &["a"],
+                &["a"],
+                &["a"],
+                &["b"],
+                &["b"],
+                &["b"],
+                &["c"],
+                &["c", "b"],
+                &["d", "c"],
+            ]),
+        ).unwrap();
+
+        // knocking out d lifts c level with b, c trailed b in round one so it goes even though b is
+        // listed last
+        assert_eq!(result.rounds[0].eliminated.as_deref(), Some("d"));
+        assert_eq!(result.rounds[1].eliminated.as_deref(), Some("c"));
+        assert_eq!(result.rounds[2].counts, vec![
+            RoundCount { place_id: "a".to_string(), votes: 4 },
+            RoundCount { place_id: "b".to_string(), votes: 4 },
+        ]);
+        assert_eq!(result.rounds[2].eliminated.as_deref(), Some("b"));
+        assert_eq!(result.rounds.len(), 4);
+        assert_eq!(result.winner, "a");
+
+        let standings: Vec<String> = result.standings().into_iter().map(|count| count.place_id).collect();
+        assert_eq!(standings, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn ballot_order_does_not_change_the_result() {
+        let candidates = candidates(&["a", "b", "c", "d"]);
+        let mut ballots = ballots(&[
+            &["d", "b"],
+            &["c", "a"],
+            &["a", "d"],
+            &["b", "c"],
+            &["c", "d"],
+        ]);
+
+        let first = tally(VotingMethod::RankedChoice, &candidates, &ballots).unwrap();
+        ballots.reverse();
+        let second = tally(VotingMethod::RankedChoice, &candidates, &ballots).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn unknown_or_repeated_choices_are_rejected() {
+        let candidates = candidates(&["a", "b"]);
+        assert!(tally(VotingMethod::Approval, &candidates, &ballots(&[&["z"]])).is_err());
+        assert!(tally(VotingMethod::RankedChoice, &candidates, &ballots(&[&["a", "a"]])).is_err());
+        assert!(tally(VotingMethod::Approval, &candidates, &ballots(&[&[]])).is_err());
+        assert!(tally(VotingMethod::Approval, &candidates, &[]).is_err());
+    }
+}